@@ -1,11 +1,21 @@
 use chrono::Duration;
 use clap::ArgMatches;
+use ffsend_api::action::params::PARAMS_DEFAULT_DOWNLOAD;
+use ffsend_api::api::DesiredVersion;
 use ffsend_api::config::SEND_DEFAULT_EXPIRE_TIME;
 use prettytable::{format::FormatBuilder, Cell, Row, Table};
+use serde_json::json;
 
 use crate::client::to_duration;
 use crate::cmd::matcher::{debug::DebugMatcher, main::MainMatcher, Matcher};
+use crate::config_file::{Source, CONFIG};
 use crate::error::ActionError;
+use crate::headers::Header;
+use crate::output::print_record;
+use crate::proxy::{bypass_proxy, redact_proxy};
+use crate::tls::TlsConfig;
+#[cfg(feature = "archive")]
+use crate::util::env_var_present;
 use crate::util::{api_version_list, features_list, format_bool, format_duration};
 
 /// A file debug action.
@@ -26,6 +36,192 @@ impl<'a> Debug<'a> {
         let matcher_main = MainMatcher::with(self.cmd_matches).unwrap();
         let matcher_debug = DebugMatcher::with(self.cmd_matches).unwrap();
 
+        // Determine where effective values are taken from
+        let settings = CONFIG.settings();
+        let matches: Vec<&ArgMatches> = vec![self.cmd_matches]
+            .into_iter()
+            .chain(self.cmd_matches.subcommand_matches("debug"))
+            .collect();
+        let source = |name, env, config| Source::of(&matches, name, env, config).name();
+        let source_host = source("host", Some("FFSEND_HOST"), settings.host.is_some());
+        let source_api = source("api", Some("FFSEND_API"), settings.api.is_some());
+        let source_basic_auth = source(
+            "basic-auth",
+            Some("FFSEND_BASIC_AUTH"),
+            settings.basic_auth.is_some(),
+        );
+        let mut source_proxy = source("proxy", Some("FFSEND_PROXY"), settings.proxy.is_some());
+        let mut source_no_proxy = source(
+            "no-proxy",
+            Some("FFSEND_NO_PROXY"),
+            settings.no_proxy.is_some(),
+        );
+        let host_settings = CONFIG.host_settings(&matcher_debug.host()).cloned();
+        let host_settings = host_settings.unwrap_or_default();
+        let source_ca_cert = source(
+            "ca-cert",
+            Some("FFSEND_CA_CERT"),
+            host_settings.ca_cert.is_some() || settings.ca_cert.is_some(),
+        );
+        let source_client_cert = source(
+            "client-cert",
+            Some("FFSEND_CLIENT_CERT"),
+            host_settings.client_cert.is_some() || settings.client_cert.is_some(),
+        );
+        let source_pin_sha256 = source(
+            "pin-sha256",
+            Some("FFSEND_PIN_SHA256"),
+            host_settings.pin_sha256.is_some() || settings.pin_sha256.is_some(),
+        );
+        #[cfg(feature = "history")]
+        let source_history = source(
+            "history",
+            Some("FFSEND_HISTORY"),
+            settings.history.is_some(),
+        );
+        let source_timeout = source(
+            "timeout",
+            Some("FFSEND_TIMEOUT"),
+            settings.timeout.is_some(),
+        );
+        let source_transfer_timeout = source(
+            "transfer-timeout",
+            Some("FFSEND_TRANSFER_TIMEOUT"),
+            settings.transfer_timeout.is_some(),
+        );
+        let source_retries = source(
+            "retries",
+            Some("FFSEND_RETRIES"),
+            settings.retries.is_some(),
+        );
+        let source_download_limit =
+            source("download-limit", None, settings.download_limit.is_some());
+        #[cfg(feature = "archive")]
+        let source_archive = source(
+            "archive",
+            Some("FFSEND_ARCHIVE"),
+            settings.archive.is_some(),
+        );
+        #[cfg(feature = "archive")]
+        let source_extract = source(
+            "extract",
+            Some("FFSEND_EXTRACT"),
+            settings.extract.is_some(),
+        );
+
+        // Determine the effective API version, basic auth user and download limit
+        let api = match matcher_main.api() {
+            DesiredVersion::Use(version) => version.to_string(),
+            DesiredVersion::Assume(version) => format!("{} (assumed)", version),
+            DesiredVersion::Lookup => "lookup".into(),
+        };
+        let basic_auth_user = matcher_main.basic_auth().map(|(user, _)| user);
+
+        // The custom headers, never show their values
+        let headers: Vec<String> = matcher_main.headers().iter().map(Header::masked).collect();
+
+        // Determine the effective proxy, which may be taken from the standard environment variables
+        let host = matcher_debug.host();
+        let proxy = matcher_main.proxy().map(|proxy| redact_proxy(&proxy));
+        let no_proxy = matcher_main.no_proxy();
+        let tls = TlsConfig::for_host(&matcher_main, &host);
+        let ca_cert = tls.ca_cert.as_ref().and_then(|path| path.to_str());
+        let client_cert = tls.client_cert.as_ref().and_then(|path| path.to_str());
+        let proxy_bypassed = proxy.is_some()
+            && no_proxy
+                .as_ref()
+                .map(|hosts| bypass_proxy(hosts, host.host_str().unwrap_or("")))
+                .unwrap_or(false);
+        if proxy.is_some() && source_proxy == Source::Default.name() {
+            source_proxy = Source::Environment.name();
+        }
+        if no_proxy.is_some() && source_no_proxy == Source::Default.name() {
+            source_no_proxy = Source::Environment.name();
+        }
+        let download_limit = settings.download_limit.unwrap_or(PARAMS_DEFAULT_DOWNLOAD);
+        #[cfg(feature = "archive")]
+        let archive = env_var_present("FFSEND_ARCHIVE") || settings.archive.unwrap_or(false);
+        #[cfg(feature = "archive")]
+        let extract = env_var_present("FFSEND_EXTRACT") || settings.extract.unwrap_or(false);
+
+        // Print a machine readable record if selected
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            #[cfg(feature = "history")]
+            let history = json!(matcher_main.history().to_str());
+            #[cfg(not(feature = "history"))]
+            let history = json!(null);
+            #[cfg(feature = "history")]
+            let source_history = json!(source_history);
+            #[cfg(not(feature = "history"))]
+            let source_history = json!(null);
+            #[cfg(feature = "archive")]
+            let (archive, extract, source_archive, source_extract) = (
+                json!(archive),
+                json!(extract),
+                json!(source_archive),
+                json!(source_extract),
+            );
+            #[cfg(not(feature = "archive"))]
+            let (archive, extract, source_archive, source_extract) =
+                (json!(null), json!(null), json!(null), json!(null));
+
+            print_record(
+                format,
+                vec![
+                    ("version", json!(crate_version!())),
+                    ("config_file", json!(CONFIG.file_path().to_str())),
+                    ("config_found", json!(CONFIG.found())),
+                    ("profile", json!(CONFIG.profile())),
+                    ("host", json!(matcher_debug.host().as_str())),
+                    ("api", json!(api)),
+                    ("basic_auth_user", json!(basic_auth_user)),
+                    ("proxy", json!(proxy)),
+                    ("no_proxy", json!(no_proxy)),
+                    ("proxy_bypassed", json!(proxy_bypassed)),
+                    ("headers", json!(headers)),
+                    ("ca_cert", json!(ca_cert)),
+                    ("client_cert", json!(client_cert)),
+                    ("pin_sha256", json!(tls.pin_sha256)),
+                    ("history_file", history),
+                    ("timeout", json!(matcher_main.timeout())),
+                    ("transfer_timeout", json!(matcher_main.transfer_timeout())),
+                    ("retries", json!(matcher_main.retries())),
+                    ("download_limit", json!(download_limit)),
+                    ("archive", archive),
+                    ("extract", extract),
+                    ("default_expiry", json!(SEND_DEFAULT_EXPIRE_TIME)),
+                    ("features", json!(features_list())),
+                    ("api_support", json!(api_version_list())),
+                    ("quiet", json!(matcher_main.quiet())),
+                    ("verbose", json!(matcher_main.verbose())),
+                    ("verbosity", json!(matcher_main.verbosity())),
+                    ("log_file", json!(matcher_main.log_file())),
+                    (
+                        "sources",
+                        json!({
+                            "host": source_host,
+                            "api": source_api,
+                            "basic_auth": source_basic_auth,
+                            "proxy": source_proxy,
+                            "no_proxy": source_no_proxy,
+                            "ca_cert": source_ca_cert,
+                            "client_cert": source_client_cert,
+                            "pin_sha256": source_pin_sha256,
+                            "history_file": source_history,
+                            "timeout": source_timeout,
+                            "transfer_timeout": source_transfer_timeout,
+                            "retries": source_retries,
+                            "download_limit": source_download_limit,
+                            "archive": source_archive,
+                            "extract": source_extract,
+                        }),
+                    ),
+                ],
+            );
+            return Ok(());
+        }
+
         // Create a table for all debug information
         let mut table = Table::new();
         table.set_format(FormatBuilder::new().padding(0, 2).build());
@@ -36,10 +232,90 @@ impl<'a> Debug<'a> {
             Cell::new(crate_version!()),
         ]));
 
+        // The configuration file and selected profile
+        table.add_row(Row::new(vec![
+            Cell::new("Config file:"),
+            Cell::new(&format!(
+                "{}{}",
+                CONFIG.file_path().to_str().unwrap_or("?"),
+                if CONFIG.found() { "" } else { " (not found)" },
+            )),
+        ]));
+        table.add_row(Row::new(vec![
+            Cell::new("Profile:"),
+            Cell::new(CONFIG.profile().unwrap_or("none")),
+        ]));
+
         // The default host
         table.add_row(Row::new(vec![
             Cell::new("Host:"),
             Cell::new(matcher_debug.host().as_str()),
+            Cell::new(source_host),
+        ]));
+
+        // The API version and basic authentication user
+        table.add_row(Row::new(vec![
+            Cell::new("API version:"),
+            Cell::new(&api),
+            Cell::new(source_api),
+        ]));
+        table.add_row(Row::new(vec![
+            Cell::new("Basic auth:"),
+            Cell::new(
+                basic_auth_user
+                    .as_ref()
+                    .map(String::as_str)
+                    .unwrap_or("none"),
+            ),
+            Cell::new(source_basic_auth),
+        ]));
+
+        // The proxy, and the hosts to bypass it for
+        table.add_row(Row::new(vec![
+            Cell::new("Proxy:"),
+            Cell::new(&match &proxy {
+                Some(proxy) if proxy_bypassed => format!("{} (bypassed)", proxy),
+                Some(proxy) => proxy.to_owned(),
+                None => "none".into(),
+            }),
+            Cell::new(source_proxy),
+        ]));
+        table.add_row(Row::new(vec![
+            Cell::new("No proxy:"),
+            Cell::new(no_proxy.as_ref().map(String::as_str).unwrap_or("none")),
+            Cell::new(source_no_proxy),
+        ]));
+
+        // The custom headers
+        table.add_row(Row::new(vec![
+            Cell::new("Headers:"),
+            Cell::new(&if headers.is_empty() {
+                "none".into()
+            } else {
+                headers.join(", ")
+            }),
+        ]));
+
+        // The TLS settings for the host
+        table.add_row(Row::new(vec![
+            Cell::new("CA certificate:"),
+            Cell::new(ca_cert.unwrap_or("system")),
+            Cell::new(source_ca_cert),
+        ]));
+        table.add_row(Row::new(vec![
+            Cell::new("Client certificate:"),
+            Cell::new(client_cert.unwrap_or("none")),
+            Cell::new(source_client_cert),
+        ]));
+        table.add_row(Row::new(vec![
+            Cell::new("Certificate pin:"),
+            Cell::new(
+                tls.pin_sha256
+                    .as_ref()
+                    .map(String::as_str)
+                    .unwrap_or("none"),
+            ),
+            Cell::new(source_pin_sha256),
         ]));
 
         // The history file
@@ -47,6 +323,7 @@ impl<'a> Debug<'a> {
         table.add_row(Row::new(vec![
             Cell::new("History file:"),
             Cell::new(matcher_main.history().to_str().unwrap_or("?")),
+            Cell::new(source_history),
         ]));
 
         // The timeouts
@@ -61,6 +338,7 @@ impl<'a> Debug<'a> {
                     })
                     .unwrap_or("disabled".into()),
             ),
+            Cell::new(source_timeout),
         ]));
         table.add_row(Row::new(vec![
             Cell::new("Transfer timeout:"),
@@ -74,9 +352,39 @@ impl<'a> Debug<'a> {
                     })
                     .unwrap_or("disabled".into()),
             ),
+            Cell::new(source_transfer_timeout),
         ]));
 
-        // The default host
+        // The number of transfer retries
+        table.add_row(Row::new(vec![
+            Cell::new("Retries:"),
+            Cell::new(&matcher_main.retries().to_string()),
+            Cell::new(source_retries),
+        ]));
+
+        // The default download limit
+        table.add_row(Row::new(vec![
+            Cell::new("Download limit:"),
+            Cell::new(&download_limit.to_string()),
+            Cell::new(source_download_limit),
+        ]));
+
+        // Whether to archive uploads and extract downloads by default
+        #[cfg(feature = "archive")]
+        {
+            table.add_row(Row::new(vec![
+                Cell::new("Archive:"),
+                Cell::new(format_bool(archive)),
+                Cell::new(source_archive),
+            ]));
+            table.add_row(Row::new(vec![
+                Cell::new("Extract:"),
+                Cell::new(format_bool(extract)),
+                Cell::new(source_extract),
+            ]));
+        }
+
+        // The default expiry
         table.add_row(Row::new(vec![
             Cell::new("Default expiry:"),
             Cell::new(&format_duration(Duration::seconds(
@@ -102,10 +410,24 @@ impl<'a> Debug<'a> {
             Cell::new(format_bool(matcher_main.quiet())),
         ]));
 
-        // Show whether verbose is used
+        // Show whether verbose is used, and at what level
         table.add_row(Row::new(vec![
             Cell::new("Verbose:"),
-            Cell::new(format_bool(matcher_main.verbose())),
+            Cell::new(&match matcher_main.verbosity() {
+                0 => format_bool(false).to_owned(),
+                level => format!("{} (level {})", format_bool(true), level),
+            }),
+        ]));
+
+        // Show the log file
+        table.add_row(Row::new(vec![
+            Cell::new("Log file:"),
+            Cell::new(
+                &matcher_main
+                    .log_file()
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "none".into()),
+            ),
         ]));
 
         // Print the debug table
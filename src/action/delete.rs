@@ -1,13 +1,34 @@
+#[cfg(feature = "history")]
+use chrono::{Duration, Utc};
 use clap::ArgMatches;
-use ffsend_api::action::delete::{Delete as ApiDelete, Error as DeleteError};
+#[cfg(feature = "history")]
+use failure::Fail;
+use ffsend_api::action::delete::Error as DeleteError;
 use ffsend_api::file::remote_file::{FileParseError, RemoteFile};
+#[cfg(feature = "history")]
+use ffsend_api::url::Url;
+#[cfg(feature = "history")]
+use prettytable::{format::FormatBuilder, Cell, Row, Table};
+use serde_json::json;
 
+use crate::api::Delete as ApiDelete;
 use crate::client::create_config;
 use crate::cmd::matcher::{delete::DeleteMatcher, main::MainMatcher, Matcher};
 use crate::error::ActionError;
 #[cfg(feature = "history")]
+use crate::history::{History as HistoryManager, HistoryFile};
+#[cfg(feature = "history")]
 use crate::history_tool;
+use crate::output::print_record;
+#[cfg(feature = "history")]
+use crate::output::{print_records, Record};
 use crate::util::{ensure_owner_token, print_success};
+#[cfg(feature = "history")]
+use crate::util::{print_error, prompt_yes, quit};
+
+/// The names of the values reported for each file in a batch delete in machine readable output.
+#[cfg(feature = "history")]
+const BATCH_RECORD_COLUMNS: [&str; 6] = ["index", "id", "name", "url", "status", "error"];
 
 /// A file delete action.
 pub struct Delete<'a> {
@@ -27,11 +48,19 @@ impl<'a> Delete<'a> {
         let matcher_main = MainMatcher::with(self.cmd_matches).unwrap();
         let matcher_delete = DeleteMatcher::with(self.cmd_matches).unwrap();
 
+        // Delete a batch of files from history if selected
+        #[cfg(feature = "history")]
+        {
+            if matcher_delete.batch() {
+                return Self::invoke_batch(&matcher_main, &matcher_delete);
+            }
+        }
+
         // Get the share link
         let url = matcher_delete.url();
 
         // Create client
-        let client_config = create_config(&matcher_main);
+        let client_config = create_config(&matcher_main, Some(&url));
         let client = client_config.client(false);
 
         // Parse the remote file based on the share link, derive the owner token from history
@@ -43,6 +72,7 @@ impl<'a> Delete<'a> {
         ensure_owner_token(file.owner_token_mut(), &matcher_main, false);
 
         // Send the file deletion request
+        debug!("deleting file {}", file.id());
         let result = ApiDelete::new(&file, None).invoke(&client);
         if let Err(DeleteError::Expired) = result {
             // Remove the file from the history manager if it does not exist
@@ -55,11 +85,211 @@ impl<'a> Delete<'a> {
         #[cfg(feature = "history")]
         history_tool::remove(&matcher_main, &file);
 
-        // Print a success message
+        // Print a machine readable record if selected, or a success message
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            print_record(
+                format,
+                vec![("id", json!(file.id())), ("deleted", json!(true))],
+            );
+            return Ok(());
+        }
         print_success("File deleted");
 
         Ok(())
     }
+
+    /// Delete a batch of files from history, selected by the given matcher.
+    ///
+    /// Only files with an owner token are selected. Deleting continues past files that fail to
+    /// delete, these are reported at the end and an error is returned.
+    #[cfg(feature = "history")]
+    fn invoke_batch(
+        matcher_main: &MainMatcher,
+        matcher_delete: &DeleteMatcher,
+    ) -> Result<(), ActionError> {
+        let format = matcher_main.output_format();
+
+        // Load the history, it is released while deleting and loaded again to update it
+        let load = || {
+            HistoryManager::load_or_new(matcher_main.history(), &|| {
+                matcher_main.history_passphrase(false)
+            })
+        };
+        let history = load()?;
+
+        // Select the files with an owner token that match the filters, along with their index
+        let host = matcher_delete
+            .filter_host()
+            .and_then(|host| host.host_str().map(|h| h.to_owned()));
+        let older_than = matcher_delete
+            .older_than()
+            .map(|secs| Duration::seconds(secs as i64));
+        let now = Utc::now();
+        let files: Vec<(usize, HistoryFile)> = history
+            .files_listed()
+            .into_iter()
+            .enumerate()
+            .map(|(i, file)| (i + 1, file))
+            .filter(|(_, file)| {
+                let remote = file.remote_file();
+                remote.has_owner_token()
+                    && host
+                        .as_ref()
+                        .map(|host| remote.host().host_str() == Some(host))
+                        .unwrap_or(true)
+                    && older_than
+                        .map(|age| file.upload_at().map(|at| now - *at > age).unwrap_or(false))
+                        .unwrap_or(true)
+            })
+            .map(|(index, file)| (index, file.clone()))
+            .collect();
+        drop(history);
+
+        // Report if there are no files to delete
+        if files.is_empty() {
+            if format.is_machine() {
+                print_records(format, &BATCH_RECORD_COLUMNS, Vec::new());
+            } else if !matcher_main.quiet() {
+                eprintln!("No files in history to delete");
+            }
+            return Ok(());
+        }
+
+        // Confirm to delete the files when not forced
+        if !matcher_main.force()
+            && !prompt_yes(
+                &format!("Delete {} file(s)?", files.len()),
+                None,
+                &matcher_main,
+            )
+        {
+            eprintln!("Deleting files cancelled");
+            quit();
+        }
+
+        // Delete each file
+        let hosts: Vec<Url> = files
+            .iter()
+            .map(|(_, file)| file.remote_file().host())
+            .collect();
+        let client_config = create_config(&matcher_main, &hosts);
+        let client = client_config.client(false);
+        let outcomes: Vec<Outcome> = files
+            .iter()
+            .map(|(_, file)| {
+                let remote = file.remote_file();
+                debug!("deleting file {}", remote.id());
+                match ApiDelete::new(remote, None).invoke(&client) {
+                    Ok(()) => Outcome::Deleted,
+                    Err(DeleteError::Expired) => Outcome::Gone,
+                    Err(err) => Outcome::Failed(err.into()),
+                }
+            })
+            .collect();
+
+        // Remove deleted and gone files from history
+        let mut history = load()?;
+        for ((_, file), outcome) in files.iter().zip(&outcomes) {
+            if let Outcome::Deleted | Outcome::Gone = outcome {
+                history.remove(file.remote_file());
+            }
+        }
+        history.save()?;
+
+        // Print machine readable records if selected, or a result table
+        if format.is_machine() {
+            let records = files
+                .iter()
+                .zip(&outcomes)
+                .map(|((index, file), outcome)| -> Record {
+                    let remote = file.remote_file();
+                    vec![
+                        ("index", json!(index)),
+                        ("id", json!(remote.id())),
+                        ("name", json!(file.name())),
+                        ("url", json!(remote.download_url(true).as_str())),
+                        ("status", json!(outcome.name())),
+                        (
+                            "error",
+                            json!(match outcome {
+                                Outcome::Failed(err) => Some(err.to_string()),
+                                _ => None,
+                            }),
+                        ),
+                    ]
+                })
+                .collect();
+            print_records(format, &BATCH_RECORD_COLUMNS, records);
+        } else if !matcher_main.quiet() {
+            let mut table = Table::new();
+            table.set_format(FormatBuilder::new().padding(0, 2).build());
+            table.add_row(Row::new(
+                vec!["#", "NAME", "STATUS", "LINK"]
+                    .into_iter()
+                    .map(Cell::new)
+                    .collect(),
+            ));
+            for ((index, file), outcome) in files.iter().zip(&outcomes) {
+                let cells: Vec<String> = vec![
+                    format!("{}", index),
+                    file.name().unwrap_or("?").into(),
+                    outcome.name().into(),
+                    file.remote_file().download_url(true).into_string(),
+                ];
+                table.add_row(Row::new(cells.into_iter().map(|c| Cell::new(&c)).collect()));
+            }
+            table.printstd();
+        }
+
+        // Report the failures at the end
+        let total = files.len();
+        let mut failed = 0;
+        for ((index, file), outcome) in files.into_iter().zip(outcomes) {
+            if let Outcome::Failed(err) = outcome {
+                failed += 1;
+                print_error(err.context(format!(
+                    "failed to delete file #{} ({})",
+                    index,
+                    file.name().unwrap_or_else(|| file.remote_file().id()),
+                )));
+            }
+        }
+        if failed > 0 {
+            return Err(Error::Batch(failed, total).into());
+        }
+
+        if !format.is_machine() && !matcher_main.quiet() {
+            print_success(&format!("Deleted {} file(s)", total));
+        }
+
+        Ok(())
+    }
+}
+
+/// The outcome of deleting a single file in a batch.
+#[cfg(feature = "history")]
+enum Outcome {
+    /// The file was deleted.
+    Deleted,
+
+    /// The file had expired or did never exist.
+    Gone,
+
+    /// Deleting the file failed.
+    Failed(Error),
+}
+
+#[cfg(feature = "history")]
+impl Outcome {
+    /// Get the name of this outcome.
+    fn name(&self) -> &'static str {
+        match self {
+            Outcome::Deleted => "deleted",
+            Outcome::Gone => "gone",
+            Outcome::Failed(_) => "failed",
+        }
+    }
 }
 
 #[derive(Debug, Fail)]
@@ -76,6 +306,11 @@ pub enum Error {
     /// An error occurred while deleting the remote file.
     #[fail(display = "failed to delete the shared file")]
     Delete(#[cause] DeleteError),
+
+    /// Some files in a batch failed to delete.
+    #[cfg(feature = "history")]
+    #[fail(display = "failed to delete {} of {} file(s)", _0, _1)]
+    Batch(usize, usize),
 }
 
 impl From<FileParseError> for Error {
@@ -1,32 +1,50 @@
+use std::collections::{HashSet, VecDeque};
 use std::env::current_dir;
-use std::fs::create_dir_all;
 #[cfg(feature = "archive")]
-use std::io::Error as IoError;
+use std::ffi::OsStr;
+use std::fs::{create_dir_all, remove_file, File};
+use std::io::{self, stderr, stdout, BufWriter, Error as IoError};
 use std::path::{self, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc::channel, Arc, Mutex};
+use std::thread;
 
 use clap::ArgMatches;
 use failure::Fail;
-use ffsend_api::action::download::{Download as ApiDownload, Error as DownloadError};
-use ffsend_api::action::exists::{Error as ExistsError, Exists as ApiExists};
-use ffsend_api::action::metadata::{Error as MetadataError, Metadata as ApiMetadata};
+use ffsend_api::action::exists::Error as ExistsError;
+use ffsend_api::action::metadata::{Error as MetadataError, MetadataResponse};
 use ffsend_api::action::version::Error as VersionError;
+use ffsend_api::api::Version;
 use ffsend_api::file::remote_file::{FileParseError, RemoteFile};
 use ffsend_api::pipe::ProgressReporter;
-#[cfg(feature = "archive")]
+use ffsend_api::url::Url;
+use pbr::MultiBar;
+use serde_json::json;
 use tempfile::{Builder as TempBuilder, NamedTempFile};
 
 use super::select_api_version;
+use crate::api::{Exists as ApiExists, Metadata as ApiMetadata};
 #[cfg(feature = "archive")]
-use crate::archive::archive::Archive;
-use crate::client::create_config;
+use crate::archive::{
+    archive::{extract_stream, Archive, Summary},
+    format::ArchiveFormat,
+};
+use crate::checksum::{sha256_file, SharedHasher};
+use crate::client::{create_config, Client};
 use crate::cmd::matcher::{download::DownloadMatcher, main::MainMatcher, Matcher};
 #[cfg(feature = "history")]
+use crate::history::{Direction, HistoryFile};
+#[cfg(feature = "history")]
 use crate::history_tool;
-use crate::progress::ProgressBar;
+use crate::output::print_record_stream;
+use crate::progress::{MultiProgressBar, ProgressBar};
+use crate::split::{fetch_manifest, Error as SplitError, Manifest, SplitDownload};
+use crate::throttle::Throttle;
+use crate::transfer::{Error as DownloadError, ResumableDownload};
+#[cfg(feature = "archive")]
+use crate::util::print_warning;
 use crate::util::{
     ensure_enough_space, ensure_password, follow_url, print_error, prompt_yes, quit, quit_error,
-    quit_error_msg, ErrorHints,
+    quit_error_msg, ErrorHints, ErrorHintsBuilder,
 };
 
 /// A file download action.
@@ -47,13 +65,202 @@ impl<'a> Download<'a> {
         let matcher_main = MainMatcher::with(self.cmd_matches).unwrap();
         let matcher_download = DownloadMatcher::with(self.cmd_matches).unwrap();
 
+        // Limit the transfer rate of all downloaded files together
+        let throttle = matcher_download.limit_rate().map(Throttle::new);
+
+        // Download multiple files in parallel if more than one URL is given
+        let mut urls = matcher_download.urls();
+        if urls.len() > 1 {
+            return Self::invoke_multi(&matcher_main, &matcher_download, urls, throttle);
+        }
+        let url = urls.remove(0);
+
         // Create a regular client
-        let client_config = create_config(&matcher_main);
+        let client_config = create_config(&matcher_main, Some(&url));
         let client = client_config.clone().client(false);
 
-        // Get the share URL, attempt to follow it
-        let url = matcher_download.url();
-        let url = match follow_url(&client, &url) {
+        // Prepare the download
+        let job = Self::prepare(&matcher_main, &matcher_download, &client, url, false)?;
+
+        // Create a progress bar reporter
+        let progress_bar = Arc::new(Mutex::new(ProgressBar::new_download()));
+        let progress_reader: Arc<Mutex<dyn ProgressReporter>> = progress_bar;
+        let progress = if !matcher_main.quiet() {
+            Some(progress_reader)
+        } else {
+            None
+        };
+
+        // Create a transfer client, download the file and finish
+        let transfer_client = client_config.client(true);
+        let done = Self::transfer(
+            job,
+            &transfer_client,
+            matcher_main.retries(),
+            throttle,
+            progress,
+            true,
+            |path| Self::prompt_overwrite(path, &matcher_main),
+        )?;
+        Self::finish(&matcher_main, done, true);
+
+        // TODO: open the file, or it's location
+        // TODO: copy the file location
+
+        Ok(())
+    }
+
+    /// Download the files at the given share URLs, with a bounded number of parallel transfers.
+    ///
+    /// Each download is prepared in turn first, so prompts such as for passwords don't
+    /// interleave. The files are then transferred in parallel, each with its own progress line.
+    /// Failed downloads don't stop the others, they are reported at the end and an error is
+    /// returned. The `throttle` limits the transfer rate of all files together.
+    fn invoke_multi(
+        matcher_main: &MainMatcher,
+        matcher_download: &DownloadMatcher,
+        urls: Vec<Url>,
+        throttle: Option<Throttle>,
+    ) -> Result<(), Error> {
+        // Multiple files can't be written to stdout
+        if matcher_download.output_stdout() {
+            quit_error_msg(
+                "multiple files can't be downloaded when writing to stdout",
+                ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+            );
+        }
+
+        // A checksum only applies to a single file
+        if matcher_download.verify().is_some() {
+            quit_error_msg(
+                "a checksum can only be verified when downloading a single file",
+                ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+            );
+        }
+
+        // Create a regular client
+        let client_config = create_config(matcher_main, &urls);
+        let client = client_config.clone().client(false);
+
+        // Prepare each download, make sure no two files are downloaded to the same path
+        let total = urls.len();
+        let mut failures: Vec<(Url, Error)> = Vec::new();
+        let mut jobs: Vec<(Url, Job)> = Vec::new();
+        let mut targets = HashSet::new();
+        for url in urls {
+            match Self::prepare(matcher_main, matcher_download, &client, url.clone(), true) {
+                Ok(job) => {
+                    if targets.insert(job.target.clone()) {
+                        jobs.push((url, job));
+                    } else {
+                        failures.push((
+                            url,
+                            Error::DuplicateTarget(job.target.to_string_lossy().into()),
+                        ));
+                    }
+                }
+                Err(err) => failures.push((url, err)),
+            }
+        }
+
+        // Create a progress line for each file
+        let mut multi_bar = MultiBar::on(stderr());
+        let queue: VecDeque<_> = jobs
+            .into_iter()
+            .enumerate()
+            .map(|(i, (url, job))| {
+                let progress = if !matcher_main.quiet() {
+                    let progress_bar = MultiProgressBar::new_download(&mut multi_bar, job.name());
+                    let progress_reader: Arc<Mutex<dyn ProgressReporter + Send>> =
+                        Arc::new(Mutex::new(progress_bar));
+                    Some(progress_reader)
+                } else {
+                    None
+                };
+                (i, url, job, progress)
+            })
+            .collect();
+        let count = queue.len();
+        let queue = Arc::new(Mutex::new(queue));
+        let listener = thread::spawn(move || multi_bar.listen());
+
+        // Spawn the workers, each transfers files from the queue until it is empty
+        let transfer_client = Arc::new(client_config.client(true));
+        let retries = matcher_main.retries();
+        let force = matcher_main.force();
+        let (sender, receiver) = channel();
+        for _ in 0..matcher_download.jobs().min(count) {
+            let queue = queue.clone();
+            let transfer_client = transfer_client.clone();
+            let throttle = throttle.clone();
+            let sender = sender.clone();
+            thread::spawn(move || loop {
+                let next = queue.lock().unwrap().pop_front();
+                let (i, url, job, progress) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+
+                // Existing files are only overwritten when extracting if forced, as we can't prompt
+                let progress = progress.map(|p| -> Arc<Mutex<dyn ProgressReporter>> { p });
+                let result = Download::transfer(
+                    job,
+                    &transfer_client,
+                    retries,
+                    throttle.clone(),
+                    progress,
+                    false,
+                    |_| force,
+                );
+                if sender.send((i, url, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Collect the results in order, wait for the progress lines to finish
+        let mut results: Vec<_> = receiver.into_iter().collect();
+        results.sort_by_key(|(i, _, _)| *i);
+        let _ = listener.join();
+
+        // Finish the completed downloads
+        let mut header = true;
+        for (_, url, result) in results {
+            match result {
+                Ok(done) => {
+                    Self::finish(matcher_main, done, header);
+                    header = false;
+                }
+                Err(err) => failures.push((url, err)),
+            }
+        }
+
+        // Report the failures at the end
+        if failures.is_empty() {
+            return Ok(());
+        }
+        let failed = failures.len();
+        for (url, err) in failures {
+            print_error(err.context(format!("failed to download '{}'", url)));
+        }
+        Err(Error::Batch(failed, total))
+    }
+
+    /// Prepare the download of the file at the given share URL.
+    ///
+    /// This checks whether the file exists, fetches its metadata and selects the target to
+    /// download to. The user is prompted for a password or whether to overwrite files when
+    /// needed. If `multi` is set, the output path is always used as directory.
+    fn prepare(
+        matcher_main: &MainMatcher,
+        matcher_download: &DownloadMatcher,
+        client: &Client,
+        url: Url,
+        multi: bool,
+    ) -> Result<Job, Error> {
+        // Attempt to follow the share URL
+        let url = match follow_url(client, &url) {
             Ok(url) => url,
             Err(err) => {
                 print_error(err.context("failed to follow share URL, ignoring").compat());
@@ -62,26 +269,35 @@ impl<'a> Download<'a> {
         };
 
         // Guess the host
-        let host = matcher_download.guess_host(Some(url.clone()));
+        let host = matcher_download.guess_host(url.clone());
 
         // Determine the API version to use
         let mut desired_version = matcher_main.api();
-        select_api_version(&client, host, &mut desired_version)?;
+        select_api_version(client, host, &mut desired_version)?;
         let api_version = desired_version.version().unwrap();
 
         // Parse the remote file based on the share URL
         let file = RemoteFile::parse_url(url, None)?;
 
         // Get the target file or directory, and the password
-        let target = matcher_download.output();
+        let mut target = matcher_download.output();
+        if multi {
+            target.push("");
+        }
         let mut password = matcher_download.password();
 
         // Check whether the file exists
-        let exists = ApiExists::new(&file).invoke(&client)?;
+        debug!("checking whether file {} exists", file.id());
+        let exists = ApiExists::new(&file).invoke(client)?;
+        debug!(
+            "file exists: {}, requires password: {}",
+            exists.exists(),
+            exists.requires_password(),
+        );
         if !exists.exists() {
             // Remove the file from the history manager if it does not exist
             #[cfg(feature = "history")]
-            history_tool::remove(&matcher_main, &file);
+            history_tool::remove(matcher_main, &file);
 
             return Err(Error::Expired);
         }
@@ -90,30 +306,61 @@ impl<'a> Download<'a> {
         ensure_password(
             &mut password,
             exists.requires_password(),
-            &matcher_main,
+            matcher_main,
             false,
         );
 
         // Fetch the file metadata
-        let metadata = ApiMetadata::new(&file, password.clone(), false).invoke(&client)?;
+        debug!("fetching metadata of file {}", file.id());
+        let metadata = ApiMetadata::new(&file, password.clone(), false).invoke(client)?;
+
+        // Fetch the manifest if the file was split into parts, the original file is downloaded
+        let split = if Manifest::is_manifest(metadata.metadata().name(), metadata.size()) {
+            Some(fetch_manifest(
+                client,
+                api_version,
+                &file,
+                password.clone(),
+                matcher_main.retries(),
+            )?)
+        } else {
+            None
+        };
+        let (name, size) = match &split {
+            Some(manifest) => (manifest.name.clone(), manifest.size),
+            None => (metadata.metadata().name().to_owned(), metadata.size()),
+        };
 
-        // A temporary archive file, only used when archiving
+        // A temporary archive file, only used when extracting an archive after it is downloaded
         // The temporary file is stored here, to ensure it's lifetime exceeds the upload process
         #[cfg(feature = "archive")]
         let mut tmp_archive: Option<NamedTempFile> = None;
 
+        // Check whether to write the file to stdout
+        let output_stdout = matcher_download.output_stdout();
+
         // Check whether to extract
         #[cfg(feature = "archive")]
         let mut extract = matcher_download.extract();
 
         #[cfg(feature = "archive")]
         {
+            // An archive can't be extracted to stdout
+            if extract && output_stdout {
+                quit_error_msg(
+                    "an archive can't be extracted when writing to stdout",
+                    ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+                );
+            }
+
             // Ask to extract if downloading an archive
-            if !extract && metadata.metadata().is_archive() {
+            let is_archive =
+                metadata.metadata().is_archive() || ArchiveFormat::from_name(&name).is_some();
+            if !extract && !output_stdout && is_archive {
                 if prompt_yes(
                     "You're downloading an archive, extract it into the selected directory?",
                     Some(true),
-                    &matcher_main,
+                    matcher_main,
                 ) {
                     extract = true;
                 }
@@ -125,22 +372,50 @@ impl<'a> Download<'a> {
         let output_dir = !extract;
         #[cfg(not(feature = "archive"))]
         let output_dir = false;
+
+        // A temporary file to download to, only used when writing a split file to stdout as its
+        // parts are joined first, other files are written to stdout while they are received
+        // The temporary file is stored here, to ensure it's lifetime exceeds the download process
+        let mut tmp_stdout: Option<NamedTempFile> = None;
+        let stream_stdout = output_stdout && split.is_none();
+
+        // Prepare the download target, use a temporary file when writing a split file to stdout
         #[allow(unused_mut)]
-        let mut target = Self::prepare_path(
-            &target,
-            metadata.metadata().name(),
-            &matcher_main,
-            output_dir,
-        );
-        #[cfg(feature = "archive")]
+        let mut target = if stream_stdout {
+            PathBuf::from("-")
+        } else if output_stdout {
+            let tmp = TempBuilder::new()
+                .prefix(&format!(".{}-stdout-", crate_name!()))
+                .tempfile()
+                .map_err(Error::Stdout)?;
+            let path = tmp.path().to_path_buf();
+            tmp_stdout = Some(tmp);
+            path
+        } else {
+            Self::prepare_path(&target, &name, matcher_main, output_dir)
+        };
         let output_path = target.clone();
 
+        // Extract archives while they are decrypted, except for zip archives which require seeking,
+        // files split into parts which are joined first, and files verified before extracting
+        let verify = matcher_download.verify();
+        #[cfg(feature = "archive")]
+        let format = ArchiveFormat::from_name(&name);
+        #[cfg(feature = "archive")]
+        let stream_extract =
+            extract && format != Some(ArchiveFormat::Zip) && split.is_none() && verify.is_none();
+
         #[cfg(feature = "archive")]
         {
-            // Allocate an archive file, and update the download and target paths
-            if extract {
-                // TODO: select the extention dynamically
-                let archive_extention = ".tar";
+            if stream_extract {
+                // Download the encrypted archive next to the extracted files, so it can be resumed
+                let name = path::Path::new(&name)
+                    .file_name()
+                    .unwrap_or_else(|| OsStr::new(crate_name!()));
+                target = output_path.join(name);
+            } else if extract {
+                // Use the archive extention of the file name, the format is detected when extracting
+                let archive_extention = format.unwrap_or(ArchiveFormat::Tar).extension();
 
                 // Allocate a temporary file to download the archive to
                 tmp_archive = Some(
@@ -156,48 +431,279 @@ impl<'a> Download<'a> {
             }
         }
 
-        // Ensure there is enough disk space available when not being forced
-        if !matcher_main.force() {
-            ensure_enough_space(target.parent().unwrap(), metadata.size());
+        // Ensure there is enough disk space available when not being forced,
+        // the encrypted file or the parts are downloaded next to the target first
+        if !matcher_main.force() && !stream_stdout {
+            ensure_enough_space(target.parent().unwrap(), size * 2);
         }
 
-        // Create a progress bar reporter
-        let progress_bar = Arc::new(Mutex::new(ProgressBar::new_download()));
-        let progress_reader: Arc<Mutex<ProgressReporter>> = progress_bar;
+        Ok(Job {
+            file,
+            api_version,
+            metadata,
+            split,
+            password,
+            target,
+            output_path,
+            verify,
+            output_stdout,
+            tmp_stdout,
+            #[cfg(feature = "archive")]
+            extract,
+            #[cfg(feature = "archive")]
+            stream_extract,
+            #[cfg(feature = "archive")]
+            tmp_archive,
+        })
+    }
 
-        // Create a transfer client
-        let transfer_client = client_config.client(true);
+    /// Transfer the prepared file, and extract or write it to stdout if selected.
+    ///
+    /// The transfer rate is limited by the given `throttle`, which may be shared with other
+    /// transfers. When extracting, `overwrite` is called with the path of each entry that already
+    /// exists to decide whether to replace it. A message is printed before extracting if `report`
+    /// is set.
+    #[cfg_attr(not(feature = "archive"), allow(unused_variables, unused_mut))]
+    fn transfer<F>(
+        job: Job,
+        client: &Client,
+        retries: u32,
+        throttle: Option<Throttle>,
+        progress: Option<Arc<Mutex<dyn ProgressReporter>>>,
+        report: bool,
+        mut overwrite: F,
+    ) -> Result<Done, Error>
+    where
+        F: FnMut(&path::Path) -> bool,
+    {
+        let Job {
+            file,
+            api_version,
+            metadata,
+            split,
+            password,
+            target,
+            output_path,
+            verify,
+            output_stdout,
+            tmp_stdout,
+            #[cfg(feature = "archive")]
+            extract,
+            #[cfg(feature = "archive")]
+            stream_extract,
+            #[cfg(feature = "archive")]
+            tmp_archive,
+        } = job;
 
-        // Execute an download action
-        let progress = if !matcher_main.quiet() {
-            Some(progress_reader)
+        // Execute an download action, use the original file if split into parts
+        let (name, size) = match &split {
+            Some(manifest) => (manifest.name.clone(), manifest.size),
+            None => (metadata.metadata().name().to_owned(), metadata.size()),
+        };
+        #[cfg(feature = "history")]
+        let mime = match &split {
+            Some(_) => mime_guess::from_path(&name)
+                .first_or_octet_stream()
+                .to_string(),
+            None => metadata.metadata().mime().to_owned(),
+        };
+        #[cfg(feature = "archive")]
+        let resumable = tmp_stdout.is_none() && tmp_archive.is_none();
+        #[cfg(not(feature = "archive"))]
+        let resumable = tmp_stdout.is_none();
+        let download = ResumableDownload::new(
+            api_version,
+            &file,
+            target.clone(),
+            password.clone(),
+            throttle.clone(),
+        );
+
+        // Download and join the parts if the file was split
+        if let Some(manifest) = &split {
+            let download =
+                SplitDownload::new(api_version, manifest, target.clone(), password, throttle);
+            if let Err(err) = download.invoke(client, retries, progress.clone(), report) {
+                // Don't keep parts for temporary targets, they can't be resumed later
+                if !resumable {
+                    for path in download.part_paths() {
+                        let _ = remove_file(path);
+                    }
+                }
+                return Err(err.into());
+            }
+        }
+
+        // Download the file, get a decrypting reader instead when extracting while decrypting,
+        // write the file to stdout while decrypting unless it was split
+        let stream_stdout = output_stdout && tmp_stdout.is_none();
+        let hasher = SharedHasher::new();
+        let stdout_writer = || hasher.writer(BufWriter::new(stdout()));
+        #[cfg(feature = "archive")]
+        let result = if split.is_some() {
+            Ok(None)
+        } else if stream_stdout {
+            download
+                .invoke_writer(client, metadata, retries, progress, stdout_writer())
+                .map(|_| None)
+        } else if stream_extract {
+            download
+                .invoke_reader(client, metadata, retries, progress)
+                .map(Some)
         } else {
-            None
+            download
+                .invoke(client, metadata, retries, progress)
+                .map(|_| None)
+        };
+        #[cfg(not(feature = "archive"))]
+        let result = if split.is_some() {
+            Ok(())
+        } else if stream_stdout {
+            download.invoke_writer(client, metadata, retries, progress, stdout_writer())
+        } else {
+            download.invoke(client, metadata, retries, progress)
+        };
+        #[allow(unused_variables)]
+        let reader = match result {
+            Ok(reader) => reader,
+            Err(err) => {
+                // Don't keep a partial file for temporary targets, it can't be resumed later
+                if !resumable {
+                    let _ = remove_file(download.partial_path());
+                }
+                return Err(err.into());
+            }
+        };
+
+        // Verify the checksum of the downloaded file, remove the file if it doesn't match
+        // The checksum of a split file is always verified when joining its parts
+        let sha256 = match verify {
+            Some(expected) => {
+                let sha256 = if stream_stdout {
+                    hasher.checksum()
+                } else {
+                    sha256_file(&target).map_err(Error::Checksum)?
+                };
+                if sha256 != expected {
+                    if !stream_stdout {
+                        let _ = remove_file(&target);
+                    }
+                    return Err(Error::ChecksumMismatch(sha256));
+                }
+                Some(sha256)
+            }
+            None => split.map(|manifest| manifest.sha256),
         };
-        ApiDownload::new(api_version, &file, target, password, false, Some(metadata))
-            .invoke(&transfer_client, progress)?;
 
         // Extract the downloaded file if working with an archive
         #[cfg(feature = "archive")]
-        {
-            if extract {
+        let summary = if extract {
+            if report {
                 eprintln!("Extracting...");
+            }
 
-                // Extract the downloaded file
-                Archive::new(tmp_archive.unwrap().into_file())
-                    .extract(output_path)
-                    .map_err(ExtractError::Extract)?;
+            // Extract the downloaded file, ask before overwriting existing files
+            let summary = match reader {
+                Some(reader) => {
+                    // Extract while decrypting, remove the encrypted file afterwards
+                    let summary = extract_stream(reader, Some(&name), &output_path, &mut overwrite);
+                    if let Err(err) = remove_file(download.partial_path()) {
+                        print_error(
+                            err.context("failed to clean up downloaded archive file, ignoring")
+                                .compat(),
+                        );
+                    }
+                    summary
+                }
+                None => Archive::new(tmp_archive.unwrap().into_file(), Some(&name))
+                    .and_then(|archive| archive.extract(&output_path, &mut overwrite)),
+            }
+            .map_err(ExtractError::Extract)?;
+            Some(summary)
+        } else {
+            None
+        };
+
+        // Write the downloaded split file to stdout, remove the temporary file
+        if let Some(tmp_stdout) = tmp_stdout {
+            let mut reader = File::open(tmp_stdout.path()).map_err(Error::Stdout)?;
+            io::copy(&mut reader, &mut stdout().lock()).map_err(Error::Stdout)?;
+            if let Err(err) = tmp_stdout.close() {
+                print_error(
+                    err.context("failed to clean up temporary download file, ignoring")
+                        .compat(),
+                );
             }
         }
 
-        // Add the file to the history
-        #[cfg(feature = "history")]
-        history_tool::add(&matcher_main, file, true);
+        Ok(Done {
+            file,
+            name,
+            size,
+            sha256,
+            #[cfg(feature = "history")]
+            mime,
+            output_path,
+            output_stdout,
+            #[cfg(feature = "archive")]
+            summary,
+        })
+    }
 
-        // TODO: open the file, or it's location
-        // TODO: copy the file location
+    /// Finish a completed download.
+    ///
+    /// The extraction summary is reported, the file is added to the history, and a machine
+    /// readable record is printed if selected. The record header is only printed if `header` is
+    /// set.
+    fn finish(matcher_main: &MainMatcher, done: Done, header: bool) {
+        // Report skipped archive entries, and print a summary
+        #[cfg(feature = "archive")]
+        {
+            if let Some(summary) = &done.summary {
+                for (path, reason) in &summary.skipped {
+                    print_warning(format!("skipped '{}': {}", path.display(), reason));
+                }
+                if !matcher_main.quiet() {
+                    eprintln!(
+                        "Extracted {} entries from '{}', skipped {}",
+                        summary.extracted,
+                        done.name,
+                        summary.skipped.len(),
+                    );
+                }
+            }
+        }
 
-        Ok(())
+        // Add the file to the history, along with its metadata
+        #[cfg(feature = "history")]
+        {
+            let mut history_file = HistoryFile::new(done.file.clone());
+            history_file.set_name(Some(done.name.clone()));
+            history_file.set_size(Some(done.size));
+            history_file.set_mime(Some(done.mime));
+            if !done.output_stdout {
+                history_file.set_path(done.output_path.canonicalize().ok());
+            }
+            history_file.set_direction(Some(Direction::Download));
+            history_file.set_sha256(done.sha256.clone());
+            history_tool::add(matcher_main, history_file, true);
+        }
+
+        // Print a machine readable record if selected, not when the file was written to stdout
+        let format = matcher_main.output_format();
+        if format.is_machine() && !done.output_stdout {
+            print_record_stream(
+                format,
+                vec![
+                    ("id", json!(done.file.id())),
+                    ("name", json!(done.name)),
+                    ("size", json!(done.size)),
+                    ("sha256", json!(done.sha256)),
+                    ("path", json!(done.output_path.to_str())),
+                ],
+                header,
+            );
+        }
     }
 
     /// This methods prepares a full file path to use for the file to
@@ -229,15 +735,9 @@ impl<'a> Download<'a> {
         }
 
         // Ask to overwrite
-        if file && target.exists() && !main_matcher.force() {
-            eprintln!(
-                "The path '{}' already exists",
-                target.to_str().unwrap_or("?"),
-            );
-            if !prompt_yes("Overwrite?", None, main_matcher) {
-                println!("Download cancelled");
-                quit();
-            }
+        if file && target.exists() && !Self::prompt_overwrite(&target, main_matcher) {
+            eprintln!("Download cancelled");
+            quit();
         }
 
         {
@@ -260,7 +760,7 @@ impl<'a> Download<'a> {
                         dir.to_str().unwrap_or("?"),
                     );
                     if !prompt_yes("Create it?", Some(true), main_matcher) {
-                        println!("Download cancelled");
+                        eprintln!("Download cancelled");
                         quit();
                     }
                 }
@@ -278,6 +778,17 @@ impl<'a> Download<'a> {
         target
     }
 
+    /// Ask whether to overwrite the existing file at the given path.
+    ///
+    /// The file is always overwritten when forced.
+    fn prompt_overwrite(path: &path::Path, main_matcher: &MainMatcher) -> bool {
+        if main_matcher.force() {
+            return true;
+        }
+        eprintln!("The path '{}' already exists", path.to_str().unwrap_or("?"));
+        prompt_yes("Overwrite?", None, main_matcher)
+    }
+
     /// This methods prepares a full file path to use for the file to
     /// download, based on the current directory, the original file name,
     /// and the user input.
@@ -349,6 +860,90 @@ impl<'a> Download<'a> {
     }
 }
 
+/// A prepared download of a single file.
+struct Job {
+    /// The remote file to download.
+    file: RemoteFile,
+
+    /// The server API version to use.
+    api_version: Version,
+
+    /// The fetched file metadata.
+    metadata: MetadataResponse,
+
+    /// The manifest of the original file, if this file holds one for a file split into parts.
+    split: Option<Manifest>,
+
+    /// The password to decrypt the file with, if protected.
+    password: Option<String>,
+
+    /// The target file to download to.
+    target: PathBuf,
+
+    /// The output file, or the directory to extract to.
+    output_path: PathBuf,
+
+    /// The SHA-256 checksum to verify the downloaded file against.
+    verify: Option<String>,
+
+    /// Whether to write the file to stdout.
+    output_stdout: bool,
+
+    /// A temporary file to download to, only used when writing a split file to stdout.
+    tmp_stdout: Option<NamedTempFile>,
+
+    /// Whether to extract the downloaded archive.
+    #[cfg(feature = "archive")]
+    extract: bool,
+
+    /// Whether to extract the archive while it is decrypted.
+    #[cfg(feature = "archive")]
+    stream_extract: bool,
+
+    /// A temporary file to download an archive to, only used when extracting it after download.
+    #[cfg(feature = "archive")]
+    tmp_archive: Option<NamedTempFile>,
+}
+
+impl Job {
+    /// Get the name of the file, or of the original file if it was split into parts.
+    fn name(&self) -> &str {
+        match &self.split {
+            Some(manifest) => &manifest.name,
+            None => self.metadata.metadata().name(),
+        }
+    }
+}
+
+/// A completed download of a single file.
+struct Done {
+    /// The downloaded remote file.
+    file: RemoteFile,
+
+    /// The file name.
+    name: String,
+
+    /// The file size in bytes.
+    size: u64,
+
+    /// The SHA-256 checksum of the file, if verified.
+    sha256: Option<String>,
+
+    /// The file MIME type.
+    #[cfg(feature = "history")]
+    mime: String,
+
+    /// The output file, or the directory the file was extracted to.
+    output_path: PathBuf,
+
+    /// Whether the file was written to stdout.
+    output_stdout: bool,
+
+    /// A summary of the extracted archive, if extracted.
+    #[cfg(feature = "archive")]
+    summary: Option<Summary>,
+}
+
 #[derive(Debug, Fail)]
 pub enum Error {
     /// Selecting the API version to use failed.
@@ -373,14 +968,41 @@ pub enum Error {
     #[fail(display = "")]
     Download(#[cause] DownloadError),
 
+    /// An error occurred while downloading or joining the parts of a split file.
+    #[fail(display = "failed to download split file")]
+    Split(#[cause] SplitError),
+
     /// An error occurred while extracting the file.
     #[cfg(feature = "archive")]
     #[fail(display = "failed the extraction procedure")]
     Extract(#[cause] ExtractError),
 
+    /// An error occurred while computing the checksum of the downloaded file.
+    #[fail(display = "failed to compute checksum of the downloaded file")]
+    Checksum(#[cause] IoError),
+
+    /// The downloaded file doesn't match the expected checksum, it was removed.
+    #[fail(
+        display = "the downloaded file doesn't match the expected checksum, got '{}'",
+        _0
+    )]
+    ChecksumMismatch(String),
+
+    /// An error occurred while writing the downloaded file to stdout.
+    #[fail(display = "failed to write the downloaded file to stdout")]
+    Stdout(#[cause] IoError),
+
     /// The given Send file has expired, or did never exist in the first place.
     #[fail(display = "the file has expired or did never exist")]
     Expired,
+
+    /// Another file in the same command is downloaded to the same path.
+    #[fail(display = "another file is downloaded to '{}'", _0)]
+    DuplicateTarget(String),
+
+    /// Some files failed to download.
+    #[fail(display = "failed to download {} of {} files", _0, _1)]
+    Batch(usize, usize),
 }
 
 impl From<VersionError> for Error {
@@ -413,6 +1035,12 @@ impl From<DownloadError> for Error {
     }
 }
 
+impl From<SplitError> for Error {
+    fn from(err: SplitError) -> Error {
+        Error::Split(err)
+    }
+}
+
 #[cfg(feature = "archive")]
 impl From<ExtractError> for Error {
     fn from(err: ExtractError) -> Error {
@@ -1,13 +1,16 @@
 use clap::ArgMatches;
-use ffsend_api::action::exists::{Error as ExistsError, Exists as ApiExists};
+use ffsend_api::action::exists::Error as ExistsError;
 use ffsend_api::file::remote_file::{FileParseError, RemoteFile};
+use serde_json::json;
 
+use crate::api::Exists as ApiExists;
 use crate::client::create_config;
 use crate::cmd::matcher::main::MainMatcher;
 use crate::cmd::matcher::{exists::ExistsMatcher, Matcher};
 use crate::error::ActionError;
 #[cfg(feature = "history")]
 use crate::history_tool;
+use crate::output::print_record;
 
 /// A file exists action.
 pub struct Exists<'a> {
@@ -31,7 +34,7 @@ impl<'a> Exists<'a> {
         let url = matcher_exists.url();
 
         // Create a reqwest client
-        let client_config = create_config(&matcher_main);
+        let client_config = create_config(&matcher_main, Some(&url));
         let client = client_config.client(false);
 
         // Parse the remote file based on the share URL
@@ -42,9 +45,28 @@ impl<'a> Exists<'a> {
         let exists = exists_response.exists();
 
         // Print the results
-        println!("Exists: {:?}", exists);
-        if exists {
-            println!("Password: {:?}", exists_response.requires_password());
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            print_record(
+                format,
+                vec![
+                    ("id", json!(file.id())),
+                    ("exists", json!(exists)),
+                    (
+                        "password",
+                        json!(if exists {
+                            Some(exists_response.requires_password())
+                        } else {
+                            None
+                        }),
+                    ),
+                ],
+            );
+        } else {
+            println!("Exists: {:?}", exists);
+            if exists {
+                println!("Password: {:?}", exists_response.requires_password());
+            }
         }
 
         // Add or remove the file from the history
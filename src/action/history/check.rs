@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+use std::sync::{mpsc::channel, Arc, Mutex};
+use std::thread;
+
+use chrono::Duration;
+use failure::Fail;
+use ffsend_api::action::exists::Error as ExistsError;
+use ffsend_api::action::info::{Error as InfoError, InfoResponse};
+use ffsend_api::file::remote_file::RemoteFile;
+
+use crate::api::{Exists as ApiExists, Info as ApiInfo};
+use crate::client::Client;
+
+/// The status of a remote file, as reported by its server.
+pub enum Status {
+    /// The file exists, along with its live info if the owner token is known.
+    Exists(Option<InfoResponse>),
+
+    /// The server reports the file doesn't exist anymore.
+    Gone,
+
+    /// Checking the file failed.
+    Failed(Error),
+}
+
+impl Status {
+    /// Get the name of this status.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Exists(_) => "ok",
+            Status::Gone => "gone",
+            Status::Failed(_) => "failed",
+        }
+    }
+
+    /// Get the live info of the file, if known.
+    pub fn info(&self) -> Option<&InfoResponse> {
+        match self {
+            Status::Exists(info) => info.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Get the remaining time to live of the file, if known.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.info()
+            .map(|info| Duration::milliseconds(info.ttl_millis() as i64))
+    }
+}
+
+/// Check the status of each of the given files on its server.
+///
+/// Up to `concurrency` files are checked at the same time. The statuses are returned in the same
+/// order as the given files.
+pub fn check_files(client: Arc<Client>, files: Vec<RemoteFile>, concurrency: usize) -> Vec<Status> {
+    let count = files.len();
+    let queue = Arc::new(Mutex::new(
+        files.into_iter().enumerate().collect::<VecDeque<_>>(),
+    ));
+    let (sender, receiver) = channel();
+
+    // Spawn the workers, each checks files from the queue until it is empty
+    for _ in 0..concurrency.max(1).min(count) {
+        let queue = queue.clone();
+        let client = client.clone();
+        let sender = sender.clone();
+        thread::spawn(move || loop {
+            let next = queue.lock().unwrap().pop_front();
+            match next {
+                Some((i, file)) => {
+                    if sender.send((i, check_file(&client, &file))).is_err() {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        });
+    }
+    drop(sender);
+
+    // Collect the statuses in order
+    let mut statuses: Vec<Option<Status>> = (0..count).map(|_| None).collect();
+    for (i, status) in receiver {
+        statuses[i] = Some(status);
+    }
+    statuses
+        .into_iter()
+        .map(|status| status.unwrap_or(Status::Failed(Error::Interrupted)))
+        .collect()
+}
+
+/// Check the status of the given file on its server.
+///
+/// The live file info is fetched as well if the owner token is known.
+fn check_file(client: &Client, file: &RemoteFile) -> Status {
+    match ApiExists::new(file).invoke(client) {
+        Ok(exists) if !exists.exists() => return Status::Gone,
+        Ok(_) => {}
+        Err(err) => return Status::Failed(Error::Exists(err)),
+    }
+
+    if file.owner_token().is_none() {
+        return Status::Exists(None);
+    }
+    match ApiInfo::new(file, None).invoke(client) {
+        Ok(info) => Status::Exists(Some(info)),
+        Err(err) => Status::Failed(Error::Info(err)),
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// An error occurred while checking if the file exists.
+    #[fail(display = "failed to check whether the file exists")]
+    Exists(#[cause] ExistsError),
+
+    /// An error occurred while fetching the file information.
+    #[fail(display = "failed to fetch file info")]
+    Info(#[cause] InfoError),
+
+    /// The check was interrupted before it completed.
+    #[fail(display = "file check was interrupted")]
+    Interrupted,
+}
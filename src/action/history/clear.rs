@@ -0,0 +1,65 @@
+use clap::ArgMatches;
+use serde_json::json;
+
+use crate::cmd::matcher::{main::MainMatcher, Matcher};
+use crate::error::ActionError;
+use crate::history::History as HistoryManager;
+use crate::output::print_record;
+use crate::util::{print_success, prompt_yes, quit};
+
+/// A history clear action.
+pub struct Clear<'a> {
+    cmd_matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Clear<'a> {
+    /// Construct a new history clear action.
+    pub fn new(cmd_matches: &'a ArgMatches<'a>) -> Self {
+        Self { cmd_matches }
+    }
+
+    /// Invoke the history clear action.
+    // TODO: create a trait for this method
+    pub fn invoke(&self) -> Result<(), ActionError> {
+        // Create the command matchers
+        let matcher_main = MainMatcher::with(self.cmd_matches).unwrap();
+
+        // Count the files in the history, don't hold the lock while prompting
+        let load = || {
+            HistoryManager::load_or_new(matcher_main.history(), &|| {
+                matcher_main.history_passphrase(false)
+            })
+        };
+        let count = load()?.files().len();
+
+        // Confirm to clear the history when not forced
+        if count > 0
+            && !matcher_main.force()
+            && !prompt_yes(
+                &format!("Remove all {} file(s) from history?", count),
+                None,
+                &matcher_main,
+            )
+        {
+            eprintln!("Clearing history cancelled");
+            quit();
+        }
+
+        // Load the history again, clear it, and save
+        let mut history = load()?;
+        let count = history.clear();
+        history.save()?;
+
+        // Print a machine readable record if selected, or a success message
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            print_record(format, vec![("removed", json!(count))]);
+            return Ok(());
+        }
+        if !matcher_main.quiet() {
+            print_success(&format!("Removed {} file(s) from history", count));
+        }
+
+        Ok(())
+    }
+}
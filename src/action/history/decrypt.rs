@@ -0,0 +1,44 @@
+use clap::ArgMatches;
+
+use super::Error;
+use crate::cmd::matcher::{main::MainMatcher, Matcher};
+use crate::error::ActionError;
+use crate::history::History as HistoryManager;
+use crate::util::print_success;
+
+/// A history decrypt action.
+pub struct Decrypt<'a> {
+    cmd_matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Decrypt<'a> {
+    /// Construct a new history decrypt action.
+    pub fn new(cmd_matches: &'a ArgMatches<'a>) -> Self {
+        Self { cmd_matches }
+    }
+
+    /// Invoke the history decrypt action.
+    // TODO: create a trait for this method
+    pub fn invoke(&self) -> Result<(), ActionError> {
+        // Create the command matchers
+        let matcher_main = MainMatcher::with(self.cmd_matches).unwrap();
+
+        // Load the history, it must be encrypted
+        let mut history = HistoryManager::load_or_new(matcher_main.history(), &|| {
+            matcher_main.history_passphrase(false)
+        })?;
+        if !history.is_encrypted() {
+            return Err(Error::NotEncrypted.into());
+        }
+
+        // Store the history in plain text
+        history.decrypt();
+        history.save()?;
+
+        if !matcher_main.quiet() {
+            print_success("History decrypted");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use clap::ArgMatches;
+
+use super::Error;
+use crate::cmd::matcher::{main::MainMatcher, Matcher};
+use crate::error::ActionError;
+use crate::history::History as HistoryManager;
+use crate::util::print_success;
+
+/// A history encrypt action.
+pub struct Encrypt<'a> {
+    cmd_matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Encrypt<'a> {
+    /// Construct a new history encrypt action.
+    pub fn new(cmd_matches: &'a ArgMatches<'a>) -> Self {
+        Self { cmd_matches }
+    }
+
+    /// Invoke the history encrypt action.
+    // TODO: create a trait for this method
+    pub fn invoke(&self) -> Result<(), ActionError> {
+        // Create the command matchers
+        let matcher_main = MainMatcher::with(self.cmd_matches).unwrap();
+
+        // The history must not be encrypted yet, obtain a new passphrase before locking it
+        let load = || {
+            HistoryManager::load_or_new(matcher_main.history(), &|| {
+                matcher_main.history_passphrase(false)
+            })
+        };
+        if load()?.is_encrypted() {
+            return Err(Error::AlreadyEncrypted.into());
+        }
+        let passphrase = matcher_main.history_passphrase(true);
+
+        // Load the history again, encrypt with the new passphrase, and save
+        let mut history = load()?;
+        if history.is_encrypted() {
+            return Err(Error::AlreadyEncrypted.into());
+        }
+        history.encrypt(&passphrase).map_err(Error::Encrypt)?;
+        history.save()?;
+
+        if !matcher_main.quiet() {
+            print_success("History encrypted");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use clap::ArgMatches;
+use serde_json::json;
+
+use crate::cmd::matcher::{main::MainMatcher, Matcher};
+use crate::error::ActionError;
+use crate::history::History as HistoryManager;
+use crate::output::print_record;
+use crate::util::print_success;
+
+/// A history garbage collect action.
+pub struct Gc<'a> {
+    cmd_matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Gc<'a> {
+    /// Construct a new history garbage collect action.
+    pub fn new(cmd_matches: &'a ArgMatches<'a>) -> Self {
+        Self { cmd_matches }
+    }
+
+    /// Invoke the history garbage collect action.
+    // TODO: create a trait for this method
+    pub fn invoke(&self) -> Result<(), ActionError> {
+        // Create the command matchers
+        let matcher_main = MainMatcher::with(self.cmd_matches).unwrap();
+
+        // Load the history without collecting, so we can report what is removed
+        let path = matcher_main.history();
+        let mut history = if path.is_file() {
+            HistoryManager::load_raw(path, &|| matcher_main.history_passphrase(false))?
+        } else {
+            HistoryManager::new(Some(path))
+        };
+
+        // Remove expired files, and save
+        let count = history.gc();
+        history.save()?;
+
+        // Print a machine readable record if selected, or a success message
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            print_record(format, vec![("removed", json!(count))]);
+            return Ok(());
+        }
+        if !matcher_main.quiet() {
+            print_success(&format!("Removed {} expired file(s) from history", count));
+        }
+
+        Ok(())
+    }
+}
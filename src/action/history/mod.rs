@@ -0,0 +1,483 @@
+pub mod check;
+pub mod clear;
+pub mod decrypt;
+pub mod encrypt;
+pub mod gc;
+pub mod remove;
+
+use std::sync::Arc;
+
+use chrono::Duration;
+use clap::ArgMatches;
+use ffsend_api::file::remote_file::RemoteFile;
+use ffsend_api::url::Url;
+use prettytable::{format::FormatBuilder, Cell, Row, Table};
+use serde_json::json;
+
+use crate::client::create_config;
+use crate::cmd::matcher::{history::HistoryMatcher, main::MainMatcher, Matcher};
+use crate::config::HISTORY_CHECK_CONCURRENCY;
+use crate::error::ActionError;
+use crate::history::{
+    History as HistoryManager, HistoryFile, LoadError as HistoryLoadError,
+    SaveError as HistorySaveError,
+};
+use crate::history_crypt::Error as HistoryCryptError;
+use crate::output::{print_records, Record};
+use crate::util::{format_bytes, format_duration, glob_match, print_error, print_success};
+use check::{check_files, Error as CheckError, Status};
+use clear::Clear;
+use decrypt::Decrypt;
+use encrypt::Encrypt;
+use gc::Gc;
+use remove::Remove;
+
+/// The names of the values reported for each history file in machine readable output.
+const RECORD_COLUMNS: [&str; 14] = [
+    "index",
+    "id",
+    "name",
+    "size",
+    "mime",
+    "direction",
+    "path",
+    "upload_at",
+    "sha256",
+    "url",
+    "expiry",
+    "expire_at",
+    "expiry_uncertain",
+    "owner_token",
+];
+
+/// The names of the values reported for each checked history file in machine readable output.
+const CHECK_RECORD_COLUMNS: [&str; 9] = [
+    "index",
+    "id",
+    "name",
+    "url",
+    "status",
+    "downloads",
+    "download_limit",
+    "expiry",
+    "expiry_uncertain",
+];
+
+/// A history action.
+pub struct History<'a> {
+    cmd_matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> History<'a> {
+    /// Construct a new history action.
+    pub fn new(cmd_matches: &'a ArgMatches<'a>) -> Self {
+        Self { cmd_matches }
+    }
+
+    /// Invoke the history action.
+    // TODO: create a trait for this method
+    pub fn invoke(&self) -> Result<(), ActionError> {
+        // Create the command matchers
+        let matcher_main = MainMatcher::with(self.cmd_matches).unwrap();
+        let matcher_history = HistoryMatcher::with(self.cmd_matches).unwrap();
+
+        // Match the history sub commands
+        if matcher_history.matcher_remove().is_some() {
+            return Remove::new(self.cmd_matches).invoke();
+        }
+        if matcher_history.matcher_clear().is_some() {
+            return Clear::new(self.cmd_matches).invoke();
+        }
+        if matcher_history.matcher_gc().is_some() {
+            return Gc::new(self.cmd_matches).invoke();
+        }
+        if matcher_history.matcher_encrypt().is_some() {
+            return Encrypt::new(self.cmd_matches).invoke();
+        }
+        if matcher_history.matcher_decrypt().is_some() {
+            return Decrypt::new(self.cmd_matches).invoke();
+        }
+
+        // Get the history path, make sure it exists
+        let format = matcher_main.output_format();
+        let history_path = matcher_main.history();
+        if !history_path.is_file() {
+            if format.is_machine() {
+                print_records(format, &RECORD_COLUMNS, Vec::new());
+            } else if !matcher_main.quiet() {
+                eprintln!("No files in history");
+            }
+            return Ok(());
+        }
+
+        // History
+        let history =
+            HistoryManager::load(history_path, &|| matcher_main.history_passphrase(false))?;
+
+        // Do not report any files if there aren't any
+        if history.files().is_empty() {
+            if format.is_machine() {
+                print_records(format, &RECORD_COLUMNS, Vec::new());
+            } else if !matcher_main.quiet() {
+                eprintln!("No files in history");
+            }
+            return Ok(());
+        }
+
+        // Get the list of files along with their index, apply the filters
+        let filter = Filter::from(&matcher_history);
+        let files: Vec<(usize, &HistoryFile)> = history
+            .files_listed()
+            .into_iter()
+            .enumerate()
+            .map(|(i, file)| (i + 1, file))
+            .filter(|(_, file)| filter.matches(file))
+            .collect();
+
+        // Report if no files match the filters
+        let columns: &[&str] = if matcher_history.check() {
+            &CHECK_RECORD_COLUMNS
+        } else {
+            &RECORD_COLUMNS
+        };
+        if files.is_empty() {
+            if format.is_machine() {
+                print_records(format, columns, Vec::new());
+            } else if !matcher_main.quiet() {
+                eprintln!("No files in history matching the filters");
+            }
+            return Ok(());
+        }
+
+        // Check the live status of each file if selected
+        if matcher_history.check() {
+            let files = files
+                .into_iter()
+                .map(|(index, file)| (index, file.clone()))
+                .collect();
+            drop(history);
+            return Self::check(&matcher_main, files);
+        }
+
+        // Print machine readable records if selected
+        if format.is_machine() {
+            let records = files
+                .iter()
+                .map(|(index, file)| -> Record {
+                    let remote = file.remote_file();
+                    vec![
+                        ("index", json!(index)),
+                        ("id", json!(remote.id())),
+                        ("name", json!(file.name())),
+                        ("size", json!(file.size())),
+                        ("mime", json!(file.mime())),
+                        ("direction", json!(file.direction().map(|d| d.name()))),
+                        ("path", json!(file.path().and_then(|p| p.to_str()))),
+                        ("upload_at", json!(file.upload_at().map(|t| t.to_rfc3339()))),
+                        ("sha256", json!(file.sha256())),
+                        ("url", json!(remote.download_url(true).as_str())),
+                        ("expiry", json!(remote.expire_duration().num_seconds())),
+                        ("expire_at", json!(remote.expire_at().to_rfc3339())),
+                        ("expiry_uncertain", json!(remote.expire_uncertain())),
+                        ("owner_token", json!(remote.owner_token())),
+                    ]
+                })
+                .collect();
+            print_records(format, &RECORD_COLUMNS, records);
+            return Ok(());
+        }
+
+        // Log a history table, or just the URLs in quiet mode
+        if !matcher_main.quiet() {
+            // Build the list of column names
+            let mut columns = vec!["#", "NAME", "SIZE", "LINK", "EXPIRY"];
+            if matcher_main.verbose() {
+                columns.push("OWNER TOKEN");
+            }
+
+            // Create a new table
+            let mut table = Table::new();
+            table.set_format(FormatBuilder::new().padding(0, 2).build());
+            table.add_row(Row::new(columns.into_iter().map(Cell::new).collect()));
+
+            // Add an entry for each file
+            for (index, file) in &files {
+                let remote = file.remote_file();
+
+                // Build the expiry time string
+                let mut expiry = format_duration(&remote.expire_duration());
+                if remote.expire_uncertain() {
+                    expiry.insert(0, '~');
+                }
+
+                // Get the owner token
+                let owner_token: String = match remote.owner_token() {
+                    Some(token) => token.clone(),
+                    None => "?".into(),
+                };
+
+                // Define the cell values
+                let mut cells: Vec<String> = vec![
+                    format!("{}", index),
+                    file.name().unwrap_or("?").into(),
+                    file.size().map(format_bytes).unwrap_or_else(|| "?".into()),
+                    remote.download_url(true).into_string(),
+                    expiry,
+                ];
+                if matcher_main.verbose() {
+                    cells.push(owner_token);
+                }
+
+                // Add the row
+                table.add_row(Row::new(cells.into_iter().map(|c| Cell::new(&c)).collect()));
+            }
+
+            // Print the table
+            table.printstd();
+        } else {
+            files
+                .iter()
+                .for_each(|(_, f)| println!("{}", f.remote_file().download_url(true)));
+        }
+
+        Ok(())
+    }
+
+    /// Check the live status of the given history files on their servers, and report it.
+    ///
+    /// Files the server reports as gone are removed from the history, the expiry time of other
+    /// files is updated when known.
+    ///
+    /// The history is not locked while checking, and is loaded again to apply the changes.
+    fn check(
+        matcher_main: &MainMatcher,
+        files: Vec<(usize, HistoryFile)>,
+    ) -> Result<(), ActionError> {
+        let format = matcher_main.output_format();
+        if !format.is_machine() && !matcher_main.quiet() {
+            eprintln!("Checking {} file(s)...", files.len());
+        }
+
+        // Check all files, with a bounded number of concurrent requests
+        let remotes: Vec<RemoteFile> = files
+            .iter()
+            .map(|(_, file)| file.remote_file().clone())
+            .collect();
+        let hosts: Vec<Url> = remotes.iter().map(|file| file.host()).collect();
+        let client = Arc::new(create_config(matcher_main, &hosts).client(false));
+        let statuses = check_files(client, remotes, HISTORY_CHECK_CONCURRENCY);
+
+        // Update the history with the live status, report failures
+        let mut history = HistoryManager::load_or_new(matcher_main.history(), &|| {
+            matcher_main.history_passphrase(false)
+        })?;
+        let mut removed = 0;
+        for ((_, file), status) in files.iter().zip(&statuses) {
+            match status {
+                Status::Exists(_) => {
+                    // Don't add back files removed from the history while checking
+                    if history.get_file(file.remote_file()).is_none() {
+                        continue;
+                    }
+                    if let Some(ttl) = status.ttl() {
+                        let mut remote = file.remote_file().clone();
+                        remote.set_expire_duration(ttl);
+                        history.add(HistoryFile::from(remote), true);
+                    }
+                }
+                Status::Gone => {
+                    if history.remove(file.remote_file()) {
+                        removed += 1;
+                    }
+                }
+                Status::Failed(err) => print_error::<CheckError>(err),
+            }
+        }
+        history.save()?;
+
+        // Print machine readable records if selected
+        if format.is_machine() {
+            let records = files
+                .iter()
+                .zip(&statuses)
+                .map(|((index, file), status)| -> Record {
+                    let remote = file.remote_file();
+                    let info = status.info();
+                    vec![
+                        ("index", json!(index)),
+                        ("id", json!(remote.id())),
+                        ("name", json!(file.name())),
+                        ("url", json!(remote.download_url(true).as_str())),
+                        ("status", json!(status.name())),
+                        ("downloads", json!(info.map(|i| i.download_count()))),
+                        ("download_limit", json!(info.map(|i| i.download_limit()))),
+                        (
+                            "expiry",
+                            json!(match status {
+                                Status::Gone => None,
+                                _ => Some(
+                                    status
+                                        .ttl()
+                                        .unwrap_or_else(|| remote.expire_duration())
+                                        .num_seconds()
+                                ),
+                            }),
+                        ),
+                        ("expiry_uncertain", json!(status.ttl().is_none())),
+                    ]
+                })
+                .collect();
+            print_records(format, &CHECK_RECORD_COLUMNS, records);
+            return Ok(());
+        }
+
+        // Only print the URLs of existing files in quiet mode
+        if matcher_main.quiet() {
+            files
+                .iter()
+                .zip(&statuses)
+                .filter(|(_, status)| match status {
+                    Status::Gone => false,
+                    _ => true,
+                })
+                .for_each(|((_, f), _)| println!("{}", f.remote_file().download_url(true)));
+            return Ok(());
+        }
+
+        // Create a new table, add an entry for each file
+        let mut table = Table::new();
+        table.set_format(FormatBuilder::new().padding(0, 2).build());
+        table.add_row(Row::new(
+            vec!["#", "NAME", "STATUS", "DOWNLOADS", "EXPIRY", "LINK"]
+                .into_iter()
+                .map(Cell::new)
+                .collect(),
+        ));
+        for ((index, file), status) in files.iter().zip(&statuses) {
+            let remote = file.remote_file();
+
+            // Build the download count and expiry time strings
+            let downloads = match status.info() {
+                Some(info) => format!("{} of {}", info.download_count(), info.download_limit()),
+                None => "?".into(),
+            };
+            let expiry = match (status, status.ttl()) {
+                (Status::Gone, _) => "-".into(),
+                (_, Some(ttl)) => format_duration(&ttl),
+                (_, None) => format!("~{}", format_duration(&remote.expire_duration())),
+            };
+
+            let cells: Vec<String> = vec![
+                format!("{}", index),
+                file.name().unwrap_or("?").into(),
+                status.name().into(),
+                downloads,
+                expiry,
+                remote.download_url(true).into_string(),
+            ];
+            table.add_row(Row::new(cells.into_iter().map(|c| Cell::new(&c)).collect()));
+        }
+        table.printstd();
+
+        if removed > 0 {
+            print_success(&format!(
+                "Removed {} file(s) that no longer exist from history",
+                removed
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// A filter for history files.
+struct Filter<'a> {
+    /// Only match files on this host.
+    host: Option<String>,
+
+    /// Only match files expiring within this duration.
+    expired_within: Option<Duration>,
+
+    /// Only match files with a name matching this glob pattern.
+    name: Option<&'a str>,
+}
+
+impl<'a> Filter<'a> {
+    /// Check whether the given file matches this filter.
+    fn matches(&self, file: &HistoryFile) -> bool {
+        let remote = file.remote_file();
+        if let Some(host) = &self.host {
+            if remote.host().host_str() != Some(host) {
+                return false;
+            }
+        }
+        if let Some(within) = self.expired_within {
+            if remote.expire_duration() > within {
+                return false;
+            }
+        }
+        if let Some(pattern) = self.name {
+            if !file
+                .name()
+                .map(|name| glob_match(pattern, name))
+                .unwrap_or(false)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl<'a> From<&'a HistoryMatcher<'a>> for Filter<'a> {
+    fn from(matcher: &'a HistoryMatcher<'a>) -> Self {
+        Self {
+            host: matcher
+                .filter_host()
+                .and_then(|host| host.host_str().map(|h| h.to_owned())),
+            expired_within: matcher
+                .filter_expired_within()
+                .map(|secs| Duration::seconds(secs as i64)),
+            name: matcher.filter_name(),
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// Failed to load the history.
+    #[fail(display = "Failed to load file history")]
+    Load(#[cause] HistoryLoadError),
+
+    /// Failed to save the history.
+    #[fail(display = "failed to save file history")]
+    Save(#[cause] HistorySaveError),
+
+    /// Failed to encrypt the history.
+    #[fail(display = "failed to encrypt file history")]
+    Encrypt(#[cause] HistoryCryptError),
+
+    /// The history is already encrypted.
+    #[fail(display = "file history is already encrypted")]
+    AlreadyEncrypted,
+
+    /// The history is not encrypted.
+    #[fail(display = "file history is not encrypted")]
+    NotEncrypted,
+
+    /// No file in the history matched the given reference.
+    #[fail(display = "no file in history matching '{}'", _0)]
+    NotFound(String),
+}
+
+impl From<HistoryLoadError> for ActionError {
+    fn from(err: HistoryLoadError) -> ActionError {
+        ActionError::History(Error::Load(err))
+    }
+}
+
+impl From<HistorySaveError> for ActionError {
+    fn from(err: HistorySaveError) -> ActionError {
+        ActionError::History(Error::Save(err))
+    }
+}
@@ -0,0 +1,77 @@
+use clap::ArgMatches;
+use ffsend_api::file::remote_file::RemoteFile;
+use serde_json::json;
+
+use super::Error;
+use crate::cmd::matcher::{history::remove::RemoveMatcher, main::MainMatcher, Matcher};
+use crate::error::ActionError;
+use crate::history::History as HistoryManager;
+use crate::output::{print_records, Record};
+use crate::util::print_success;
+
+/// The names of the values reported for each removed file in machine readable output.
+const RECORD_COLUMNS: [&str; 2] = ["id", "url"];
+
+/// A history remove action.
+pub struct Remove<'a> {
+    cmd_matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Remove<'a> {
+    /// Construct a new history remove action.
+    pub fn new(cmd_matches: &'a ArgMatches<'a>) -> Self {
+        Self { cmd_matches }
+    }
+
+    /// Invoke the history remove action.
+    // TODO: create a trait for this method
+    pub fn invoke(&self) -> Result<(), ActionError> {
+        // Create the command matchers
+        let matcher_main = MainMatcher::with(self.cmd_matches).unwrap();
+        let matcher_remove = RemoveMatcher::with(self.cmd_matches).unwrap();
+
+        // Load the history
+        let mut history = HistoryManager::load_or_new(matcher_main.history(), &|| {
+            matcher_main.history_passphrase(false)
+        })?;
+
+        // Resolve all references first, as removing files changes the indices
+        let files: Vec<RemoteFile> = matcher_remove
+            .files()
+            .into_iter()
+            .map(|(raw, reference)| {
+                history
+                    .resolve(&reference)
+                    .map(|file| file.remote_file().clone())
+                    .ok_or_else(|| Error::NotFound(raw.into()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        // Remove the files, and save
+        for file in &files {
+            history.remove(file);
+        }
+        history.save()?;
+
+        // Print a machine readable record if selected, or a success message
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            let records = files
+                .iter()
+                .map(|file| -> Record {
+                    vec![
+                        ("id", json!(file.id())),
+                        ("url", json!(file.download_url(true).as_str())),
+                    ]
+                })
+                .collect();
+            print_records(format, &RECORD_COLUMNS, records);
+            return Ok(());
+        }
+        if !matcher_main.quiet() {
+            print_success(&format!("Removed {} file(s) from history", files.len()));
+        }
+
+        Ok(())
+    }
+}
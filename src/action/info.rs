@@ -1,16 +1,18 @@
 use chrono::Duration;
 use clap::ArgMatches;
 use failure::Fail;
-use ffsend_api::action::exists::{Error as ExistsError, Exists as ApiExists};
-use ffsend_api::action::info::{Error as InfoError, Info as ApiInfo};
-use ffsend_api::action::metadata::Metadata as ApiMetadata;
+use ffsend_api::action::exists::Error as ExistsError;
+use ffsend_api::action::info::Error as InfoError;
 use ffsend_api::file::remote_file::{FileParseError, RemoteFile};
 use prettytable::{format::FormatBuilder, Cell, Row, Table};
+use serde_json::json;
 
+use crate::api::{Exists as ApiExists, Info as ApiInfo, Metadata as ApiMetadata};
 use crate::client::create_config;
 use crate::cmd::matcher::{info::InfoMatcher, main::MainMatcher, Matcher};
 #[cfg(feature = "history")]
 use crate::history_tool;
+use crate::output::print_record;
 use crate::util::{
     ensure_owner_token, ensure_password, format_bytes, format_duration, print_error,
 };
@@ -37,7 +39,7 @@ impl<'a> Info<'a> {
         let url = matcher_info.url();
 
         // Create a reqwest client
-        let client_config = create_config(&matcher_main);
+        let client_config = create_config(&matcher_main, Some(&url));
         let client = client_config.client(false);
 
         // Parse the remote file based on the share URL, derive the owner token from history
@@ -95,6 +97,47 @@ impl<'a> Info<'a> {
         #[cfg(feature = "history")]
         history_tool::add(&matcher_main, file.clone(), true);
 
+        // Get the checksum recorded in the history
+        #[cfg(feature = "history")]
+        let sha256 = history_tool::get(&matcher_main, &file)
+            .and_then(|file| file.sha256().map(|sha256| sha256.to_owned()));
+        #[cfg(not(feature = "history"))]
+        let sha256: Option<String> = None;
+
+        // Print a machine readable record if selected
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            print_record(
+                format,
+                vec![
+                    ("id", json!(file.id())),
+                    (
+                        "name",
+                        json!(metadata.as_ref().map(|m| m.metadata().name())),
+                    ),
+                    ("size", json!(metadata.as_ref().map(|m| m.size()))),
+                    (
+                        "mime",
+                        json!(metadata.as_ref().map(|m| m.metadata().mime())),
+                    ),
+                    ("sha256", json!(sha256)),
+                    (
+                        "downloads",
+                        json!(info.as_ref().map(|i| i.download_count())),
+                    ),
+                    (
+                        "download_limit",
+                        json!(info.as_ref().map(|i| i.download_limit())),
+                    ),
+                    (
+                        "expiry",
+                        json!(info.as_ref().map(|i| i.ttl_millis() / 1000)),
+                    ),
+                ],
+            );
+            return Ok(());
+        }
+
         // Create a new table for the information
         let mut table = Table::new();
         table.set_format(FormatBuilder::new().padding(0, 2).build());
@@ -128,6 +171,11 @@ impl<'a> Info<'a> {
             ]));
         }
 
+        // Show the recorded checksum
+        if let Some(sha256) = &sha256 {
+            table.add_row(Row::new(vec![Cell::new("SHA-256:"), Cell::new(sha256)]));
+        }
+
         // Show file info if available
         if let Some(info) = &info {
             // The download count
@@ -10,13 +10,18 @@ pub mod params;
 pub mod password;
 pub mod upload;
 pub mod version;
+pub mod watch;
 
-use ffsend_api::action::version::{Error as VersionError, Version as ApiVersion};
+use std::time::Instant;
+
+use ffsend_api::action::version::Error as VersionError;
 use ffsend_api::api::DesiredVersion;
-use ffsend_api::client::Client;
 use ffsend_api::url::Url;
 
+use crate::api::Version as ApiVersion;
+use crate::client::Client;
 use crate::config::API_VERSION_ASSUME;
+use crate::logger::redact_url;
 use crate::util::print_warning;
 
 /// Based on the given desired API version, select a version we can use.
@@ -37,9 +42,19 @@ fn select_api_version(
     // TODO: only lookup if `DesiredVersion::Assume` after first operation attempt failed
 
     // Look up the version
-    match ApiVersion::new(host).invoke(&client) {
+    debug!("probing server API version of {}", redact_url(&host));
+    let start = Instant::now();
+    let result = ApiVersion::new(host).invoke(&client);
+    debug!(
+        "server API version probe finished in {:.0?}",
+        start.elapsed()
+    );
+    match result {
         // Use the probed version
-        Ok(v) => *desired = DesiredVersion::Use(v),
+        Ok(v) => {
+            info!("using server API version v{}", v);
+            *desired = DesiredVersion::Use(v);
+        }
 
         // If unknown, just assume the default version
         Err(VersionError::Unknown) => {
@@ -51,7 +66,10 @@ fn select_api_version(
         }
 
         // Propegate other errors
-        Err(e) => return Err(e),
+        Err(e) => {
+            debug!("server API version probe failed: {}", e);
+            return Err(e);
+        }
     }
 
     Ok(())
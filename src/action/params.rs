@@ -1,13 +1,19 @@
+#[cfg(feature = "history")]
+use chrono::{Duration, Utc};
 use clap::ArgMatches;
-use ffsend_api::action::params::{Error as ParamsError, Params as ApiParams, ParamsDataBuilder};
+use ffsend_api::action::params::{Error as ParamsError, ParamsDataBuilder};
 use ffsend_api::file::remote_file::RemoteFile;
+use serde_json::json;
 
+use super::select_api_version;
+use crate::api::Params as ApiParams;
 use crate::client::create_config;
 use crate::cmd::matcher::{main::MainMatcher, params::ParamsMatcher, Matcher};
 use crate::error::ActionError;
 #[cfg(feature = "history")]
 use crate::history_tool;
-use crate::util::{ensure_owner_token, print_success};
+use crate::output::print_record;
+use crate::util::{ensure_expiry_time, ensure_owner_token, print_success};
 
 /// A file parameters action.
 pub struct Params<'a> {
@@ -31,7 +37,7 @@ impl<'a> Params<'a> {
         let url = matcher_params.url();
 
         // Create a reqwest client
-        let client_config = create_config(&matcher_main);
+        let client_config = create_config(&matcher_main, Some(&url));
         let client = client_config.client(false);
 
         // Parse the remote file based on the share URL, derive the owner token from history
@@ -42,9 +48,22 @@ impl<'a> Params<'a> {
         // Ensure the owner token is set
         ensure_owner_token(file.owner_token_mut(), &matcher_main, false);
 
+        // Get the expiry time, ensure the server supports it
+        let expiry_time = matcher_params.expiry_time();
+        if let Some(expiry_time) = expiry_time {
+            // Determine the API version to use
+            let mut desired_version = matcher_main.api();
+            select_api_version(&client, file.host(), &mut desired_version)?;
+            let api_version = desired_version.version().unwrap();
+
+            // TODO: set false parameter to authentication state
+            ensure_expiry_time(expiry_time, api_version, false, &matcher_main);
+        }
+
         // Build the parameters data object
         let data = ParamsDataBuilder::default()
             .download_limit(matcher_params.download_limit())
+            .expiry_time(expiry_time)
             .build()
             .unwrap();
 
@@ -59,11 +78,42 @@ impl<'a> Params<'a> {
         }
         result?;
 
+        // Set the new expiry time on the file, to track it precisely in the history
+        #[cfg(feature = "history")]
+        {
+            if let Some(expiry_time) = expiry_time {
+                // Remote files don't expose their upload time, take it from the history
+                let upload_at =
+                    history_tool::get(&matcher_main, &file).and_then(|f| f.upload_at().cloned());
+                file = RemoteFile::new(
+                    file.id().into(),
+                    upload_at,
+                    Some(Utc::now() + Duration::seconds(expiry_time as i64)),
+                    file.host(),
+                    file.url().clone(),
+                    file.secret_raw().clone(),
+                    file.owner_token().cloned(),
+                );
+            }
+        }
+
         // Update the history
         #[cfg(feature = "history")]
-        history_tool::add(&matcher_main, file, true);
+        history_tool::add(&matcher_main, file.clone(), true);
 
-        // Print a success message
+        // Print a machine readable record if selected, or a success message
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            print_record(
+                format,
+                vec![
+                    ("id", json!(file.id())),
+                    ("download_limit", json!(matcher_params.download_limit())),
+                    ("expiry", json!(expiry_time)),
+                ],
+            );
+            return Ok(());
+        }
         print_success("Parameters set");
 
         Ok(())
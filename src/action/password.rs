@@ -1,13 +1,16 @@
 use clap::ArgMatches;
-use ffsend_api::action::password::{Error as PasswordError, Password as ApiPassword};
+use ffsend_api::action::password::Error as PasswordError;
 use ffsend_api::file::remote_file::RemoteFile;
 use prettytable::{format::FormatBuilder, Cell, Row, Table};
+use serde_json::json;
 
+use crate::api::Password as ApiPassword;
 use crate::client::create_config;
 use crate::cmd::matcher::{main::MainMatcher, password::PasswordMatcher, Matcher};
 use crate::error::ActionError;
 #[cfg(feature = "history")]
 use crate::history_tool;
+use crate::output::print_record;
 use crate::util::{ensure_owner_token, print_success};
 
 /// A file password action.
@@ -32,7 +35,7 @@ impl<'a> Password<'a> {
         let url = matcher_password.url();
 
         // Create a reqwest client
-        let client_config = create_config(&matcher_main);
+        let client_config = create_config(&matcher_main, Some(&url));
         let client = client_config.client(false);
 
         // Parse the remote file based on the share URL, derive the owner token from history
@@ -57,7 +60,27 @@ impl<'a> Password<'a> {
 
         // Add the file to the history
         #[cfg(feature = "history")]
-        history_tool::add(&matcher_main, file, true);
+        history_tool::add(&matcher_main, file.clone(), true);
+
+        // Print a machine readable record if selected, include a generated passphrase
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            print_record(
+                format,
+                vec![
+                    ("id", json!(file.id())),
+                    (
+                        "passphrase",
+                        json!(if password_generated {
+                            Some(&password)
+                        } else {
+                            None
+                        }),
+                    ),
+                ],
+            );
+            return Ok(());
+        }
 
         // Print the passphrase if one was generated
         if password_generated {
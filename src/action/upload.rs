@@ -1,39 +1,78 @@
 use std::fs::File;
-#[cfg(feature = "archive")]
-use std::io::Error as IoError;
-use std::path::Path;
+use std::io::{self, stdin, Cursor, Error as IoError, Read};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
+#[cfg(feature = "history")]
+use chrono::Utc;
 use clap::ArgMatches;
 use failure::Fail;
 use ffsend_api::action::params::ParamsDataBuilder;
-use ffsend_api::action::upload::{Error as UploadError, Upload as ApiUpload};
+use ffsend_api::action::upload::{
+    Error as UploadError, FileError, UploadError as UploadRequestError,
+};
 use ffsend_api::action::version::Error as VersionError;
 use ffsend_api::config::{upload_size_max, UPLOAD_SIZE_MAX_RECOMMENDED};
+use ffsend_api::file::remote_file::RemoteFile;
 use ffsend_api::pipe::ProgressReporter;
+use ffsend_api::url::Url;
 use prettytable::{format::FormatBuilder, Cell, Row, Table};
 #[cfg(feature = "qrcode")]
 use qr2term::print_qr;
-#[cfg(feature = "archive")]
+use serde_json::json;
 use tempfile::{Builder as TempBuilder, NamedTempFile};
 
 use super::select_api_version;
 #[cfg(feature = "archive")]
-use crate::archive::archiver::Archiver;
-use crate::client::create_config;
+use crate::archive::{
+    archiver::{walk, ArchiveStream},
+    format::ArchiveFormat,
+};
+use crate::checksum::{sha256_finish, sha256_hasher, HashReader};
+use crate::client::{create_config, Client};
 use crate::cmd::matcher::{MainMatcher, Matcher, UploadMatcher};
 #[cfg(feature = "history")]
+use crate::history::{Direction, HistoryFile};
+#[cfg(feature = "history")]
 use crate::history_tool;
+use crate::logger::redact_url;
+use crate::output::{print_records, OutputFormat, Record};
 use crate::progress::ProgressBar;
+use crate::split::{part_sizes, Manifest, Part, Source};
+use crate::stream_upload::StreamUpload;
+use crate::throttle::Throttle;
+use crate::transfer::{is_transient_response, retry};
 #[cfg(feature = "urlshorten")]
 use crate::urlshorten;
+#[cfg(feature = "qrcode")]
+use crate::util::print_warning;
 #[cfg(feature = "clipboard")]
 use crate::util::set_clipboard;
 use crate::util::{
-    format_bytes, open_url, print_error, print_error_msg, prompt_yes, quit, quit_error_msg,
-    ErrorHintsBuilder,
+    ensure_expiry_time, format_bytes, open_url, print_error, print_error_msg, prompt_yes, quit,
+    quit_error_msg, ErrorHintsBuilder,
 };
 
+/// The file name to use for data uploaded from stdin, if no name is given.
+const STDIN_FILE_NAME: &str = "stdin";
+
+/// The names of the values reported for each uploaded file in machine readable output.
+const RECORD_COLUMNS: [&str; 9] = [
+    "name",
+    "url",
+    "id",
+    "owner_token",
+    "passphrase",
+    "sha256",
+    "expiry",
+    "expire_at",
+    "expiry_uncertain",
+];
+
+/// The names of the values reported for each archived file when listing an archive.
+#[cfg(feature = "archive")]
+const ARCHIVE_LIST_COLUMNS: [&str; 3] = ["path", "dir", "size"];
+
 /// A file upload action.
 pub struct Upload<'a> {
     cmd_matches: &'a ArgMatches<'a>,
@@ -53,12 +92,31 @@ impl<'a> Upload<'a> {
         let matcher_upload = UploadMatcher::with(self.cmd_matches).unwrap();
 
         // Get API parameters
-        #[allow(unused_mut)]
-        let mut path = Path::new(matcher_upload.file()).to_path_buf();
+        let mut paths: Vec<PathBuf> = matcher_upload
+            .files()
+            .into_iter()
+            .map(|file| Path::new(file).to_path_buf())
+            .collect();
         let host = matcher_upload.host();
 
+        // Limit the transfer rate of all uploaded files together
+        let throttle = matcher_upload.limit_rate().map(Throttle::new);
+
+        // List the files that would be archived if selected, before anything is uploaded
+        #[cfg(feature = "archive")]
+        {
+            if matcher_upload.archive_list() {
+                return Ok(Self::print_archive_list(
+                    &matcher_main,
+                    &matcher_upload,
+                    &paths,
+                    matcher_upload.name(),
+                )?);
+            }
+        }
+
         // Create a reqwest client capable for uploading files
-        let client_config = create_config(&matcher_main);
+        let client_config = create_config(&matcher_main, Some(&host));
         let client = client_config.clone().client(false);
 
         // Determine the API version to use
@@ -66,47 +124,17 @@ impl<'a> Upload<'a> {
         select_api_version(&client, host.clone(), &mut desired_version)?;
         let api_version = desired_version.version().unwrap();
 
-        // TODO: ensure the file exists and is accessible
+        // TODO: ensure the files exist and are accessible
 
         // Determine the max file size
         // TODO: set false parameter to authentication state
         let max_size = upload_size_max(api_version, false);
 
-        // Get the file size to warn about large files
-        if let Ok(size) = File::open(&path)
-            .and_then(|f| f.metadata())
-            .map(|m| m.len())
-        {
-            if size > max_size && !matcher_main.force() {
-                // The file is too large, show an error and quit
-                quit_error_msg(
-                    format!(
-                        "the file size is {}, bigger than the maximum allowed of {}",
-                        format_bytes(size),
-                        format_bytes(max_size),
-                    ),
-                    ErrorHintsBuilder::default()
-                        .force(true)
-                        .verbose(false)
-                        .build()
-                        .unwrap(),
-                );
-            } else if size > UPLOAD_SIZE_MAX_RECOMMENDED && !matcher_main.force() {
-                // The file is larger than the recommended maximum, warn
-                eprintln!(
-                    "The file size is {}, bigger than the recommended maximum of {}",
-                    format_bytes(size),
-                    format_bytes(UPLOAD_SIZE_MAX_RECOMMENDED),
-                );
-
-                // Prompt the user to continue, quit if the user answered no
-                if !prompt_yes("Continue uploading?", Some(true), &matcher_main) {
-                    println!("Upload cancelled");
-                    quit();
-                }
-            }
-        } else {
-            print_error_msg("failed to check the file size, ignoring");
+        // Get the expiry time, ensure the server supports it
+        // TODO: set false parameter to authentication state
+        let expiry_time = matcher_upload.expiry_time();
+        if let Some(expiry_time) = expiry_time {
+            ensure_expiry_time(expiry_time, api_version, false, &matcher_main);
         }
 
         // Create a reqwest client capable for uploading files
@@ -115,37 +143,64 @@ impl<'a> Upload<'a> {
         // Create a progress bar reporter
         let progress_bar = Arc::new(Mutex::new(ProgressBar::new_upload()));
 
-        // Build a parameters object to set for the file
-        let params = {
-            // Build the parameters data object
-            let params = ParamsDataBuilder::default()
-                .download_limit(matcher_upload.download_limit())
-                .build()
-                .unwrap();
-
-            // Wrap the data in an option if not empty
-            if params.is_empty() {
-                None
-            } else {
-                Some(params)
-            }
-        };
-
         // The file name to use
-        #[allow(unused_mut)]
         let mut file_name = matcher_upload.name().map(|s| s.to_owned());
 
-        // A temporary archive file, only used when archiving
+        // A temporary file holding data read from stdin, only used when uploading from stdin
         // The temporary file is stored here, to ensure it's lifetime exceeds the upload process
+        let mut tmp_stdin: Option<NamedTempFile> = None;
+
+        // Buffer stdin to a temporary file if selected, the upload size must be known up front
+        if matcher_upload.stdin() {
+            if paths.len() > 1 {
+                quit_error_msg(
+                    "stdin can't be uploaded along with other files",
+                    ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+                );
+            }
+
+            // Read all data from stdin into a new temporary file
+            let mut tmp = TempBuilder::new()
+                .prefix(&format!(".{}-stdin-", crate_name!()))
+                .tempfile()
+                .map_err(Error::Stdin)?;
+            io::copy(&mut stdin().lock(), tmp.as_file_mut()).map_err(Error::Stdin)?;
+
+            // Upload the temporary file, use a default name if not set
+            paths = vec![tmp.path().to_path_buf()];
+            if file_name.is_none() {
+                file_name = Some(STDIN_FILE_NAME.into());
+            }
+            tmp_stdin = Some(tmp);
+        }
+
+        // The local paths selected by the user, tracked in history
+        #[cfg(feature = "history")]
+        let source_paths = if tmp_stdin.is_some() {
+            Vec::new()
+        } else {
+            paths.clone()
+        };
+
+        // An archive that is built while uploading, only used when archiving
         #[allow(unused_mut)]
         #[cfg(feature = "archive")]
-        let mut tmp_archive: Option<NamedTempFile> = None;
+        let mut archive_stream: Option<ArchiveStream> = None;
 
         #[cfg(feature = "archive")]
         {
-            // Determine whether to archive, ask if a directory was selected
+            // Determine whether to archive, ask if multiple files or a directory was selected
             let mut archive = matcher_upload.archive();
-            if !archive && path.is_dir() {
+            if !archive && paths.len() > 1 {
+                if prompt_yes(
+                    "You've selected multiple files, each file will be uploaded separately.\n\
+                     Archive the files into a single file instead?",
+                    Some(true),
+                    &matcher_main,
+                ) {
+                    archive = true;
+                }
+            } else if !archive && paths[0].is_dir() {
                 if prompt_yes(
                     "You've selected a directory, only a single file may be uploaded.\n\
                      Archive the directory into a single file?",
@@ -156,160 +211,325 @@ impl<'a> Upload<'a> {
                 }
             }
 
-            // Archive the selected file or directory
+            // Archive the selected files or directories
             if archive {
                 eprintln!("Archiving...");
-                let archive_extention = ".tar";
-
-                // Create a new temporary file to write the archive to
-                tmp_archive = Some(
-                    TempBuilder::new()
-                        .prefix(&format!(".{}-archive-", crate_name!()))
-                        .suffix(archive_extention)
-                        .tempfile()
-                        .map_err(ArchiveError::TempFile)?,
-                );
-                if let Some(tmp_archive) = &tmp_archive {
-                    // Get the path, and the actual file
-                    let archive_path = tmp_archive.path().to_path_buf();
-                    let archive_file = tmp_archive
-                        .as_file()
-                        .try_clone()
-                        .map_err(ArchiveError::CloneHandle)?;
-
-                    // Select the file name to use if not set
-                    if file_name.is_none() {
-                        file_name = Some(
-                            path.canonicalize()
-                                .map_err(|err| ArchiveError::FileName(Some(err)))?
-                                .file_name()
-                                .ok_or(ArchiveError::FileName(None))?
-                                .to_str()
-                                .map(|s| s.to_owned())
-                                .ok_or(ArchiveError::FileName(None))?,
-                        );
+
+                // Select the archive format, compress by default if archiving a directory
+                let format = matcher_upload.archive_format().unwrap_or_else(|| {
+                    if paths.iter().any(|path| path.is_dir()) {
+                        ArchiveFormat::TarGz
+                    } else {
+                        ArchiveFormat::Tar
                     }
+                });
+
+                // Select the file name to use if not set
+                if file_name.is_none() {
+                    file_name = Some(if paths.len() == 1 {
+                        Self::path_name(&paths[0])?
+                    } else {
+                        format!("{}-archive", crate_name!())
+                    });
+                }
 
-                    // Build an archiver and append the file
-                    let mut archiver = Archiver::new(archive_file);
-                    archiver
-                        .append_path(file_name.as_ref().unwrap(), &path)
-                        .map_err(ArchiveError::AddFile)?;
+                // Prepare the archive to stream into the upload, this determines its size
+                let sources = Self::archive_names(&paths, file_name.as_ref().map(String::as_str))?
+                    .into_iter()
+                    .zip(paths.iter().cloned())
+                    .collect();
+                archive_stream = Some(
+                    ArchiveStream::new(sources, format, matcher_upload.archive_filter())
+                        .map_err(ArchiveError::AddFile)?,
+                );
 
-                    // Finish the archival process, writes the archive file
-                    archiver.finish().map_err(ArchiveError::Write)?;
+                // Append archive extention to name, upload the archive under this name
+                if let Some(ref mut file_name) = file_name {
+                    file_name.push_str(format.extension());
+                    paths = vec![PathBuf::from(file_name.as_str())];
+                }
+            }
+        }
 
-                    // Append archive extention to name, set to upload archived file
-                    if let Some(ref mut file_name) = file_name {
-                        file_name.push_str(archive_extention);
+        // Each file is uploaded separately, make sure this is possible
+        if paths.len() > 1 {
+            if file_name.is_some() {
+                quit_error_msg(
+                    "a custom file name can't be used when uploading multiple files separately",
+                    ErrorHintsBuilder::default()
+                        .add_info("Archive the files to upload them with a custom name".into())
+                        .verbose(false)
+                        .build()
+                        .unwrap(),
+                );
+            }
+            if let Some(dir) = paths.iter().find(|p| p.is_dir()) {
+                quit_error_msg(
+                    format!(
+                        "cannot upload directory '{}' without archiving it",
+                        dir.to_str().unwrap_or("?"),
+                    ),
+                    ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+                );
+            }
+        }
+
+        // Determine the size of each file to upload, and check it
+        let sizes: Vec<Option<u64>> = paths
+            .iter()
+            .map(|path| {
+                #[cfg(feature = "archive")]
+                {
+                    if let Some(archive_stream) = &archive_stream {
+                        return Some(archive_stream.size());
                     }
-                    path = archive_path;
                 }
+                path.metadata().map(|m| m.len()).ok()
+            })
+            .collect();
+        let split = matcher_upload.split();
+        for size in &sizes {
+            // Files bigger than the maximum are uploaded in parts when splitting
+            if split && size.map(|size| size > max_size).unwrap_or(false) {
+                continue;
             }
+            Self::check_size(*size, max_size, &matcher_main);
         }
 
         // Build the progress reporter
         let progress_reporter: Arc<Mutex<ProgressReporter>> = progress_bar;
 
-        // Get the password to use and whether it was generated
+        // Get the password to use and whether it was generated, shared by all uploaded files
         let password = matcher_upload.password();
         let (password, password_generated) =
             password.map(|(p, g)| (Some(p), g)).unwrap_or((None, false));
 
-        // Execute an upload action, obtain the URL
-        let reporter = if !matcher_main.quiet() {
-            Some(&progress_reporter)
-        } else {
-            None
-        };
-        let file = ApiUpload::new(
-            api_version,
-            host,
-            path.clone(),
-            file_name,
-            password.clone(),
-            params,
-        )
-        .invoke(&transfer_client, reporter)?;
+        // Upload each file, collect the remote files and the checksums of their contents
+        let mut files: Vec<RemoteFile> = Vec::with_capacity(paths.len());
+        let mut checksums: Vec<Option<String>> = Vec::with_capacity(paths.len());
+        #[allow(unused_variables)]
+        for (i, path) in paths.iter().enumerate() {
+            // Build a parameters object to set for the file
+            let params = {
+                // Build the parameters data object
+                let params = ParamsDataBuilder::default()
+                    .download_limit(matcher_upload.download_limit())
+                    .expiry_time(expiry_time)
+                    .build()
+                    .unwrap();
+
+                // Wrap the data in an option if not empty
+                if params.is_empty() {
+                    None
+                } else {
+                    Some(params)
+                }
+            };
+
+            // Execute an upload action, obtain the remote file
+            let reporter = if !matcher_main.quiet() {
+                Some(&progress_reporter)
+            } else {
+                None
+            };
+            let (file, sha256) = if split && sizes[i].map(|size| size > max_size).unwrap_or(false) {
+                // Upload the file in parts along with a manifest, built from the same settings
+                #[cfg(feature = "archive")]
+                let source = match &archive_stream {
+                    Some(archive_stream) => Source::Archive(archive_stream),
+                    None => Source::File(path),
+                };
+                #[cfg(not(feature = "archive"))]
+                let source = Source::File(path);
+                let name = file_name.clone().unwrap_or_else(|| {
+                    path.file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or("file")
+                        .into()
+                });
+                Self::upload_split(
+                    |name| {
+                        StreamUpload::new(
+                            api_version,
+                            host.clone(),
+                            name,
+                            password.clone(),
+                            params.clone(),
+                            throttle.clone(),
+                        )
+                    },
+                    &transfer_client,
+                    source,
+                    &name,
+                    max_size,
+                    &matcher_main,
+                    reporter,
+                )
+                .map(|(file, sha256)| (file, Some(sha256)))?
+            } else {
+                // Stream the file or archive into the upload, hash the data while it is sent
+                let hashed = Arc::new(Mutex::new(None));
+                let file = retry(
+                    matcher_main.retries(),
+                    || {
+                        #[cfg(feature = "archive")]
+                        let (name, reader, len): (
+                            String,
+                            Box<dyn Read + Send>,
+                            u64,
+                        ) = match &archive_stream {
+                            Some(archive_stream) => (
+                                file_name.clone().unwrap(),
+                                Box::new(archive_stream.reader()),
+                                archive_stream.size(),
+                            ),
+                            None => Self::open_file(path, file_name.as_ref())?,
+                        };
+                        #[cfg(not(feature = "archive"))]
+                        let (name, reader, len) = Self::open_file(path, file_name.as_ref())?;
+
+                        debug!(
+                            "uploading {} ({} bytes) to {}",
+                            name,
+                            len,
+                            redact_url(&host)
+                        );
+                        StreamUpload::new(
+                            api_version,
+                            host.clone(),
+                            name,
+                            password.clone(),
+                            params.clone(),
+                            throttle.clone(),
+                        )
+                        .invoke(
+                            &transfer_client,
+                            Box::new(HashReader::new(
+                                reader,
+                                len,
+                                sha256_hasher(),
+                                hashed.clone(),
+                            )),
+                            len,
+                            reporter,
+                        )
+                    },
+                    is_transient,
+                )?;
+                let sha256 = hashed.lock().unwrap().take().map(sha256_finish);
+                (file, sha256)
+            };
+
+            // Add the file to the history manager, along with its metadata
+            // The source path is unknown when multiple paths were archived into one file
+            #[cfg(feature = "history")]
+            {
+                let source = if source_paths.len() == paths.len() {
+                    source_paths.get(i)
+                } else {
+                    None
+                };
+                history_tool::add(
+                    &matcher_main,
+                    Self::history_file(
+                        &file,
+                        path,
+                        sizes[i],
+                        file_name.as_ref(),
+                        source,
+                        sha256.clone(),
+                    ),
+                    false,
+                );
+            }
+
+            files.push(file);
+            checksums.push(sha256);
+        }
+
+        // Get the share URL for each file
         #[allow(unused_mut)]
-        let mut url = file.download_url(true);
+        let mut urls: Vec<_> = files.iter().map(|file| file.download_url(true)).collect();
 
-        // Shorten the share URL if requested, prompt the user to confirm
+        // Shorten the share URLs if requested, prompt the user to confirm
         #[cfg(feature = "urlshorten")]
         {
             if matcher_upload.shorten() {
                 if prompt_yes("URL shortening is a security risk. This shares the secret URL with a 3rd party.\nDo you want to shorten the share URL?", Some(false), &matcher_main) {
-                    match urlshorten::shorten_url(&client, &url) {
-                        Ok(short) => url = short,
-                        Err(err) => print_error(
-                            err.context("failed to shorten share URL, ignoring")
-                                .compat(),
-                        ),
+                    for url in urls.iter_mut() {
+                        match urlshorten::shorten_url(&client, url) {
+                            Ok(short) => *url = short,
+                            Err(err) => print_error(
+                                err.context("failed to shorten share URL, ignoring")
+                                    .compat(),
+                            ),
+                        }
                     }
                 }
             }
         }
 
         // Report the result
-        if !matcher_main.quiet() {
-            // Create a table
-            let mut table = Table::new();
-            table.set_format(FormatBuilder::new().padding(0, 2).build());
-
-            // Show the original URL when shortening, verbose and different
-            #[cfg(feature = "urlshorten")]
-            {
-                let full_url = file.download_url(true);
-                if matcher_main.verbose() && matcher_upload.shorten() && url != full_url {
-                    table.add_row(Row::new(vec![
-                        Cell::new("Full share link:"),
-                        Cell::new(full_url.as_str()),
-                    ]));
-                }
-            }
-
-            // Show the share URL
-            table.add_row(Row::new(vec![
-                Cell::new("Share link:"),
-                Cell::new(url.as_str()),
-            ]));
-
-            // Show a generate passphrase
-            if password_generated {
-                table.add_row(Row::new(vec![
-                    Cell::new("Passphrase:"),
-                    Cell::new(&password.unwrap_or("?".into())),
-                ]));
-            }
-
-            // Show the owner token
-            if matcher_main.verbose() {
-                table.add_row(Row::new(vec![
-                    Cell::new("Owner token:"),
-                    Cell::new(file.owner_token().unwrap()),
-                ]));
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            Self::print_records(
+                format,
+                &paths,
+                file_name.as_ref().map(String::as_str),
+                &files,
+                &urls,
+                &checksums,
+                if password_generated {
+                    password.as_ref().map(String::as_str)
+                } else {
+                    None
+                },
+            );
+        } else if !matcher_main.quiet() {
+            if files.len() == 1 {
+                Self::print_file(
+                    &files[0],
+                    &urls[0],
+                    checksums[0].as_ref().map(String::as_str),
+                    password.as_ref().map(String::as_str),
+                    password_generated,
+                    &matcher_main,
+                    &matcher_upload,
+                );
+            } else {
+                Self::print_files(
+                    &paths,
+                    &files,
+                    &urls,
+                    &checksums,
+                    password.as_ref().map(String::as_str),
+                    password_generated,
+                    &matcher_main,
+                );
             }
-            table.printstd();
         } else {
-            println!("{}", url);
+            urls.iter().for_each(|url| println!("{}", url));
         }
 
-        // Add the file to the history manager
-        #[cfg(feature = "history")]
-        history_tool::add(&matcher_main, file.clone(), false);
-
-        // Open the URL in the browser
+        // Open the URLs in the browser
         if matcher_upload.open() {
-            if let Err(err) = open_url(&url) {
-                print_error(err.context("failed to open the share link in the browser"));
-            };
+            for url in &urls {
+                if let Err(err) = open_url(url) {
+                    print_error(err.context("failed to open the share link in the browser"));
+                };
+            }
         }
 
-        // Copy the URL or command to the user's clipboard
+        // Copy the URLs or commands to the user's clipboard
         #[cfg(feature = "clipboard")]
         {
             if let Some(copy_mode) = matcher_upload.copy() {
-                if let Err(err) = set_clipboard(copy_mode.build(url.as_str())) {
+                let content = urls
+                    .iter()
+                    .map(|url| copy_mode.build(url.as_str()))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if let Err(err) = set_clipboard(content) {
                     print_error(
                         err.context("failed to copy the share link to the clipboard, ignoring"),
                     );
@@ -317,31 +537,467 @@ impl<'a> Upload<'a> {
             }
         }
 
-        // Print a QR code for the share URL
+        // Print a QR code for the share URLs
         #[cfg(feature = "qrcode")]
         {
-            if matcher_upload.qrcode() {
-                if let Err(err) = print_qr(url.as_str()) {
-                    print_error(err.context("failed to print QR code, ignoring").compat());
+            if matcher_upload.qrcode() && format.is_machine() {
+                print_warning("not printing QR code with machine readable output");
+            } else if matcher_upload.qrcode() {
+                for url in &urls {
+                    if let Err(err) = print_qr(url.as_str()) {
+                        print_error(err.context("failed to print QR code, ignoring").compat());
+                    }
                 }
             }
         }
 
-        #[cfg(feature = "archive")]
-        {
-            // Close the temporary zip file, to ensure it's removed
-            if let Some(tmp_archive) = tmp_archive.take() {
-                if let Err(err) = tmp_archive.close() {
-                    print_error(
-                        err.context("failed to clean up temporary archive file, ignoring")
-                            .compat(),
-                    );
+        // Close the temporary stdin file, to ensure it's removed
+        if let Some(tmp_stdin) = tmp_stdin.take() {
+            if let Err(err) = tmp_stdin.close() {
+                print_error(
+                    err.context("failed to clean up temporary stdin file, ignoring")
+                        .compat(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Upload the given source in parts of at most `part_size` bytes, followed by a manifest
+    /// listing the share URLs, sizes and checksums of the parts.
+    ///
+    /// Each part and the manifest is uploaded as built by `new_upload` for its file name, based on
+    /// the given `name`. The parts are added to the history. The uploaded manifest is returned,
+    /// along with the checksum of the whole file.
+    fn upload_split<U>(
+        new_upload: U,
+        client: &Client,
+        source: Source,
+        name: &str,
+        part_size: u64,
+        matcher_main: &MainMatcher,
+        reporter: Option<&Arc<Mutex<dyn ProgressReporter>>>,
+    ) -> Result<(RemoteFile, String), Error>
+    where
+        U: Fn(String) -> StreamUpload,
+    {
+        let size = source.size().map_err(Error::Split)?;
+        let sizes = part_sizes(size, part_size);
+        let count = sizes.len();
+
+        // Upload each part, checksum each part and the whole file while sending, these are listed
+        // in the manifest
+        let mut parts = Vec::with_capacity(count);
+        let mut offset = 0;
+        let mut total = sha256_hasher();
+        for (i, len) in sizes.into_iter().enumerate() {
+            if !matcher_main.quiet() {
+                eprintln!("Uploading part {} of {}...", i + 1, count);
+            }
+            let part_name = format!("{}.{:03}", name, i + 1);
+            let hashed_part = Arc::new(Mutex::new(None));
+            let hashed_total = Arc::new(Mutex::new(None));
+            let file = retry(
+                matcher_main.retries(),
+                || {
+                    let reader = source
+                        .open(offset, len)
+                        .map_err(|err| UploadError::File(FileError::Open(err)))?;
+                    let reader = HashReader::new(reader, len, sha256_hasher(), hashed_part.clone());
+                    let reader = HashReader::new(reader, len, total.clone(), hashed_total.clone());
+                    new_upload(part_name.clone()).invoke(client, Box::new(reader), len, reporter)
+                },
+                is_transient,
+            )?;
+
+            // Take the checksums, these are only available if the whole part was sent
+            let (hashed_part, hashed_total) = (
+                hashed_part.lock().unwrap().take(),
+                hashed_total.lock().unwrap().take(),
+            );
+            let sha256 = match (hashed_part, hashed_total) {
+                (Some(part), Some(hashed_total)) => {
+                    total = hashed_total;
+                    sha256_finish(part)
+                }
+                _ => {
+                    return Err(Error::Checksum(IoError::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "file changed while reading it",
+                    )))
+                }
+            };
+
+            #[cfg(feature = "history")]
+            history_tool::add(
+                matcher_main,
+                Self::history_file(
+                    &file,
+                    Path::new(&part_name),
+                    Some(len),
+                    None,
+                    None,
+                    Some(sha256.clone()),
+                ),
+                false,
+            );
+
+            parts.push(Part {
+                url: file.download_url(true).into_string(),
+                size: len,
+                sha256,
+            });
+            offset += len;
+        }
+
+        // Upload the manifest
+        let sha256 = sha256_finish(total);
+        let manifest = Manifest::new(name.into(), size, sha256.clone(), parts).to_vec();
+        let file = retry(
+            matcher_main.retries(),
+            || {
+                new_upload(Manifest::file_name(name)).invoke(
+                    client,
+                    Box::new(Cursor::new(manifest.clone())),
+                    manifest.len() as u64,
+                    None,
+                )
+            },
+            is_transient,
+        )?;
+        Ok((file, sha256))
+    }
+
+    /// Open the file at the given path to upload, along with its size.
+    ///
+    /// The file name to upload the file as is returned as well, which is `name` if given.
+    fn open_file(
+        path: &Path,
+        name: Option<&String>,
+    ) -> Result<(String, Box<dyn Read + Send>, u64), UploadError> {
+        let name = name.cloned().unwrap_or_else(|| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("file")
+                .into()
+        });
+        let file = File::open(path).map_err(FileError::from)?;
+        let len = file.metadata().map_err(FileError::from)?.len();
+        Ok((name, Box::new(file), len))
+    }
+
+    /// Check whether a file of the given `size` isn't too big to upload.
+    ///
+    /// If the file is bigger than `max_size`, the program will quit with an error unless forced.
+    /// If the file is bigger than the recommended maximum, the user is prompted to continue.
+    /// A `None` size means the file size could not be determined.
+    fn check_size(size: Option<u64>, max_size: u64, matcher_main: &MainMatcher) {
+        // Warn about large files
+        if let Some(size) = size {
+            if size > max_size && !matcher_main.force() {
+                // The file is too large, show an error and quit
+                quit_error_msg(
+                    format!(
+                        "the file size is {}, bigger than the maximum allowed of {}",
+                        format_bytes(size),
+                        format_bytes(max_size),
+                    ),
+                    ErrorHintsBuilder::default()
+                        .add_info("Use '--split' to upload it in linked parts".into())
+                        .force(true)
+                        .verbose(false)
+                        .build()
+                        .unwrap(),
+                );
+            } else if size > UPLOAD_SIZE_MAX_RECOMMENDED && !matcher_main.force() {
+                // The file is larger than the recommended maximum, warn
+                eprintln!(
+                    "The file size is {}, bigger than the recommended maximum of {}",
+                    format_bytes(size),
+                    format_bytes(UPLOAD_SIZE_MAX_RECOMMENDED),
+                );
+
+                // Prompt the user to continue, quit if the user answered no
+                if !prompt_yes("Continue uploading?", Some(true), matcher_main) {
+                    eprintln!("Upload cancelled");
+                    quit();
                 }
             }
+        } else {
+            print_error_msg("failed to check the file size, ignoring");
         }
+    }
+
+    /// Print the upload result for a single uploaded file.
+    fn print_file(
+        file: &RemoteFile,
+        url: &Url,
+        sha256: Option<&str>,
+        password: Option<&str>,
+        password_generated: bool,
+        matcher_main: &MainMatcher,
+        #[allow(unused)] matcher_upload: &UploadMatcher,
+    ) {
+        // Create a table
+        let mut table = Table::new();
+        table.set_format(FormatBuilder::new().padding(0, 2).build());
+
+        // Show the original URL when shortening, verbose and different
+        #[cfg(feature = "urlshorten")]
+        {
+            let full_url = file.download_url(true);
+            if matcher_main.verbose() && matcher_upload.shorten() && *url != full_url {
+                table.add_row(Row::new(vec![
+                    Cell::new("Full share link:"),
+                    Cell::new(full_url.as_str()),
+                ]));
+            }
+        }
+
+        // Show the share URL
+        table.add_row(Row::new(vec![
+            Cell::new("Share link:"),
+            Cell::new(url.as_str()),
+        ]));
+
+        // Show the checksum of the file contents
+        if let Some(sha256) = sha256 {
+            table.add_row(Row::new(vec![Cell::new("SHA-256:"), Cell::new(sha256)]));
+        }
+
+        // Show a generate passphrase
+        if password_generated {
+            table.add_row(Row::new(vec![
+                Cell::new("Passphrase:"),
+                Cell::new(password.unwrap_or("?")),
+            ]));
+        }
+
+        // Show the owner token
+        if matcher_main.verbose() {
+            table.add_row(Row::new(vec![
+                Cell::new("Owner token:"),
+                Cell::new(file.owner_token().unwrap()),
+            ]));
+        }
+        table.printstd();
+    }
+
+    /// Print the upload result for multiple separately uploaded files, as a table of share
+    /// links.
+    fn print_files(
+        paths: &[PathBuf],
+        files: &[RemoteFile],
+        urls: &[Url],
+        checksums: &[Option<String>],
+        password: Option<&str>,
+        password_generated: bool,
+        matcher_main: &MainMatcher,
+    ) {
+        // Build the list of column names
+        let mut columns = vec!["#", "NAME", "LINK", "SHA-256"];
+        if matcher_main.verbose() {
+            columns.push("OWNER TOKEN");
+        }
+
+        // Create a new table
+        let mut table = Table::new();
+        table.set_format(FormatBuilder::new().padding(0, 2).build());
+        table.add_row(Row::new(columns.into_iter().map(Cell::new).collect()));
+
+        // Add an entry for each file
+        for (i, (((path, file), url), sha256)) in
+            paths.iter().zip(files).zip(urls).zip(checksums).enumerate()
+        {
+            let mut cells: Vec<String> = vec![
+                format!("{}", i + 1),
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("?")
+                    .into(),
+                url.as_str().into(),
+                sha256.clone().unwrap_or_else(|| "?".into()),
+            ];
+            if matcher_main.verbose() {
+                cells.push(file.owner_token().cloned().unwrap_or_else(|| "?".into()));
+            }
+            table.add_row(Row::new(cells.into_iter().map(|c| Cell::new(&c)).collect()));
+        }
+        table.printstd();
+
+        // Show the shared generated passphrase
+        if password_generated {
+            println!();
+            let mut table = Table::new();
+            table.set_format(FormatBuilder::new().padding(0, 2).build());
+            table.add_row(Row::new(vec![
+                Cell::new("Passphrase:"),
+                Cell::new(password.unwrap_or("?")),
+            ]));
+            table.printstd();
+        }
+    }
+
+    /// Print the upload result for all uploaded files as machine readable records.
+    ///
+    /// The `passphrase` should only be given if it was generated, a passphrase given by the user
+    /// is not included.
+    fn print_records(
+        format: OutputFormat,
+        paths: &[PathBuf],
+        name: Option<&str>,
+        files: &[RemoteFile],
+        urls: &[Url],
+        checksums: &[Option<String>],
+        passphrase: Option<&str>,
+    ) {
+        let records = paths
+            .iter()
+            .zip(files)
+            .zip(urls)
+            .zip(checksums)
+            .map(|(((path, file), url), sha256)| -> Record {
+                vec![
+                    (
+                        "name",
+                        json!(name.or_else(|| path.file_name().and_then(|n| n.to_str()))),
+                    ),
+                    ("url", json!(url.as_str())),
+                    ("id", json!(file.id())),
+                    ("owner_token", json!(file.owner_token())),
+                    ("passphrase", json!(passphrase)),
+                    ("sha256", json!(sha256)),
+                    ("expiry", json!(file.expire_duration().num_seconds())),
+                    ("expire_at", json!(file.expire_at().to_rfc3339())),
+                    ("expiry_uncertain", json!(file.expire_uncertain())),
+                ]
+            })
+            .collect();
+        print_records(format, &RECORD_COLUMNS, records);
+    }
+
+    /// Build the history file for an uploaded file, holding its metadata.
+    ///
+    /// The uploaded file at `path` is used to determine the MIME type, along with the upload
+    /// `name` if set. The `size` is the uploaded file size. The `source` is the path selected by
+    /// the user. The `sha256` is the checksum of the uploaded file contents.
+    #[cfg(feature = "history")]
+    fn history_file(
+        file: &RemoteFile,
+        path: &Path,
+        size: Option<u64>,
+        name: Option<&String>,
+        source: Option<&PathBuf>,
+        sha256: Option<String>,
+    ) -> HistoryFile {
+        let mut history_file = HistoryFile::new(file.clone());
+        history_file.set_name(
+            name.cloned()
+                .or_else(|| path.file_name().and_then(|n| n.to_str()).map(|n| n.into())),
+        );
+        history_file.set_size(size);
+        history_file.set_mime(Some(
+            mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .to_string(),
+        ));
+        history_file.set_upload_at(Some(Utc::now()));
+        history_file.set_path(source.and_then(|path| path.canonicalize().ok()));
+        history_file.set_direction(Some(Direction::Upload));
+        history_file.set_sha256(sha256);
+        history_file
+    }
+
+    /// Get the relative path in the archive, for each of the given paths to archive.
+    ///
+    /// If a single path is archived, the given `name` is used if set.
+    #[cfg(feature = "archive")]
+    fn archive_names(paths: &[PathBuf], name: Option<&str>) -> Result<Vec<String>, ArchiveError> {
+        match name {
+            Some(name) if paths.len() == 1 => Ok(vec![name.into()]),
+            _ => paths.iter().map(|path| Self::path_name(path)).collect(),
+        }
+    }
+
+    /// Print the files that would be archived for the given paths, along with their total size.
+    #[cfg(feature = "archive")]
+    fn print_archive_list(
+        matcher_main: &MainMatcher,
+        matcher_upload: &UploadMatcher,
+        paths: &[PathBuf],
+        name: Option<&str>,
+    ) -> Result<(), ArchiveError> {
+        // Walk all paths to collect the entries that would be archived
+        let filter = matcher_upload.archive_filter();
+        let mut entries = Vec::new();
+        for (name, path) in Self::archive_names(paths, name)?.iter().zip(paths) {
+            entries.extend(walk(name, path, &filter).map_err(ArchiveError::List)?);
+        }
+
+        // Print machine readable records if selected
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            let records = entries
+                .iter()
+                .map(|entry| {
+                    vec![
+                        ("path", json!(entry.path.to_str())),
+                        ("dir", json!(entry.dir)),
+                        ("size", json!(entry.size)),
+                    ]
+                })
+                .collect();
+            print_records(format, &ARCHIVE_LIST_COLUMNS, records);
+            return Ok(());
+        }
+
+        // Print each entry, and the total
+        for entry in &entries {
+            if entry.dir {
+                println!("{:>12}  {}/", "", entry.path.display());
+            } else {
+                println!("{:>12}  {}", format_bytes(entry.size), entry.path.display());
+            }
+        }
+        println!(
+            "{} files, {} in total before compression",
+            entries.iter().filter(|entry| !entry.dir).count(),
+            format_bytes(entries.iter().map(|entry| entry.size).sum()),
+        );
 
         Ok(())
     }
+
+    /// Get the file name of the given path, used as name for the file in an archive.
+    #[cfg(feature = "archive")]
+    fn path_name(path: &Path) -> Result<String, ArchiveError> {
+        path.canonicalize()
+            .map_err(|err| ArchiveError::FileName(Some(err)))?
+            .file_name()
+            .ok_or(ArchiveError::FileName(None))?
+            .to_str()
+            .map(|s| s.to_owned())
+            .ok_or(ArchiveError::FileName(None))
+    }
+}
+
+/// Check whether the given upload error is transient, and whether the upload might succeed when it
+/// is retried.
+///
+/// Only errors that occur while transferring the file are considered transient. Errors that occur
+/// after the file was uploaded, such as when setting parameters, are not retried.
+fn is_transient(err: &UploadError) -> bool {
+    match err {
+        UploadError::Upload(err) => match err {
+            UploadRequestError::Request | UploadRequestError::InvalidResponse => true,
+            #[cfg(feature = "send3")]
+            UploadRequestError::UploadStream(_) => true,
+            UploadRequestError::Response(err) => is_transient_response(err),
+            _ => false,
+        },
+        _ => false,
+    }
 }
 
 #[derive(Debug, Fail)]
@@ -356,6 +1012,18 @@ pub enum Error {
     #[fail(display = "failed to archive file to upload")]
     Archive(#[cause] ArchiveError),
 
+    /// An error occurred while reading the file to upload from stdin.
+    #[fail(display = "failed to read file to upload from stdin")]
+    Stdin(#[cause] IoError),
+
+    /// An error occurred while computing the checksum of the file to upload.
+    #[fail(display = "failed to compute checksum of file to upload")]
+    Checksum(#[cause] IoError),
+
+    /// An error occurred while reading the file to upload in parts.
+    #[fail(display = "failed to read file to split into parts")]
+    Split(#[cause] IoError),
+
     /// An error occurred while uploading the file.
     #[fail(display = "")]
     Upload(#[cause] UploadError),
@@ -383,14 +1051,6 @@ impl From<UploadError> for Error {
 #[cfg(feature = "archive")]
 #[derive(Debug, Fail)]
 pub enum ArchiveError {
-    /// An error occurred while creating the temporary archive file.
-    #[fail(display = "failed to create temporary archive file")]
-    TempFile(#[cause] IoError),
-
-    /// An error occurred while internally cloning the handle to the temporary archive file.
-    #[fail(display = "failed to clone handle to temporary archive file")]
-    CloneHandle(#[cause] IoError),
-
     /// Failed to infer a file name for the archive.
     #[fail(display = "failed to infer a file name for the archive")]
     FileName(Option<IoError>),
@@ -399,7 +1059,7 @@ pub enum ArchiveError {
     #[fail(display = "failed to add file to the archive")]
     AddFile(#[cause] IoError),
 
-    /// Failed to write the created archive to the disk.
-    #[fail(display = "failed to write archive to disk")]
-    Write(#[cause] IoError),
+    /// Failed to walk the files to archive, to list them.
+    #[fail(display = "failed to list files to archive")]
+    List(#[cause] IoError),
 }
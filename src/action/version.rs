@@ -1,10 +1,13 @@
 use clap::ArgMatches;
-use ffsend_api::action::version::{Error as VersionError, Version as ApiVersion};
+use ffsend_api::action::version::Error as VersionError;
+use serde_json::json;
 
+use crate::api::Version as ApiVersion;
 use crate::client::create_config;
 use crate::cmd::matcher::main::MainMatcher;
 use crate::cmd::matcher::{version::VersionMatcher, Matcher};
 use crate::error::ActionError;
+use crate::output::print_record;
 
 /// A file version action.
 pub struct Version<'a> {
@@ -28,11 +31,31 @@ impl<'a> Version<'a> {
         let host = matcher_version.host();
 
         // Create a reqwest client
-        let client_config = create_config(&matcher_main);
+        let client_config = create_config(&matcher_main, Some(&host));
         let client = client_config.client(false);
 
         // Make sure the file version
-        let response = ApiVersion::new(host).invoke(&client);
+        let response = ApiVersion::new(host.clone()).invoke(&client);
+
+        // Print a machine readable record if selected
+        let format = matcher_main.output_format();
+        if format.is_machine() {
+            let (version, supported) = match response {
+                Ok(v) => (Some(v.to_string()), true),
+                Err(VersionError::Unknown) => (None, false),
+                Err(VersionError::Unsupported(v)) => (Some(v), false),
+                Err(e) => return Err(e.into()),
+            };
+            print_record(
+                format,
+                vec![
+                    ("host", json!(host.as_str())),
+                    ("version", json!(version)),
+                    ("supported", json!(supported)),
+                ],
+            );
+            return Ok(());
+        }
 
         // Print the result
         match response {
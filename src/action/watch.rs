@@ -0,0 +1,355 @@
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration as StdDuration, Instant};
+
+use chrono::{Duration, Local};
+use clap::ArgMatches;
+use failure::Fail;
+use ffsend_api::action::info::{Error as InfoError, InfoResponse};
+use ffsend_api::file::remote_file::{FileParseError, RemoteFile};
+use serde_json::json;
+
+use crate::api::Info as ApiInfo;
+use crate::client::create_config;
+use crate::cmd::matcher::{main::MainMatcher, watch::WatchMatcher, Matcher};
+use crate::config::{WATCH_EXIT_EXPIRED, WATCH_EXIT_TIMEOUT};
+#[cfg(feature = "history")]
+use crate::history_tool;
+use crate::output::{print_record_stream, OutputFormat};
+use crate::util::{
+    ensure_owner_token, format_duration, print_error, print_success, print_warning, quit_code,
+};
+
+/// A file watch action.
+pub struct Watch<'a> {
+    cmd_matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Watch<'a> {
+    /// Construct a new watch action.
+    pub fn new(cmd_matches: &'a ArgMatches<'a>) -> Self {
+        Self { cmd_matches }
+    }
+
+    /// Invoke the watch action.
+    ///
+    /// Returns when the file reaches its download limit. The application quits with a distinct
+    /// exit code if the file expires, or if the maximum watch time elapses.
+    // TODO: create a trait for this method
+    pub fn invoke(&self) -> Result<(), Error> {
+        // Create the command matchers
+        let matcher_main = MainMatcher::with(self.cmd_matches).unwrap();
+        let matcher_watch = WatchMatcher::with(self.cmd_matches).unwrap();
+
+        // Get the share URL and watch settings
+        let url = matcher_watch.url();
+        let interval = StdDuration::from_secs(matcher_watch.interval() as u64);
+        let deadline = matcher_watch
+            .max_time()
+            .map(|secs| Instant::now() + StdDuration::from_secs(secs as u64));
+
+        // Create a reqwest client
+        let client_config = create_config(&matcher_main, Some(&url));
+        let client = client_config.client(false);
+
+        // Parse the remote file based on the share URL, derive the owner token from history
+        let mut file = RemoteFile::parse_url(url, matcher_watch.owner())?;
+        #[cfg(feature = "history")]
+        history_tool::derive_file_properties(&matcher_main, &mut file);
+
+        // The owner token is required to fetch the file info
+        ensure_owner_token(file.owner_token_mut(), &matcher_main, false);
+
+        let mut reporter = Reporter::new(&matcher_main, &file, matcher_watch.exec());
+        let mut last: Option<Snapshot> = None;
+        loop {
+            debug!("fetching info of file {}", file.id());
+            match ApiInfo::new(&file, None).invoke(&client) {
+                Ok(info) => {
+                    let snapshot = Snapshot::from(&info);
+                    reporter.report(last, snapshot);
+
+                    // The server removes the file once it reaches its limit
+                    if snapshot.downloads >= snapshot.limit {
+                        #[cfg(feature = "history")]
+                        history_tool::remove(&matcher_main, &file);
+                        reporter.finish(Finish::Limit, Some(snapshot));
+                        return Ok(());
+                    }
+
+                    // Keep the expiry time in history up to date
+                    file.set_expire_duration(Duration::milliseconds(info.ttl_millis() as i64));
+                    #[cfg(feature = "history")]
+                    history_tool::add(&matcher_main, file.clone(), true);
+
+                    last = Some(snapshot);
+                }
+
+                // The file is gone, it was downloaded for the last time if it had one download
+                // left and its expiry time did not pass yet
+                Err(InfoError::Expired) => {
+                    #[cfg(feature = "history")]
+                    history_tool::remove(&matcher_main, &file);
+
+                    match last {
+                        Some(last) if last.downloads + 1 >= last.limit && !file.has_expired() => {
+                            let snapshot = Snapshot {
+                                downloads: last.limit,
+                                limit: last.limit,
+                                ttl: None,
+                            };
+                            reporter.report(Some(last), snapshot);
+                            reporter.finish(Finish::Limit, Some(snapshot));
+                            return Ok(());
+                        }
+                        _ => {
+                            reporter.finish(Finish::Expired, last);
+                            quit_code(WATCH_EXIT_EXPIRED);
+                        }
+                    }
+                }
+
+                // Fail on the first request, keep watching on later transient errors
+                Err(err) => {
+                    if last.is_none() {
+                        return Err(err.into());
+                    }
+                    print_error(err.context("failed to fetch file info, retrying"));
+                }
+            }
+
+            // Wait for the next poll, stop watching when the maximum time elapsed
+            let wait = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        reporter.finish(Finish::Timeout, last);
+                        quit_code(WATCH_EXIT_TIMEOUT);
+                    }
+                    interval.min(deadline - now)
+                }
+                None => interval,
+            };
+            debug!("next poll in {:.0?}", wait);
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// A snapshot of the live file info.
+#[derive(Copy, Clone)]
+struct Snapshot {
+    /// The number of times the file has been downloaded.
+    downloads: usize,
+
+    /// The download limit.
+    limit: usize,
+
+    /// The time to live in milliseconds, if known.
+    ttl: Option<u64>,
+}
+
+impl From<&InfoResponse> for Snapshot {
+    fn from(info: &InfoResponse) -> Self {
+        Snapshot {
+            downloads: info.download_count(),
+            limit: info.download_limit(),
+            ttl: Some(info.ttl_millis()),
+        }
+    }
+}
+
+/// The reason watching a file finished.
+#[derive(Copy, Clone)]
+enum Finish {
+    /// The file reached its download limit.
+    Limit,
+
+    /// The file expired, or was deleted.
+    Expired,
+
+    /// The maximum watch time elapsed.
+    Timeout,
+}
+
+impl Finish {
+    /// Get the event name for this reason.
+    fn name(self) -> &'static str {
+        match self {
+            Finish::Limit => "limit",
+            Finish::Expired => "expired",
+            Finish::Timeout => "timeout",
+        }
+    }
+}
+
+/// Reports watch events to the user, and runs the download hook.
+struct Reporter<'a> {
+    /// The output format.
+    format: OutputFormat,
+
+    /// Whether to suppress human readable output.
+    quiet: bool,
+
+    /// The ID of the watched file.
+    id: String,
+
+    /// The share URL of the watched file, without secret.
+    url: String,
+
+    /// The shell command to run on each download.
+    exec: Option<&'a str>,
+
+    /// Whether a record has been printed already.
+    printed: bool,
+}
+
+impl<'a> Reporter<'a> {
+    /// Construct a new reporter for the given file.
+    fn new(matcher_main: &MainMatcher, file: &RemoteFile, exec: Option<&'a str>) -> Self {
+        Self {
+            format: matcher_main.output_format(),
+            quiet: matcher_main.quiet(),
+            id: file.id().to_owned(),
+            url: file.download_url(false).into_string(),
+            exec,
+            printed: false,
+        }
+    }
+
+    /// Report the given file info snapshot, compared to the previous snapshot.
+    ///
+    /// The first snapshot is reported as the start of watching. After that, only changes in the
+    /// download count are reported, and the download hook is run for each new download.
+    fn report(&mut self, last: Option<Snapshot>, snapshot: Snapshot) {
+        let last = match last {
+            Some(last) if last.downloads == snapshot.downloads => return,
+            last => last,
+        };
+
+        match last {
+            None => {
+                self.event("watching", Some(snapshot));
+                if !self.format.is_machine() && !self.quiet {
+                    println!(
+                        "Watching file, {} of {} downloads, expires in {}",
+                        snapshot.downloads,
+                        snapshot.limit,
+                        format_duration(Duration::milliseconds(snapshot.ttl.unwrap_or(0) as i64)),
+                    );
+                }
+            }
+            Some(last) => {
+                self.event("download", Some(snapshot));
+                if !self.format.is_machine() && !self.quiet {
+                    println!(
+                        "[{}] Downloaded, {} of {} downloads",
+                        Local::now().format("%H:%M:%S"),
+                        snapshot.downloads,
+                        snapshot.limit,
+                    );
+                }
+
+                // Run the hook for each new download
+                for count in last.downloads + 1..=snapshot.downloads {
+                    self.run_hook(count, snapshot.limit);
+                }
+            }
+        }
+    }
+
+    /// Report that watching finished for the given reason, with the last known snapshot.
+    fn finish(&mut self, finish: Finish, snapshot: Option<Snapshot>) {
+        self.event(finish.name(), snapshot);
+        if self.format.is_machine() || self.quiet {
+            return;
+        }
+        match finish {
+            Finish::Limit => print_success("File reached its download limit"),
+            Finish::Expired => print_warning("file expired or was deleted"),
+            Finish::Timeout => print_warning("stopped watching, maximum watch time elapsed"),
+        }
+    }
+
+    /// Print a machine readable record for the given event, if selected.
+    fn event(&mut self, event: &'static str, snapshot: Option<Snapshot>) {
+        if !self.format.is_machine() {
+            return;
+        }
+        print_record_stream(
+            self.format,
+            vec![
+                ("event", json!(event)),
+                ("id", json!(self.id)),
+                ("downloads", json!(snapshot.map(|s| s.downloads))),
+                ("download_limit", json!(snapshot.map(|s| s.limit))),
+                (
+                    "expiry",
+                    json!(snapshot.and_then(|s| s.ttl).map(|t| t / 1000)),
+                ),
+            ],
+            !self.printed,
+        );
+        self.printed = true;
+    }
+
+    /// Run the download hook, if set, for the download with the given count.
+    ///
+    /// The command is run through the shell. Failures are reported, but don't stop watching.
+    fn run_hook(&self, count: usize, limit: usize) {
+        let cmd = match self.exec {
+            Some(cmd) => cmd,
+            None => return,
+        };
+
+        #[cfg(not(windows))]
+        let mut command = Command::new("sh");
+        #[cfg(not(windows))]
+        command.arg("-c");
+        #[cfg(windows)]
+        let mut command = Command::new("cmd");
+        #[cfg(windows)]
+        command.arg("/C");
+
+        let status = command
+            .arg(cmd)
+            .env("FFSEND_ID", &self.id)
+            .env("FFSEND_URL", &self.url)
+            .env("FFSEND_DOWNLOADS", count.to_string())
+            .env("FFSEND_DOWNLOAD_LIMIT", limit.to_string())
+            .stdin(Stdio::null())
+            .status();
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => print_warning(format!(
+                "download hook failed with exit code {}",
+                status.code().unwrap_or(0),
+            )),
+            Err(err) => print_error(err.context("failed to run download hook")),
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// Failed to parse a share URL, it was invalid.
+    /// This error is not related to a specific action.
+    #[fail(display = "invalid share link")]
+    InvalidUrl(#[cause] FileParseError),
+
+    /// An error occurred while fetching the file information.
+    #[fail(display = "failed to fetch file info")]
+    Info(#[cause] InfoError),
+}
+
+impl From<FileParseError> for Error {
+    fn from(err: FileParseError) -> Error {
+        Error::InvalidUrl(err)
+    }
+}
+
+impl From<InfoError> for Error {
+    fn from(err: InfoError) -> Error {
+        Error::Info(err)
+    }
+}
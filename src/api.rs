@@ -0,0 +1,672 @@
+//! Send API requests, sent with the network client.
+//!
+//! These mirror the actions of the ffsend API, which can only be sent through its own network
+//! client. The same errors and responses are used.
+
+use ffsend_api::action::delete::{
+    DeleteData, DeleteDataError, DeleteError, Error as DeleteActionError,
+    PrepareError as DeletePrepareError,
+};
+use ffsend_api::action::exists::{Error as ExistsError, ExistsResponse};
+use ffsend_api::action::info::{
+    Error as InfoActionError, InfoData, InfoError, InfoResponse, PrepareError as InfoPrepareError,
+};
+use ffsend_api::action::metadata::{
+    Error as MetadataError, MetaError, MetadataResponse, RawMetadataResponse,
+};
+use ffsend_api::action::params::{
+    ChangeError as ParamsChangeError, Error as ParamsError, ParamsData,
+    PrepareError as ParamsPrepareError,
+};
+use ffsend_api::action::password::{
+    ChangeError as PasswordChangeError, Error as PasswordError,
+    PrepareError as PasswordPrepareError,
+};
+use ffsend_api::action::version::{Error as VersionError, VersionResponse};
+use ffsend_api::api::data::OwnedData;
+use ffsend_api::api::nonce::NonceError;
+use ffsend_api::api::request::ResponseError;
+use ffsend_api::api::url::UrlBuilder;
+use ffsend_api::api::Version as ApiVersion;
+use ffsend_api::config::{HTTP_STATUS_EXPIRED, HTTP_STATUS_UNAUTHORIZED};
+use ffsend_api::crypto::b64;
+use ffsend_api::crypto::key_set::KeySet;
+use ffsend_api::crypto::sig::signature_encoded;
+use ffsend_api::file::remote_file::RemoteFile;
+use ffsend_api::url::Url;
+
+use crate::client::{Client, Response};
+
+/// The endpoint the server version is reported at.
+const VERSION_ENDPOINT: &str = "__version__";
+
+/// An endpoint only Firefox Send v2 servers have, to probe for the version.
+#[cfg(feature = "send2")]
+const V2_PROBE_ENDPOINT: &str = "jsconfig.js";
+
+/// An endpoint only Firefox Send v3 servers have, to probe for the version.
+#[cfg(feature = "send3")]
+const V3_PROBE_ENDPOINT: &str = "app.webmanifest";
+
+/// The header the server sends a new authentication nonce in.
+const HEADER_NONCE: &str = "WWW-Authenticate";
+
+/// Make sure the given response has a successful status.
+pub fn ensure_success(response: &Response) -> Result<(), ResponseError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    if status == HTTP_STATUS_EXPIRED {
+        return Err(ResponseError::Expired);
+    }
+    if status == HTTP_STATUS_UNAUTHORIZED {
+        return Err(ResponseError::Unauthorized);
+    }
+
+    let text = match status.canonical_reason() {
+        Some(reason) => format!("{} {}", status.as_u16(), reason),
+        None => status.as_u16().to_string(),
+    };
+    Err(ResponseError::OtherHttp(status, text))
+}
+
+/// Request a new authentication nonce for the given file.
+pub fn request_nonce(client: &Client, file: &RemoteFile) -> Result<Vec<u8>, NonceError> {
+    let response = client
+        .get(UrlBuilder::download(file, false))
+        .send()
+        .map_err(|_| NonceError::Request)?;
+    ensure_success(&response)?;
+    header_nonce(&response)
+}
+
+/// Get the new authentication nonce the server sent with the given response.
+pub fn header_nonce(response: &Response) -> Result<Vec<u8>, NonceError> {
+    let nonce = response
+        .header(HEADER_NONCE)
+        .ok_or(NonceError::NoNonceHeader)?
+        .split_terminator(' ')
+        .nth(1)
+        .ok_or(NonceError::MalformedNonce)?;
+    b64::decode(nonce).map_err(|_| NonceError::MalformedNonce)
+}
+
+/// An action to determine the API version of a Send server.
+pub struct Version {
+    /// The server host.
+    host: Url,
+}
+
+impl Version {
+    /// Construct a new version action for the given host.
+    pub fn new(host: Url) -> Self {
+        Self { host }
+    }
+
+    /// Invoke the version action.
+    ///
+    /// The version reported by the server is used, if it doesn't report one the server is probed
+    /// for endpoints specific to each version.
+    pub fn invoke(self, client: &Client) -> Result<ApiVersion, VersionError> {
+        match self.fetch_version(client) {
+            Err(VersionError::Unknown) => self.probe(client),
+            result => result,
+        }
+    }
+
+    /// Fetch the version reported by the server.
+    fn fetch_version(&self, client: &Client) -> Result<ApiVersion, VersionError> {
+        let url = self.host.join(VERSION_ENDPOINT).expect("invalid host");
+        let mut response = client.get(url).send().map_err(|_| VersionError::Request)?;
+        if ensure_success(&response).is_err() {
+            return Err(VersionError::Unknown);
+        }
+
+        response
+            .json::<VersionResponse>()
+            .map_err(|_| VersionError::Unknown)?
+            .determine_version()
+    }
+
+    /// Probe the server for endpoints specific to each version.
+    fn probe(&self, client: &Client) -> Result<ApiVersion, VersionError> {
+        #[cfg(feature = "send3")]
+        {
+            if self.exists(client, V3_PROBE_ENDPOINT) {
+                return Ok(ApiVersion::V3);
+            }
+        }
+
+        #[cfg(feature = "send2")]
+        {
+            if self.exists(client, V2_PROBE_ENDPOINT) {
+                return Ok(ApiVersion::V2);
+            }
+        }
+
+        Err(VersionError::Unknown)
+    }
+
+    /// Check whether the given endpoint exists on the server.
+    fn exists(&self, client: &Client, endpoint: &str) -> bool {
+        let url = self.host.join(endpoint).expect("invalid host");
+        client
+            .get(url)
+            .send()
+            .map(|response| response.status())
+            .map(|status| status.is_success() || status.is_redirection())
+            .unwrap_or(false)
+    }
+}
+
+/// An action to check whether a remote file exists.
+pub struct Exists<'a> {
+    /// The remote file to check.
+    file: &'a RemoteFile,
+}
+
+impl<'a> Exists<'a> {
+    /// Construct a new exists action for the given file.
+    pub fn new(file: &'a RemoteFile) -> Self {
+        Self { file }
+    }
+
+    /// Invoke the exists action.
+    pub fn invoke(self, client: &Client) -> Result<ExistsResponse, ExistsError> {
+        let mut response = client
+            .get(UrlBuilder::api_exists(self.file))
+            .send()
+            .map_err(|_| ExistsError::Request)?;
+        match ensure_success(&response) {
+            Ok(()) => {}
+            Err(ResponseError::Expired) => return Ok(ExistsResponse::new(false, false)),
+            Err(err) => return Err(ExistsError::Response(err)),
+        }
+
+        let mut response = response
+            .json::<ExistsResponse>()
+            .map_err(|_| ExistsError::Malformed)?;
+        response.set_exists(true);
+        Ok(response)
+    }
+}
+
+/// An action to fetch the metadata of a remote file.
+pub struct Metadata<'a> {
+    /// The remote file to fetch the metadata for.
+    file: &'a RemoteFile,
+
+    /// An optional password to decrypt a protected file.
+    password: Option<String>,
+
+    /// Whether to check whether the file exists first.
+    check_exists: bool,
+}
+
+impl<'a> Metadata<'a> {
+    /// Construct a new metadata action for the given file.
+    pub fn new(file: &'a RemoteFile, password: Option<String>, check_exists: bool) -> Self {
+        Self {
+            file,
+            password,
+            check_exists,
+        }
+    }
+
+    /// Invoke the metadata action.
+    pub fn invoke(self, client: &Client) -> Result<MetadataResponse, MetadataError> {
+        // Make sure the file exists, and whether a password is required
+        if self.check_exists {
+            let exists = Exists::new(self.file).invoke(client)?;
+            if !exists.exists() {
+                return Err(MetadataError::Expired);
+            }
+            if self.password.is_none() && exists.requires_password() {
+                return Err(MetadataError::PasswordRequired);
+            }
+        }
+
+        let key = KeySet::from(self.file, self.password.as_ref());
+        let nonce = request_nonce(client, self.file)?;
+        Ok(self.fetch_metadata(client, &key, &nonce)?)
+    }
+
+    /// Fetch and decrypt the metadata, authenticated with a signature of the given nonce.
+    fn fetch_metadata(
+        &self,
+        client: &Client,
+        key: &KeySet,
+        nonce: &[u8],
+    ) -> Result<MetadataResponse, MetaError> {
+        let sig = signature_encoded(key.auth_key().unwrap(), nonce)
+            .map_err(|_| MetaError::ComputeSignature)?;
+        let mut response = client
+            .get(UrlBuilder::api_metadata(self.file))
+            .header("Authorization", format!("send-v1 {}", sig))
+            .send()
+            .map_err(|_| MetaError::NonceRequest)?;
+        ensure_success(&response).map_err(MetaError::NonceResponse)?;
+
+        let nonce = header_nonce(&response).map_err(MetaError::Nonce)?;
+        let raw = response
+            .json::<RawMetadataResponse>()
+            .map_err(|_| MetaError::Malformed)?;
+        MetadataResponse::from(&raw, key, nonce).map_err(|_| MetaError::Decrypt)
+    }
+}
+
+/// An action to fetch the download count and expiry of a remote file, which must be owned.
+pub struct Info<'a> {
+    /// The remote file to fetch the info for.
+    file: &'a RemoteFile,
+
+    /// The authentication nonce, fetched if empty.
+    nonce: Vec<u8>,
+}
+
+impl<'a> Info<'a> {
+    /// Construct a new info action for the given file.
+    pub fn new(file: &'a RemoteFile, nonce: Option<Vec<u8>>) -> Self {
+        Self {
+            file,
+            nonce: nonce.unwrap_or_default(),
+        }
+    }
+
+    /// Invoke the info action.
+    pub fn invoke(mut self, client: &Client) -> Result<InfoResponse, InfoActionError> {
+        if self.nonce.is_empty() {
+            self.nonce = request_nonce(client, self.file)?;
+        }
+        let data = OwnedData::from(InfoData::new(), self.file)
+            .map_err(|err| -> InfoPrepareError { err.into() })?;
+
+        let mut response = client
+            .post(UrlBuilder::api_info(self.file))
+            .json(&data)
+            .send()
+            .map_err(|_| InfoError::Request)?;
+        ensure_success(&response)?;
+        response
+            .json()
+            .map_err(|_| InfoError::Response(ResponseError::Undefined).into())
+    }
+}
+
+/// An action to change the parameters of a remote file, which must be owned.
+pub struct Params<'a> {
+    /// The remote file to change the parameters of.
+    file: &'a RemoteFile,
+
+    /// The parameters to set.
+    params: ParamsData,
+
+    /// The authentication nonce, fetched if empty.
+    nonce: Vec<u8>,
+}
+
+impl<'a> Params<'a> {
+    /// Construct a new parameters action for the given file.
+    pub fn new(file: &'a RemoteFile, params: ParamsData, nonce: Option<Vec<u8>>) -> Self {
+        Self {
+            file,
+            params,
+            nonce: nonce.unwrap_or_default(),
+        }
+    }
+
+    /// Invoke the parameters action.
+    pub fn invoke(mut self, client: &Client) -> Result<(), ParamsError> {
+        if self.nonce.is_empty() {
+            self.nonce = request_nonce(client, self.file)?;
+        }
+        let data = OwnedData::from(self.params.clone(), self.file)
+            .map_err(|err| -> ParamsPrepareError { err.into() })?;
+
+        let response = client
+            .post(UrlBuilder::api_params(self.file))
+            .json(&data)
+            .send()
+            .map_err(|_| ParamsChangeError::Request)?;
+        ensure_success(&response).map_err(|err| err.into())
+    }
+}
+
+/// An action to change the password of a remote file, which must be owned.
+pub struct Password<'a> {
+    /// The remote file to change the password of.
+    file: &'a RemoteFile,
+
+    /// The new password.
+    password: &'a str,
+
+    /// The authentication nonce, fetched if empty.
+    nonce: Vec<u8>,
+}
+
+impl<'a> Password<'a> {
+    /// Construct a new password action for the given file.
+    pub fn new(file: &'a RemoteFile, password: &'a str, nonce: Option<Vec<u8>>) -> Self {
+        Self {
+            file,
+            password,
+            nonce: nonce.unwrap_or_default(),
+        }
+    }
+
+    /// Invoke the password action.
+    pub fn invoke(mut self, client: &Client) -> Result<(), PasswordError> {
+        if self.nonce.is_empty() {
+            self.nonce = request_nonce(client, self.file)?;
+        }
+
+        // Derive the authentication key from the new password
+        let mut key = KeySet::from(self.file, None);
+        key.derive_auth_password(self.password, &UrlBuilder::download(self.file, true));
+        let data = OwnedData::from(
+            PasswordData {
+                auth: key.auth_key_encoded().unwrap(),
+            },
+            self.file,
+        )
+        .map_err(|err| -> PasswordPrepareError { err.into() })?;
+
+        let response = client
+            .post(UrlBuilder::api_password(self.file))
+            .json(&data)
+            .send()
+            .map_err(|_| PasswordChangeError::Request)?;
+        ensure_success(&response).map_err(|err| err.into())
+    }
+}
+
+/// The data to send to change the password of a file.
+#[derive(Debug, Serialize)]
+struct PasswordData {
+    /// The authentication key derived from the new password, encoded as base64.
+    auth: String,
+}
+
+/// An action to delete a remote file, which must be owned.
+pub struct Delete<'a> {
+    /// The remote file to delete.
+    file: &'a RemoteFile,
+
+    /// The authentication nonce, fetched if empty.
+    nonce: Vec<u8>,
+}
+
+impl<'a> Delete<'a> {
+    /// Construct a new delete action for the given file.
+    pub fn new(file: &'a RemoteFile, nonce: Option<Vec<u8>>) -> Self {
+        Self {
+            file,
+            nonce: nonce.unwrap_or_default(),
+        }
+    }
+
+    /// Invoke the delete action.
+    pub fn invoke(mut self, client: &Client) -> Result<(), DeleteActionError> {
+        if self.nonce.is_empty() {
+            self.nonce = request_nonce(client, self.file)?;
+        }
+        let data = OwnedData::from(DeleteData::new(), self.file)
+            .map_err(|err| DeletePrepareError::DeleteData(DeleteDataError::Owned(err)))?;
+
+        let response = client
+            .post(UrlBuilder::api_delete(self.file))
+            .json(&data)
+            .send()
+            .map_err(|_| DeleteError::Request)?;
+        ensure_success(&response).map_err(|err| err.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+
+    use ffsend_api::action::exists::Error as ExistsError;
+    use ffsend_api::action::metadata::RequestError;
+
+    use super::*;
+    use crate::client::ClientConfig;
+
+    /// The ID of the remote file used in tests.
+    const FILE_ID: &str = "0123456789abcdef";
+
+    /// The secret of the remote file used in tests, encoded as base64.
+    const FILE_SECRET: &str = "AAECAwQFBgcICQoLDA0ODw";
+
+    /// Build a raw HTTP response with the given status, extra headers and body.
+    fn response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+            status,
+            body.len(),
+            headers,
+            body,
+        )
+    }
+
+    /// Serve the given raw responses on a local port, one for each connection in order.
+    ///
+    /// The host URL is returned, along with a receiver for the head of each request.
+    fn serve(responses: Vec<String>) -> (Url, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let (sender, receiver) = channel();
+
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut head = Vec::new();
+                let mut byte = [0; 1];
+                while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() > 0 {
+                    head.push(byte[0]);
+                }
+                let _ = sender.send(String::from_utf8(head).unwrap());
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (host, receiver)
+    }
+
+    fn client() -> Client {
+        ClientConfig::default().client(false)
+    }
+
+    fn remote_file(host: &Url) -> RemoteFile {
+        let url = host
+            .join(&format!("download/{}/#{}", FILE_ID, FILE_SECRET))
+            .unwrap();
+        RemoteFile::parse_url(url, None).unwrap()
+    }
+
+    #[test]
+    fn status_mapping() {
+        let (host, _) = serve(vec![
+            response("200 OK", "", ""),
+            response("404 Not Found", "", ""),
+            response("401 Unauthorized", "", ""),
+            response("500 Internal Server Error", "", ""),
+        ]);
+        let client = client();
+        let status = || ensure_success(&client.get(host.clone()).send().unwrap());
+
+        assert!(status().is_ok());
+        match status() {
+            Err(ResponseError::Expired) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match status() {
+            Err(ResponseError::Unauthorized) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match status() {
+            Err(ResponseError::OtherHttp(status, text)) => {
+                assert_eq!(status.as_u16(), 500);
+                assert_eq!(text, "500 Internal Server Error");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn nonce_header() {
+        let (host, _) = serve(vec![
+            response("200 OK", "WWW-Authenticate: send-v1 AQID\r\n", ""),
+            response("200 OK", "", ""),
+            response("200 OK", "WWW-Authenticate: send-v1\r\n", ""),
+            response("200 OK", "WWW-Authenticate: send-v1 !!!\r\n", ""),
+        ]);
+        let client = client();
+        let nonce = || header_nonce(&client.get(host.clone()).send().unwrap());
+
+        assert_eq!(nonce().unwrap(), vec![1, 2, 3]);
+        match nonce() {
+            Err(NonceError::NoNonceHeader) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        for _ in 0..2 {
+            match nonce() {
+                Err(NonceError::MalformedNonce) => {}
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn request_nonce_expired() {
+        let (host, requests) = serve(vec![response("404 Not Found", "", "")]);
+        let file = remote_file(&host);
+
+        match request_nonce(&client(), &file) {
+            Err(NonceError::Expired) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with(&format!("GET /download/{}/ ", FILE_ID)));
+    }
+
+    #[test]
+    fn exists() {
+        let (host, requests) = serve(vec![
+            response("200 OK", "", "{\"requiresPassword\":true}"),
+            response("404 Not Found", "", ""),
+            response("500 Internal Server Error", "", ""),
+            response("200 OK", "", "not json"),
+        ]);
+        let file = remote_file(&host);
+        let client = client();
+
+        let response = Exists::new(&file).invoke(&client).unwrap();
+        assert!(response.exists());
+        assert!(response.requires_password());
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with(&format!("GET /api/exists/{} ", FILE_ID)));
+
+        let response = Exists::new(&file).invoke(&client).unwrap();
+        assert!(!response.exists());
+        match Exists::new(&file).invoke(&client) {
+            Err(ExistsError::Response(ResponseError::OtherHttp(..))) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match Exists::new(&file).invoke(&client) {
+            Err(ExistsError::Malformed) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[cfg(feature = "send3")]
+    #[test]
+    fn version() {
+        let (host, requests) = serve(vec![
+            response("200 OK", "", "{\"version\":\"v3.0.21\"}"),
+            response("404 Not Found", "", ""),
+            response("200 OK", "", ""),
+        ]);
+        let client = client();
+
+        // The version reported by the server is used
+        assert_eq!(
+            Version::new(host.clone()).invoke(&client).unwrap(),
+            ApiVersion::V3
+        );
+        assert!(requests.recv().unwrap().starts_with("GET /__version__ "));
+
+        // Without a reported version, the server is probed
+        assert_eq!(
+            Version::new(host.clone()).invoke(&client).unwrap(),
+            ApiVersion::V3
+        );
+        assert!(requests.recv().unwrap().starts_with("GET /__version__ "));
+        assert!(requests
+            .recv()
+            .unwrap()
+            .starts_with("GET /app.webmanifest "));
+    }
+
+    #[test]
+    fn metadata_signs_nonce() {
+        let (host, requests) = serve(vec![
+            response("200 OK", "WWW-Authenticate: send-v1 AQID\r\n", ""),
+            response("200 OK", "WWW-Authenticate: send-v1 BAUG\r\n", "{}"),
+            response("200 OK", "WWW-Authenticate: send-v1 AQID\r\n", ""),
+            response("401 Unauthorized", "", ""),
+        ]);
+        let file = remote_file(&host);
+        let client = client();
+
+        // The authorization signs the nonce with the authentication key of the file
+        match Metadata::new(&file, None, false).invoke(&client) {
+            Err(MetadataError::Request(RequestError::Meta(MetaError::Malformed))) => {}
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+        let key = KeySet::from(&file, None);
+        let sig = signature_encoded(key.auth_key().unwrap(), &[1, 2, 3]).unwrap();
+        assert!(requests
+            .recv()
+            .unwrap()
+            .starts_with(&format!("GET /download/{}/ ", FILE_ID)));
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with(&format!("GET /api/metadata/{} ", FILE_ID)));
+        assert!(request.contains(&format!("Authorization: send-v1 {}\r\n", sig)));
+
+        match Metadata::new(&file, None, false).invoke(&client) {
+            Err(MetadataError::Request(RequestError::Meta(MetaError::NonceResponse(
+                ResponseError::Unauthorized,
+            )))) => {}
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn redirect_strips_authorization() {
+        let (other, other_requests) = serve(vec![response("200 OK", "", "")]);
+        let (host, requests) = serve(vec![
+            response("302 Found", "Location: /moved\r\n", ""),
+            response("302 Found", &format!("Location: {}\r\n", other), ""),
+        ]);
+
+        let response = client()
+            .get(host.clone())
+            .header("Authorization", "secret")
+            .send()
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.url(), &other);
+
+        // Authorization is only sent along to the same host
+        assert!(requests.recv().unwrap().contains("Authorization: secret"));
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("GET /moved "));
+        assert!(request.contains("Authorization: secret"));
+        assert!(!other_requests.recv().unwrap().contains("Authorization"));
+    }
+}
@@ -1,25 +1,399 @@
-use std::io::{Error as IoError, Read};
-use std::path::Path;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor, Error as IoError, ErrorKind as IoErrorKind, Read, Seek};
+use std::path::{Component, Path, PathBuf};
 
-use super::tar::Archive as TarArchive;
+use super::flate2::read::GzDecoder;
+use super::format::{ArchiveFormat, DETECT_LEN};
+use super::tar::{Archive as TarArchive, EntryType};
+use super::zip::ZipArchive;
+use super::zstd;
 
 pub type Result<T> = ::std::result::Result<T, IoError>;
 
-pub struct Archive<R: Read> {
-    /// The tar archive
-    inner: TarArchive<R>,
+pub struct Archive<R: Read + Seek> {
+    /// The archive reader.
+    reader: R,
+
+    /// The detected archive format.
+    format: ArchiveFormat,
 }
 
-impl<R: Read> Archive<R> {
+impl<R: Read + Seek> Archive<R> {
     /// Construct a new archive extractor.
-    pub fn new(reader: R) -> Archive<R> {
-        Archive {
-            inner: TarArchive::new(reader),
-        }
+    ///
+    /// The archive format is detected from the magic bytes the archive starts with, falling back
+    /// to the extension of the given file `name`, and to a plain tar archive.
+    /// An error is returned if the archive header can't be read.
+    pub fn new(mut reader: R, name: Option<&str>) -> Result<Archive<R>> {
+        let format = ArchiveFormat::detect(&mut reader)?
+            .or_else(|| name.and_then(ArchiveFormat::from_name))
+            .unwrap_or(ArchiveFormat::Tar);
+        Ok(Archive { reader, format })
     }
 
     /// Extract the archive to the given destination.
-    pub fn extract<P: AsRef<Path>>(&mut self, destination: P) -> Result<()> {
-        self.inner.unpack(destination)
+    ///
+    /// Each entry is validated before it is written. Entries with an absolute path or a path
+    /// outside the destination, links with an absolute target or a target with parent directory
+    /// components, and device nodes are skipped. If an entry already exists in the destination,
+    /// `overwrite` is called with its path to decide whether to replace it, the entry is skipped
+    /// otherwise.
+    ///
+    /// A summary of the extracted and skipped entries is returned.
+    pub fn extract<P, F>(self, destination: P, mut overwrite: F) -> Result<Summary>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&Path) -> bool,
+    {
+        let destination = destination.as_ref();
+        match self.format {
+            ArchiveFormat::Tar => extract_tar(self.reader, destination, &mut overwrite),
+            ArchiveFormat::TarGz => {
+                extract_tar(GzDecoder::new(self.reader), destination, &mut overwrite)
+            }
+            ArchiveFormat::TarZst => {
+                extract_tar(zstd::decode(self.reader)?, destination, &mut overwrite)
+            }
+            ArchiveFormat::Zip => ZipArchive::new(self.reader).unpack(destination, &mut overwrite),
+        }
+    }
+}
+
+/// Extract the archive from the given reader to the given destination, while it is being read.
+///
+/// This works like `Archive::extract`, but doesn't require seeking so the archive may be streamed
+/// from a download. Zip archives can't be extracted this way, and return an error. Tar.zst
+/// archives are decompressed to a temporary file first.
+/// All trailing data is read from the reader after extracting, so it is fully consumed.
+pub fn extract_stream<R, P, F>(
+    mut reader: R,
+    name: Option<&str>,
+    destination: P,
+    mut overwrite: F,
+) -> Result<Summary>
+where
+    R: Read,
+    P: AsRef<Path>,
+    F: FnMut(&Path) -> bool,
+{
+    // Peek at the header to detect the format, and chain it back in front of the reader
+    let mut header = Vec::with_capacity(DETECT_LEN);
+    reader
+        .by_ref()
+        .take(DETECT_LEN as u64)
+        .read_to_end(&mut header)?;
+    let format = ArchiveFormat::detect_header(&header)
+        .or_else(|| name.and_then(ArchiveFormat::from_name))
+        .unwrap_or(ArchiveFormat::Tar);
+    let mut reader = Cursor::new(header).chain(reader);
+
+    let destination = destination.as_ref();
+    let summary = match format {
+        ArchiveFormat::Tar => extract_tar(&mut reader, destination, &mut overwrite)?,
+        ArchiveFormat::TarGz => {
+            extract_tar(GzDecoder::new(&mut reader), destination, &mut overwrite)?
+        }
+        ArchiveFormat::TarZst => {
+            extract_tar(zstd::decode(&mut reader)?, destination, &mut overwrite)?
+        }
+        ArchiveFormat::Zip => {
+            return Err(IoError::new(
+                IoErrorKind::InvalidInput,
+                "zip archives can't be extracted while streaming",
+            ))
+        }
+    };
+
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(summary)
+}
+
+/// Extract the tar archive from the given reader to the given destination, validating each entry.
+fn extract_tar<R: Read>(
+    reader: R,
+    destination: &Path,
+    overwrite: &mut dyn FnMut(&Path) -> bool,
+) -> Result<Summary> {
+    let mut summary = Summary::default();
+    fs::create_dir_all(destination)?;
+
+    let mut archive = TarArchive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        // Validate the entry path and type
+        let rel_path = match safe_path(&path) {
+            Some(rel_path) => rel_path,
+            None => {
+                summary.skip(path, SkipReason::UnsafePath);
+                continue;
+            }
+        };
+        if rel_path.as_os_str().is_empty() {
+            continue;
+        }
+        let kind = entry.header().entry_type();
+        match kind {
+            EntryType::Regular
+            | EntryType::Continuous
+            | EntryType::GNUSparse
+            | EntryType::Directory => {}
+            EntryType::Symlink | EntryType::Link => {
+                // Link targets must be relative without parent directory components, these can't
+                // be checked lexically as a chain of links may resolve outside the destination
+                let safe = entry.link_name()?.map_or(false, |target| {
+                    !target.as_os_str().is_empty() && safe_path(&target).is_some()
+                });
+                if !safe {
+                    summary.skip(path, SkipReason::UnsafeLink);
+                    continue;
+                }
+            }
+            EntryType::Char | EntryType::Block | EntryType::Fifo => {
+                summary.skip(path, SkipReason::Device);
+                continue;
+            }
+            _ => {
+                summary.skip(path, SkipReason::Unsupported);
+                continue;
+            }
+        }
+
+        // Don't write through existing links that resolve outside the destination
+        if !inside_destination(destination, &rel_path)? {
+            summary.skip(path, SkipReason::UnsafePath);
+            continue;
+        }
+
+        // Ask whether to overwrite existing entries, directories are merged
+        let target = destination.join(&rel_path);
+        if let Ok(metadata) = target.symlink_metadata() {
+            if !(kind == EntryType::Directory && metadata.is_dir()) {
+                if !overwrite(&target) {
+                    summary.skip(path, SkipReason::Exists);
+                    continue;
+                }
+                if metadata.is_dir() {
+                    fs::remove_dir_all(&target)?;
+                } else {
+                    fs::remove_file(&target)?;
+                }
+            }
+        }
+
+        if entry.unpack_in(destination)? {
+            summary.extracted += 1;
+        } else {
+            summary.skip(path, SkipReason::UnsafePath);
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Get the given archive entry path as safe relative path.
+///
+/// `None` is returned if the path is absolute, or if it has parent directory components.
+/// Current directory components are removed.
+pub fn safe_path(path: &Path) -> Option<PathBuf> {
+    let mut safe = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => safe.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(safe)
+}
+
+/// Check whether the given safe relative path stays inside the destination directory on disk.
+///
+/// Existing parent directories of the path may be links, the deepest existing parent must resolve
+/// inside the destination. The path itself isn't resolved, as an existing entry is replaced.
+pub fn inside_destination(destination: &Path, rel_path: &Path) -> Result<bool> {
+    let root = destination.canonicalize()?;
+    let mut parent = rel_path.parent();
+    while let Some(path) = parent {
+        let path_dst = destination.join(path);
+        if path_dst.symlink_metadata().is_ok() {
+            return Ok(path_dst
+                .canonicalize()
+                .map(|path| path.starts_with(&root))
+                .unwrap_or(false));
+        }
+        parent = path.parent();
+    }
+    Ok(true)
+}
+
+/// A summary of an extracted archive.
+#[derive(Debug, Default)]
+pub struct Summary {
+    /// The number of extracted entries.
+    pub extracted: usize,
+
+    /// The skipped entries, with their path in the archive and the reason they were skipped.
+    pub skipped: Vec<(PathBuf, SkipReason)>,
+}
+
+impl Summary {
+    /// Register the entry at the given path as skipped.
+    pub fn skip(&mut self, path: PathBuf, reason: SkipReason) {
+        self.skipped.push((path, reason));
+    }
+}
+
+/// The reason an archive entry was skipped when extracting.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The entry path is absolute, or points outside the destination.
+    UnsafePath,
+
+    /// The entry is a link that is absolute or has parent directory components, so it may
+    /// point outside the destination.
+    UnsafeLink,
+
+    /// The entry is a device node or pipe.
+    Device,
+
+    /// The entry type is not supported.
+    Unsupported,
+
+    /// The entry already exists, and it was chosen not to overwrite it.
+    Exists,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            SkipReason::UnsafePath => "path points outside the destination",
+            SkipReason::UnsafeLink => "link may point outside the destination",
+            SkipReason::Device => "device nodes are not extracted",
+            SkipReason::Unsupported => "unsupported entry type",
+            SkipReason::Exists => "already exists",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::super::tar::{Builder as TarBuilder, Header};
+    use super::*;
+
+    /// Append an entry of the given type to the archive, with the given link target or data.
+    fn append(builder: &mut TarBuilder<Vec<u8>>, kind: EntryType, path: &str, link: &str) {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_path(path).unwrap();
+        header.set_mode(0o755);
+        let data = match kind {
+            EntryType::Regular => link.as_bytes(),
+            EntryType::Symlink | EntryType::Link => {
+                header.set_link_name(link).unwrap();
+                &[]
+            }
+            _ => &[],
+        };
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+
+    #[test]
+    fn extract_links() {
+        let mut builder = TarBuilder::new(Vec::new());
+        append(&mut builder, EntryType::Directory, "dir", "");
+        append(&mut builder, EntryType::Regular, "dir/file.txt", "data");
+        append(&mut builder, EntryType::Symlink, "dir/link", "file.txt");
+        append(&mut builder, EntryType::Link, "hard", "dir/file.txt");
+        let data = builder.into_inner().unwrap();
+
+        let dir = tempdir().unwrap();
+        let summary = extract_tar(Cursor::new(data), dir.path(), &mut |_| false).unwrap();
+        assert!(summary.skipped.is_empty());
+        assert_eq!(summary.extracted, 4);
+        assert_eq!(fs::read(dir.path().join("dir/link")).unwrap(), b"data");
+        assert_eq!(fs::read(dir.path().join("hard")).unwrap(), b"data");
+    }
+
+    #[test]
+    fn extract_malicious_links() {
+        // Each link stays inside lexically, but `a/b/c` resolves to the parent of the destination
+        // on disk, through the `a/b` link
+        let mut builder = TarBuilder::new(Vec::new());
+        append(&mut builder, EntryType::Directory, "a", "");
+        append(&mut builder, EntryType::Symlink, "a/b", "..");
+        append(&mut builder, EntryType::Symlink, "a/b/c", "..");
+        append(&mut builder, EntryType::Symlink, "d", ".");
+        append(&mut builder, EntryType::Symlink, "e", "d/..");
+        append(&mut builder, EntryType::Symlink, "abs", "/etc/passwd");
+        append(&mut builder, EntryType::Link, "hard", "../secret");
+        append(&mut builder, EntryType::Regular, "a/b/c/escape", "data");
+        let data = builder.into_inner().unwrap();
+
+        let root = tempdir().unwrap();
+        let dest = root.path().join("dest");
+        let summary = extract_tar(Cursor::new(data), &dest, &mut |_| false).unwrap();
+        let unsafe_links: Vec<_> = summary
+            .skipped
+            .iter()
+            .filter(|(_, reason)| *reason == SkipReason::UnsafeLink)
+            .map(|(path, _)| path.to_str().unwrap())
+            .collect();
+        assert_eq!(unsafe_links, ["a/b", "a/b/c", "e", "abs", "hard"]);
+        assert!(!root.path().join("escape").exists());
+        assert!(!root.path().join("c").exists());
+        assert_eq!(fs::read(dest.join("a/b/c/escape")).unwrap(), b"data");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn extract_through_existing_link() {
+        use std::os::unix::fs::symlink;
+
+        // Don't write through a link that points outside the destination
+        let root = tempdir().unwrap();
+        let dest = root.path().join("dest");
+        fs::create_dir_all(&dest).unwrap();
+        symlink("..", dest.join("up")).unwrap();
+
+        let mut builder = TarBuilder::new(Vec::new());
+        append(&mut builder, EntryType::Regular, "up/escape", "data");
+        let data = builder.into_inner().unwrap();
+
+        let summary = extract_tar(Cursor::new(data), &dest, &mut |_| false).unwrap_or_default();
+        assert_eq!(summary.extracted, 0);
+        assert!(!root.path().join("escape").exists());
+    }
+
+    #[test]
+    fn extract_tar_zst() {
+        // Skip if the zstd command isn't available
+        let mut encoder = match zstd::Encoder::new(Vec::new()) {
+            Ok(encoder) => encoder,
+            Err(_) => return,
+        };
+        let mut builder = TarBuilder::new(Vec::new());
+        append(&mut builder, EntryType::Directory, "dir", "");
+        append(&mut builder, EntryType::Regular, "dir/file.txt", "data");
+        io::copy(&mut &builder.into_inner().unwrap()[..], &mut encoder).unwrap();
+        let data = encoder.finish().unwrap();
+
+        let dir = tempdir().unwrap();
+        let archive = Archive::new(Cursor::new(data.clone()), None).unwrap();
+        assert_eq!(archive.format, ArchiveFormat::TarZst);
+        assert_eq!(archive.extract(dir.path(), |_| false).unwrap().extracted, 2);
+        assert_eq!(fs::read(dir.path().join("dir/file.txt")).unwrap(), b"data");
+
+        let dir = tempdir().unwrap();
+        let summary = extract_stream(&data[..], None, dir.path(), |_| false).unwrap();
+        assert_eq!(summary.extracted, 2);
+        assert_eq!(fs::read(dir.path().join("dir/file.txt")).unwrap(), b"data");
     }
 }
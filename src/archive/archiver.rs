@@ -1,28 +1,78 @@
-use std::fs::File;
-use std::io::{Error as IoError, Write};
-use std::path::Path;
+use std::cmp::min;
+use std::fs::{self, File};
+use std::io::{BufWriter, Error as IoError, ErrorKind as IoErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
 
+use super::flate2::write::GzEncoder;
+use super::flate2::Compression;
+use super::format::ArchiveFormat;
 use super::tar::Builder as TarBuilder;
+use super::zip::ZipWriter;
+use super::zstd::Encoder as ZstdEncoder;
+use crate::util::glob_match;
 
 pub type Result<T> = ::std::result::Result<T, IoError>;
 
+/// The size of chunks of archive data sent to an archive reader, in bytes.
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The maximum number of chunks buffered for an archive reader.
+const STREAM_CHANNEL_SIZE: usize = 16;
+
+/// The name of the gitignore file.
+const GITIGNORE_FILE: &str = ".gitignore";
+
+/// The name of the git directory, always excluded when gitignore files are used.
+const GIT_DIR: &str = ".git";
+
 pub struct Archiver<W: Write> {
-    /// The tar builder.
-    inner: TarBuilder<W>,
+    /// The archive builder for the selected format.
+    inner: Inner<W>,
+
+    /// The filter for entries to exclude when walking directories.
+    filter: Filter,
+}
+
+/// An archive builder for a specific format.
+enum Inner<W: Write> {
+    /// A tar builder.
+    Tar(TarBuilder<W>),
+
+    /// A tar builder, writing through a gzip encoder.
+    TarGz(TarBuilder<GzEncoder<W>>),
+
+    /// A tar builder, writing through a zstd encoder.
+    TarZst(TarBuilder<ZstdEncoder<W>>),
+
+    /// A zip writer.
+    Zip(ZipWriter<W>),
 }
 
 impl<W: Write> Archiver<W> {
-    /// Construct a new archive builder.
-    pub fn new(writer: W) -> Archiver<W> {
-        Archiver {
-            inner: TarBuilder::new(writer),
-        }
+    /// Construct a new archive builder, for the given archive format.
+    ///
+    /// An error is returned if the compressor for the format can't be started.
+    pub fn new(writer: W, format: ArchiveFormat, filter: Filter) -> Result<Archiver<W>> {
+        Ok(Archiver {
+            filter,
+            inner: match format {
+                ArchiveFormat::Tar => Inner::Tar(TarBuilder::new(writer)),
+                ArchiveFormat::TarGz => Inner::TarGz(TarBuilder::new(GzEncoder::new(
+                    writer,
+                    Compression::default(),
+                ))),
+                ArchiveFormat::TarZst => Inner::TarZst(TarBuilder::new(ZstdEncoder::new(writer)?)),
+                ArchiveFormat::Zip => Inner::Zip(ZipWriter::new(writer)),
+            },
+        })
     }
 
     /// Add the entry at the given `src` path, to the given relative `path` in the archive.
     ///
     /// If a directory path is given, the whole directory including it's contents is added to the
-    /// archive.
+    /// archive, skipping entries excluded by the filter.
     ///
     /// If no entry exists at the given `src_path`, an error is returned.
     pub fn append_path<P, Q>(&mut self, path: P, src_path: Q) -> Result<()>
@@ -30,15 +80,14 @@ impl<W: Write> Archiver<W> {
         P: AsRef<Path>,
         Q: AsRef<Path>,
     {
-        // Append the path as file or directory
-        if src_path.as_ref().is_file() {
-            self.append_file(path, &mut File::open(src_path)?)
-        } else if src_path.as_ref().is_dir() {
-            self.append_dir(path, src_path)
-        } else {
-            // TODO: return a IO NotFound error here!
-            panic!("Unable to append path to archive, not a file or directory");
+        for entry in walk(path, src_path, &self.filter)? {
+            if entry.dir {
+                self.append_dir(&entry.path, &entry.src_path)?;
+            } else {
+                self.append_file(&entry.path, &mut File::open(&entry.src_path)?)?;
+            }
         }
+        Ok(())
     }
 
     /// Append a file to the archive builder.
@@ -46,21 +95,583 @@ impl<W: Write> Archiver<W> {
     where
         P: AsRef<Path>,
     {
-        self.inner.append_file(path, file)
+        match &mut self.inner {
+            Inner::Tar(inner) => inner.append_file(path, file),
+            Inner::TarGz(inner) => inner.append_file(path, file),
+            Inner::TarZst(inner) => inner.append_file(path, file),
+            Inner::Zip(inner) => inner.append_file(path, file),
+        }
     }
 
-    /// Append a directory to the archive builder.
-    // TODO: Define a flag to add recursively or not
+    /// Append a directory entry to the archive builder, without its contents.
     pub fn append_dir<P, Q>(&mut self, path: P, src_path: Q) -> Result<()>
     where
         P: AsRef<Path>,
         Q: AsRef<Path>,
     {
-        self.inner.append_dir_all(path, src_path)
+        match &mut self.inner {
+            Inner::Tar(inner) => inner.append_dir(path, src_path),
+            Inner::TarGz(inner) => inner.append_dir(path, src_path),
+            Inner::TarZst(inner) => inner.append_dir(path, src_path),
+            Inner::Zip(inner) => inner.append_dir(path, src_path),
+        }
+    }
+
+    /// Finish the archive, writing any trailing data and flushing compressors.
+    ///
+    /// The inner writer is returned.
+    pub fn finish(self) -> Result<W> {
+        match self.inner {
+            Inner::Tar(inner) => inner.into_inner(),
+            Inner::TarGz(inner) => inner.into_inner()?.finish(),
+            Inner::TarZst(inner) => inner.into_inner()?.finish(),
+            Inner::Zip(inner) => inner.finish(),
+        }
+    }
+}
+
+/// An archive that is built on the fly while it is read, such as when uploading.
+///
+/// The size of the archive is computed up front by building it once without storing it, so the
+/// archive is built twice. Archives are deterministic, if the archived files are changed in between
+/// the archive doesn't match the computed size and reading it fails.
+#[derive(Clone, Debug)]
+pub struct ArchiveStream {
+    /// The sources to archive, as relative path in the archive and source path.
+    sources: Vec<(String, PathBuf)>,
+
+    /// The archive format.
+    format: ArchiveFormat,
+
+    /// The filter for entries to exclude when walking directories.
+    filter: Filter,
+
+    /// The size of the archive in bytes.
+    size: u64,
+}
+
+impl ArchiveStream {
+    /// Prepare an archive of the given sources, and compute its size.
+    pub fn new(
+        sources: Vec<(String, PathBuf)>,
+        format: ArchiveFormat,
+        filter: Filter,
+    ) -> Result<Self> {
+        let mut stream = Self {
+            sources,
+            format,
+            filter,
+            size: 0,
+        };
+        stream.size = stream.build(CountWriter::default())?.0;
+        Ok(stream)
+    }
+
+    /// Get the size of the archive in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Start building the archive in a background thread, and get a reader to read it from.
+    pub fn reader(&self) -> ArchiveReader {
+        let (sender, receiver) = sync_channel(STREAM_CHANNEL_SIZE);
+        let stream = self.clone();
+        thread::spawn(move || {
+            let writer = BufWriter::with_capacity(STREAM_CHUNK_SIZE, ChannelWriter(sender.clone()));
+            if let Err(err) = stream
+                .build(writer)
+                .and_then(|writer| writer.into_inner().map_err(IoError::from))
+            {
+                let _ = sender.send(Err(err));
+            }
+        });
+
+        ArchiveReader {
+            receiver,
+            chunk: Vec::new(),
+            pos: 0,
+            remaining: self.size,
+        }
+    }
+
+    /// Build the archive into the given writer.
+    fn build<W: Write>(&self, writer: W) -> Result<W> {
+        let mut archiver = Archiver::new(writer, self.format, self.filter.clone())?;
+        for (path, src_path) in &self.sources {
+            archiver.append_path(path, src_path)?;
+        }
+        archiver.finish()
+    }
+}
+
+/// A reader for an archive that is built in a background thread.
+///
+/// Reading fails if the built archive doesn't match the computed size.
+pub struct ArchiveReader {
+    /// The receiver for chunks of archive data.
+    receiver: Receiver<Result<Vec<u8>>>,
+
+    /// The current chunk, and the position in it.
+    chunk: Vec<u8>,
+    pos: usize,
+
+    /// The number of bytes that are expected to remain.
+    remaining: u64,
+}
+
+impl Read for ArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        // Receive the next chunk if the current one is consumed
+        while self.pos >= self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                Err(_) if self.remaining == 0 => return Ok(0),
+                Err(_) => return Err(changed_error()),
+            }
+        }
+
+        // Copy data from the chunk, make sure the archive doesn't exceed the computed size
+        let len = min(buf.len(), self.chunk.len() - self.pos);
+        if len as u64 > self.remaining {
+            return Err(changed_error());
+        }
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        self.remaining -= len as u64;
+        Ok(len)
     }
+}
+
+/// The error for an archive that doesn't match its computed size.
+fn changed_error() -> IoError {
+    IoError::new(
+        IoErrorKind::InvalidData,
+        "archived files changed while the archive was being built",
+    )
+}
+
+/// A writer sending chunks of written data over a channel.
+struct ChannelWriter(SyncSender<Result<Vec<u8>>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0
+            .send(Ok(buf.to_vec()))
+            .map_err(|_| IoError::new(IoErrorKind::BrokenPipe, "archive reader was closed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A writer discarding all data, counting the number of bytes written.
+#[derive(Default)]
+struct CountWriter(u64);
+
+impl Write for CountWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A filter for entries to exclude when archiving directories.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    /// Glob patterns for entries to exclude.
+    ///
+    /// A pattern is matched against the entry file name, and against its path relative to the
+    /// directory being archived.
+    excludes: Vec<String>,
+
+    /// Whether to exclude entries ignored by `.gitignore` files.
+    gitignore: bool,
+}
+
+impl Filter {
+    /// Construct a new filter.
+    pub fn new(excludes: Vec<String>, gitignore: bool) -> Self {
+        Self {
+            excludes,
+            gitignore,
+        }
+    }
+
+    /// Check whether the given exclude patterns match the given relative path.
+    fn is_excluded(&self, rel_path: &str) -> bool {
+        let name = rel_path.rsplit('/').next().unwrap_or(rel_path);
+        self.excludes
+            .iter()
+            .any(|pattern| glob_match(pattern, name) || glob_match(pattern, rel_path))
+    }
+}
+
+/// An entry to add to an archive, found by walking a path.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// The relative path in the archive.
+    pub path: PathBuf,
+
+    /// The path of the source file or directory.
+    pub src_path: PathBuf,
+
+    /// Whether this entry is a directory.
+    pub dir: bool,
 
-    // TODO: some description
-    pub fn finish(mut self) -> Result<()> {
-        self.inner.finish()
+    /// The file size in bytes, zero for directories.
+    pub size: u64,
+}
+
+/// Walk the given `src_path`, and collect the entries to add at the relative `path` in an archive.
+///
+/// Directories are walked recursively, in a stable order. Entries excluded by the given `filter`
+/// are skipped, along with the contents of excluded directories. The given `src_path` itself is
+/// never excluded.
+///
+/// If no entry exists at the given `src_path`, an error is returned.
+pub fn walk<P, Q>(path: P, src_path: Q, filter: &Filter) -> Result<Vec<Entry>>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let (path, src_path) = (path.as_ref(), src_path.as_ref());
+    let mut entries = Vec::new();
+
+    if src_path.is_file() {
+        entries.push(Entry {
+            path: path.to_path_buf(),
+            src_path: src_path.to_path_buf(),
+            dir: false,
+            size: fs::metadata(src_path)?.len(),
+        });
+    } else if src_path.is_dir() {
+        entries.push(Entry {
+            path: path.to_path_buf(),
+            src_path: src_path.to_path_buf(),
+            dir: true,
+            size: 0,
+        });
+        walk_dir(path, src_path, "", filter, &mut Vec::new(), &mut entries)?;
+    } else {
+        return Err(IoError::new(
+            IoErrorKind::NotFound,
+            format!(
+                "unable to append path to archive, not a file or directory: {}",
+                src_path.display()
+            ),
+        ));
+    }
+
+    Ok(entries)
+}
+
+/// Walk the contents of the directory at `src_path` recursively, collecting entries.
+///
+/// The `rel_path` is the path of the directory relative to the walked root, using forward
+/// slashes. The `ignores` hold the rules of all `.gitignore` files found in parent directories.
+fn walk_dir(
+    path: &Path,
+    src_path: &Path,
+    rel_path: &str,
+    filter: &Filter,
+    ignores: &mut Vec<IgnoreFile>,
+    entries: &mut Vec<Entry>,
+) -> Result<()> {
+    // Load the ignore rules for this directory
+    let ignore_file = if filter.gitignore {
+        IgnoreFile::load(src_path, rel_path)?
+    } else {
+        None
+    };
+    let pushed = ignore_file.is_some();
+    ignores.extend(ignore_file);
+
+    // Walk the directory contents, in a stable order
+    let mut children = fs::read_dir(src_path)?.collect::<Result<Vec<_>>>()?;
+    children.sort_by_key(|child| child.file_name());
+    for child in children {
+        let name = child.file_name();
+        let name = name.to_string_lossy();
+        let child_rel = if rel_path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", rel_path, name)
+        };
+        let child_src = child.path();
+        let dir = child_src.is_dir();
+
+        // Skip excluded and ignored entries
+        if filter.is_excluded(&child_rel)
+            || (filter.gitignore
+                && (name == GIT_DIR || IgnoreFile::is_ignored(ignores, &child_rel, dir)))
+        {
+            continue;
+        }
+
+        let child_path = path.join(child.file_name());
+        if dir {
+            entries.push(Entry {
+                path: child_path.clone(),
+                src_path: child_src.clone(),
+                dir: true,
+                size: 0,
+            });
+            walk_dir(
+                &child_path,
+                &child_src,
+                &child_rel,
+                filter,
+                ignores,
+                entries,
+            )?;
+        } else if child_src.is_file() {
+            entries.push(Entry {
+                path: child_path,
+                src_path: child_src,
+                size: child.metadata()?.len(),
+                dir: false,
+            });
+        }
+    }
+
+    if pushed {
+        ignores.pop();
+    }
+    Ok(())
+}
+
+/// The rules of a `.gitignore` file.
+#[derive(Debug)]
+struct IgnoreFile {
+    /// The path of the directory holding the file, relative to the walked root.
+    base: String,
+
+    /// The rules, in the order they were defined.
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreFile {
+    /// Load the `.gitignore` file in the given directory, if there is any.
+    fn load(dir: &Path, base: &str) -> Result<Option<Self>> {
+        let path = dir.join(GITIGNORE_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let rules = fs::read_to_string(path)?
+            .lines()
+            .filter_map(IgnoreRule::parse)
+            .collect();
+        Ok(Some(Self {
+            base: base.into(),
+            rules,
+        }))
+    }
+
+    /// Check whether the given relative path is ignored by any of the given ignore files.
+    ///
+    /// Later rules, and rules in deeper directories, take precedence.
+    fn is_ignored(files: &[IgnoreFile], rel_path: &str, dir: bool) -> bool {
+        let mut ignored = false;
+        for file in files {
+            // Get the path relative to the directory holding the ignore file
+            let path = if file.base.is_empty() {
+                rel_path
+            } else if rel_path.starts_with(file.base.as_str())
+                && rel_path[file.base.len()..].starts_with('/')
+            {
+                &rel_path[file.base.len() + 1..]
+            } else {
+                continue;
+            };
+
+            for rule in &file.rules {
+                if rule.matches(path, dir) {
+                    ignored = !rule.negate;
+                }
+            }
+        }
+        ignored
+    }
+}
+
+/// A single `.gitignore` rule.
+#[derive(Debug)]
+struct IgnoreRule {
+    /// The glob pattern.
+    pattern: String,
+
+    /// Whether this rule re-includes matching entries, prefixed with `!`.
+    negate: bool,
+
+    /// Whether this rule only matches directories, suffixed with `/`.
+    dir_only: bool,
+
+    /// Whether the pattern is matched against the relative path rather than the file name.
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    /// Parse a rule from a line, `None` is returned for empty lines and comments.
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        // Parse prefixes and suffixes
+        let negate = line.starts_with('!');
+        let line = if negate || line.starts_with('\\') {
+            &line[1..]
+        } else {
+            line
+        };
+        let dir_only = line.ends_with('/');
+        let line = if dir_only {
+            &line[..line.len() - 1]
+        } else {
+            line
+        };
+        let line = if line.starts_with("**/") {
+            &line[3..]
+        } else {
+            line
+        };
+        let anchored = line.contains('/');
+        let pattern = if line.starts_with('/') {
+            &line[1..]
+        } else {
+            line
+        };
+        if pattern.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            pattern: pattern.into(),
+            negate,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// Check whether this rule matches the given path, relative to the ignore file directory.
+    fn matches(&self, path: &str, dir: bool) -> bool {
+        if self.dir_only && !dir {
+            return false;
+        }
+        if self.anchored {
+            glob_match(&self.pattern, path)
+        } else {
+            glob_match(&self.pattern, path.rsplit('/').next().unwrap_or(path))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(lines: &str) -> Vec<IgnoreFile> {
+        vec![IgnoreFile {
+            base: String::new(),
+            rules: lines.lines().filter_map(IgnoreRule::parse).collect(),
+        }]
+    }
+
+    #[test]
+    fn ignore_rule_parse_valid() {
+        let rule = IgnoreRule::parse("*.log").unwrap();
+        assert_eq!(rule.pattern, "*.log");
+        assert!(!rule.negate && !rule.dir_only && !rule.anchored);
+
+        let rule = IgnoreRule::parse("!/build/  ").unwrap();
+        assert_eq!(rule.pattern, "build");
+        assert!(rule.negate && rule.dir_only && rule.anchored);
+
+        let rule = IgnoreRule::parse("**/target/").unwrap();
+        assert_eq!(rule.pattern, "target");
+        assert!(rule.dir_only && !rule.anchored);
+
+        let rule = IgnoreRule::parse("doc/*.md").unwrap();
+        assert_eq!(rule.pattern, "doc/*.md");
+        assert!(rule.anchored);
+
+        let rule = IgnoreRule::parse("\\!important").unwrap();
+        assert_eq!(rule.pattern, "!important");
+        assert!(!rule.negate);
+    }
+
+    #[test]
+    fn ignore_rule_parse_invalid() {
+        assert!(IgnoreRule::parse("").is_none());
+        assert!(IgnoreRule::parse("   ").is_none());
+        assert!(IgnoreRule::parse("# comment").is_none());
+        assert!(IgnoreRule::parse("/").is_none());
+        assert!(IgnoreRule::parse("!").is_none());
+    }
+
+    #[test]
+    fn ignore_rule_matches() {
+        let rule = IgnoreRule::parse("*.log").unwrap();
+        assert!(rule.matches("a/b/debug.log", false));
+        assert!(!rule.matches("debug.log.txt", false));
+
+        let rule = IgnoreRule::parse("build/").unwrap();
+        assert!(rule.matches("src/build", true));
+        assert!(!rule.matches("src/build", false));
+
+        let rule = IgnoreRule::parse("/doc/*.md").unwrap();
+        assert!(rule.matches("doc/readme.md", false));
+        assert!(!rule.matches("src/doc/readme.md", false));
+    }
+
+    #[test]
+    fn ignore_file_precedence() {
+        let files = rules("*.log\n!keep.log");
+        assert!(IgnoreFile::is_ignored(&files, "debug.log", false));
+        assert!(!IgnoreFile::is_ignored(&files, "keep.log", false));
+
+        let mut files = rules("*.log");
+        files.push(IgnoreFile {
+            base: "sub".into(),
+            rules: vec![IgnoreRule::parse("!*.log").unwrap()],
+        });
+        assert!(IgnoreFile::is_ignored(&files, "debug.log", false));
+        assert!(!IgnoreFile::is_ignored(&files, "sub/debug.log", false));
+        assert!(IgnoreFile::is_ignored(&files, "subdir/debug.log", false));
+    }
+
+    #[test]
+    fn stream_tar_zst() {
+        // Skip if the zstd command isn't available
+        if ZstdEncoder::new(Vec::new()).is_err() {
+            return;
+        }
+
+        // The built archive must match the size computed up front
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..20 {
+            let data: Vec<u8> = (0..10_000u32).map(|b| (b * i % 251) as u8).collect();
+            fs::write(dir.path().join(format!("{}.bin", i)), data).unwrap();
+        }
+        let stream = ArchiveStream::new(
+            vec![("dir".into(), dir.path().to_path_buf())],
+            ArchiveFormat::TarZst,
+            Filter::default(),
+        )
+        .unwrap();
+        let mut data = Vec::new();
+        stream.reader().read_to_end(&mut data).unwrap();
+        assert_eq!(data.len() as u64, stream.size());
     }
 }
@@ -0,0 +1,132 @@
+use std::fmt;
+use std::io::{Read, Result, Seek, SeekFrom};
+use std::str::FromStr;
+
+/// The names of all supported archive formats, to use as possible argument values.
+pub const ARCHIVE_FORMATS: [&str; 4] = ["tar", "tar.gz", "tar.zst", "zip"];
+
+/// The magic bytes a gzip file starts with.
+const MAGIC_GZIP: &[u8] = &[0x1f, 0x8b];
+
+/// The magic bytes a zip file starts with, for non-empty and empty archives.
+const MAGIC_ZIP: &[u8] = b"PK\x03\x04";
+const MAGIC_ZIP_EMPTY: &[u8] = b"PK\x05\x06";
+
+/// The magic bytes a zstd file starts with.
+const MAGIC_ZSTD: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The magic bytes in a tar header, and their offset.
+const MAGIC_TAR: &[u8] = b"ustar";
+const MAGIC_TAR_OFFSET: usize = 257;
+
+/// The number of bytes at the start of an archive needed to detect its format.
+///
+/// This is the end of the tar magic bytes, the length can't be taken in a constant on Rust 1.32.
+pub const DETECT_LEN: usize = MAGIC_TAR_OFFSET + 5;
+
+/// An archive format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// An uncompressed tar archive.
+    Tar,
+
+    /// A gzip compressed tar archive.
+    TarGz,
+
+    /// A zstd compressed tar archive.
+    TarZst,
+
+    /// A zip archive, compressed with deflate.
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Get the name of this format.
+    pub fn name(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    /// Get the file extension for this format, including the leading dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => ".tar",
+            ArchiveFormat::TarGz => ".tar.gz",
+            ArchiveFormat::TarZst => ".tar.zst",
+            ArchiveFormat::Zip => ".zip",
+        }
+    }
+
+    /// Determine the archive format from the extension of the given file name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// Detect the archive format from the magic bytes the given reader starts with.
+    ///
+    /// The reader is rewound to the start afterwards. `None` is returned if the format is unknown.
+    pub fn detect<R: Read + Seek>(reader: &mut R) -> Result<Option<Self>> {
+        // Read the header, rewind the reader
+        let mut header = Vec::with_capacity(DETECT_LEN);
+        reader
+            .by_ref()
+            .take(DETECT_LEN as u64)
+            .read_to_end(&mut header)?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        Ok(Self::detect_header(&header))
+    }
+
+    /// Detect the archive format from the magic bytes in the given archive header.
+    ///
+    /// The header should hold the first `DETECT_LEN` bytes of the archive, or the whole archive if
+    /// it is shorter. `None` is returned if the format is unknown.
+    pub fn detect_header(header: &[u8]) -> Option<Self> {
+        if header.starts_with(MAGIC_GZIP) {
+            Some(ArchiveFormat::TarGz)
+        } else if header.starts_with(MAGIC_ZIP) || header.starts_with(MAGIC_ZIP_EMPTY) {
+            Some(ArchiveFormat::Zip)
+        } else if header.starts_with(MAGIC_ZSTD) {
+            Some(ArchiveFormat::TarZst)
+        } else if header.get(MAGIC_TAR_OFFSET..) == Some(MAGIC_TAR) {
+            Some(ArchiveFormat::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = ();
+
+    fn from_str(format: &str) -> ::std::result::Result<Self, Self::Err> {
+        match format.trim().to_lowercase().as_str() {
+            "tar" => Ok(ArchiveFormat::Tar),
+            "tar.gz" | "tgz" | "gz" | "gzip" => Ok(ArchiveFormat::TarGz),
+            "tar.zst" | "tzst" | "zst" | "zstd" => Ok(ArchiveFormat::TarZst),
+            "zip" => Ok(ArchiveFormat::Zip),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
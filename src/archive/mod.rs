@@ -1,4 +1,8 @@
+extern crate flate2;
 extern crate tar;
 
 pub mod archive;
 pub mod archiver;
+pub mod format;
+pub mod zip;
+pub mod zstd;
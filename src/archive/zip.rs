@@ -0,0 +1,939 @@
+//! A minimal zip archive writer and extractor.
+//!
+//! Entries are compressed with deflate. The writer streams entries and uses data descriptors, so
+//! it doesn't need to seek. Zip64 extensions are used for entries, offsets and entry counts that
+//! don't fit the classic zip format.
+
+use std::fs::{self, File};
+use std::io::{self, Error as IoError, ErrorKind as IoErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Datelike, Local, Timelike};
+
+use super::archive::{inside_destination, safe_path, SkipReason, Summary};
+use super::flate2::read::DeflateDecoder;
+use super::flate2::write::DeflateEncoder;
+use super::flate2::{Compression, Crc};
+
+pub type Result<T> = ::std::result::Result<T, IoError>;
+
+/// Zip record signatures.
+const SIG_LOCAL_HEADER: u32 = 0x0403_4b50;
+const SIG_DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+const SIG_CENTRAL_HEADER: u32 = 0x0201_4b50;
+const SIG_END_OF_CENTRAL_DIR: u32 = 0x0605_4b50;
+const SIG_ZIP64_END_OF_CENTRAL_DIR: u32 = 0x0606_4b50;
+const SIG_ZIP64_END_LOCATOR: u32 = 0x0706_4b50;
+
+/// The zip version needed to extract, 2.0 for deflate and directories.
+const VERSION: u16 = 20;
+
+/// The zip version needed to extract zip64 entries and archives, 4.5.
+const VERSION_ZIP64: u16 = 45;
+
+/// The header ID of the zip64 extended information extra field.
+const EXTRA_ZIP64: u16 = 0x0001;
+
+/// The version made by, 2.0 on unix so file modes in external attributes are used.
+const VERSION_MADE_BY: u16 = (3 << 8) | VERSION;
+
+/// General purpose flags: sizes in data descriptor, and UTF-8 names.
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+const FLAG_ENCRYPTED: u16 = 1;
+
+/// Compression methods.
+const METHOD_STORE: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+/// The unix file type bits in the file mode, and the type of symbolic links.
+const MODE_TYPE_MASK: u32 = 0o170_000;
+const MODE_SYMLINK: u32 = 0o120_000;
+
+/// The size of fixed records.
+const LOCAL_HEADER_LEN: u64 = 30;
+const CENTRAL_HEADER_LEN: usize = 46;
+const END_OF_CENTRAL_DIR_LEN: usize = 22;
+const ZIP64_END_OF_CENTRAL_DIR_LEN: u64 = 56;
+const ZIP64_END_LOCATOR_LEN: usize = 20;
+
+/// The value of 32-bit fields, and the 16-bit entry count, that don't fit the classic zip format.
+/// The actual value is stored in a zip64 record instead.
+const MAX_U32: u64 = 0xffff_ffff;
+const MAX_U16: u64 = 0xffff;
+
+/// The maximum length of the comment at the end of a zip archive.
+const MAX_COMMENT_LEN: usize = 0xffff;
+
+/// A zip archive writer.
+pub struct ZipWriter<W: Write> {
+    /// The writer to write the archive to.
+    inner: CountWriter<W>,
+
+    /// The entries written, for the central directory.
+    entries: Vec<Entry>,
+
+    /// The largest value of sizes and offsets written without zip64 extensions.
+    limit: u64,
+}
+
+impl<W: Write> ZipWriter<W> {
+    /// Construct a new zip writer.
+    pub fn new(writer: W) -> Self {
+        Self {
+            inner: CountWriter::new(writer),
+            entries: Vec::new(),
+            limit: MAX_U32 - 1,
+        }
+    }
+
+    /// Append a file to the archive, at the given relative `path`.
+    ///
+    /// An error is returned if the file size changes while it is added.
+    pub fn append_file<P: AsRef<Path>>(&mut self, path: P, file: &mut File) -> Result<()> {
+        let len = file.metadata()?.len();
+        let mut entry = Entry::new(
+            entry_name(path.as_ref(), false)?,
+            file.metadata()?.modified(),
+        );
+        entry.method = METHOD_DEFLATE;
+        entry.flags |= FLAG_DATA_DESCRIPTOR;
+        entry.offset = self.inner.count;
+
+        // Use zip64 if the file may not fit, deflate may grow incompressible data a little
+        entry.zip64 = len.saturating_add(len / 1024 + 1024) > self.limit;
+        entry.write_local_header(&mut self.inner)?;
+
+        // Compress the file contents, keep track of the checksum and sizes
+        let start = self.inner.count;
+        let mut crc = Crc::new();
+        let mut size = 0;
+        {
+            let mut encoder = DeflateEncoder::new(&mut self.inner, Compression::default());
+            let mut buf = [0u8; 64 * 1024];
+            loop {
+                let read = file.read(&mut buf)?;
+                if read == 0 {
+                    break;
+                }
+                crc.update(&buf[..read]);
+                encoder.write_all(&buf[..read])?;
+                size += read as u64;
+            }
+            encoder.finish()?;
+        }
+        if size != len {
+            return Err(IoError::new(
+                IoErrorKind::UnexpectedEof,
+                "file changed while adding it to the archive",
+            ));
+        }
+        entry.crc = crc.sum();
+        entry.size = size;
+        entry.compressed_size = self.inner.count - start;
+        if !entry.zip64 && entry.compressed_size > self.limit {
+            return Err(too_large());
+        }
+
+        // Write the data descriptor holding the checksum and sizes, 64-bit sizes for zip64
+        write_u32(&mut self.inner, SIG_DATA_DESCRIPTOR)?;
+        write_u32(&mut self.inner, entry.crc)?;
+        if entry.zip64 {
+            write_u64(&mut self.inner, entry.compressed_size)?;
+            write_u64(&mut self.inner, entry.size)?;
+        } else {
+            write_u32(&mut self.inner, entry.compressed_size as u32)?;
+            write_u32(&mut self.inner, entry.size as u32)?;
+        }
+
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Append a directory entry to the archive, at the given relative `path`.
+    ///
+    /// The directory contents are not added.
+    pub fn append_dir<P, Q>(&mut self, path: P, src_path: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let mut entry = Entry::new(
+            entry_name(path.as_ref(), true)?,
+            fs::metadata(src_path)?.modified(),
+        );
+        entry.external_attributes = (0o040_755 << 16) | 0x10;
+        entry.offset = self.inner.count;
+        entry.write_local_header(&mut self.inner)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Finish the archive by writing the central directory.
+    ///
+    /// Zip64 end of central directory records are written if the entry count, or the central
+    /// directory size or offset don't fit the classic record.
+    pub fn finish(mut self) -> Result<W> {
+        let start = self.inner.count;
+        for entry in &self.entries {
+            entry.write_central_header(&mut self.inner, self.limit)?;
+        }
+        let size = self.inner.count - start;
+        let count = self.entries.len() as u64;
+
+        // Write the zip64 end of central directory record and locator if needed
+        let zip64 = count >= MAX_U16 || size > self.limit || start > self.limit;
+        if zip64 {
+            let end = self.inner.count;
+            write_u32(&mut self.inner, SIG_ZIP64_END_OF_CENTRAL_DIR)?;
+            write_u64(&mut self.inner, ZIP64_END_OF_CENTRAL_DIR_LEN - 12)?;
+            write_u16(&mut self.inner, VERSION_MADE_BY)?;
+            write_u16(&mut self.inner, VERSION_ZIP64)?;
+            write_u32(&mut self.inner, 0)?;
+            write_u32(&mut self.inner, 0)?;
+            write_u64(&mut self.inner, count)?;
+            write_u64(&mut self.inner, count)?;
+            write_u64(&mut self.inner, size)?;
+            write_u64(&mut self.inner, start)?;
+
+            write_u32(&mut self.inner, SIG_ZIP64_END_LOCATOR)?;
+            write_u32(&mut self.inner, 0)?;
+            write_u64(&mut self.inner, end)?;
+            write_u32(&mut self.inner, 1)?;
+        }
+
+        // Write the end of central directory record
+        let (count, size, start) = if zip64 {
+            (MAX_U16 as u16, MAX_U32 as u32, MAX_U32 as u32)
+        } else {
+            (count as u16, size as u32, start as u32)
+        };
+        write_u32(&mut self.inner, SIG_END_OF_CENTRAL_DIR)?;
+        write_u16(&mut self.inner, 0)?;
+        write_u16(&mut self.inner, 0)?;
+        write_u16(&mut self.inner, count)?;
+        write_u16(&mut self.inner, count)?;
+        write_u32(&mut self.inner, size)?;
+        write_u32(&mut self.inner, start)?;
+        write_u16(&mut self.inner, 0)?;
+
+        self.inner.flush()?;
+        Ok(self.inner.inner)
+    }
+}
+
+/// A zip archive extractor.
+pub struct ZipArchive<R: Read + Seek> {
+    /// The reader to read the archive from.
+    reader: R,
+}
+
+impl<R: Read + Seek> ZipArchive<R> {
+    /// Construct a new zip archive extractor.
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Extract the archive to the given destination directory.
+    ///
+    /// Entries with an absolute path or a path outside the destination, entries written through an
+    /// existing link resolving outside the destination, and symbolic links are skipped. If an entry already exists, `overwrite` is called with its path to decide whether
+    /// to replace it, the entry is skipped otherwise.
+    pub fn unpack<P: AsRef<Path>>(
+        &mut self,
+        destination: P,
+        overwrite: &mut dyn FnMut(&Path) -> bool,
+    ) -> Result<Summary> {
+        let destination = destination.as_ref();
+        let mut summary = Summary::default();
+        fs::create_dir_all(destination)?;
+
+        for entry in self.entries()? {
+            // Determine the output path, make sure it is safe
+            let name = PathBuf::from(&entry.name);
+            let path = match safe_path(&name) {
+                Some(path) if path.as_os_str().is_empty() => continue,
+                Some(ref path) if !inside_destination(destination, path)? => {
+                    summary.skip(name, SkipReason::UnsafePath);
+                    continue;
+                }
+                Some(path) => destination.join(path),
+                None => {
+                    summary.skip(name, SkipReason::UnsafePath);
+                    continue;
+                }
+            };
+            let dir = entry.name.ends_with('/');
+            if entry.version_made_by >> 8 == 3
+                && (entry.external_attributes >> 16) & MODE_TYPE_MASK == MODE_SYMLINK
+            {
+                summary.skip(name, SkipReason::Unsupported);
+                continue;
+            }
+            if entry.flags & FLAG_ENCRYPTED != 0 {
+                return Err(invalid("encrypted zip entries are not supported"));
+            }
+
+            // Ask whether to overwrite existing entries, directories are merged
+            if let Ok(metadata) = path.symlink_metadata() {
+                if dir && metadata.is_dir() {
+                    continue;
+                }
+                if !overwrite(&path) {
+                    summary.skip(name, SkipReason::Exists);
+                    continue;
+                }
+                if metadata.is_dir() {
+                    fs::remove_dir_all(&path)?;
+                } else {
+                    fs::remove_file(&path)?;
+                }
+            }
+            if dir {
+                fs::create_dir_all(&path)?;
+                summary.extracted += 1;
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            // Skip the local header, to find the entry data
+            self.reader.seek(SeekFrom::Start(entry.offset))?;
+            let mut header = [0u8; LOCAL_HEADER_LEN as usize];
+            self.reader.read_exact(&mut header)?;
+            if read_u32(&header, 0) != SIG_LOCAL_HEADER {
+                return Err(invalid("invalid zip local file header"));
+            }
+            let skip = u64::from(read_u16(&header, 26)) + u64::from(read_u16(&header, 28));
+            self.reader.seek(SeekFrom::Current(skip as i64))?;
+
+            // Decompress the entry data into the file, verify the checksum
+            let data = (&mut self.reader).take(entry.compressed_size);
+            let mut reader: Box<dyn Read> = match entry.method {
+                METHOD_STORE => Box::new(data),
+                METHOD_DEFLATE => Box::new(DeflateDecoder::new(data)),
+                _ => return Err(invalid("unsupported zip compression method")),
+            };
+            let mut file = File::create(&path)?;
+            if let Err(err) = copy_verify(&mut reader, &mut file, &entry) {
+                // Don't leave a partially written file behind
+                drop(file);
+                let _ = fs::remove_file(&path);
+                return Err(err);
+            }
+
+            // Apply unix file permissions if known
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+
+                let mode = (entry.external_attributes >> 16) & 0o777;
+                if entry.version_made_by >> 8 == 3 && mode != 0 {
+                    fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+                }
+            }
+
+            summary.extracted += 1;
+        }
+
+        Ok(summary)
+    }
+
+    /// Read all entries from the central directory.
+    fn entries(&mut self) -> Result<Vec<Entry>> {
+        // Find the end of central directory record, searching backwards from the end
+        let len = self.reader.seek(SeekFrom::End(0))?;
+        let tail_len = len.min((END_OF_CENTRAL_DIR_LEN + MAX_COMMENT_LEN) as u64);
+        let tail_start = len - tail_len;
+        self.reader.seek(SeekFrom::Start(tail_start))?;
+        let mut tail = Vec::with_capacity(tail_len as usize);
+        (&mut self.reader).take(tail_len).read_to_end(&mut tail)?;
+        let end = (0..=tail.len().saturating_sub(END_OF_CENTRAL_DIR_LEN))
+            .rev()
+            .find(|&i| read_u32(&tail, i) == SIG_END_OF_CENTRAL_DIR)
+            .ok_or_else(|| invalid("zip end of central directory not found"))?;
+        let mut count = u64::from(read_u16(&tail, end + 10));
+        let mut size = u64::from(read_u32(&tail, end + 12));
+        let mut offset = u64::from(read_u32(&tail, end + 16));
+
+        // Use the zip64 end of central directory record if any field doesn't fit
+        if count == MAX_U16 || size == MAX_U32 || offset == MAX_U32 {
+            let locator = end
+                .checked_sub(ZIP64_END_LOCATOR_LEN)
+                .filter(|&i| read_u32(&tail, i) == SIG_ZIP64_END_LOCATOR);
+            if let Some(locator) = locator {
+                self.reader
+                    .seek(SeekFrom::Start(read_u64(&tail, locator + 8)))?;
+                let mut record = [0u8; ZIP64_END_OF_CENTRAL_DIR_LEN as usize];
+                self.reader.read_exact(&mut record)?;
+                if read_u32(&record, 0) != SIG_ZIP64_END_OF_CENTRAL_DIR {
+                    return Err(invalid("invalid zip64 end of central directory"));
+                }
+                count = read_u64(&record, 32);
+                size = read_u64(&record, 40);
+                offset = read_u64(&record, 48);
+            }
+        }
+        if offset.saturating_add(size) > len {
+            return Err(invalid("invalid zip central directory"));
+        }
+
+        // Read the central directory, and parse each entry
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut dir = vec![0u8; size as usize];
+        self.reader.read_exact(&mut dir)?;
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        for _ in 0..count {
+            if dir.len() < pos + CENTRAL_HEADER_LEN || read_u32(&dir, pos) != SIG_CENTRAL_HEADER {
+                return Err(invalid("invalid zip central directory"));
+            }
+            let name_len = read_u16(&dir, pos + 28) as usize;
+            let extra_len = read_u16(&dir, pos + 30) as usize;
+            let comment_len = read_u16(&dir, pos + 32) as usize;
+            let name_start = pos + CENTRAL_HEADER_LEN;
+            let name = dir
+                .get(name_start..name_start + name_len)
+                .ok_or_else(|| invalid("invalid zip central directory"))?;
+            let extra = dir
+                .get(name_start + name_len..name_start + name_len + extra_len)
+                .ok_or_else(|| invalid("invalid zip central directory"))?;
+            let mut entry = Entry {
+                name: String::from_utf8_lossy(name).replace('\\', "/"),
+                version_made_by: read_u16(&dir, pos + 4),
+                flags: read_u16(&dir, pos + 8),
+                method: read_u16(&dir, pos + 10),
+                time: read_u16(&dir, pos + 12),
+                date: read_u16(&dir, pos + 14),
+                crc: read_u32(&dir, pos + 16),
+                compressed_size: u64::from(read_u32(&dir, pos + 20)),
+                size: u64::from(read_u32(&dir, pos + 24)),
+                external_attributes: read_u32(&dir, pos + 38),
+                offset: u64::from(read_u32(&dir, pos + 42)),
+                zip64: false,
+            };
+            entry.read_zip64_extra(extra)?;
+            entries.push(entry);
+            pos += CENTRAL_HEADER_LEN + name_len + extra_len + comment_len;
+        }
+
+        Ok(entries)
+    }
+}
+
+/// A zip archive entry.
+struct Entry {
+    /// The entry name, a relative path using forward slashes. Directories end with a slash.
+    name: String,
+    version_made_by: u16,
+    flags: u16,
+    method: u16,
+    time: u16,
+    date: u16,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    external_attributes: u32,
+
+    /// The offset of the local header in the archive.
+    offset: u64,
+
+    /// Whether the local header and data descriptor of this entry use zip64 extensions.
+    zip64: bool,
+}
+
+impl Entry {
+    /// Construct a new entry with the given name and modification time, to write.
+    fn new(name: String, modified: io::Result<SystemTime>) -> Self {
+        let (time, date) = dos_time(modified.ok());
+        Self {
+            name,
+            version_made_by: VERSION_MADE_BY,
+            flags: FLAG_UTF8,
+            method: METHOD_STORE,
+            time,
+            date,
+            crc: 0,
+            compressed_size: 0,
+            size: 0,
+            external_attributes: 0o100_644 << 16,
+            offset: 0,
+            zip64: false,
+        }
+    }
+
+    /// Read the 64-bit sizes and offset from the zip64 extra field in the given extra data, for
+    /// the fields that don't fit the central directory header.
+    fn read_zip64_extra(&mut self, mut extra: &[u8]) -> Result<()> {
+        while extra.len() >= 4 {
+            let id = read_u16(extra, 0);
+            let len = read_u16(extra, 2) as usize;
+            let data = extra
+                .get(4..4 + len)
+                .ok_or_else(|| invalid("invalid zip extra field"))?;
+            if id == EXTRA_ZIP64 {
+                let mut pos = 0;
+                for field in &mut [&mut self.size, &mut self.compressed_size, &mut self.offset] {
+                    if **field == MAX_U32 {
+                        if data.len() < pos + 8 {
+                            return Err(invalid("invalid zip64 extra field"));
+                        }
+                        **field = read_u64(data, pos);
+                        pos += 8;
+                    }
+                }
+                return Ok(());
+            }
+            extra = &extra[4 + len..];
+        }
+        Ok(())
+    }
+
+    /// Write the local header for this entry.
+    ///
+    /// When a data descriptor is used, the checksum and sizes are written after the data instead.
+    /// Zip64 entries have their sizes in a zip64 extra field.
+    fn write_local_header<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let (version, size, compressed_size, extra_len) = if self.zip64 {
+            (VERSION_ZIP64, MAX_U32 as u32, MAX_U32 as u32, 20)
+        } else {
+            (VERSION, self.size as u32, self.compressed_size as u32, 0)
+        };
+        write_u32(writer, SIG_LOCAL_HEADER)?;
+        write_u16(writer, version)?;
+        write_u16(writer, self.flags)?;
+        write_u16(writer, self.method)?;
+        write_u16(writer, self.time)?;
+        write_u16(writer, self.date)?;
+        write_u32(writer, self.crc)?;
+        write_u32(writer, compressed_size)?;
+        write_u32(writer, size)?;
+        write_u16(writer, to_u16(self.name.len())?)?;
+        write_u16(writer, extra_len)?;
+        writer.write_all(self.name.as_bytes())?;
+        if self.zip64 {
+            write_u16(writer, EXTRA_ZIP64)?;
+            write_u16(writer, 16)?;
+            write_u64(writer, self.size)?;
+            write_u64(writer, self.compressed_size)?;
+        }
+        Ok(())
+    }
+
+    /// Write the central directory header for this entry.
+    ///
+    /// Sizes and the offset above `limit` are written to a zip64 extra field instead.
+    fn write_central_header<W: Write>(&self, writer: &mut W, limit: u64) -> Result<()> {
+        // Move the values that don't fit into the zip64 extra field
+        let mut zip64 = Vec::new();
+        let mut field = |value: u64| -> u32 {
+            if value > limit {
+                zip64.push(value);
+                MAX_U32 as u32
+            } else {
+                value as u32
+            }
+        };
+        let size = field(self.size);
+        let compressed_size = field(self.compressed_size);
+        let offset = field(self.offset);
+        let version = if self.zip64 || !zip64.is_empty() {
+            VERSION_ZIP64
+        } else {
+            VERSION
+        };
+        let extra_len = if zip64.is_empty() {
+            0
+        } else {
+            4 + 8 * zip64.len() as u16
+        };
+
+        write_u32(writer, SIG_CENTRAL_HEADER)?;
+        write_u16(writer, self.version_made_by & 0xff00 | version)?;
+        write_u16(writer, version)?;
+        write_u16(writer, self.flags)?;
+        write_u16(writer, self.method)?;
+        write_u16(writer, self.time)?;
+        write_u16(writer, self.date)?;
+        write_u32(writer, self.crc)?;
+        write_u32(writer, compressed_size)?;
+        write_u32(writer, size)?;
+        write_u16(writer, to_u16(self.name.len())?)?;
+        write_u16(writer, extra_len)?;
+        write_u16(writer, 0)?;
+        write_u16(writer, 0)?;
+        write_u16(writer, 0)?;
+        write_u32(writer, self.external_attributes)?;
+        write_u32(writer, offset)?;
+        writer.write_all(self.name.as_bytes())?;
+        if !zip64.is_empty() {
+            write_u16(writer, EXTRA_ZIP64)?;
+            write_u16(writer, 8 * zip64.len() as u16)?;
+            for value in zip64 {
+                write_u64(writer, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A writer counting the number of bytes written.
+struct CountWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> CountWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<W: Write> Write for CountWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Build the zip entry name for the given relative path, using forward slashes.
+fn entry_name(path: &Path, dir: bool) -> Result<String> {
+    let mut name = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_str()),
+            _ => None,
+        })
+        .collect::<Option<Vec<&str>>>()
+        .ok_or_else(|| invalid("file name in archive is not valid UTF-8"))?
+        .join("/");
+    if dir {
+        name.push('/');
+    }
+    Ok(name)
+}
+
+/// Convert the given time into a DOS time and date, as used in zip archives.
+fn dos_time(time: Option<SystemTime>) -> (u16, u16) {
+    let time: DateTime<Local> = time.unwrap_or_else(SystemTime::now).into();
+    if time.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    (
+        ((time.hour() << 11) | (time.minute() << 5) | (time.second() / 2)) as u16,
+        ((((time.year() - 1980) as u32) << 9) | (time.month() << 5) | time.day()) as u16,
+    )
+}
+
+fn write_u16<W: Write>(writer: &mut W, value: u16) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> u16 {
+    buf.get(pos..pos + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .unwrap_or(0)
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> u32 {
+    buf.get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .unwrap_or(0)
+}
+
+fn read_u64(buf: &[u8], pos: usize) -> u64 {
+    buf.get(pos..pos + 8)
+        .map(|b| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(b);
+            u64::from_le_bytes(bytes)
+        })
+        .unwrap_or(0)
+}
+
+fn to_u16(value: usize) -> Result<u16> {
+    if value > std::u16::MAX as usize {
+        return Err(too_large());
+    }
+    Ok(value as u16)
+}
+
+fn too_large() -> IoError {
+    IoError::new(
+        IoErrorKind::InvalidInput,
+        "archive too large for the zip format",
+    )
+}
+
+/// Copy the decompressed data of the given entry into the writer, verifying its size and checksum.
+fn copy_verify<W: Write>(reader: &mut dyn Read, writer: &mut W, entry: &Entry) -> Result<()> {
+    let mut crc = Crc::new();
+    let mut size = 0;
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        crc.update(&buf[..read]);
+        writer.write_all(&buf[..read])?;
+        size += read as u64;
+    }
+    if crc.sum() != entry.crc || size != entry.size {
+        return Err(invalid("zip entry checksum mismatch"));
+    }
+    Ok(())
+}
+
+fn invalid(msg: &str) -> IoError {
+    IoError::new(IoErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::process::Command;
+
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    /// Some data that doesn't compress well, so entries span multiple deflate blocks.
+    fn data() -> Vec<u8> {
+        (0..200_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect()
+    }
+
+    /// Create a source directory with some files, and a zip archive of it at `archive.zip`.
+    ///
+    /// Sizes and offsets above `limit` are written with zip64 extensions.
+    fn create(limit: u64) -> TempDir {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/sub")).unwrap();
+        fs::write(dir.path().join("src/a.txt"), "hello").unwrap();
+        fs::write(dir.path().join("src/sub/b.bin"), data()).unwrap();
+
+        let mut writer = ZipWriter::new(File::create(dir.path().join("archive.zip")).unwrap());
+        writer.limit = limit;
+        writer
+            .append_file(
+                "a.txt",
+                &mut File::open(dir.path().join("src/a.txt")).unwrap(),
+            )
+            .unwrap();
+        writer
+            .append_dir("sub", dir.path().join("src/sub"))
+            .unwrap();
+        writer
+            .append_file(
+                "sub/b.bin",
+                &mut File::open(dir.path().join("src/sub/b.bin")).unwrap(),
+            )
+            .unwrap();
+        writer.finish().unwrap();
+        dir
+    }
+
+    /// Extract the archive at `archive.zip` to `out` and check its contents.
+    fn extract(dir: &Path) {
+        let summary = ZipArchive::new(File::open(dir.join("archive.zip")).unwrap())
+            .unpack(dir.join("out"), &mut |_| false)
+            .unwrap();
+        assert_eq!(summary.extracted, 3);
+        check(&dir.join("out"));
+    }
+
+    /// Check the contents extracted to the given directory.
+    fn check(out: &Path) {
+        assert_eq!(fs::read(out.join("a.txt")).unwrap(), b"hello");
+        assert_eq!(fs::read(out.join("sub/b.bin")).unwrap(), data());
+    }
+
+    /// Run the given command, `None` is returned if it isn't available.
+    fn run(cmd: &mut Command) -> Option<()> {
+        let output = cmd.output().ok()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Some(())
+    }
+
+    /// Extract the archive at `archive.zip` to `out` with `unzip` and check its contents.
+    fn unzip(dir: &Path) {
+        let extracted = run(Command::new("unzip")
+            .arg("-q")
+            .arg(dir.join("archive.zip"))
+            .arg("-d")
+            .arg(dir.join("out")));
+        if extracted.is_some() {
+            check(&dir.join("out"));
+        }
+    }
+
+    /// Extract the archive at `archive.zip` to `out` with python `zipfile` and check its contents.
+    fn python_extract(dir: &Path) {
+        let extracted = run(Command::new("python3")
+            .arg("-c")
+            .arg("import sys, zipfile; zipfile.ZipFile(sys.argv[1]).extractall(sys.argv[2])")
+            .arg(dir.join("archive.zip"))
+            .arg(dir.join("out")));
+        if extracted.is_some() {
+            check(&dir.join("out"));
+        }
+    }
+
+    /// Create `archive.zip` from the source directory with python `zipfile`.
+    ///
+    /// Returns `false` if python isn't available.
+    fn python_create(dir: &Path, zip64: bool) -> bool {
+        let script = "
+import sys, zipfile
+src, zip64 = sys.argv[2], sys.argv[3] == 'true'
+with zipfile.ZipFile(sys.argv[1], 'w', zipfile.ZIP_DEFLATED) as archive:
+    archive.writestr('sub/', '')
+    for name in ['a.txt', 'sub/b.bin']:
+        with open(src + '/' + name, 'rb') as file, archive.open(name, 'w', force_zip64=zip64) as entry:
+            entry.write(file.read())
+";
+        run(Command::new("python3")
+            .arg("-c")
+            .arg(script)
+            .arg(dir.join("archive.zip"))
+            .arg(dir.join("src"))
+            .arg(zip64.to_string()))
+        .is_some()
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = create(MAX_U32 - 1);
+        extract(dir.path());
+    }
+
+    #[test]
+    fn round_trip_zip64() {
+        let dir = create(0);
+        extract(dir.path());
+    }
+
+    #[test]
+    fn file_changed() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        fs::write(&path, "hello").unwrap();
+
+        // Read part of the file only, as if it was truncated while adding it
+        let mut file = File::open(&path).unwrap();
+        file.seek(SeekFrom::Start(2)).unwrap();
+        let mut writer = ZipWriter::new(Vec::new());
+        let err = writer.append_file("a.txt", &mut file).unwrap_err();
+        assert_eq!(err.kind(), IoErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn invalid_archive() {
+        let mut archive = ZipArchive::new(Cursor::new(b"not a zip archive".to_vec()));
+        let dir = tempdir().unwrap();
+        let err = archive.unpack(dir.path(), &mut |_| false).unwrap_err();
+        assert_eq!(err.kind(), IoErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn corrupt_entry() {
+        let dir = create(MAX_U32 - 1);
+        let path = dir.path().join("archive.zip");
+        let mut data = fs::read(&path).unwrap();
+        data[1000] ^= 0xff;
+        let mut archive = ZipArchive::new(Cursor::new(data));
+        assert!(archive
+            .unpack(dir.path().join("out"), &mut |_| false)
+            .is_err());
+
+        // The partially extracted file must be removed
+        assert!(dir.path().join("out/a.txt").exists());
+        assert!(!dir.path().join("out/sub/b.bin").exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn extract_through_existing_link() {
+        use std::os::unix::fs::symlink;
+
+        // Don't write through a link that points outside the destination
+        let root = tempdir().unwrap();
+        let dest = root.path().join("dest");
+        fs::create_dir_all(&dest).unwrap();
+        symlink("..", dest.join("up")).unwrap();
+
+        let mut writer = ZipWriter::new(Vec::new());
+        fs::write(root.path().join("src.txt"), "data").unwrap();
+        for name in &["up/escape", "up/dir/escape"] {
+            writer
+                .append_file(name, &mut File::open(root.path().join("src.txt")).unwrap())
+                .unwrap();
+        }
+        let data = writer.finish().unwrap();
+
+        let summary = ZipArchive::new(Cursor::new(data))
+            .unpack(&dest, &mut |_| false)
+            .unwrap();
+        assert_eq!(summary.extracted, 0);
+        assert_eq!(summary.skipped.len(), 2);
+        assert!(!root.path().join("escape").exists());
+        assert!(!root.path().join("dir").exists());
+    }
+
+    #[test]
+    fn interop_unzip() {
+        unzip(create(MAX_U32 - 1).path());
+    }
+
+    #[test]
+    fn interop_unzip_zip64() {
+        unzip(create(0).path());
+    }
+
+    #[test]
+    fn interop_python_read() {
+        python_extract(create(MAX_U32 - 1).path());
+    }
+
+    #[test]
+    fn interop_python_read_zip64() {
+        python_extract(create(0).path());
+    }
+
+    #[test]
+    fn interop_python_write() {
+        let dir = create(MAX_U32 - 1);
+        if python_create(dir.path(), false) {
+            extract(dir.path());
+        }
+    }
+
+    #[test]
+    fn interop_python_write_zip64() {
+        let dir = create(MAX_U32 - 1);
+        if python_create(dir.path(), true) {
+            extract(dir.path());
+        }
+    }
+}
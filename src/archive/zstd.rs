@@ -0,0 +1,216 @@
+use std::fs::File;
+use std::io::{self, Error as IoError, ErrorKind as IoErrorKind, Read, Seek, SeekFrom, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+pub type Result<T> = ::std::result::Result<T, IoError>;
+
+/// The zstd compression level.
+///
+/// The level and number of threads are always given explicitly, so compressing the same data
+/// twice produces the same output. Archive streams rely on this to compute their size up front.
+const LEVEL: &str = "-3";
+
+/// The size of chunks read from the zstd process output, in bytes.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A writer compressing written data with zstd, into the given inner writer.
+///
+/// Data is compressed by an external `zstd` process, which must be installed on the system.
+/// The compressed output is collected by a background thread, and written to the inner writer
+/// while writing to this encoder.
+pub struct Encoder<W: Write> {
+    /// The inner writer to write compressed data to.
+    writer: W,
+
+    /// The zstd process, and its input.
+    process: Child,
+    stdin: Option<ChildStdin>,
+
+    /// The receiver for chunks of compressed data read from the zstd process.
+    receiver: Receiver<Result<Vec<u8>>>,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Construct a new encoder, starting a zstd process.
+    pub fn new(writer: W) -> Result<Self> {
+        let mut process = spawn(&[LEVEL, "-T1", "-q", "-c"], Stdio::piped())?;
+        let stdin = process.stdin.take();
+
+        // Read the compressed output in a background thread, so the process never blocks on it
+        let (sender, receiver) = channel();
+        let mut stdout = process.stdout.take().unwrap();
+        thread::spawn(move || loop {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            match stdout.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => {
+                    chunk.truncate(len);
+                    if sender.send(Ok(chunk)).is_err() {
+                        break;
+                    }
+                }
+                Err(ref err) if err.kind() == IoErrorKind::Interrupted => {}
+                Err(err) => {
+                    let _ = sender.send(Err(err));
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            writer,
+            process,
+            stdin,
+            receiver,
+        })
+    }
+
+    /// Write all compressed data that is currently available to the inner writer.
+    fn drain(&mut self) -> Result<()> {
+        while let Ok(chunk) = self.receiver.try_recv() {
+            self.writer.write_all(&chunk?)?;
+        }
+        Ok(())
+    }
+
+    /// Finish compressing, and write all remaining compressed data to the inner writer.
+    ///
+    /// The inner writer is returned.
+    pub fn finish(mut self) -> Result<W> {
+        // Close the input, write the remaining output until the process closes it
+        self.stdin.take();
+        for chunk in self.receiver.iter() {
+            self.writer.write_all(&chunk?)?;
+        }
+
+        wait(self.process)?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.drain()?;
+        let len = self.stdin.as_mut().unwrap().write(buf)?;
+        self.drain()?;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.drain()?;
+        self.writer.flush()
+    }
+}
+
+/// Decompress the zstd compressed data from the given reader, into a temporary file.
+///
+/// Data is decompressed by an external `zstd` process, which must be installed on the system.
+/// The temporary file is returned rewound to the start, and is removed when it is dropped.
+pub fn decode<R: Read>(mut reader: R) -> Result<File> {
+    let mut file = tempfile::tempfile()?;
+    let mut process = spawn(&["-d", "-q", "-c"], Stdio::from(file.try_clone()?))?;
+
+    // Write the compressed data to the process, a broken pipe means the process quit early
+    let copied = {
+        let mut stdin = process.stdin.take().unwrap();
+        io::copy(&mut reader, &mut stdin)
+    };
+    wait(process)?;
+    match copied {
+        Err(ref err) if err.kind() == IoErrorKind::BrokenPipe => {}
+        result => {
+            result?;
+        }
+    }
+
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// Spawn a zstd process with the given arguments, writing its output to `stdout`.
+fn spawn(args: &[&str], stdout: Stdio) -> Result<Child> {
+    Command::new(option_env!("ZSTD_PATH").unwrap_or("zstd"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(stdout)
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            IoErrorKind::NotFound => IoError::new(
+                IoErrorKind::NotFound,
+                "the zstd command is required for tar.zst archives, but it is not installed",
+            ),
+            _ => err,
+        })
+}
+
+/// Wait for the given zstd process to exit, an error is returned if it failed.
+fn wait(process: Child) -> Result<()> {
+    let output = process.wait_with_output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let message = String::from_utf8_lossy(&output.stderr);
+    Err(IoError::new(
+        IoErrorKind::InvalidData,
+        format!(
+            "zstd exited with status code {}: {}",
+            output.status.code().unwrap_or(0),
+            message.trim(),
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check whether the zstd command is available, tests are skipped if it isn't.
+    fn available() -> bool {
+        Command::new(option_env!("ZSTD_PATH").unwrap_or("zstd"))
+            .arg("--version")
+            .stdout(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    }
+
+    fn encode(data: &[u8]) -> Vec<u8> {
+        let mut encoder = Encoder::new(Vec::new()).unwrap();
+        for chunk in data.chunks(1000) {
+            encoder.write_all(chunk).unwrap();
+        }
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        if !available() {
+            return;
+        }
+
+        let data: Vec<u8> = (0..500_000u32).map(|i| (i % 251) as u8).collect();
+        let compressed = encode(&data);
+        assert!(compressed.len() < data.len());
+        assert_eq!(compressed, encode(&data));
+
+        let mut decoded = Vec::new();
+        decode(&compressed[..])
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn decode_invalid() {
+        if !available() {
+            return;
+        }
+
+        assert!(decode(&b"not zstd compressed data"[..]).is_err());
+    }
+}
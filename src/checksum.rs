@@ -0,0 +1,205 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use openssl::hash::{Hasher, MessageDigest};
+use openssl::sha::Sha256;
+
+/// The size of the buffer used when hashing data.
+const BUF_SIZE: usize = 64 * 1024;
+
+/// Compute the SHA-256 checksum of the file at the given path, as hexadecimal string.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    hash_reader(File::open(path)?, |chunk| hasher.update(chunk))?;
+    Ok(hex(&hasher.finish()))
+}
+
+/// Check whether the given text is a valid SHA-256 checksum, as hexadecimal string.
+pub fn is_sha256(text: &str) -> bool {
+    text.len() == 64 && text.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Read all data from the given reader, and pass each chunk to `update`.
+///
+/// The number of bytes read is returned.
+pub fn hash_reader<R: Read, F: FnMut(&[u8])>(mut reader: R, mut update: F) -> io::Result<u64> {
+    let mut buf = vec![0; BUF_SIZE];
+    let mut read = 0;
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(read),
+            Ok(n) => {
+                update(&buf[..n]);
+                read += n as u64;
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Format the given bytes as lowercase hexadecimal string.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Create a new SHA-256 hasher.
+pub fn sha256_hasher() -> Hasher {
+    Hasher::new(MessageDigest::sha256()).expect("failed to create SHA-256 hasher")
+}
+
+/// Finish the given SHA-256 hasher, and get the checksum as hexadecimal string.
+pub fn sha256_finish(mut hasher: Hasher) -> String {
+    hex(&hasher.finish().expect("failed to finish SHA-256 checksum"))
+}
+
+/// A reader computing the SHA-256 checksum of the data read through it.
+///
+/// The given hasher is updated with the data, and is stored in the shared slot once exactly `len`
+/// bytes have been read. It may then be finished, or be continued with more data. A reader that is
+/// dropped before reading all data leaves the slot untouched, so this may be used for an upload
+/// that is retried.
+pub struct HashReader<R: Read> {
+    /// The inner reader.
+    inner: R,
+
+    /// The hasher, taken once all data has been read.
+    hasher: Option<Hasher>,
+
+    /// The number of bytes left to read.
+    remaining: u64,
+
+    /// The slot to store the hasher in once all data has been read.
+    slot: Arc<Mutex<Option<Hasher>>>,
+}
+
+impl<R: Read> HashReader<R> {
+    /// Construct a new hashing reader for `len` bytes of data from the given reader, updating the
+    /// given `hasher`.
+    pub fn new(inner: R, len: u64, hasher: Hasher, slot: Arc<Mutex<Option<Hasher>>>) -> Self {
+        let mut reader = Self {
+            inner,
+            hasher: Some(hasher),
+            remaining: len,
+            slot,
+        };
+        reader.store_if_done();
+        reader
+    }
+
+    /// Store the hasher if all data has been read.
+    fn store_if_done(&mut self) {
+        if self.remaining == 0 {
+            if let Some(hasher) = self.hasher.take() {
+                *self.slot.lock().unwrap() = Some(hasher);
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher
+                .update(&buf[..n])
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            self.remaining = self.remaining.saturating_sub(n as u64);
+            self.store_if_done();
+        }
+        Ok(n)
+    }
+}
+
+/// A SHA-256 checksum computed over the data written through its writers.
+#[derive(Clone)]
+pub struct SharedHasher(Arc<Mutex<Sha256>>);
+
+impl SharedHasher {
+    /// Construct a new hasher.
+    pub fn new() -> Self {
+        SharedHasher(Arc::new(Mutex::new(Sha256::new())))
+    }
+
+    /// Wrap the given writer, to hash all data written to it.
+    pub fn writer<W: Write>(&self, inner: W) -> HashWriter<W> {
+        HashWriter {
+            inner,
+            hasher: self.clone(),
+        }
+    }
+
+    /// Get the checksum of the data written so far, as hexadecimal string.
+    ///
+    /// The hasher is reset.
+    pub fn checksum(&self) -> String {
+        let mut hasher = self.0.lock().unwrap();
+        hex(&mem::replace(&mut *hasher, Sha256::new()).finish())
+    }
+}
+
+/// A writer computing the SHA-256 checksum of the data written through it.
+pub struct HashWriter<W: Write> {
+    /// The inner writer.
+    inner: W,
+
+    /// The hasher.
+    hasher: SharedHasher,
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.0.lock().unwrap().update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// The SHA-256 checksum of `abc`.
+    const ABC: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn hash_reader_continues_hasher() {
+        let (part, total) = (Arc::new(Mutex::new(None)), Arc::new(Mutex::new(None)));
+        let mut hasher = sha256_hasher();
+        for chunk in ["a", "bc"].iter() {
+            let len = chunk.len() as u64;
+            let reader = HashReader::new(Cursor::new(*chunk), len, sha256_hasher(), part.clone());
+            let mut reader = HashReader::new(reader, len, hasher.clone(), total.clone());
+            io::copy(&mut reader, &mut io::sink()).unwrap();
+            hasher = total.lock().unwrap().take().unwrap();
+            assert!(part.lock().unwrap().take().is_some());
+        }
+        assert_eq!(sha256_finish(hasher), ABC);
+    }
+
+    #[test]
+    fn hash_reader_incomplete() {
+        let slot = Arc::new(Mutex::new(None));
+        let mut reader = HashReader::new(Cursor::new("ab"), 3, sha256_hasher(), slot.clone());
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert!(slot.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn hash_writer() {
+        let hasher = SharedHasher::new();
+        let mut writer = hasher.writer(Vec::new());
+        writer.write_all(b"ab").unwrap();
+        writer.write_all(b"c").unwrap();
+        assert_eq!(hasher.checksum(), ABC);
+    }
+}
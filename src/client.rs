@@ -1,22 +1,86 @@
+use std::io::{self, Error as IoError, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
 use std::time::Duration;
 
-use ffsend_api::client::{ClientConfig, ClientConfigBuilder};
+use failure::{Compat, Fail};
+use ffsend_api::reqwest::StatusCode;
+use ffsend_api::url::{ParseError as UrlError, Url};
+use hyper::client::{Body, IntoUrl, RedirectPolicy, Response as HyperResponse};
+use hyper::header::{Authorization, Basic, ContentLength, ContentType, Headers, Location};
+use hyper::method::Method;
+use hyper::net::{NetworkConnector, NetworkStream};
+use openssl::ssl::SslStream;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Error as JsonError;
+#[cfg(feature = "send3")]
+use websocket::{
+    client::sync::Client as WsClient, result::WebSocketError, stream::sync::AsTcpStream,
+    ClientBuilder as WsClientBuilder,
+};
 
 use crate::cmd::matcher::MainMatcher;
+use crate::headers::{self, Header};
+use crate::proxy::{self, bypass_proxy, proxy_port, redact_proxy};
+use crate::tls::{Connectors, Error as TlsError};
+use crate::util::{quit_error, ErrorHintsBuilder};
+
+/// The maximum number of redirects to follow for a request.
+const MAX_REDIRECTS: usize = 10;
+
+/// The protocol to report to the server when uploading through a websocket.
+#[cfg(feature = "send3")]
+const WEBSOCKET_PROTOCOL: &str = "ffsend";
 
 /// Create a client configuration for ffsend actions.
 ///
 /// A client configuration allows you to build a client, which must be passed to ffsend API
 /// actions.
-// TODO: properly handle errors, do not unwrap
-pub fn create_config(matcher_main: &MainMatcher) -> ClientConfig {
-    // TODO: configure HTTP authentication properties
-    ClientConfigBuilder::default()
-        .timeout(to_duration(matcher_main.timeout()))
-        .transfer_timeout(to_duration(matcher_main.transfer_timeout()))
-        .basic_auth(matcher_main.basic_auth())
-        .build()
-        .expect("failed to create network client configuration")
+///
+/// The TLS settings for the given hosts are loaded up front, the application quits with an error
+/// if these are invalid.
+pub fn create_config<'u, I>(matcher_main: &MainMatcher, hosts: I) -> ClientConfig
+where
+    I: IntoIterator<Item = &'u Url>,
+{
+    let proxy = matcher_main.proxy();
+    let no_proxy = matcher_main.no_proxy();
+    report_proxy(matcher_main, proxy.as_ref());
+    let headers = matcher_main.headers();
+    report_headers(matcher_main, &headers);
+    let tls = Connectors::new(matcher_main);
+    if let Err(err) = tls.prepare(hosts) {
+        quit_error(
+            err.context("failed to set up TLS"),
+            ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+        );
+    }
+
+    ClientConfig {
+        timeout: to_duration(matcher_main.timeout()),
+        transfer_timeout: to_duration(matcher_main.transfer_timeout()),
+        basic_auth: matcher_main.basic_auth(),
+        headers,
+        tls: Arc::new(tls),
+        proxy,
+        no_proxy,
+    }
+}
+
+/// Report the proxy to connect through in verbose mode, with its password redacted.
+fn report_proxy(matcher_main: &MainMatcher, proxy: Option<&Url>) {
+    if let Some(proxy) = proxy.filter(|_| matcher_main.verbose()) {
+        eprintln!("Proxy: {}", redact_proxy(proxy));
+    }
+}
+
+/// Report the custom headers in verbose mode, with their values masked.
+fn report_headers(matcher_main: &MainMatcher, headers: &[Header]) {
+    if !matcher_main.verbose() || headers.is_empty() {
+        return;
+    }
+    let headers: Vec<String> = headers.iter().map(Header::masked).collect();
+    eprintln!("Custom headers: {}", headers.join(", "));
 }
 
 /// Convert the given number of seconds into an optional duration, used for clients.
@@ -27,3 +91,524 @@ pub fn to_duration(secs: u64) -> Option<Duration> {
         None
     }
 }
+
+/// The configuration to build network clients with.
+///
+/// The default configuration has no timeouts, proxy or custom headers, and uses the default TLS
+/// settings.
+#[derive(Clone, Default)]
+pub struct ClientConfig {
+    /// The timeout for connecting and each read or write, except for file transfers.
+    timeout: Option<Duration>,
+
+    /// The timeout for connecting and each read or write in file transfers.
+    transfer_timeout: Option<Duration>,
+
+    /// Basic HTTP authentication credentials.
+    basic_auth: Option<(String, Option<String>)>,
+
+    /// The custom headers to send with each request.
+    headers: Vec<Header>,
+
+    /// The TLS connectors, shared by all clients.
+    tls: Arc<Connectors>,
+
+    /// The proxy to connect through.
+    proxy: Option<Url>,
+
+    /// The hosts to not use the proxy for, as comma separated list.
+    no_proxy: Option<String>,
+}
+
+impl ClientConfig {
+    /// Build a network client, which uses the transfer timeout if `transfer` is set.
+    pub fn client(self, transfer: bool) -> Client {
+        let timeout = if transfer {
+            self.transfer_timeout
+        } else {
+            self.timeout
+        };
+        let connector = Connector {
+            tls: self.tls.clone(),
+            timeout,
+            proxy: self.proxy,
+            no_proxy: self.no_proxy,
+        };
+        let mut inner = hyper::Client::with_connector(connector.clone());
+        inner.set_redirect_policy(RedirectPolicy::FollowNone);
+        inner.set_read_timeout(timeout);
+        inner.set_write_timeout(timeout);
+
+        Client {
+            inner,
+            connector,
+            basic_auth: self.basic_auth,
+            headers: self.headers,
+        }
+    }
+}
+
+/// A network client, to send requests to a Send server with.
+pub struct Client {
+    /// The HTTP client.
+    inner: hyper::Client,
+
+    /// The connector the HTTP client uses, used directly to open websocket connections.
+    #[cfg_attr(not(feature = "send3"), allow(dead_code))]
+    connector: Connector,
+
+    /// Basic HTTP authentication credentials.
+    basic_auth: Option<(String, Option<String>)>,
+
+    /// The custom headers to send with each request.
+    headers: Vec<Header>,
+}
+
+impl Client {
+    /// Build a GET request to the given URL.
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::Get, url)
+    }
+
+    /// Build a POST request to the given URL.
+    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::Post, url)
+    }
+
+    /// Build a request with the given method to the given URL.
+    fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+        RequestBuilder {
+            client: self,
+            method,
+            url: url.into_url(),
+            headers: self.headers(),
+            body: None,
+        }
+    }
+
+    /// Get the headers to send with each request, including the custom headers.
+    fn headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        if let Some((user, password)) = &self.basic_auth {
+            headers.set(Authorization(Basic {
+                username: user.to_owned(),
+                password: password.to_owned(),
+            }));
+        }
+        headers::apply(&mut headers, &self.headers);
+        headers
+    }
+
+    /// Connect to the websocket at the given URL.
+    #[cfg(feature = "send3")]
+    pub fn websocket(&self, url: &Url) -> Result<WsClient<Stream>, Error> {
+        let scheme = match url.scheme() {
+            "wss" | "https" => "https",
+            _ => "http",
+        };
+        let stream = self
+            .connector
+            .connect(
+                url.host_str().unwrap_or(""),
+                url.port_or_known_default().unwrap_or(443),
+                scheme,
+            )
+            .map_err(Error::from_hyper)
+            .map_err(Error::check_tls)?;
+
+        WsClientBuilder::from_url(url)
+            .add_protocol(WEBSOCKET_PROTOCOL)
+            .custom_headers(&self.headers())
+            .connect_on(stream)
+            .map_err(Error::Websocket)
+    }
+}
+
+/// A request to send with a network client.
+pub struct RequestBuilder<'c> {
+    /// The client to send the request with.
+    client: &'c Client,
+
+    /// The request method.
+    method: Method,
+
+    /// The request URL.
+    url: Result<Url, UrlError>,
+
+    /// The request headers.
+    headers: Headers,
+
+    /// The request body.
+    body: Option<RequestBody>,
+}
+
+impl<'c> RequestBuilder<'c> {
+    /// Add a header with the given name, along with any header of the same name set before.
+    pub fn header<V: Into<String>>(mut self, name: &str, value: V) -> Self {
+        self.headers
+            .append_raw(name.to_owned(), value.into().into_bytes());
+        self
+    }
+
+    /// Set the given data as body.
+    #[cfg_attr(not(feature = "urlshorten"), allow(dead_code))]
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = Some(RequestBody::Data(body.into()));
+        self
+    }
+
+    /// Set the given data serialized as JSON as body.
+    pub fn json<T: Serialize>(mut self, data: &T) -> Self {
+        let body = serde_json::to_vec(data).expect("failed to serialize request body");
+        self.headers.set(ContentType::json());
+        self.body = Some(RequestBody::Data(body));
+        self
+    }
+
+    /// Set the data read from the given reader as body, which is exactly `len` bytes.
+    #[cfg_attr(not(feature = "send2"), allow(dead_code))]
+    pub fn reader(mut self, reader: Box<dyn Read + Send>, len: u64) -> Self {
+        self.body = Some(RequestBody::Reader(reader, len));
+        self
+    }
+
+    /// Send the request, and get the response.
+    ///
+    /// Redirects are followed for GET requests. Authorization is not sent along when redirected
+    /// to another host.
+    pub fn send(self) -> Result<Response, Error> {
+        let RequestBuilder {
+            client,
+            method,
+            url,
+            mut headers,
+            mut body,
+        } = self;
+        let mut url = url.map_err(Error::Url)?;
+
+        let mut redirects = 0;
+        loop {
+            let request = client
+                .inner
+                .request(method.clone(), url.clone())
+                .headers(headers.clone());
+            let response = match &mut body {
+                Some(RequestBody::Data(data)) => request.body(&data[..]).send(),
+                Some(RequestBody::Reader(reader, len)) => {
+                    request.body(Body::SizedBody(reader, *len)).send()
+                }
+                None => request.send(),
+            }
+            .map_err(Error::from_hyper)
+            .map_err(Error::check_tls)?;
+
+            // Follow redirects for GET requests
+            let location = match response.headers.get::<Location>() {
+                Some(location)
+                    if method == Method::Get
+                        && response.status.is_redirection()
+                        && redirects < MAX_REDIRECTS =>
+                {
+                    url.join(location).ok()
+                }
+                _ => None,
+            };
+            let location = match location {
+                Some(location) => location,
+                None => return Ok(Response::new(response)),
+            };
+            debug!("redirected to another location");
+            if location.host_str() != url.host_str()
+                || location.port_or_known_default() != url.port_or_known_default()
+            {
+                headers.remove_raw("Authorization");
+            }
+            url = location;
+            redirects += 1;
+        }
+    }
+}
+
+/// The body of a request.
+enum RequestBody {
+    /// The given data.
+    Data(Vec<u8>),
+
+    /// The data read from the given reader, of the given length.
+    #[cfg_attr(not(feature = "send2"), allow(dead_code))]
+    Reader(Box<dyn Read + Send>, u64),
+}
+
+/// A response to a request.
+pub struct Response {
+    /// The HTTP response.
+    inner: HyperResponse,
+
+    /// The response status.
+    status: StatusCode,
+}
+
+impl Response {
+    /// Wrap the given HTTP response.
+    fn new(inner: HyperResponse) -> Self {
+        // A status code outside the valid range is a bad response from the server
+        let status = StatusCode::from_u16(inner.status.to_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+        Self { inner, status }
+    }
+
+    /// Get the response status.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Get the final URL of the response, after following redirects.
+    pub fn url(&self) -> &Url {
+        &self.inner.url
+    }
+
+    /// Get the value of the header with the given name, if set and valid UTF-8.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.inner
+            .headers
+            .get_raw(name)
+            .and_then(|values| values.first())
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// Get the name and value of each header.
+    pub fn headers(&self) -> Vec<(String, String)> {
+        self.inner
+            .headers
+            .iter()
+            .map(|header| (header.name().to_lowercase(), header.value_string()))
+            .collect()
+    }
+
+    /// Get the length of the response body, if known.
+    pub fn content_length(&self) -> Option<u64> {
+        self.inner
+            .headers
+            .get::<ContentLength>()
+            .map(|length| length.0)
+    }
+
+    /// Read the response body, and deserialize it from JSON.
+    pub fn json<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        serde_json::from_reader(self).map_err(Error::Decode)
+    }
+
+    /// Read the response body as text.
+    #[cfg_attr(not(feature = "urlshorten"), allow(dead_code))]
+    pub fn text(&mut self) -> Result<String, Error> {
+        let mut text = String::new();
+        self.read_to_string(&mut text).map_err(Error::Read)?;
+        Ok(text)
+    }
+}
+
+impl Read for Response {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// Opens the connections for the network client.
+#[derive(Clone)]
+struct Connector {
+    /// The TLS connectors.
+    tls: Arc<Connectors>,
+
+    /// The timeout for connecting.
+    timeout: Option<Duration>,
+
+    /// The proxy to connect through.
+    proxy: Option<Url>,
+
+    /// The hosts to not use the proxy for, as comma separated list.
+    no_proxy: Option<String>,
+}
+
+impl Connector {
+    /// Open a TCP connection to the first address the given host resolves to.
+    fn tcp(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let addr = (host.trim_start_matches('[').trim_end_matches(']'), port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| {
+                IoError::new(io::ErrorKind::Other, "host did not resolve to any address")
+            })?;
+        match self.timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+            None => TcpStream::connect(addr),
+        }
+    }
+
+    /// Get the proxy to connect to the given host through, if it isn't bypassed for the host.
+    fn proxy(&self, host: &str) -> Option<&Url> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let bypass = self
+            .no_proxy
+            .as_ref()
+            .map_or(false, |hosts| bypass_proxy(hosts, host));
+        self.proxy.as_ref().filter(|_| !bypass)
+    }
+}
+
+impl NetworkConnector for Connector {
+    type Stream = Stream;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> hyper::Result<Stream> {
+        // Connect directly, or through a tunnel opened by the proxy
+        let stream = match self.proxy(host) {
+            Some(proxy) => {
+                debug!("connecting through proxy {}", redact_proxy(proxy));
+                let mut stream = self.tcp(proxy.host_str().unwrap_or(""), proxy_port(proxy))?;
+                stream.set_read_timeout(self.timeout)?;
+                stream.set_write_timeout(self.timeout)?;
+                proxy::tunnel(&mut stream, proxy, host, port)?;
+                stream
+            }
+            None => self.tcp(host, port)?,
+        };
+
+        match scheme {
+            "http" => Ok(Stream::Http(stream)),
+            "https" => {
+                // The stream timeouts are set by the HTTP client, set them for the handshake
+                stream.set_read_timeout(self.timeout)?;
+                stream.set_write_timeout(self.timeout)?;
+                match self.tls.connect(host, port, stream) {
+                    Ok(stream) => Ok(Stream::Https(stream)),
+                    Err(err) => Err(hyper::Error::Ssl(Box::new(err.compat()))),
+                }
+            }
+            scheme => Err(hyper::Error::Io(IoError::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported URL scheme '{}'", scheme),
+            ))),
+        }
+    }
+}
+
+/// A connection of the network client.
+pub enum Stream {
+    /// A plain connection.
+    Http(TcpStream),
+
+    /// A TLS connection.
+    Https(SslStream<TcpStream>),
+}
+
+impl Stream {
+    /// Get the underlying TCP stream.
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Stream::Http(stream) => stream,
+            Stream::Https(stream) => stream.get_ref(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Http(stream) => stream.read(buf),
+            Stream::Https(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Http(stream) => stream.write(buf),
+            Stream::Https(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Http(stream) => stream.flush(),
+            Stream::Https(stream) => stream.flush(),
+        }
+    }
+}
+
+impl NetworkStream for Stream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.tcp().peer_addr()
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_write_timeout(dur)
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        self.tcp().shutdown(how)
+    }
+}
+
+#[cfg(feature = "send3")]
+impl AsTcpStream for Stream {
+    fn as_tcp(&self) -> &TcpStream {
+        self.tcp()
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// The request URL is invalid.
+    #[fail(display = "invalid request URL")]
+    Url(#[cause] UrlError),
+
+    /// Failed to set up a secure connection.
+    #[fail(display = "failed to set up a secure connection")]
+    Tls(#[cause] TlsError),
+
+    /// Failed to send the request, or to receive the response.
+    #[fail(display = "failed to send the request")]
+    Request(#[cause] hyper::Error),
+
+    /// Failed to read the response body.
+    #[fail(display = "failed to read the response")]
+    #[cfg_attr(not(feature = "urlshorten"), allow(dead_code))]
+    Read(#[cause] IoError),
+
+    /// Failed to decode the response body.
+    #[fail(display = "failed to decode the response")]
+    Decode(#[cause] JsonError),
+
+    /// Failed to connect to a websocket.
+    #[cfg(feature = "send3")]
+    #[fail(display = "failed to connect to the websocket")]
+    Websocket(#[cause] WebSocketError),
+}
+
+impl Error {
+    /// Map the given error of the HTTP client, errors setting up TLS are extracted.
+    fn from_hyper(err: hyper::Error) -> Self {
+        match err {
+            hyper::Error::Ssl(err) => match err.downcast::<Compat<TlsError>>() {
+                Ok(err) => Error::Tls(err.into_inner()),
+                Err(err) => Error::Request(hyper::Error::Ssl(err)),
+            },
+            err => Error::Request(err),
+        }
+    }
+
+    /// Quit with an error if a secure connection could not be set up due to the TLS settings or
+    /// the host certificate, as retrying or continuing is pointless.
+    fn check_tls(self) -> Self {
+        match self {
+            Error::Tls(err) if !err.is_transient() => quit_error(
+                err.context("failed to set up a secure connection"),
+                ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+            ),
+            err => err,
+        }
+    }
+}
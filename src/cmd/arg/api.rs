@@ -3,6 +3,7 @@ use ffsend_api::api::{DesiredVersion, Version};
 
 use super::{CmdArg, CmdArgOption};
 use crate::config::API_VERSION_DESIRED_DEFAULT;
+use crate::config_file::CONFIG;
 use crate::util::{quit_error_msg, ErrorHints};
 
 /// The api argument.
@@ -14,7 +15,7 @@ impl CmdArg for ArgApi {
     }
 
     fn build<'b, 'c>() -> Arg<'b, 'c> {
-        Arg::with_name("api")
+        let arg = Arg::with_name("api")
             .long("api")
             .short("A")
             .value_name("VERSION")
@@ -27,7 +28,13 @@ impl CmdArg for ArgApi {
                  2, 3: Firefox Send API versions\n\
                  auto, -: probe server to determine\
                  ",
-            )
+            );
+
+        // Use the configured version as default
+        match &CONFIG.settings().api {
+            Some(api) => arg.default_value(api).hide_default_value(true),
+            None => arg,
+        }
     }
 }
 
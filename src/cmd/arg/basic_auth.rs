@@ -1,6 +1,7 @@
 use clap::{Arg, ArgMatches};
 
 use super::{CmdArg, CmdArgOption};
+use crate::config_file::CONFIG;
 
 /// The basicauth argument.
 pub struct ArgBasicAuth {}
@@ -11,7 +12,7 @@ impl CmdArg for ArgBasicAuth {
     }
 
     fn build<'b, 'c>() -> Arg<'b, 'c> {
-        Arg::with_name("basic-auth")
+        let arg = Arg::with_name("basic-auth")
             .long("basic-auth")
             .alias("basic-authentication")
             .alias("http-basic-authentication")
@@ -20,7 +21,13 @@ impl CmdArg for ArgBasicAuth {
             .env("FFSEND_BASIC_AUTH")
             .hide_env_values(true)
             .global(true)
-            .help("HTTP basic authentication credentials")
+            .help("HTTP basic authentication credentials");
+
+        // Use the configured credentials as default, never show them
+        match &CONFIG.settings().basic_auth {
+            Some(auth) => arg.default_value(auth).hide_default_value(true),
+            None => arg,
+        }
     }
 }
 
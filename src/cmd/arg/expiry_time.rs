@@ -0,0 +1,44 @@
+use clap::{Arg, ArgMatches};
+
+use super::{CmdArg, CmdArgFlag, CmdArgOption};
+
+use crate::util::{parse_duration, quit_error_msg, ErrorHintsBuilder};
+
+/// The expiry time argument.
+pub struct ArgExpiryTime {}
+
+impl CmdArg for ArgExpiryTime {
+    fn name() -> &'static str {
+        "expiry-time"
+    }
+
+    fn build<'b, 'c>() -> Arg<'b, 'c> {
+        Arg::with_name("expiry-time")
+            .long("expiry-time")
+            .short("e")
+            .alias("expire")
+            .alias("expiry")
+            .value_name("TIME")
+            .help("The file expiry time, such as 5m, 1h, 1d or 7d")
+    }
+}
+
+impl CmdArgFlag for ArgExpiryTime {}
+
+impl<'a> CmdArgOption<'a> for ArgExpiryTime {
+    type Value = Option<usize>;
+
+    fn value<'b: 'a>(matches: &'a ArgMatches<'b>) -> Self::Value {
+        Self::value_raw(matches).map(|t| match parse_duration(t) {
+            Ok(seconds) => seconds,
+            Err(err) => quit_error_msg(
+                format!("invalid expiry time '{}', {}", t, err),
+                ErrorHintsBuilder::default()
+                    .add_info("use a time such as '5m', '1h', '1d' or '7d'".into())
+                    .verbose(false)
+                    .build()
+                    .unwrap(),
+            ),
+        })
+    }
+}
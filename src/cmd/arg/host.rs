@@ -4,6 +4,7 @@ use ffsend_api::config::SEND_DEFAULT_HOST;
 use ffsend_api::url::Url;
 
 use super::{CmdArg, CmdArgOption};
+use crate::config_file::CONFIG;
 use crate::host::parse_host;
 use crate::util::{quit_error, ErrorHints};
 
@@ -20,7 +21,14 @@ impl CmdArg for ArgHost {
             .long("host")
             .short("h")
             .value_name("URL")
-            .default_value(SEND_DEFAULT_HOST)
+            .default_value(
+                CONFIG
+                    .settings()
+                    .host
+                    .as_ref()
+                    .map(String::as_str)
+                    .unwrap_or(SEND_DEFAULT_HOST),
+            )
             .env("FFSEND_HOST")
             .hide_env_values(true)
             .help("The remote host to upload to")
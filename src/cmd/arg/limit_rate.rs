@@ -0,0 +1,54 @@
+use clap::{Arg, ArgMatches};
+use failure::Fail;
+
+use super::{CmdArg, CmdArgOption};
+use crate::config_file::CONFIG;
+use crate::throttle::{parse_rate, RateLimit};
+use crate::util::{quit_error, ErrorHints};
+
+/// The transfer rate limit argument.
+pub struct ArgLimitRate {}
+
+impl CmdArg for ArgLimitRate {
+    fn name() -> &'static str {
+        "limit-rate"
+    }
+
+    fn build<'b, 'c>() -> Arg<'b, 'c> {
+        Arg::with_name("limit-rate")
+            .long("limit-rate")
+            .alias("rate")
+            .value_name("RATE")
+            .env("FFSEND_LIMIT_RATE")
+            .help("Limit the transfer rate, in bytes per second such as 500K or 2M")
+            .validator(|arg| parse_rate(&arg).map(|_| ()).map_err(|err| err.to_string()))
+    }
+}
+
+impl<'a> CmdArgOption<'a> for ArgLimitRate {
+    type Value = Option<RateLimit>;
+
+    /// Get the rate limit.
+    ///
+    /// The argument takes precedence over the configured rate schedule, which takes precedence over
+    /// the configured rate. `None` is returned if the rate is unlimited.
+    fn value<'b: 'a>(matches: &'a ArgMatches<'b>) -> Self::Value {
+        let settings = CONFIG.settings();
+        let limit = match (Self::value_raw(matches), &settings.limit_rate_schedule) {
+            (Some(rate), _) => parse_rate(rate).map(|rate| rate.map(RateLimit::Fixed)),
+            (None, Some(schedule)) => RateLimit::schedule(schedule).map(Some),
+            (None, None) => match &settings.limit_rate {
+                Some(rate) => parse_rate(rate).map(|rate| rate.map(RateLimit::Fixed)),
+                None => Ok(None),
+            },
+        };
+
+        match limit {
+            Ok(limit) => limit,
+            Err(err) => quit_error(
+                err.context("failed to determine the transfer rate limit"),
+                ErrorHints::default(),
+            ),
+        }
+    }
+}
@@ -1,20 +1,26 @@
 pub mod api;
 pub mod basic_auth;
 pub mod download_limit;
+pub mod expiry_time;
 pub mod gen_passphrase;
 pub mod host;
+pub mod limit_rate;
 pub mod owner;
 pub mod password;
+pub mod proxy;
 pub mod url;
 
 // Re-eexport to arg module
 pub use self::api::ArgApi;
 pub use self::basic_auth::ArgBasicAuth;
 pub use self::download_limit::ArgDownloadLimit;
+pub use self::expiry_time::ArgExpiryTime;
 pub use self::gen_passphrase::ArgGenPassphrase;
 pub use self::host::ArgHost;
+pub use self::limit_rate::ArgLimitRate;
 pub use self::owner::ArgOwner;
 pub use self::password::ArgPassword;
+pub use self::proxy::{ArgNoProxy, ArgProxy};
 pub use self::url::ArgUrl;
 
 use clap::{Arg, ArgMatches};
@@ -0,0 +1,81 @@
+use clap::{Arg, ArgMatches};
+use failure::Fail;
+use ffsend_api::url::Url;
+
+use super::{CmdArg, CmdArgOption};
+use crate::config_file::CONFIG;
+use crate::proxy::parse_proxy;
+use crate::util::{quit_error, ErrorHints};
+
+/// The proxy argument.
+pub struct ArgProxy {}
+
+impl CmdArg for ArgProxy {
+    fn name() -> &'static str {
+        "proxy"
+    }
+
+    fn build<'b, 'c>() -> Arg<'b, 'c> {
+        let arg = Arg::with_name("proxy")
+            .long("proxy")
+            .value_name("URL")
+            .env("FFSEND_PROXY")
+            .hide_env_values(true)
+            .global(true)
+            .help("HTTP or SOCKS5 proxy to connect through")
+            .validator(|arg| parse_proxy(&arg).map(|_| ()).map_err(|err| err.to_string()));
+
+        // Use the configured proxy as default, never show it as it may hold credentials
+        match &CONFIG.settings().proxy {
+            Some(proxy) => arg.default_value(proxy).hide_default_value(true),
+            None => arg,
+        }
+    }
+}
+
+impl<'a> CmdArgOption<'a> for ArgProxy {
+    type Value = Option<Url>;
+
+    fn value<'b: 'a>(matches: &'a ArgMatches<'b>) -> Self::Value {
+        let raw = Self::value_raw(matches)?;
+        match parse_proxy(raw) {
+            Ok(url) => Some(url),
+            Err(err) => quit_error(
+                err.context("failed to parse the given proxy"),
+                ErrorHints::default(),
+            ),
+        }
+    }
+}
+
+/// The no proxy argument.
+pub struct ArgNoProxy {}
+
+impl CmdArg for ArgNoProxy {
+    fn name() -> &'static str {
+        "no-proxy"
+    }
+
+    fn build<'b, 'c>() -> Arg<'b, 'c> {
+        let arg = Arg::with_name("no-proxy")
+            .long("no-proxy")
+            .value_name("HOSTS")
+            .env("FFSEND_NO_PROXY")
+            .hide_env_values(true)
+            .global(true)
+            .help("Comma separated hosts to not use the proxy for, '*' for all hosts");
+
+        match &CONFIG.settings().no_proxy {
+            Some(hosts) => arg.default_value(hosts),
+            None => arg,
+        }
+    }
+}
+
+impl<'a> CmdArgOption<'a> for ArgNoProxy {
+    type Value = Option<&'a str>;
+
+    fn value<'b: 'a>(matches: &'a ArgMatches<'b>) -> Self::Value {
+        Self::value_raw(matches)
+    }
+}
@@ -3,7 +3,13 @@ use failure::Fail;
 use ffsend_api::url::Url;
 
 use super::{CmdArg, CmdArgOption};
+#[cfg(feature = "history")]
+use crate::cmd::matcher::{MainMatcher, Matcher};
+#[cfg(feature = "history")]
+use crate::history::{History, Reference};
 use crate::host::parse_host;
+#[cfg(feature = "history")]
+use crate::util::{bin_name, highlight, quit_error_msg, ErrorHintsBuilder};
 use crate::util::{quit_error, ErrorHints};
 
 /// The URL argument.
@@ -19,6 +25,11 @@ impl CmdArg for ArgUrl {
             .required(true)
             .multiple(false)
             .help("The share URL")
+            .long_help(
+                "The share URL\n\
+                 A file from history may be referenced by its index as '#3', or as '@last' for \
+                 the last file added to history",
+            )
     }
 }
 
@@ -26,8 +37,25 @@ impl<'a> CmdArgOption<'a> for ArgUrl {
     type Value = Url;
 
     fn value<'b: 'a>(matches: &'a ArgMatches<'b>) -> Self::Value {
-        // Get the URL
-        let url = Self::value_raw(matches).expect("missing URL");
+        Self::parse(matches, Self::value_raw(matches).expect("missing URL"))
+    }
+}
+
+impl ArgUrl {
+    /// Parse the given raw share URL into an `Url`.
+    ///
+    /// History file references are resolved as well.
+    /// If the given URL is invalid,
+    /// the program will quit with an error message.
+    #[cfg_attr(not(feature = "history"), allow(unused_variables))]
+    pub fn parse(matches: &ArgMatches, url: &str) -> Url {
+        // Resolve history file references
+        #[cfg(feature = "history")]
+        {
+            if let Some(reference) = Reference::parse_explicit(url) {
+                return resolve_reference(matches, url, &reference);
+            }
+        }
 
         // Parse the URL
         match parse_host(&url) {
@@ -39,3 +67,37 @@ impl<'a> CmdArgOption<'a> for ArgUrl {
         }
     }
 }
+
+/// Resolve the given history file reference into a share URL.
+///
+/// The program will quit with an error message if the history could not be loaded, or if no file
+/// matches the reference.
+#[cfg(feature = "history")]
+fn resolve_reference(matches: &ArgMatches, raw: &str, reference: &Reference) -> Url {
+    // Load the history
+    let matcher_main = MainMatcher::with(matches).unwrap();
+    let path = matcher_main.history();
+    let history = match History::load_or_new(path, &|| matcher_main.history_passphrase(false)) {
+        Ok(history) => history,
+        Err(err) => quit_error(
+            err.context("failed to load file history to resolve file reference"),
+            ErrorHints::default(),
+        ),
+    };
+
+    // Find the referenced file
+    match history.resolve(reference) {
+        Some(file) => file.remote_file().download_url(true),
+        None => quit_error_msg(
+            format!("no file in history matching '{}'", raw),
+            ErrorHintsBuilder::default()
+                .add_info(format!(
+                    "Use '{}' to list files in history",
+                    highlight(&format!("{} history", bin_name()))
+                ))
+                .verbose(false)
+                .build()
+                .unwrap(),
+        ),
+    }
+}
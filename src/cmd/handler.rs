@@ -5,22 +5,26 @@ use std::ffi::OsString;
 
 use clap::{App, AppSettings, Arg, ArgMatches};
 
-use super::arg::{ArgApi, ArgBasicAuth, CmdArg};
+use super::arg::{ArgApi, ArgBasicAuth, ArgNoProxy, ArgProxy, CmdArg};
 #[cfg(feature = "history")]
 use super::matcher::HistoryMatcher;
 use super::matcher::{
     DebugMatcher, DeleteMatcher, DownloadMatcher, ExistsMatcher, GenerateMatcher, InfoMatcher,
-    Matcher, ParamsMatcher, PasswordMatcher, UploadMatcher, VersionMatcher,
+    Matcher, ParamsMatcher, PasswordMatcher, UploadMatcher, VersionMatcher, WatchMatcher,
 };
 #[cfg(feature = "history")]
 use super::subcmd::CmdHistory;
 use super::subcmd::{
     CmdDebug, CmdDelete, CmdDownload, CmdExists, CmdGenerate, CmdInfo, CmdParams, CmdPassword,
-    CmdUpload, CmdVersion,
+    CmdUpload, CmdVersion, CmdWatch,
 };
 #[cfg(feature = "infer-command")]
 use crate::config::INFER_COMMANDS;
-use crate::config::{CLIENT_TIMEOUT, CLIENT_TRANSFER_TIMEOUT};
+use crate::config::{CLIENT_TIMEOUT, CLIENT_TRANSFER_TIMEOUT, TRANSFER_RETRIES};
+use crate::config_file::CONFIG;
+use crate::headers::Header;
+use crate::output::OUTPUT_FORMATS;
+use crate::tls::parse_pin;
 #[cfg(feature = "history")]
 use crate::util::app_history_file_path_string;
 #[cfg(feature = "infer-command")]
@@ -28,16 +32,33 @@ use crate::util::bin_name;
 
 #[cfg(feature = "history")]
 lazy_static! {
-    /// The default history file
-    static ref DEFAULT_HISTORY_FILE: String = app_history_file_path_string();
+    /// The default history file, from the configuration if set
+    static ref DEFAULT_HISTORY_FILE: String = CONFIG
+        .settings()
+        .history
+        .as_ref()
+        .and_then(|path| path.to_str())
+        .map(|path| path.to_owned())
+        .unwrap_or_else(app_history_file_path_string);
 }
 
 lazy_static! {
-    /// The default client timeout in seconds as a string
-    static ref DEFAULT_TIMEOUT: String = format!("{}", CLIENT_TIMEOUT);
+    /// The default client timeout in seconds as a string, from the configuration if set
+    static ref DEFAULT_TIMEOUT: String =
+        format!("{}", CONFIG.settings().timeout.unwrap_or(CLIENT_TIMEOUT));
 
-    /// The default client transfer timeout in seconds as a string
-    static ref DEFAULT_TRANSFER_TIMEOUT: String = format!("{}", CLIENT_TRANSFER_TIMEOUT);
+    /// The default client transfer timeout in seconds as a string, from the configuration if set
+    static ref DEFAULT_TRANSFER_TIMEOUT: String = format!(
+        "{}",
+        CONFIG
+            .settings()
+            .transfer_timeout
+            .unwrap_or(CLIENT_TRANSFER_TIMEOUT),
+    );
+
+    /// The default number of transfer retries as a string, from the configuration if set
+    static ref DEFAULT_RETRIES: String =
+        format!("{}", CONFIG.settings().retries.unwrap_or(TRANSFER_RETRIES));
 }
 
 /// CLI argument handler.
@@ -133,6 +154,25 @@ impl<'a: 'b, 'b> Handler<'a> {
                         ))
                     ),
             )
+            .arg(
+                Arg::with_name("retries")
+                    .long("retries")
+                    .alias("retry")
+                    .global(true)
+                    .value_name("COUNT")
+                    .help("Retry failed transfers (0 to disable)")
+                    .default_value(&DEFAULT_RETRIES)
+                    .hide_default_value(true)
+                    .env("FFSEND_RETRIES")
+                    .hide_env_values(true)
+                    .validator(|arg| arg
+                        .parse::<u32>()
+                        .map(|_| ())
+                        .map_err(|_| String::from(
+                                "Retries must be a positive number, or 0 to disable."
+                        ))
+                    ),
+            )
             .arg(
                 Arg::with_name("quiet")
                     .long("quiet")
@@ -146,10 +186,111 @@ impl<'a: 'b, 'b> Handler<'a> {
                     .short("v")
                     .multiple(true)
                     .global(true)
-                    .help("Enable verbose information and logging"),
+                    .help("Enable verbose information and logging, repeat to log requests"),
+            )
+            .arg(
+                Arg::with_name("log-file")
+                    .long("log-file")
+                    .global(true)
+                    .value_name("FILE")
+                    .env("FFSEND_LOG_FILE")
+                    .hide_env_values(true)
+                    .help("Append a detailed log to the given file, for bug reports"),
+            )
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .alias("output-format")
+                    .global(true)
+                    .value_name("FORMAT")
+                    .possible_values(&OUTPUT_FORMATS)
+                    .help("Output format for results, json and tsv are machine readable")
+                    .default_value("human")
+                    .hide_default_value(true)
+                    .env("FFSEND_FORMAT")
+                    .hide_env_values(true),
+            )
+            .arg(
+                Arg::with_name("profile")
+                    .long("profile")
+                    .global(true)
+                    .value_name("NAME")
+                    .help("Use the named profile from the configuration file")
+                    .env("FFSEND_PROFILE")
+                    .hide_env_values(true),
             )
             .arg(ArgApi::build())
             .arg(ArgBasicAuth::build())
+            .arg(ArgProxy::build())
+            .arg(ArgNoProxy::build())
+            .arg(
+                Arg::with_name("header")
+                    .long("header")
+                    .value_name("NAME: VALUE")
+                    .global(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Custom HTTP header to send, may be repeated")
+                    .validator(|arg| {
+                        arg.parse::<Header>()
+                            .map(|_| ())
+                            .map_err(|err| err.to_string())
+                    }),
+            )
+            .arg(
+                Arg::with_name("bearer-token")
+                    .long("bearer-token")
+                    .alias("bearer")
+                    .value_name("TOKEN")
+                    .global(true)
+                    .help("Bearer token to send as HTTP authorization")
+                    .env("FFSEND_BEARER_TOKEN")
+                    .hide_env_values(true),
+            )
+            .arg(
+                Arg::with_name("ca-cert")
+                    .long("ca-cert")
+                    .alias("cacert")
+                    .value_name("FILE")
+                    .global(true)
+                    .help("PEM file with CA certificates to trust")
+                    .env("FFSEND_CA_CERT")
+                    .hide_env_values(true),
+            )
+            .arg(
+                Arg::with_name("client-cert")
+                    .long("client-cert")
+                    .value_name("FILE")
+                    .global(true)
+                    .help("PEM file with a TLS client certificate")
+                    .env("FFSEND_CLIENT_CERT")
+                    .hide_env_values(true),
+            )
+            .arg(
+                Arg::with_name("client-key")
+                    .long("client-key")
+                    .value_name("FILE")
+                    .global(true)
+                    .requires("client-cert")
+                    .help("PEM file with the private key of the TLS client certificate")
+                    .env("FFSEND_CLIENT_KEY")
+                    .hide_env_values(true),
+            )
+            .arg(
+                Arg::with_name("pin-sha256")
+                    .long("pin-sha256")
+                    .alias("pin")
+                    .value_name("HASH")
+                    .global(true)
+                    .help("SHA-256 hash of the public key the server certificate must have")
+                    .env("FFSEND_PIN_SHA256")
+                    .hide_env_values(true)
+                    .validator(|arg| {
+                        parse_pin(&arg).map(|_| ()).ok_or_else(|| {
+                            String::from("Pin must be a SHA-256 hash in hex or base64.")
+                        })
+                    }),
+            )
             .subcommand(CmdDebug::build())
             .subcommand(CmdDelete::build())
             .subcommand(CmdDownload::build().display_order(2))
@@ -159,7 +300,8 @@ impl<'a: 'b, 'b> Handler<'a> {
             .subcommand(CmdParams::build())
             .subcommand(CmdPassword::build())
             .subcommand(CmdUpload::build().display_order(1))
-            .subcommand(CmdVersion::build());
+            .subcommand(CmdVersion::build())
+            .subcommand(CmdWatch::build());
 
         // With history support, a flag for the history file and incognito mode
         #[cfg(feature = "history")]
@@ -186,6 +328,16 @@ impl<'a: 'b, 'b> Handler<'a> {
                     .global(true)
                     .help("Don't update local history for actions"),
             )
+            .arg(
+                Arg::with_name("history-passphrase-cmd")
+                    .long("history-passphrase-cmd")
+                    .alias("history-pass-cmd")
+                    .value_name("COMMAND")
+                    .global(true)
+                    .help("Command to get the history passphrase from, such as a keyring helper")
+                    .env("FFSEND_HISTORY_PASSPHRASE_CMD")
+                    .hide_env_values(true),
+            )
             .subcommand(CmdHistory::build());
 
         // Disable color usage if compiled without color support
@@ -294,4 +446,9 @@ impl<'a: 'b, 'b> Handler<'a> {
     pub fn version(&'a self) -> Option<VersionMatcher> {
         VersionMatcher::with(&self.matches)
     }
+
+    /// Get the watch sub command, if matched.
+    pub fn watch(&'a self) -> Option<WatchMatcher> {
+        WatchMatcher::with(&self.matches)
+    }
 }
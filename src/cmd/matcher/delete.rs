@@ -1,8 +1,14 @@
 use clap::ArgMatches;
+#[cfg(feature = "history")]
+use failure::Fail;
 use ffsend_api::url::Url;
 
 use super::Matcher;
 use crate::cmd::arg::{ArgOwner, ArgUrl, CmdArgOption};
+#[cfg(feature = "history")]
+use crate::host::parse_host_filter;
+#[cfg(feature = "history")]
+use crate::util::{parse_duration, quit_error, quit_error_msg, ErrorHints, ErrorHintsBuilder};
 
 /// The delete command matcher.
 pub struct DeleteMatcher<'a> {
@@ -24,6 +30,55 @@ impl<'a: 'b, 'b> DeleteMatcher<'a> {
         // TODO: just return a string reference here?
         ArgOwner::value(self.matches).map(|token| token.to_owned())
     }
+
+    /// Check whether to delete a batch of files from history, instead of a single URL.
+    #[cfg(feature = "history")]
+    pub fn batch(&'a self) -> bool {
+        self.all()
+            || self.matches.is_present("filter-host")
+            || self.matches.is_present("older-than")
+    }
+
+    /// Check whether to delete all files in history.
+    #[cfg(feature = "history")]
+    pub fn all(&'a self) -> bool {
+        self.matches.is_present("all")
+    }
+
+    /// Get the host to delete files on.
+    ///
+    /// A host without URL scheme is accepted as well.
+    /// If the given host is invalid,
+    /// the program will quit with an error message.
+    #[cfg(feature = "history")]
+    pub fn filter_host(&'a self) -> Option<Url> {
+        let host = self.matches.value_of("filter-host")?;
+        match parse_host_filter(host) {
+            Ok(url) => Some(url),
+            Err(err) => quit_error(
+                err.context("failed to parse the given host to delete files on"),
+                ErrorHints::default(),
+            ),
+        }
+    }
+
+    /// Get the time in seconds files must have been uploaded longer ago than.
+    #[cfg(feature = "history")]
+    pub fn older_than(&'a self) -> Option<usize> {
+        self.matches
+            .value_of("older-than")
+            .map(|t| match parse_duration(t) {
+                Ok(seconds) => seconds,
+                Err(err) => quit_error_msg(
+                    format!("invalid time '{}', {}", t, err),
+                    ErrorHintsBuilder::default()
+                        .add_info("use a time such as '5m', '1h', '1d' or '7d'".into())
+                        .verbose(false)
+                        .build()
+                        .unwrap(),
+                ),
+            })
+    }
 }
 
 impl<'a> Matcher<'a> for DeleteMatcher<'a> {
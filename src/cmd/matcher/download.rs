@@ -1,12 +1,19 @@
+use std::fs;
 use std::path::PathBuf;
 
 use clap::ArgMatches;
+use failure::Fail;
 use ffsend_api::url::Url;
 
 use super::Matcher;
-use crate::cmd::arg::{ArgPassword, ArgUrl, CmdArgOption};
+use crate::cmd::arg::{ArgLimitRate, ArgPassword, ArgUrl, CmdArg, CmdArgOption};
+use crate::config::DOWNLOAD_JOBS;
+#[cfg(feature = "archive")]
+use crate::config_file::CONFIG;
+use crate::throttle::RateLimit;
 #[cfg(feature = "archive")]
 use crate::util::env_var_present;
+use crate::util::{quit_error, quit_error_msg, ErrorHints, ErrorHintsBuilder};
 
 /// The download command matcher.
 pub struct DownloadMatcher<'a> {
@@ -14,20 +21,48 @@ pub struct DownloadMatcher<'a> {
 }
 
 impl<'a: 'b, 'b> DownloadMatcher<'a> {
-    /// Get the file share URL.
+    /// Get the file share URLs.
     ///
-    /// This method parses the URL into an `Url`.
-    /// If the given URL is invalid,
+    /// The URLs given as argument are returned, followed by the URLs listed in the file given
+    /// with `--from-file`. This method parses the URLs into an `Url`.
+    /// If a given URL is invalid, or if the file could not be read,
     /// the program will quit with an error message.
-    pub fn url(&'a self) -> Url {
-        ArgUrl::value(self.matches)
+    pub fn urls(&'a self) -> Vec<Url> {
+        let mut urls: Vec<Url> = self
+            .matches
+            .values_of(ArgUrl::name())
+            .map(|urls| urls.map(|url| ArgUrl::parse(self.matches, url)).collect())
+            .unwrap_or_default();
+
+        // Read the URLs from the given file, skip empty lines and comments
+        if let Some(path) = self.matches.value_of("from-file") {
+            let contents = match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(err) => quit_error(
+                    err.context(format!("failed to read share URLs from '{}'", path)),
+                    ErrorHints::default(),
+                ),
+            };
+            urls.extend(
+                contents
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(|url| ArgUrl::parse(self.matches, url)),
+            );
+        }
+
+        if urls.is_empty() {
+            quit_error_msg(
+                "no share URLs to download",
+                ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+            );
+        }
+        urls
     }
 
-    /// Guess the file share host, based on the file share URL.
-    ///
-    /// See `Self::url`.
-    pub fn guess_host(&'a self, url: Option<Url>) -> Url {
-        let mut url = url.unwrap_or(self.url());
+    /// Guess the file share host, based on the given file share URL.
+    pub fn guess_host(&'a self, mut url: Url) -> Url {
         url.set_path("");
         url.set_query(None);
         url.set_fragment(None);
@@ -50,10 +85,39 @@ impl<'a: 'b, 'b> DownloadMatcher<'a> {
             .unwrap_or_else(|| PathBuf::from("./"))
     }
 
+    /// Check whether to write the downloaded file to stdout.
+    ///
+    /// This is the case if `-` is given as output path.
+    pub fn output_stdout(&'a self) -> bool {
+        self.matches.value_of("output") == Some("-")
+    }
+
+    /// Get the maximum number of files to download at the same time.
+    pub fn jobs(&'a self) -> usize {
+        self.matches
+            .value_of("jobs")
+            .and_then(|jobs| jobs.parse().ok())
+            .unwrap_or(DOWNLOAD_JOBS)
+    }
+
+    /// Get the SHA-256 checksum to verify the downloaded file against, in lowercase.
+    pub fn verify(&'a self) -> Option<String> {
+        self.matches
+            .value_of("verify")
+            .map(|sha256| sha256.trim().to_lowercase())
+    }
+
+    /// Get the transfer rate limit, `None` if unlimited.
+    pub fn limit_rate(&'a self) -> Option<RateLimit> {
+        ArgLimitRate::value(self.matches)
+    }
+
     /// Check whether to extract an archived file.
     #[cfg(feature = "archive")]
     pub fn extract(&self) -> bool {
-        self.matches.is_present("extract") || env_var_present("FFSEND_EXTRACT")
+        self.matches.is_present("extract")
+            || env_var_present("FFSEND_EXTRACT")
+            || CONFIG.settings().extract.unwrap_or(false)
     }
 }
 
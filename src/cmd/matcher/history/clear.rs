@@ -0,0 +1,18 @@
+use clap::ArgMatches;
+
+use super::Matcher;
+
+/// The history clear command matcher.
+pub struct ClearMatcher<'a> {
+    #[allow(unused)]
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Matcher<'a> for ClearMatcher<'a> {
+    fn with(matches: &'a ArgMatches) -> Option<Self> {
+        matches
+            .subcommand_matches("history")?
+            .subcommand_matches("clear")
+            .map(|matches| ClearMatcher { matches })
+    }
+}
@@ -0,0 +1,18 @@
+use clap::ArgMatches;
+
+use super::Matcher;
+
+/// The history decrypt command matcher.
+pub struct DecryptMatcher<'a> {
+    #[allow(unused)]
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Matcher<'a> for DecryptMatcher<'a> {
+    fn with(matches: &'a ArgMatches) -> Option<Self> {
+        matches
+            .subcommand_matches("history")?
+            .subcommand_matches("decrypt")
+            .map(|matches| DecryptMatcher { matches })
+    }
+}
@@ -0,0 +1,18 @@
+use clap::ArgMatches;
+
+use super::Matcher;
+
+/// The history encrypt command matcher.
+pub struct EncryptMatcher<'a> {
+    #[allow(unused)]
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Matcher<'a> for EncryptMatcher<'a> {
+    fn with(matches: &'a ArgMatches) -> Option<Self> {
+        matches
+            .subcommand_matches("history")?
+            .subcommand_matches("encrypt")
+            .map(|matches| EncryptMatcher { matches })
+    }
+}
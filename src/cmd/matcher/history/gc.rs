@@ -0,0 +1,18 @@
+use clap::ArgMatches;
+
+use super::Matcher;
+
+/// The history garbage collect command matcher.
+pub struct GcMatcher<'a> {
+    #[allow(unused)]
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Matcher<'a> for GcMatcher<'a> {
+    fn with(matches: &'a ArgMatches) -> Option<Self> {
+        matches
+            .subcommand_matches("history")?
+            .subcommand_matches("gc")
+            .map(|matches| GcMatcher { matches })
+    }
+}
@@ -0,0 +1,101 @@
+pub mod clear;
+pub mod decrypt;
+pub mod encrypt;
+pub mod gc;
+pub mod remove;
+
+use clap::ArgMatches;
+use failure::Fail;
+use ffsend_api::url::Url;
+
+use super::Matcher;
+use crate::host::parse_host_filter;
+use crate::util::{parse_duration, quit_error, quit_error_msg, ErrorHints, ErrorHintsBuilder};
+use clear::ClearMatcher;
+use decrypt::DecryptMatcher;
+use encrypt::EncryptMatcher;
+use gc::GcMatcher;
+use remove::RemoveMatcher;
+
+/// The history command matcher.
+pub struct HistoryMatcher<'a> {
+    root: &'a ArgMatches<'a>,
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a: 'b, 'b> HistoryMatcher<'a> {
+    /// Get the history remove sub command, if matched.
+    pub fn matcher_remove(&'a self) -> Option<RemoveMatcher> {
+        RemoveMatcher::with(&self.root)
+    }
+
+    /// Get the history clear sub command, if matched.
+    pub fn matcher_clear(&'a self) -> Option<ClearMatcher> {
+        ClearMatcher::with(&self.root)
+    }
+
+    /// Get the history garbage collect sub command, if matched.
+    pub fn matcher_gc(&'a self) -> Option<GcMatcher> {
+        GcMatcher::with(&self.root)
+    }
+
+    /// Get the history encrypt sub command, if matched.
+    pub fn matcher_encrypt(&'a self) -> Option<EncryptMatcher> {
+        EncryptMatcher::with(&self.root)
+    }
+
+    /// Get the history decrypt sub command, if matched.
+    pub fn matcher_decrypt(&'a self) -> Option<DecryptMatcher> {
+        DecryptMatcher::with(&self.root)
+    }
+
+    /// Get the host to filter files by.
+    ///
+    /// A host without URL scheme is accepted as well.
+    /// If the given host is invalid,
+    /// the program will quit with an error message.
+    pub fn filter_host(&'a self) -> Option<Url> {
+        let host = self.matches.value_of("filter-host")?;
+        match parse_host_filter(host) {
+            Ok(url) => Some(url),
+            Err(err) => quit_error(
+                err.context("failed to parse the given host to filter by"),
+                ErrorHints::default(),
+            ),
+        }
+    }
+
+    /// Get the time in seconds files must expire within.
+    pub fn filter_expired_within(&'a self) -> Option<usize> {
+        self.matches
+            .value_of("expired-within")
+            .map(|t| match parse_duration(t) {
+                Ok(seconds) => seconds,
+                Err(err) => quit_error_msg(
+                    format!("invalid time '{}', {}", t, err),
+                    ErrorHintsBuilder::default()
+                        .add_info("use a time such as '5m', '1h', '1d' or '7d'".into())
+                        .verbose(false)
+                        .build()
+                        .unwrap(),
+                ),
+            })
+    }
+
+    /// Get the glob pattern file names must match.
+    pub fn filter_name(&'a self) -> Option<&'a str> {
+        self.matches.value_of("filter-name")
+    }
+
+    /// Check whether to check the live status of each file on its server.
+    pub fn check(&self) -> bool {
+        self.matches.is_present("check")
+    }
+}
+
+impl<'a> Matcher<'a> for HistoryMatcher<'a> {
+    fn with(root: &'a ArgMatches) -> Option<Self> {
+        root.subcommand_matches("history")
+            .map(|matches| HistoryMatcher { root, matches })
+    }
+}
@@ -0,0 +1,43 @@
+use clap::ArgMatches;
+
+use super::Matcher;
+use crate::history::Reference;
+use crate::util::{quit_error_msg, ErrorHintsBuilder};
+
+/// The history remove command matcher.
+pub struct RemoveMatcher<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a: 'b, 'b> RemoveMatcher<'a> {
+    /// Get the references to the files to remove, along with the raw reference.
+    ///
+    /// If a reference is invalid,
+    /// the program will quit with an error message.
+    pub fn files(&'a self) -> Vec<(&'a str, Reference)> {
+        self.matches
+            .values_of("FILE")
+            .expect("no files were given")
+            .map(|raw| match raw.parse() {
+                Ok(reference) => (raw, reference),
+                Err(_) => quit_error_msg(
+                    format!("invalid file reference '{}'", raw),
+                    ErrorHintsBuilder::default()
+                        .add_info("use an index such as '3', a file ID or a share URL".into())
+                        .verbose(false)
+                        .build()
+                        .unwrap(),
+                ),
+            })
+            .collect()
+    }
+}
+
+impl<'a> Matcher<'a> for RemoveMatcher<'a> {
+    fn with(matches: &'a ArgMatches) -> Option<Self> {
+        matches
+            .subcommand_matches("history")?
+            .subcommand_matches("remove")
+            .map(|matches| RemoveMatcher { matches })
+    }
+}
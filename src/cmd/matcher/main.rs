@@ -1,14 +1,30 @@
-#[cfg(feature = "history")]
+use std::env::var;
 use std::path::PathBuf;
+#[cfg(feature = "history")]
+use std::sync::Mutex;
 
 use clap::ArgMatches;
+use failure::Fail;
 use ffsend_api::api::DesiredVersion;
+use ffsend_api::url::Url;
 
 use super::Matcher;
-use crate::cmd::arg::{ArgApi, ArgBasicAuth, CmdArgOption};
+use crate::cmd::arg::{ArgApi, ArgBasicAuth, ArgNoProxy, ArgProxy, CmdArgOption};
+#[cfg(feature = "history")]
+use crate::config_file::CONFIG;
+use crate::headers::{Header, HeaderError};
+use crate::output::OutputFormat;
+use crate::proxy::{env_first, parse_proxy, NO_PROXY_ENV_VARS, PROXY_ENV_VARS};
 use crate::util::env_var_present;
 #[cfg(feature = "history")]
-use crate::util::{quit_error_msg, ErrorHintsBuilder};
+use crate::util::{passphrase_cmd, prompt_history_passphrase, quit_error_msg};
+use crate::util::{print_warning, quit_error, ErrorHintsBuilder};
+
+#[cfg(feature = "history")]
+lazy_static! {
+    /// The passphrase for the history, once obtained.
+    static ref HISTORY_PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+}
 
 /// The main command matcher.
 pub struct MainMatcher<'a> {
@@ -41,6 +57,85 @@ impl<'a: 'b, 'b> MainMatcher<'a> {
         ArgBasicAuth::value(self.matches)
     }
 
+    /// Get the proxy to connect through.
+    ///
+    /// Falls back to the standard proxy environment variables if no proxy is configured, invalid
+    /// proxies in these variables are ignored with a warning.
+    pub fn proxy(&'a self) -> Option<Url> {
+        ArgProxy::value(self.matches).or_else(|| {
+            let (name, proxy) = env_first(&PROXY_ENV_VARS)?;
+            match parse_proxy(&proxy) {
+                Ok(proxy) => Some(proxy),
+                Err(err) => {
+                    print_warning(format!("ignoring invalid proxy in {}: {}", name, err));
+                    None
+                }
+            }
+        })
+    }
+
+    /// Get the hosts to not use the proxy for, as comma separated list.
+    ///
+    /// Falls back to the standard `NO_PROXY` environment variable if not configured.
+    pub fn no_proxy(&'a self) -> Option<String> {
+        ArgNoProxy::value(self.matches)
+            .map(|hosts| hosts.to_owned())
+            .or_else(|| env_first(&NO_PROXY_ENV_VARS).map(|(_, hosts)| hosts))
+    }
+
+    /// Get the custom headers to send with requests.
+    ///
+    /// Headers are taken from the arguments, and from the newline separated `FFSEND_HEADERS`
+    /// environment variable. A bearer token is added as authorization header.
+    /// The program quits with an error if a header is invalid.
+    pub fn headers(&self) -> Vec<Header> {
+        let env = var("FFSEND_HEADERS").unwrap_or_default();
+        let raw = self
+            .matches
+            .values_of("header")
+            .into_iter()
+            .flatten()
+            .chain(env.lines().filter(|line| !line.trim().is_empty()));
+        let headers: Result<Vec<Header>, HeaderError> = raw.map(|header| header.parse()).collect();
+        let mut headers = match headers {
+            Ok(headers) => headers,
+            Err(err) => quit_error(
+                err.context("failed to parse custom header"),
+                ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+            ),
+        };
+        if let Some(token) = self.matches.value_of("bearer-token") {
+            match Header::bearer(token) {
+                Ok(header) => headers.push(header),
+                Err(err) => quit_error(
+                    err.context("invalid bearer token"),
+                    ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+                ),
+            }
+        }
+        headers
+    }
+
+    /// Get the PEM file with CA certificates to trust.
+    pub fn ca_cert(&self) -> Option<PathBuf> {
+        self.matches.value_of("ca-cert").map(PathBuf::from)
+    }
+
+    /// Get the PEM file with the TLS client certificate.
+    pub fn client_cert(&self) -> Option<PathBuf> {
+        self.matches.value_of("client-cert").map(PathBuf::from)
+    }
+
+    /// Get the PEM file with the private key of the TLS client certificate.
+    pub fn client_key(&self) -> Option<PathBuf> {
+        self.matches.value_of("client-key").map(PathBuf::from)
+    }
+
+    /// Get the SHA-256 hash of the public key the server certificate must have.
+    pub fn pin_sha256(&self) -> Option<&str> {
+        self.matches.value_of("pin-sha256")
+    }
+
     /// Get the history file to use.
     #[cfg(feature = "history")]
     pub fn history(&self) -> PathBuf {
@@ -61,6 +156,54 @@ impl<'a: 'b, 'b> MainMatcher<'a> {
         }
     }
 
+    /// Get the passphrase to encrypt or decrypt the history with.
+    ///
+    /// The passphrase is taken from the `FFSEND_HISTORY_PASSPHRASE` environment variable, from the
+    /// passphrase command if set, or is prompted for. If prompted and `confirm` is set, the
+    /// passphrase must be entered twice.
+    ///
+    /// The program will quit with an error if no passphrase could be obtained.
+    ///
+    /// The passphrase is obtained once, and is reused when the history is loaded again.
+    #[cfg(feature = "history")]
+    pub fn history_passphrase(&self, confirm: bool) -> String {
+        let mut cached = HISTORY_PASSPHRASE.lock().unwrap();
+        if let Some(passphrase) = cached.as_ref() {
+            return passphrase.clone();
+        }
+        let passphrase = self.history_passphrase_uncached(confirm);
+        *cached = Some(passphrase.clone());
+        passphrase
+    }
+
+    /// Obtain the passphrase to encrypt or decrypt the history with, see `history_passphrase`.
+    #[cfg(feature = "history")]
+    fn history_passphrase_uncached(&self, confirm: bool) -> String {
+        if let Ok(passphrase) = var("FFSEND_HISTORY_PASSPHRASE") {
+            return passphrase;
+        }
+
+        // Use the passphrase command from the arguments or configuration
+        let cmd = self.matches.value_of("history-passphrase-cmd").or_else(|| {
+            CONFIG
+                .settings()
+                .history_passphrase_cmd
+                .as_ref()
+                .map(String::as_str)
+        });
+        if let Some(cmd) = cmd {
+            return match passphrase_cmd(cmd) {
+                Ok(passphrase) => passphrase,
+                Err(err) => quit_error(
+                    err.context("failed to get history passphrase from command"),
+                    ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+                ),
+            };
+        }
+
+        prompt_history_passphrase(self, confirm)
+    }
+
     /// Get the timeout in seconds
     pub fn timeout(&self) -> u64 {
         self.matches
@@ -77,12 +220,28 @@ impl<'a: 'b, 'b> MainMatcher<'a> {
             .expect("invalid transfer-timeout value")
     }
 
+    /// Get the number of times to retry failed transfers.
+    pub fn retries(&self) -> u32 {
+        self.matches
+            .value_of("retries")
+            .and_then(|arg| arg.parse().ok())
+            .expect("invalid retries value")
+    }
+
     /// Check whether we are incognito from the file history.
     #[cfg(feature = "history")]
     pub fn incognito(&self) -> bool {
         self.matches.is_present("incognito") || env_var_present("FFSEND_INCOGNITO")
     }
 
+    /// Get the format to print results in.
+    pub fn output_format(&self) -> OutputFormat {
+        self.matches
+            .value_of("format")
+            .and_then(|format| format.parse().ok())
+            .unwrap_or(OutputFormat::Human)
+    }
+
     /// Check whether quiet mode is used.
     pub fn quiet(&self) -> bool {
         !self.verbose() && (self.matches.is_present("quiet") || env_var_present("FFSEND_QUIET"))
@@ -90,7 +249,22 @@ impl<'a: 'b, 'b> MainMatcher<'a> {
 
     /// Check whether verbose mode is used.
     pub fn verbose(&self) -> bool {
-        self.matches.is_present("verbose") || env_var_present("FFSEND_VERBOSE")
+        self.verbosity() > 0
+    }
+
+    /// Get the verbosity level, the number of times the verbose flag is given.
+    ///
+    /// The `FFSEND_VERBOSE` variable counts as a single flag.
+    pub fn verbosity(&self) -> u64 {
+        match self.matches.occurrences_of("verbose") {
+            0 if env_var_present("FFSEND_VERBOSE") => 1,
+            count => count,
+        }
+    }
+
+    /// Get the path of the file to write a log to.
+    pub fn log_file(&self) -> Option<PathBuf> {
+        self.matches.value_of("log-file").map(PathBuf::from)
     }
 }
 
@@ -11,6 +11,7 @@ pub mod params;
 pub mod password;
 pub mod upload;
 pub mod version;
+pub mod watch;
 
 // Re-export to matcher module
 pub use self::debug::DebugMatcher;
@@ -26,6 +27,7 @@ pub use self::params::ParamsMatcher;
 pub use self::password::PasswordMatcher;
 pub use self::upload::{CopyMode, UploadMatcher};
 pub use self::version::VersionMatcher;
+pub use self::watch::WatchMatcher;
 
 use clap::ArgMatches;
 
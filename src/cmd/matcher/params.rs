@@ -2,7 +2,7 @@ use clap::ArgMatches;
 use ffsend_api::url::Url;
 
 use super::Matcher;
-use crate::cmd::arg::{ArgDownloadLimit, ArgOwner, ArgUrl, CmdArgOption};
+use crate::cmd::arg::{ArgDownloadLimit, ArgExpiryTime, ArgOwner, ArgUrl, CmdArgOption};
 
 /// The params command matcher.
 pub struct ParamsMatcher<'a> {
@@ -29,6 +29,11 @@ impl<'a: 'b, 'b> ParamsMatcher<'a> {
     pub fn download_limit(&'a self) -> Option<u8> {
         ArgDownloadLimit::value(self.matches)
     }
+
+    /// Get the expiry time in seconds.
+    pub fn expiry_time(&'a self) -> Option<usize> {
+        ArgExpiryTime::value(self.matches)
+    }
 }
 
 impl<'a> Matcher<'a> for ParamsMatcher<'a> {
@@ -3,9 +3,18 @@ use ffsend_api::action::params::PARAMS_DEFAULT_DOWNLOAD as DOWNLOAD_DEFAULT;
 use ffsend_api::url::Url;
 
 use super::Matcher;
+#[cfg(feature = "archive")]
+use crate::archive::{
+    archiver::Filter,
+    format::{ArchiveFormat, ARCHIVE_FORMATS},
+};
 use crate::cmd::arg::{
-    ArgDownloadLimit, ArgGenPassphrase, ArgHost, ArgPassword, CmdArgFlag, CmdArgOption,
+    ArgDownloadLimit, ArgExpiryTime, ArgGenPassphrase, ArgHost, ArgLimitRate, ArgPassword,
+    CmdArgFlag, CmdArgOption,
 };
+#[cfg(feature = "archive")]
+use crate::config_file::CONFIG;
+use crate::throttle::RateLimit;
 use crate::util::{bin_name, env_var_present, quit_error_msg, ErrorHintsBuilder};
 
 /// The upload command matcher.
@@ -14,12 +23,22 @@ pub struct UploadMatcher<'a> {
 }
 
 impl<'a: 'b, 'b> UploadMatcher<'a> {
-    /// Get the selected file to upload.
-    // TODO: maybe return a file or path instance here
-    pub fn file(&'a self) -> &'a str {
+    /// Get the selected files to upload.
+    ///
+    /// At least one file is always returned.
+    // TODO: maybe return file or path instances here
+    pub fn files(&'a self) -> Vec<&'a str> {
         self.matches
-            .value_of("FILE")
-            .expect("no file specified to upload")
+            .values_of("FILE")
+            .map(|files| files.collect())
+            .expect("no files specified to upload")
+    }
+
+    /// Check whether to upload data read from stdin.
+    ///
+    /// This is the case if `-` is given as file to upload.
+    pub fn stdin(&'a self) -> bool {
+        self.files().contains(&"-")
     }
 
     /// The the name to use for the uploaded file.
@@ -82,10 +101,66 @@ impl<'a: 'b, 'b> UploadMatcher<'a> {
         })
     }
 
+    /// Get the expiry time in seconds.
+    /// If no expiry time was given, `None` is returned to use the server default.
+    pub fn expiry_time(&'a self) -> Option<usize> {
+        ArgExpiryTime::value(self.matches)
+    }
+
+    /// Get the transfer rate limit, `None` if unlimited.
+    pub fn limit_rate(&'a self) -> Option<RateLimit> {
+        ArgLimitRate::value(self.matches)
+    }
+
     /// Check whether to archive the file to upload.
     #[cfg(feature = "archive")]
     pub fn archive(&self) -> bool {
-        self.matches.is_present("archive") || env_var_present("FFSEND_ARCHIVE")
+        self.matches.is_present("archive")
+            || self.matches.is_present("archive-format")
+            || self.archive_list()
+            || env_var_present("FFSEND_ARCHIVE")
+            || CONFIG.settings().archive.unwrap_or(false)
+    }
+
+    /// Get the archive format to use.
+    ///
+    /// If no format is given, `None` is returned to select a format based on the files.
+    #[cfg(feature = "archive")]
+    pub fn archive_format(&self) -> Option<ArchiveFormat> {
+        // Get the format from the arguments, or from the configuration
+        let format = match self.matches.value_of("archive-format") {
+            Some(format) => format,
+            None => CONFIG.settings().archive_format.as_ref()?.as_str(),
+        };
+
+        match format.parse() {
+            Ok(format) => Some(format),
+            Err(_) => quit_error_msg(
+                format!("invalid archive format '{}' in configuration", format),
+                ErrorHintsBuilder::default()
+                    .add_info(format!("Use one of: {}", ARCHIVE_FORMATS.join(", ")))
+                    .verbose(false)
+                    .build()
+                    .unwrap(),
+            ),
+        }
+    }
+
+    /// Get the filter for files to exclude when archiving directories.
+    #[cfg(feature = "archive")]
+    pub fn archive_filter(&self) -> Filter {
+        let excludes = self
+            .matches
+            .values_of("exclude")
+            .map(|excludes| excludes.map(|e| e.to_owned()).collect())
+            .unwrap_or_default();
+        Filter::new(excludes, self.matches.is_present("gitignore"))
+    }
+
+    /// Check whether to only list the files that would be archived, without uploading.
+    #[cfg(feature = "archive")]
+    pub fn archive_list(&self) -> bool {
+        self.matches.is_present("archive-list")
     }
 
     /// Check whether to open the file URL in the user's browser.
@@ -93,6 +168,11 @@ impl<'a: 'b, 'b> UploadMatcher<'a> {
         self.matches.is_present("open") || env_var_present("FFSEND_OPEN")
     }
 
+    /// Check whether to split files bigger than the server limit into parts.
+    pub fn split(&self) -> bool {
+        self.matches.is_present("split") || env_var_present("FFSEND_SPLIT")
+    }
+
     /// Check whether to copy the file URL in the user's clipboard, get the copy mode.
     #[cfg(feature = "clipboard")]
     pub fn copy(&self) -> Option<CopyMode> {
@@ -0,0 +1,70 @@
+use ffsend_api::url::Url;
+
+use clap::ArgMatches;
+
+use super::Matcher;
+use crate::cmd::arg::{ArgOwner, ArgUrl, CmdArgOption};
+use crate::util::{parse_duration, quit_error_msg, ErrorHintsBuilder};
+
+/// The watch command matcher.
+pub struct WatchMatcher<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a: 'b, 'b> WatchMatcher<'a> {
+    /// Get the file share URL.
+    ///
+    /// This method parses the URL into an `Url`.
+    /// If the given URL is invalid,
+    /// the program will quit with an error message.
+    pub fn url(&'a self) -> Url {
+        ArgUrl::value(self.matches)
+    }
+
+    /// Get the owner token.
+    pub fn owner(&'a self) -> Option<String> {
+        ArgOwner::value(self.matches).map(|token| token.to_owned())
+    }
+
+    /// Get the interval in seconds to poll the file info at.
+    ///
+    /// The interval is at least one second.
+    pub fn interval(&'a self) -> usize {
+        Self::parse_time(self.matches.value_of("interval").unwrap()).max(1)
+    }
+
+    /// Get the maximum time in seconds to watch the file for.
+    pub fn max_time(&'a self) -> Option<usize> {
+        self.matches.value_of("max-time").map(Self::parse_time)
+    }
+
+    /// Get the shell command to run on each download.
+    pub fn exec(&'a self) -> Option<&'a str> {
+        self.matches.value_of("exec")
+    }
+
+    /// Parse the given human readable time into a number of seconds.
+    ///
+    /// If the time is invalid, the program will quit with an error message.
+    fn parse_time(time: &str) -> usize {
+        match parse_duration(time) {
+            Ok(seconds) => seconds,
+            Err(err) => quit_error_msg(
+                format!("invalid time '{}', {}", time, err),
+                ErrorHintsBuilder::default()
+                    .add_info("use a time such as '30s', '5m' or '1h'".into())
+                    .verbose(false)
+                    .build()
+                    .unwrap(),
+            ),
+        }
+    }
+}
+
+impl<'a> Matcher<'a> for WatchMatcher<'a> {
+    fn with(matches: &'a ArgMatches) -> Option<Self> {
+        matches
+            .subcommand_matches("watch")
+            .map(|matches| WatchMatcher { matches })
+    }
+}
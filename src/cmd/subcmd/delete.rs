@@ -1,3 +1,5 @@
+#[cfg(feature = "history")]
+use clap::Arg;
 use clap::{App, SubCommand};
 
 use crate::cmd::arg::{ArgOwner, ArgUrl, CmdArg};
@@ -7,10 +9,55 @@ pub struct CmdDelete;
 
 impl CmdDelete {
     pub fn build<'a, 'b>() -> App<'a, 'b> {
-        SubCommand::with_name("delete")
+        // Create the subcommand
+        let mut cmd = SubCommand::with_name("delete")
             .about("Delete a shared file")
             .visible_alias("del")
-            .arg(ArgUrl::build())
-            .arg(ArgOwner::build())
+            .arg(ArgOwner::build());
+
+        // Without history support, only a single URL may be deleted
+        #[cfg(not(feature = "history"))]
+        {
+            cmd = cmd.arg(ArgUrl::build());
+        }
+
+        // With history support, delete a batch of files from history instead of a single URL
+        #[cfg(feature = "history")]
+        {
+            cmd = cmd
+                .arg(ArgUrl::build().required_unless_one(&["all", "filter-host", "older-than"]))
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .short("a")
+                        .conflicts_with_all(&["URL", "owner"])
+                        .help("Delete all files in history")
+                        .long_help(
+                            "Delete all files in history that have an owner token. Files that \
+                             fail to delete are reported, the other files are deleted anyway.",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("filter-host")
+                        .long("host")
+                        .value_name("URL")
+                        .conflicts_with_all(&["URL", "owner"])
+                        .help("Delete all files in history on the given host"),
+                )
+                .arg(
+                    Arg::with_name("older-than")
+                        .long("older-than")
+                        .value_name("TIME")
+                        .conflicts_with_all(&["URL", "owner"])
+                        .help("Delete all files in history uploaded longer ago than the given time")
+                        .long_help(
+                            "Delete all files in history uploaded longer ago than the given \
+                             time, such as 1h or 1d. Files with an unknown upload time are not \
+                             deleted. May be combined with --host.",
+                        ),
+                );
+        }
+
+        cmd
     }
 }
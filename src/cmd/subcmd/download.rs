@@ -1,6 +1,13 @@
 use clap::{App, Arg, SubCommand};
 
-use crate::cmd::arg::{ArgPassword, ArgUrl, CmdArg};
+use crate::checksum::is_sha256;
+use crate::cmd::arg::{ArgLimitRate, ArgPassword, ArgUrl, CmdArg};
+use crate::config::DOWNLOAD_JOBS;
+
+lazy_static! {
+    /// The default number of files to download at the same time
+    static ref DEFAULT_JOBS: String = format!("{}", DOWNLOAD_JOBS);
+}
 
 /// The download command definition.
 pub struct CmdDownload;
@@ -13,8 +20,14 @@ impl CmdDownload {
             .about("Download files")
             .visible_alias("d")
             .visible_alias("down")
-            .arg(ArgUrl::build())
+            .arg(
+                ArgUrl::build()
+                    .multiple(true)
+                    .required_unless("from-file")
+                    .help("The share URL(s)"),
+            )
             .arg(ArgPassword::build())
+            .arg(ArgLimitRate::build())
             .arg(
                 Arg::with_name("output")
                     .long("output")
@@ -23,7 +36,58 @@ impl CmdDownload {
                     .alias("out")
                     .alias("file")
                     .value_name("PATH")
-                    .help("Output file or directory"),
+                    .help("Output file or directory, '-' for stdout"),
+            )
+            .arg(
+                Arg::with_name("from-file")
+                    .long("from-file")
+                    .short("F")
+                    .alias("input-file")
+                    .value_name("FILE")
+                    .help("Download the share URLs listed in the given file")
+                    .long_help(
+                        "Download the share URLs listed in the given file, one per line. Empty \
+                         lines and lines starting with '#' are ignored.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("jobs")
+                    .long("jobs")
+                    .short("j")
+                    .alias("parallel")
+                    .value_name("COUNT")
+                    .default_value(&DEFAULT_JOBS)
+                    .help("Maximum number of files to download at the same time")
+                    .long_help(
+                        "Maximum number of files to download at the same time, when downloading \
+                         multiple files. The output path is used as directory. When extracting \
+                         multiple archives, existing files are only overwritten when forced.",
+                    )
+                    .validator(|arg| match arg.parse::<usize>() {
+                        Ok(jobs) if jobs > 0 => Ok(()),
+                        _ => Err(String::from("Job count must be a positive number")),
+                    }),
+            )
+            .arg(
+                Arg::with_name("verify")
+                    .long("verify")
+                    .alias("sha256")
+                    .value_name("SHA256")
+                    .help("Verify the SHA-256 checksum of the downloaded file")
+                    .long_help(
+                        "Verify the SHA-256 checksum of the downloaded file, given as hexadecimal \
+                         string. The download fails and the downloaded file is removed if it \
+                         doesn't match.",
+                    )
+                    .validator(|arg| {
+                        if is_sha256(arg.trim()) {
+                            Ok(())
+                        } else {
+                            Err(String::from(
+                                "Checksum must be a SHA-256 hash of 64 hexadecimal characters",
+                            ))
+                        }
+                    }),
             );
 
         // Optional archive support
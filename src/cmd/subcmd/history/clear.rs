@@ -0,0 +1,13 @@
+use clap::{App, SubCommand};
+
+/// The history clear command definition.
+pub struct CmdClear;
+
+impl CmdClear {
+    pub fn build<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name("clear")
+            .about("Remove all files from history")
+            .alias("clean")
+            .alias("purge")
+    }
+}
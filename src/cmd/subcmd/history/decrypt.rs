@@ -0,0 +1,13 @@
+use clap::{App, SubCommand};
+
+/// The history decrypt command definition.
+pub struct CmdDecrypt;
+
+impl CmdDecrypt {
+    pub fn build<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name("decrypt")
+            .about("Decrypt the history file, store it in plain text")
+            .alias("dec")
+            .alias("unlock")
+    }
+}
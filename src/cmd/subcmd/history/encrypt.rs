@@ -0,0 +1,13 @@
+use clap::{App, SubCommand};
+
+/// The history encrypt command definition.
+pub struct CmdEncrypt;
+
+impl CmdEncrypt {
+    pub fn build<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name("encrypt")
+            .about("Encrypt the history file with a passphrase")
+            .alias("enc")
+            .alias("lock")
+    }
+}
@@ -0,0 +1,12 @@
+use clap::{App, SubCommand};
+
+/// The history garbage collect command definition.
+pub struct CmdGc;
+
+impl CmdGc {
+    pub fn build<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name("gc")
+            .about("Remove expired files from history")
+            .alias("garbage-collect")
+    }
+}
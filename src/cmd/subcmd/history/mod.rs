@@ -0,0 +1,64 @@
+pub mod clear;
+pub mod decrypt;
+pub mod encrypt;
+pub mod gc;
+pub mod remove;
+
+use clap::{App, Arg, SubCommand};
+
+use clear::CmdClear;
+use decrypt::CmdDecrypt;
+use encrypt::CmdEncrypt;
+use gc::CmdGc;
+use remove::CmdRemove;
+
+/// The history command definition.
+pub struct CmdHistory;
+
+impl CmdHistory {
+    pub fn build<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name("history")
+            .about("View file history")
+            .visible_alias("h")
+            .alias("ls")
+            .arg(
+                Arg::with_name("filter-host")
+                    .long("host")
+                    .value_name("URL")
+                    .help("Only show files on the given host"),
+            )
+            .arg(
+                Arg::with_name("expired-within")
+                    .long("expired-within")
+                    .short("e")
+                    .alias("expire-within")
+                    .alias("expiring-within")
+                    .value_name("TIME")
+                    .help("Only show files expiring within the given time, such as 1h or 1d"),
+            )
+            .arg(
+                Arg::with_name("filter-name")
+                    .long("name")
+                    .short("n")
+                    .value_name("GLOB")
+                    .help("Only show files with a name matching the given pattern"),
+            )
+            .arg(
+                Arg::with_name("check")
+                    .long("check")
+                    .short("c")
+                    .alias("status")
+                    .help("Check the live status of each file on its server")
+                    .long_help(
+                        "Check the live status of each file on its server. Shows the live \
+                         download count and remaining expiry time, files the server reports as \
+                         gone are removed from history.",
+                    ),
+            )
+            .subcommand(CmdRemove::build())
+            .subcommand(CmdClear::build())
+            .subcommand(CmdGc::build())
+            .subcommand(CmdEncrypt::build())
+            .subcommand(CmdDecrypt::build())
+    }
+}
@@ -0,0 +1,20 @@
+use clap::{App, Arg, SubCommand};
+
+/// The history remove command definition.
+pub struct CmdRemove;
+
+impl CmdRemove {
+    pub fn build<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name("remove")
+            .about("Remove files from history")
+            .visible_alias("rm")
+            .alias("delete")
+            .alias("del")
+            .arg(
+                Arg::with_name("FILE")
+                    .help("The file(s) to remove, by index, ID or share URL")
+                    .required(true)
+                    .multiple(true),
+            )
+    }
+}
@@ -10,6 +10,7 @@ pub mod params;
 pub mod password;
 pub mod upload;
 pub mod version;
+pub mod watch;
 
 // Re-export to cmd module
 pub use self::debug::CmdDebug;
@@ -24,3 +25,4 @@ pub use self::params::CmdParams;
 pub use self::password::CmdPassword;
 pub use self::upload::CmdUpload;
 pub use self::version::CmdVersion;
+pub use self::watch::CmdWatch;
@@ -1,6 +1,6 @@
 use clap::{App, SubCommand};
 
-use crate::cmd::arg::{ArgDownloadLimit, ArgOwner, ArgUrl, CmdArg};
+use crate::cmd::arg::{ArgDownloadLimit, ArgExpiryTime, ArgOwner, ArgUrl, CmdArg};
 
 /// The params command definition.
 pub struct CmdParams;
@@ -8,7 +8,7 @@ pub struct CmdParams;
 impl CmdParams {
     pub fn build<'a, 'b>() -> App<'a, 'b> {
         // Create a list of parameter arguments, of which one is required
-        let param_args = [ArgDownloadLimit::name()];
+        let param_args = [ArgDownloadLimit::name(), ArgExpiryTime::name()];
 
         SubCommand::with_name("parameters")
             .about("Change parameters of a shared file")
@@ -18,5 +18,6 @@ impl CmdParams {
             .arg(ArgUrl::build())
             .arg(ArgOwner::build())
             .arg(ArgDownloadLimit::build().required_unless_one(&param_args))
+            .arg(ArgExpiryTime::build().required_unless_one(&param_args))
     }
 }
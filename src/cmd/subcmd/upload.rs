@@ -1,7 +1,21 @@
 use clap::{App, Arg, SubCommand};
 use ffsend_api::action::params::PARAMS_DEFAULT_DOWNLOAD_STR as DOWNLOAD_DEFAULT;
 
-use crate::cmd::arg::{ArgDownloadLimit, ArgGenPassphrase, ArgHost, ArgPassword, CmdArg};
+#[cfg(feature = "archive")]
+use crate::archive::format::ARCHIVE_FORMATS;
+use crate::cmd::arg::{
+    ArgDownloadLimit, ArgExpiryTime, ArgGenPassphrase, ArgHost, ArgLimitRate, ArgPassword, CmdArg,
+};
+use crate::config_file::CONFIG;
+
+lazy_static! {
+    /// The default download limit as a string, from the configuration if set
+    static ref DEFAULT_DOWNLOAD_LIMIT: String = CONFIG
+        .settings()
+        .download_limit
+        .map(|limit| limit.to_string())
+        .unwrap_or_else(|| DOWNLOAD_DEFAULT.into());
+}
 
 /// The upload command definition.
 pub struct CmdUpload;
@@ -16,14 +30,16 @@ impl CmdUpload {
             .visible_alias("up")
             .arg(
                 Arg::with_name("FILE")
-                    .help("The file to upload")
+                    .help("The file(s) to upload, '-' to read from stdin")
                     .required(true)
-                    .multiple(false),
+                    .multiple(true),
             )
             .arg(ArgPassword::build().help("Protect the file with a password"))
             .arg(ArgGenPassphrase::build())
-            .arg(ArgDownloadLimit::build().default_value(DOWNLOAD_DEFAULT))
+            .arg(ArgDownloadLimit::build().default_value(&DEFAULT_DOWNLOAD_LIMIT))
+            .arg(ArgExpiryTime::build())
             .arg(ArgHost::build())
+            .arg(ArgLimitRate::build())
             .arg(
                 Arg::with_name("name")
                     .long("name")
@@ -38,18 +54,53 @@ impl CmdUpload {
                     .long("open")
                     .short("o")
                     .help("Open the share link in your browser"),
+            )
+            .arg(
+                Arg::with_name("split")
+                    .long("split")
+                    .alias("parts")
+                    .help("Upload files bigger than the server limit in linked parts"),
             );
 
         // Optional archive support
         #[cfg(feature = "archive")]
         {
-            cmd = cmd.arg(
-                Arg::with_name("archive")
-                    .long("archive")
-                    .short("a")
-                    .alias("arch")
-                    .help("Archive the upload in a single file"),
-            )
+            cmd = cmd
+                .arg(
+                    Arg::with_name("archive")
+                        .long("archive")
+                        .short("a")
+                        .alias("arch")
+                        .help("Archive the upload in a single file"),
+                )
+                .arg(
+                    Arg::with_name("archive-format")
+                        .long("archive-format")
+                        .value_name("FORMAT")
+                        .possible_values(&ARCHIVE_FORMATS)
+                        .env("FFSEND_ARCHIVE_FORMAT")
+                        .hide_env_values(true)
+                        .help("The archive format to use, implies --archive"),
+                )
+                .arg(
+                    Arg::with_name("exclude")
+                        .long("exclude")
+                        .short("x")
+                        .value_name("GLOB")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Exclude files and directories matching a glob when archiving"),
+                )
+                .arg(
+                    Arg::with_name("gitignore")
+                        .long("gitignore")
+                        .help("Exclude files ignored by .gitignore files when archiving"),
+                )
+                .arg(
+                    Arg::with_name("archive-list")
+                        .long("archive-list")
+                        .help("List the files that would be archived, without uploading"),
+                )
         }
 
         // Optional clipboard support
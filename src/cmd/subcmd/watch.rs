@@ -0,0 +1,63 @@
+use clap::{App, Arg, SubCommand};
+
+use crate::cmd::arg::{ArgOwner, ArgUrl, CmdArg};
+use crate::config::{WATCH_EXIT_EXPIRED, WATCH_EXIT_TIMEOUT, WATCH_INTERVAL};
+
+lazy_static! {
+    /// The default interval to poll the file info at
+    static ref DEFAULT_INTERVAL: String = format!("{}s", WATCH_INTERVAL);
+
+    /// The long help for the watch command, listing the exit codes
+    static ref LONG_ABOUT: String = format!(
+        "Watch a shared file, and report each time it is downloaded.\n\n\
+         Polls the file info until the file reaches its download limit, expires or the maximum \
+         watch time elapses. The owner token is required, it is taken from history if known.\n\n\
+         Exits with code 0 when the download limit is reached, {} when the file expired or was \
+         deleted and {} when the maximum watch time elapsed.",
+        WATCH_EXIT_EXPIRED, WATCH_EXIT_TIMEOUT,
+    );
+}
+
+/// The watch command definition.
+pub struct CmdWatch;
+
+impl CmdWatch {
+    pub fn build<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name("watch")
+            .about("Watch a shared file until it is downloaded")
+            .long_about(LONG_ABOUT.as_str())
+            .visible_alias("w")
+            .alias("wait")
+            .arg(ArgUrl::build())
+            .arg(ArgOwner::build())
+            .arg(
+                Arg::with_name("interval")
+                    .long("interval")
+                    .alias("every")
+                    .value_name("TIME")
+                    .default_value(&DEFAULT_INTERVAL)
+                    .help("The interval to poll the file info at, such as 10s or 1m"),
+            )
+            .arg(
+                Arg::with_name("max-time")
+                    .long("max-time")
+                    .short("m")
+                    .alias("watch-timeout")
+                    .value_name("TIME")
+                    .help("Stop watching after the given time, such as 1h or 1d"),
+            )
+            .arg(
+                Arg::with_name("exec")
+                    .long("exec")
+                    .short("x")
+                    .alias("run")
+                    .value_name("CMD")
+                    .help("Run the given shell command on each download")
+                    .long_help(
+                        "Run the given shell command each time the file is downloaded. The \
+                         FFSEND_ID, FFSEND_URL, FFSEND_DOWNLOADS and FFSEND_DOWNLOAD_LIMIT \
+                         environment variables are set for the command.",
+                    ),
+            )
+    }
+}
@@ -10,6 +10,38 @@ pub const CLIENT_TIMEOUT: u64 = 30;
 /// Make sure this is big enough, or file uploads will be dropped. `0` to disable.
 pub const CLIENT_TRANSFER_TIMEOUT: u64 = 24 * 60 * 60;
 
+/// The number of times to retry a file transfer (upload/download) that failed due to a transient
+/// error, such as a dropped connection. `0` to disable.
+pub const TRANSFER_RETRIES: u32 = 3;
+
+/// The delay in seconds before retrying a failed transfer for the first time.
+/// The delay is doubled on each following retry, up to `TRANSFER_RETRY_DELAY_MAX`.
+pub const TRANSFER_RETRY_DELAY: u64 = 2;
+
+/// The maximum delay in seconds before retrying a failed transfer.
+pub const TRANSFER_RETRY_DELAY_MAX: u64 = 60;
+
+/// The default maximum number of files to download at the same time, when downloading multiple
+/// files.
+pub const DOWNLOAD_JOBS: usize = 4;
+
+/// The time in seconds to wait for the history file lock held by another process, before giving up.
+#[cfg(feature = "history")]
+pub const HISTORY_LOCK_TIMEOUT: u64 = 30;
+
+/// The maximum number of history files to check the status of at the same time.
+#[cfg(feature = "history")]
+pub const HISTORY_CHECK_CONCURRENCY: usize = 8;
+
+/// The default interval in seconds to poll the file info at when watching a file.
+pub const WATCH_INTERVAL: u64 = 10;
+
+/// The exit code used when a watched file expired, or was deleted before reaching its limit.
+pub const WATCH_EXIT_EXPIRED: i32 = 2;
+
+/// The exit code used when the maximum watch time elapsed before the file reached its limit.
+pub const WATCH_EXIT_TIMEOUT: i32 = 3;
+
 /// The default desired version to select for the server API.
 pub const API_VERSION_DESIRED_DEFAULT: DesiredVersion = DesiredVersion::Assume(API_VERSION_ASSUME);
 
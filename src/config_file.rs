@@ -0,0 +1,356 @@
+extern crate toml;
+
+use std::collections::HashMap;
+use std::env::{self, var_os};
+use std::fs;
+use std::io::Error as IoError;
+use std::path::PathBuf;
+
+use self::toml::de::Error as DeError;
+use clap::ArgMatches;
+use failure::Fail;
+use ffsend_api::url::Url;
+
+use crate::util::{app_project_dirs, quit_error, ErrorHintsBuilder};
+
+/// The name of the configuration file, in the application configuration directory.
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+lazy_static! {
+    /// The loaded configuration, with the selected profile applied.
+    ///
+    /// The application quits with an error if the configuration file could not be loaded, or if
+    /// the selected profile does not exist.
+    pub static ref CONFIG: Config = match Config::load(Config::path(), selected_profile()) {
+        Ok(config) => config,
+        Err(err) => quit_error(
+            err.context("failed to load configuration file"),
+            ErrorHintsBuilder::default().verbose(false).build().unwrap(),
+        ),
+    };
+}
+
+/// The configuration file.
+///
+/// The `defaults` table sets defaults for all invocations, each table in `profiles` holds a
+/// named profile that overrides these defaults when selected with `--profile`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    /// The default settings.
+    #[serde(default)]
+    defaults: Settings,
+
+    /// Named profiles.
+    #[serde(default)]
+    profiles: HashMap<String, Settings>,
+
+    /// TLS settings for specific hosts, by host name.
+    #[serde(default)]
+    hosts: HashMap<String, HostSettings>,
+}
+
+/// A set of settings, used as defaults for command line arguments.
+///
+/// Every setting is optional, unset settings fall back to the built-in default.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// The host to upload to.
+    pub host: Option<String>,
+
+    /// The server API version to use.
+    pub api: Option<String>,
+
+    /// The request timeout in seconds.
+    pub timeout: Option<u64>,
+
+    /// The transfer timeout in seconds.
+    pub transfer_timeout: Option<u64>,
+
+    /// The number of times to retry failed transfers.
+    pub retries: Option<u32>,
+
+    /// The download limit for uploaded files.
+    pub download_limit: Option<u8>,
+
+    /// Whether to archive uploaded files.
+    pub archive: Option<bool>,
+
+    /// The archive format to use, such as `tar.gz`, `tar.zst` or `zip`.
+    pub archive_format: Option<String>,
+
+    /// Whether to extract downloaded archives.
+    pub extract: Option<bool>,
+
+    /// HTTP basic authentication credentials, as `USER:PASSWORD`.
+    pub basic_auth: Option<String>,
+
+    /// The HTTP or SOCKS5 proxy to connect through, such as `http://proxy:8080`.
+    pub proxy: Option<String>,
+
+    /// Comma separated hosts to not use the proxy for.
+    pub no_proxy: Option<String>,
+
+    /// A PEM file with CA certificates to trust.
+    pub ca_cert: Option<PathBuf>,
+
+    /// A PEM file with a TLS client certificate.
+    pub client_cert: Option<PathBuf>,
+
+    /// A PEM file with the private key of the TLS client certificate.
+    pub client_key: Option<PathBuf>,
+
+    /// The SHA-256 hash of the public key the server certificate must have.
+    pub pin_sha256: Option<String>,
+
+    /// The transfer rate limit, such as `2M`.
+    pub limit_rate: Option<String>,
+
+    /// A schedule of transfer rate limits, from times of the day such as `19:00` to rates.
+    pub limit_rate_schedule: Option<HashMap<String, String>>,
+
+    /// The history file path.
+    pub history: Option<PathBuf>,
+
+    /// A command outputting the history passphrase, such as a keyring helper.
+    pub history_passphrase_cmd: Option<String>,
+}
+
+impl Settings {
+    /// Override settings in this set with all settings that are set in `other`.
+    fn apply(&mut self, other: Settings) {
+        self.host = other.host.or_else(|| self.host.take());
+        self.api = other.api.or_else(|| self.api.take());
+        self.timeout = other.timeout.or(self.timeout);
+        self.transfer_timeout = other.transfer_timeout.or(self.transfer_timeout);
+        self.retries = other.retries.or(self.retries);
+        self.download_limit = other.download_limit.or(self.download_limit);
+        self.archive = other.archive.or(self.archive);
+        self.archive_format = other.archive_format.or_else(|| self.archive_format.take());
+        self.extract = other.extract.or(self.extract);
+        self.basic_auth = other.basic_auth.or_else(|| self.basic_auth.take());
+        self.proxy = other.proxy.or_else(|| self.proxy.take());
+        self.no_proxy = other.no_proxy.or_else(|| self.no_proxy.take());
+        self.ca_cert = other.ca_cert.or_else(|| self.ca_cert.take());
+        self.client_cert = other.client_cert.or_else(|| self.client_cert.take());
+        self.client_key = other.client_key.or_else(|| self.client_key.take());
+        self.pin_sha256 = other.pin_sha256.or_else(|| self.pin_sha256.take());
+        self.limit_rate = other.limit_rate.or_else(|| self.limit_rate.take());
+        self.limit_rate_schedule = other
+            .limit_rate_schedule
+            .or_else(|| self.limit_rate_schedule.take());
+        self.history = other.history.or_else(|| self.history.take());
+        self.history_passphrase_cmd = other
+            .history_passphrase_cmd
+            .or_else(|| self.history_passphrase_cmd.take());
+    }
+}
+
+/// TLS settings for a specific host, taking precedence over the default settings.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostSettings {
+    /// A PEM file with CA certificates to trust.
+    pub ca_cert: Option<PathBuf>,
+
+    /// A PEM file with a TLS client certificate.
+    pub client_cert: Option<PathBuf>,
+
+    /// A PEM file with the private key of the TLS client certificate.
+    pub client_key: Option<PathBuf>,
+
+    /// The SHA-256 hash of the public key the server certificate must have.
+    pub pin_sha256: Option<String>,
+}
+
+/// The loaded configuration.
+#[derive(Debug)]
+pub struct Config {
+    /// The path of the configuration file.
+    path: PathBuf,
+
+    /// Whether the configuration file was found.
+    found: bool,
+
+    /// The name of the selected profile, if any.
+    profile: Option<String>,
+
+    /// The effective settings, with the selected profile applied.
+    settings: Settings,
+
+    /// TLS settings for specific hosts, by host name.
+    hosts: HashMap<String, HostSettings>,
+}
+
+impl Config {
+    /// Get the path of the configuration file.
+    pub fn path() -> PathBuf {
+        app_project_dirs().config_dir().join(CONFIG_FILE_NAME)
+    }
+
+    /// Load the configuration file at the given path, and apply the given profile.
+    ///
+    /// If there is no file at the given path, an empty configuration is used.
+    /// An error is returned if a profile is given that doesn't exist.
+    pub fn load(path: PathBuf, profile: Option<String>) -> Result<Self, LoadError> {
+        // Read and parse the file if it exists
+        let found = path.is_file();
+        let file: ConfigFile = if found {
+            toml::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            ConfigFile::default()
+        };
+
+        // Apply the selected profile on top of the defaults
+        let mut settings = file.defaults;
+        if let Some(name) = &profile {
+            let mut profiles = file.profiles;
+            match profiles.remove(name) {
+                Some(profile) => settings.apply(profile),
+                None => return Err(LoadError::UnknownProfile(name.clone())),
+            }
+        }
+
+        Ok(Config {
+            path,
+            found,
+            profile,
+            settings,
+            hosts: file.hosts,
+        })
+    }
+
+    /// Get the path of the configuration file that was loaded.
+    pub fn file_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Check whether the configuration file was found.
+    pub fn found(&self) -> bool {
+        self.found
+    }
+
+    /// Get the name of the selected profile, if any.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_ref().map(String::as_str)
+    }
+
+    /// Get the effective settings.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Get the TLS settings for the given host, if configured.
+    ///
+    /// Hosts are configured by name, optionally with a port, such as `send.example.com:8443`.
+    pub fn host_settings(&self, host: &Url) -> Option<&HostSettings> {
+        let name = host.host_str()?;
+        let port = host.port_or_known_default();
+        self.hosts
+            .iter()
+            .find(|(key, _)| {
+                let key = key.trim().trim_end_matches('/');
+                let key = key.split("://").last().unwrap_or(key);
+                match key.rfind(':') {
+                    Some(i) if key[i + 1..].parse::<u16>().is_ok() => {
+                        key[..i].eq_ignore_ascii_case(name) && key[i + 1..].parse().ok() == port
+                    }
+                    _ => key.eq_ignore_ascii_case(name),
+                }
+            })
+            .map(|(_, settings)| settings)
+    }
+}
+
+/// Determine the profile selected by the user.
+///
+/// The configuration is used to define defaults for command line arguments, so it must be loaded
+/// before these are parsed. Therefore the `--profile` argument is scanned for in the raw program
+/// arguments, falling back to the `FFSEND_PROFILE` environment variable.
+fn selected_profile() -> Option<String> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--profile" {
+            return args.next();
+        } else if arg.starts_with("--profile=") {
+            return Some(arg["--profile=".len()..].to_owned());
+        }
+    }
+
+    var_os("FFSEND_PROFILE").and_then(|p| p.into_string().ok())
+}
+
+/// The source an effective setting value was taken from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// Given as command line argument.
+    Argument,
+
+    /// Given as environment variable.
+    Environment,
+
+    /// Set in the configuration file.
+    Config,
+
+    /// The built-in default.
+    Default,
+}
+
+impl Source {
+    /// Determine the source of a setting.
+    ///
+    /// The argument with the given `name` is checked for in all given `matches`, the `env`
+    /// variable is checked if given, and `config` defines whether the setting is set in the
+    /// configuration.
+    pub fn of(matches: &[&ArgMatches], name: &str, env: Option<&str>, config: bool) -> Self {
+        if matches.iter().any(|m| m.occurrences_of(name) > 0) {
+            Source::Argument
+        } else if env.map(|env| var_os(env).is_some()).unwrap_or(false) {
+            Source::Environment
+        } else if config {
+            Source::Config
+        } else {
+            Source::Default
+        }
+    }
+
+    /// Get the name of this source.
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Argument => "argument",
+            Source::Environment => "environment",
+            Source::Config => "config",
+            Source::Default => "default",
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum LoadError {
+    /// Failed to read the configuration file.
+    #[fail(display = "failed to read configuration file")]
+    Read(#[cause] IoError),
+
+    /// Failed to parse the configuration file.
+    #[fail(display = "failed to parse configuration file")]
+    Parse(#[cause] DeError),
+
+    /// The selected profile is not defined in the configuration file.
+    #[fail(display = "profile '{}' is not defined in the configuration file", _0)]
+    UnknownProfile(String),
+}
+
+impl From<IoError> for LoadError {
+    fn from(err: IoError) -> Self {
+        LoadError::Read(err)
+    }
+}
+
+impl From<DeError> for LoadError {
+    fn from(err: DeError) -> Self {
+        LoadError::Parse(err)
+    }
+}
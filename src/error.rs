@@ -5,12 +5,14 @@ use ffsend_api::action::password::Error as PasswordError;
 use ffsend_api::action::version::Error as VersionError;
 use ffsend_api::file::remote_file::FileParseError;
 
+use crate::action::delete::Error as CliDeleteError;
 use crate::action::download::Error as CliDownloadError;
 use crate::action::generate::completions::Error as CliGenerateCompletionsError;
 #[cfg(feature = "history")]
 use crate::action::history::Error as CliHistoryError;
 use crate::action::info::Error as CliInfoError;
 use crate::action::upload::Error as CliUploadError;
+use crate::action::watch::Error as CliWatchError;
 
 #[derive(Fail, Debug)]
 pub enum Error {
@@ -19,6 +21,17 @@ pub enum Error {
     Action(#[cause] ActionError),
 }
 
+impl Error {
+    /// Get a stable code identifying the kind of this error.
+    ///
+    /// This code is reported in machine readable error output.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Action(err) => err.code(),
+        }
+    }
+}
+
 impl From<CliDownloadError> for Error {
     fn from(err: CliDownloadError) -> Error {
         Error::Action(ActionError::Download(err))
@@ -37,6 +50,12 @@ impl From<CliUploadError> for Error {
     }
 }
 
+impl From<CliWatchError> for Error {
+    fn from(err: CliWatchError) -> Error {
+        Error::Action(ActionError::Watch(err))
+    }
+}
+
 impl From<ActionError> for Error {
     fn from(err: ActionError) -> Error {
         Error::Action(err)
@@ -47,7 +66,7 @@ impl From<ActionError> for Error {
 pub enum ActionError {
     /// An error occurred while invoking the delete action.
     #[fail(display = "failed to delete the file")]
-    Delete(#[cause] DeleteError),
+    Delete(#[cause] CliDeleteError),
 
     /// An error occurred while invoking the download action.
     #[fail(display = "failed to download the requested file")]
@@ -86,15 +105,48 @@ pub enum ActionError {
     #[fail(display = "failed to upload the specified file")]
     Upload(#[cause] CliUploadError),
 
+    /// An error occurred while invoking the watch action.
+    #[fail(display = "failed to watch the file")]
+    Watch(#[cause] CliWatchError),
+
     /// Failed to parse a share URL, it was invalid.
     /// This error is not related to a specific action.
     #[fail(display = "invalid share URL")]
     InvalidUrl(#[cause] FileParseError),
 }
 
+impl ActionError {
+    /// Get a stable code identifying the kind of this error.
+    ///
+    /// This code is reported in machine readable error output.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ActionError::Delete(_) => "delete",
+            ActionError::Download(_) => "download",
+            ActionError::Exists(_) => "exists",
+            ActionError::GenerateCompletions(_) => "generate_completions",
+            #[cfg(feature = "history")]
+            ActionError::History(_) => "history",
+            ActionError::Info(_) => "info",
+            ActionError::Params(_) => "params",
+            ActionError::Password(_) => "password",
+            ActionError::Version(_) => "version",
+            ActionError::Upload(_) => "upload",
+            ActionError::Watch(_) => "watch",
+            ActionError::InvalidUrl(_) => "invalid_url",
+        }
+    }
+}
+
+impl From<CliDeleteError> for ActionError {
+    fn from(err: CliDeleteError) -> ActionError {
+        ActionError::Delete(err)
+    }
+}
+
 impl From<DeleteError> for ActionError {
     fn from(err: DeleteError) -> ActionError {
-        ActionError::Delete(err)
+        ActionError::Delete(err.into())
     }
 }
 
@@ -0,0 +1,154 @@
+use std::str::FromStr;
+
+use ffsend_api::reqwest::header::{HeaderName, HeaderValue};
+use hyper::header::Headers;
+
+/// Add the given custom headers to the given request headers.
+///
+/// A custom header replaces any header of the same name already set, such as basic
+/// authorization being replaced by a bearer token.
+pub fn apply(headers: &mut Headers, custom: &[Header]) {
+    for header in custom {
+        headers.remove_raw(&header.name);
+    }
+    for header in custom {
+        headers.append_raw(header.name.clone(), header.value.clone().into_bytes());
+    }
+}
+
+/// A custom HTTP header.
+#[derive(Clone, Debug)]
+pub struct Header {
+    /// The header name.
+    name: String,
+
+    /// The header value.
+    value: String,
+}
+
+impl Header {
+    /// Construct an authorization header with the given bearer token.
+    pub fn bearer(token: &str) -> Result<Self, HeaderError> {
+        Self::new("Authorization", &format!("Bearer {}", token.trim()))
+    }
+
+    /// Construct a header with the given name and value, which are validated.
+    fn new(name: &str, value: &str) -> Result<Self, HeaderError> {
+        let name = name.trim();
+        HeaderName::from_bytes(name.as_bytes()).map_err(|_| HeaderError::Name(name.into()))?;
+        let value = value.trim();
+        HeaderValue::from_str(value).map_err(|_| HeaderError::Value(name.into()))?;
+        Ok(Header {
+            name: name.into(),
+            value: value.into(),
+        })
+    }
+
+    /// Format this header for display, with its value masked.
+    pub fn masked(&self) -> String {
+        format!("{}: ***", self.name)
+    }
+}
+
+impl FromStr for Header {
+    type Err = HeaderError;
+
+    /// Parse a header in the `Name: value` format.
+    fn from_str(header: &str) -> Result<Self, Self::Err> {
+        match header.find(':') {
+            Some(pos) => Header::new(&header[..pos], &header[pos + 1..]),
+            None => Err(HeaderError::Format),
+        }
+    }
+}
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum HeaderError {
+    /// The header is not in the `Name: value` format.
+    #[fail(display = "invalid header, expected 'Name: value'")]
+    Format,
+
+    /// The header name is invalid.
+    #[fail(display = "invalid header name '{}'", _0)]
+    Name(String),
+
+    /// The header value is invalid.
+    #[fail(display = "invalid value for header '{}'", _0)]
+    Value(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the raw values of the given header as strings.
+    fn values(headers: &Headers, name: &str) -> Vec<String> {
+        headers
+            .get_raw(name)
+            .unwrap_or(&[])
+            .iter()
+            .map(|value| String::from_utf8(value.clone()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_header() {
+        let header: Header = "X-Api-Key:  secret value ".parse().unwrap();
+        assert_eq!(header.name, "X-Api-Key");
+        assert_eq!(header.value, "secret value");
+
+        let header: Header = "X-Empty:".parse().unwrap();
+        assert_eq!(header.value, "");
+    }
+
+    #[test]
+    fn parse_header_value_with_colon() {
+        let header: Header = "X-Url: https://example.com:8080/".parse().unwrap();
+        assert_eq!(header.name, "X-Url");
+        assert_eq!(header.value, "https://example.com:8080/");
+    }
+
+    #[test]
+    fn parse_header_invalid() {
+        let error = |header: &str| header.parse::<Header>().unwrap_err();
+        assert_eq!(error("X-Api-Key secret"), HeaderError::Format);
+        assert_eq!(error(": value"), HeaderError::Name("".into()));
+        assert_eq!(
+            error("X Api Key: value"),
+            HeaderError::Name("X Api Key".into())
+        );
+        assert_eq!(
+            error("X-Api-Key: line\nbreak"),
+            HeaderError::Value("X-Api-Key".into())
+        );
+    }
+
+    #[test]
+    fn bearer() {
+        let header = Header::bearer(" token\n").unwrap();
+        assert_eq!(header.name, "Authorization");
+        assert_eq!(header.value, "Bearer token");
+        assert_eq!(header.masked(), "Authorization: ***");
+        assert_eq!(
+            Header::bearer("to\0ken").unwrap_err(),
+            HeaderError::Value("Authorization".into())
+        );
+    }
+
+    #[test]
+    fn apply_replaces_same_name() {
+        let mut headers = Headers::new();
+        headers.set_raw("authorization", vec![b"Basic dXNlcg==".to_vec()]);
+        headers.set_raw("Accept", vec![b"*/*".to_vec()]);
+        let custom = vec![
+            Header::bearer("token").unwrap(),
+            "X-Tag: a".parse().unwrap(),
+            "x-tag: b".parse().unwrap(),
+        ];
+        apply(&mut headers, &custom);
+
+        assert_eq!(values(&headers, "Authorization"), vec!["Bearer token"]);
+        assert_eq!(values(&headers, "X-Tag"), vec!["a", "b"]);
+        assert_eq!(values(&headers, "Accept"), vec!["*/*"]);
+    }
+}
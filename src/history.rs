@@ -1,16 +1,28 @@
+extern crate fs2;
 extern crate toml;
 extern crate version_compare;
 
-use std::fs;
-use std::io::Error as IoError;
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error as IoError, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use self::fs2::{lock_contended_error, FileExt};
 use self::toml::de::Error as DeError;
 use self::toml::ser::Error as SerError;
+use self::toml::value::Table;
 use self::version_compare::{CompOp, VersionCompare};
+use chrono::{DateTime, Utc};
 use failure::Fail;
 use ffsend_api::file::remote_file::RemoteFile;
+use ffsend_api::url::Url;
+use tempfile::Builder as TempBuilder;
 
+use crate::config::HISTORY_LOCK_TIMEOUT;
+use crate::history_crypt::{EncryptedData, Error as CryptError, Key};
+use crate::host::parse_host;
 use crate::util::{print_error, print_warning};
 
 /// The minimum supported history file version.
@@ -26,7 +38,7 @@ pub struct History {
     version: Option<String>,
 
     /// The file history.
-    files: Vec<RemoteFile>,
+    files: Vec<HistoryFile>,
 
     /// Whether the list of files has changed.
     #[serde(skip)]
@@ -35,6 +47,14 @@ pub struct History {
     /// An optional path to automatically save the history to.
     #[serde(skip)]
     autosave: Option<PathBuf>,
+
+    /// The lock held on the history file, released once saved or dropped.
+    #[serde(skip)]
+    lock: Option<File>,
+
+    /// The key to encrypt the history file with, if encrypted.
+    #[serde(skip)]
+    key: Option<Key>,
 }
 
 impl History {
@@ -47,13 +67,63 @@ impl History {
     }
 
     /// Load the history from the given file.
-    pub fn load(path: PathBuf) -> Result<Self, LoadError> {
+    ///
+    /// Files that have expired are garbage collected.
+    ///
+    /// If the history file is encrypted, `passphrase` is called to obtain the passphrase to
+    /// decrypt it with.
+    pub fn load(path: PathBuf, passphrase: &dyn Fn() -> String) -> Result<Self, LoadError> {
+        let mut history = Self::load_raw(path, passphrase)?;
+        history.gc();
+        Ok(history)
+    }
+
+    /// Load the history from the given file, without garbage collecting expired files.
+    ///
+    /// An exclusive lock is taken on the history file, which is held until the history is saved
+    /// or dropped.
+    pub fn load_raw(path: PathBuf, passphrase: &dyn Fn() -> String) -> Result<Self, LoadError> {
+        let early = Self::early_passphrase(&path, passphrase);
+        let lock = Self::lock(&path)?;
+        Self::read(path, lock, &|| early.clone().unwrap_or_else(passphrase))
+    }
+
+    /// Obtain the passphrase before locking if the history file at the given path is encrypted,
+    /// so the lock isn't held while the user is prompted for it.
+    ///
+    /// `None` is returned if the file isn't encrypted or can't be read. The passphrase is then
+    /// obtained while reading, in case the file changed since.
+    fn early_passphrase(path: &Path, passphrase: &dyn Fn() -> String) -> Option<String> {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|data| toml::from_str::<EncryptedHistory>(&data).ok())
+            .and_then(|history| history.encrypted)
+            .map(|_| passphrase())
+    }
+
+    /// Read the history from the given file, for which the given lock is held.
+    fn read(path: PathBuf, lock: File, passphrase: &dyn Fn() -> String) -> Result<Self, LoadError> {
         // Read the file to a string
-        let data = fs::read_to_string(&path)?;
+        let mut data = fs::read_to_string(&path)?;
+
+        // Decrypt the history if encrypted, keep the key to encrypt it again when saving
+        let mut key = None;
+        if let Some(encrypted) = toml::from_str::<EncryptedHistory>(&data)?.encrypted {
+            let (plaintext, k) = encrypted.decrypt(&passphrase())?;
+            data = String::from_utf8(plaintext).map_err(|_| CryptError::Malformed)?;
+            key = Some(k);
+        }
 
-        // Parse the data, set the autosave path
-        let mut history: Self = toml::from_str(&data)?;
+        // Parse the data, migrate old history files, set the autosave path
+        let legacy = LegacyHistory::is_legacy(&data)?;
+        let mut history: Self = if legacy {
+            LegacyHistory::migrate(&data)?
+        } else {
+            toml::from_str(&data)?
+        };
         history.autosave = Some(path);
+        history.lock = Some(lock);
+        history.key = key;
 
         // Make sure the file version is supported
         if history.version.is_none() {
@@ -70,8 +140,10 @@ impl History {
             }
         }
 
-        // Garbage collect
-        history.gc();
+        // Migrated history is saved in the current format
+        if legacy {
+            history.version = Some(crate_version!().into());
+        }
 
         Ok(history)
     }
@@ -80,11 +152,61 @@ impl History {
     /// If the file doesn't exist, create a new empty history instance.
     ///
     /// Autosaving will be enabled, and will save to the given file path.
-    pub fn load_or_new(file: PathBuf) -> Result<Self, LoadError> {
+    ///
+    /// An exclusive lock is taken on the history file, which is held until the history is saved
+    /// or dropped. This prevents losing changes made by other processes at the same time.
+    ///
+    /// If the history file is encrypted, `passphrase` is called to obtain the passphrase to
+    /// decrypt it with.
+    pub fn load_or_new(file: PathBuf, passphrase: &dyn Fn() -> String) -> Result<Self, LoadError> {
+        let early = Self::early_passphrase(&file, passphrase);
+        let lock = Self::lock(&file)?;
         if file.is_file() {
-            Self::load(file)
+            let mut history = Self::read(file, lock, &|| early.clone().unwrap_or_else(passphrase))?;
+            history.gc();
+            Ok(history)
         } else {
-            Ok(Self::new(Some(file)))
+            let mut history = Self::new(Some(file));
+            history.lock = Some(lock);
+            Ok(history)
+        }
+    }
+
+    /// Take an exclusive lock for the history file at the given path.
+    ///
+    /// A separate lock file is used next to the history file, as the history file itself is
+    /// replaced when saving. If the lock is held by another process, this waits for it to be
+    /// released until `HISTORY_LOCK_TIMEOUT` is reached.
+    fn lock(path: &Path) -> Result<File, LoadError> {
+        // Ensure the parent directories are available, open the lock file
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(LoadError::Lock)?;
+        }
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .map_err(LoadError::Lock)?;
+
+        // Try to take the lock until the timeout is reached
+        let start = Instant::now();
+        loop {
+            match lock.try_lock_exclusive() {
+                Ok(()) => return Ok(lock),
+                Err(err) if err.kind() != lock_contended_error().kind() => {
+                    return Err(LoadError::Lock(err))
+                }
+                Err(_) if start.elapsed() >= Duration::from_secs(HISTORY_LOCK_TIMEOUT) => {
+                    return Err(LoadError::LockTimeout(
+                        lock_path.to_string_lossy().into_owned(),
+                    ))
+                }
+                Err(_) => thread::sleep(Duration::from_millis(100)),
+            }
         }
     }
 
@@ -97,57 +219,80 @@ impl History {
         let path = self.autosave.as_ref().ok_or(SaveError::NoPath)?;
 
         // If we have no files, remove the history file if it exists
-        if self.files.is_empty() {
+        // An encrypted history is kept, so it stays encrypted when files are added again
+        if self.files.is_empty() && self.key.is_none() {
             if path.is_file() {
                 fs::remove_file(&path).map_err(SaveError::Delete)?;
             }
+            self.changed = false;
+            self.lock = None;
             return Ok(());
         }
 
         // Ensure the file parent directories are available
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-
-        // Set file permissions on unix based systems
-        #[cfg(unix)]
-        {
-            use std::fs::Permissions;
-            use std::os::unix::fs::PermissionsExt;
-
-            if !path.exists() {
-                let file = fs::File::create(path).map_err(SaveError::Write)?;
+        let parent = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(parent)?;
+
+        // Write to a temporary file next to the history file first, so it is replaced atomically
+        let mut tmp = TempBuilder::new()
+            .prefix(".history-")
+            .suffix(".toml")
+            .tempfile_in(parent)?;
+
+        // Keep the file permissions, or set Read/Write permissions for the user on unix
+        if let Ok(metadata) = fs::metadata(path) {
+            tmp.as_file()
+                .set_permissions(metadata.permissions())
+                .map_err(SaveError::SetPermissions)?;
+        } else {
+            #[cfg(unix)]
+            {
+                use std::fs::Permissions;
+                use std::os::unix::fs::PermissionsExt;
 
-                // Set Read/Write permissions for the user
-                file.set_permissions(Permissions::from_mode(0o600))
+                tmp.as_file()
+                    .set_permissions(Permissions::from_mode(0o600))
                     .map_err(SaveError::SetPermissions)?;
             }
         }
 
-        // Build the data and write to a file
-        let data = toml::to_string(self)?;
-        fs::write(&path, data)?;
+        // Build the data, encrypt it if enabled, write and move it into place
+        let mut data = toml::to_string(self)?;
+        if let Some(key) = &self.key {
+            data = toml::to_string(&EncryptedHistory {
+                version: self.version.clone(),
+                encrypted: Some(key.encrypt(data.as_bytes())?),
+            })?;
+        }
+        tmp.write_all(data.as_bytes())?;
+        tmp.as_file().sync_all()?;
+        tmp.persist(path)
+            .map_err(|err| SaveError::Write(err.error))?;
 
-        // There are no new changes, set the flag
+        // There are no new changes, set the flag, release the lock
         self.changed = false;
+        self.lock = None;
 
         Ok(())
     }
 
-    /// Add the given remote file to the history.
+    /// Add the given file to the history.
     /// If a file with the same ID as the given file exists,
-    /// the files are merged, see `RemoteFile::merge()`.
+    /// the files are merged, see `HistoryFile::merge()`.
     ///
     /// If `overwrite` is set to true, the given file will overwrite
     /// properties on the existing file.
-    pub fn add(&mut self, file: RemoteFile, overwrite: bool) {
+    pub fn add(&mut self, file: HistoryFile, overwrite: bool) {
         // Merge any existing file with the same ID
         {
             // Find anything to merge
             let merge_info: Vec<bool> = self
                 .files
                 .iter_mut()
-                .filter(|f| f.id() == file.id())
+                .filter(|f| f.remote_file().id() == file.remote_file().id())
                 .map(|ref mut f| f.merge(&file, overwrite))
                 .collect();
             let merged = !merge_info.is_empty();
@@ -176,7 +321,7 @@ impl History {
             .files
             .iter()
             .enumerate()
-            .filter(|&(_, f)| f.id() == file.id())
+            .filter(|&(_, f)| f.remote_file().id() == file.id())
             .map(|(i, _)| i)
             .collect();
 
@@ -186,25 +331,86 @@ impl History {
         }
 
         // Set the changed flag, and return
-        if expired_indices.is_empty() {
+        if !expired_indices.is_empty() {
             self.changed = true;
         }
         !expired_indices.is_empty()
     }
 
+    /// Check whether this history is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Encrypt the history file with a key derived from the given passphrase, once saved.
+    pub fn encrypt(&mut self, passphrase: &str) -> Result<(), CryptError> {
+        self.key = Some(Key::new(passphrase)?);
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Don't encrypt the history file anymore, once saved.
+    pub fn decrypt(&mut self) {
+        self.key = None;
+        self.changed = true;
+    }
+
+    /// Remove all files.
+    ///
+    /// The number of removed files is returned.
+    pub fn clear(&mut self) -> usize {
+        let count = self.files.len();
+        if count > 0 {
+            self.files.clear();
+            self.changed = true;
+        }
+        count
+    }
+
     /// Get all files.
-    pub fn files(&self) -> &Vec<RemoteFile> {
+    pub fn files(&self) -> &Vec<HistoryFile> {
         &self.files
     }
 
+    /// Get all files in the order they are listed in.
+    ///
+    /// Files are sorted by their expiry time, with the first expiring file last.
+    /// The position of a file in this list, starting at `1`, is used as its index.
+    pub fn files_listed(&self) -> Vec<&HistoryFile> {
+        let mut files: Vec<&HistoryFile> = self.files().iter().collect();
+        files.sort_by(|a, b| {
+            b.remote_file()
+                .expire_at()
+                .cmp(&a.remote_file().expire_at())
+        });
+        files
+    }
+
+    /// Find the file for the given reference.
+    ///
+    /// If no file matches, `None` is returned.
+    pub fn resolve(&self, reference: &Reference) -> Option<&HistoryFile> {
+        match reference {
+            Reference::Index(index) => index
+                .checked_sub(1)
+                .and_then(|i| self.files_listed().get(i).cloned()),
+            Reference::Last => self.files().last(),
+            Reference::Id(id) => self.files().iter().find(|f| f.remote_file().id() == id),
+            Reference::Url(url) => RemoteFile::parse_url(url.clone(), None)
+                .ok()
+                .and_then(|file| self.get_file(&file)),
+        }
+    }
+
     /// Get a file from the history, based on the given remote file.
     /// The file ID and host will be compared against all files in this history.
     /// If multiple files exist within the history that are equal, only one is returned.
     /// If no matching file was found, `None` is returned.
-    pub fn get_file(&self, file: &RemoteFile) -> Option<&RemoteFile> {
-        self.files
-            .iter()
-            .find(|f| f.id() == file.id() && f.host() == file.host())
+    pub fn get_file(&self, file: &RemoteFile) -> Option<&HistoryFile> {
+        self.files.iter().find(|f| {
+            let remote = f.remote_file();
+            remote.id() == file.id() && remote.host() == file.host()
+        })
     }
 
     /// Garbage collect (remove) all files that have been expired,
@@ -218,6 +424,7 @@ impl History {
         let expired: Vec<RemoteFile> = self
             .files
             .iter()
+            .map(|f| f.remote_file())
             .filter(|f| f.has_expired())
             .cloned()
             .collect();
@@ -237,6 +444,335 @@ impl History {
     }
 }
 
+/// An encrypted history file.
+///
+/// The version is kept in plain text for compatibility checking.
+#[derive(Serialize, Deserialize)]
+struct EncryptedHistory {
+    /// The application version the history file was built with.
+    version: Option<String>,
+
+    /// The encrypted history, if encrypted.
+    encrypted: Option<EncryptedData>,
+}
+
+/// A history file from before file metadata was stored.
+///
+/// Used to load and migrate old history files.
+#[derive(Deserialize)]
+struct LegacyHistory {
+    /// The application version the history file was built with.
+    version: Option<String>,
+
+    /// The file history.
+    files: Vec<RemoteFile>,
+}
+
+impl LegacyHistory {
+    /// Check whether the given history file data uses the legacy format.
+    ///
+    /// Legacy files store each remote file directly, instead of wrapping it in a `remote` table
+    /// along with its metadata.
+    fn is_legacy(data: &str) -> Result<bool, DeError> {
+        /// Only the raw files of a history file.
+        #[derive(Deserialize)]
+        struct Files {
+            #[serde(default)]
+            files: Vec<Table>,
+        }
+
+        Ok(toml::from_str::<Files>(data)?
+            .files
+            .iter()
+            .any(|file| !file.contains_key("remote")))
+    }
+
+    /// Parse the given legacy history file data, and migrate it to the current history format.
+    ///
+    /// The history is marked as changed, so the migrated history is saved.
+    fn migrate(data: &str) -> Result<History, DeError> {
+        /// The upload times of legacy files, which remote files don't expose.
+        #[derive(Deserialize)]
+        struct UploadTimes {
+            files: Vec<UploadTime>,
+        }
+        #[derive(Deserialize)]
+        struct UploadTime {
+            upload_at: Option<DateTime<Utc>>,
+        }
+
+        let legacy: Self = toml::from_str(data)?;
+        let times: UploadTimes = toml::from_str(data)?;
+
+        // Wrap each remote file, keep the upload time
+        let files = legacy
+            .files
+            .into_iter()
+            .zip(times.files)
+            .map(|(remote, time)| {
+                let mut file = HistoryFile::new(remote);
+                file.upload_at = time.upload_at;
+                file
+            })
+            .collect();
+
+        Ok(History {
+            version: legacy.version,
+            files,
+            changed: true,
+            autosave: None,
+            lock: None,
+            key: None,
+        })
+    }
+}
+
+/// A file in the history.
+///
+/// This wraps a remote file, along with metadata of the file that is known locally.
+/// Metadata might be unknown, for example for files added by an older version.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryFile {
+    /// The file name.
+    name: Option<String>,
+
+    /// The file size in bytes.
+    size: Option<u64>,
+
+    /// The file MIME type.
+    mime: Option<String>,
+
+    /// The time the file was uploaded at.
+    upload_at: Option<DateTime<Utc>>,
+
+    /// The local path the file was uploaded from, or downloaded to.
+    path: Option<PathBuf>,
+
+    /// Whether the file was uploaded or downloaded.
+    direction: Option<Direction>,
+
+    /// The SHA-256 checksum of the file contents, as hexadecimal string.
+    sha256: Option<String>,
+
+    /// The remote file.
+    ///
+    /// This is a table, so it must be serialized last.
+    remote: RemoteFile,
+}
+
+impl HistoryFile {
+    /// Construct a new history file for the given remote file, without any metadata.
+    pub fn new(remote: RemoteFile) -> Self {
+        Self {
+            name: None,
+            size: None,
+            mime: None,
+            upload_at: None,
+            path: None,
+            direction: None,
+            sha256: None,
+            remote,
+        }
+    }
+
+    /// Get the remote file.
+    pub fn remote_file(&self) -> &RemoteFile {
+        &self.remote
+    }
+
+    /// Get the file name, if known.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(String::as_str)
+    }
+
+    /// Set the file name.
+    pub fn set_name(&mut self, name: Option<String>) {
+        self.name = name;
+    }
+
+    /// Get the file size in bytes, if known.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Set the file size in bytes.
+    pub fn set_size(&mut self, size: Option<u64>) {
+        self.size = size;
+    }
+
+    /// Get the file MIME type, if known.
+    pub fn mime(&self) -> Option<&str> {
+        self.mime.as_ref().map(String::as_str)
+    }
+
+    /// Set the file MIME type.
+    pub fn set_mime(&mut self, mime: Option<String>) {
+        self.mime = mime;
+    }
+
+    /// Get the time the file was uploaded at, if known.
+    pub fn upload_at(&self) -> Option<&DateTime<Utc>> {
+        self.upload_at.as_ref()
+    }
+
+    /// Set the time the file was uploaded at.
+    pub fn set_upload_at(&mut self, upload_at: Option<DateTime<Utc>>) {
+        self.upload_at = upload_at;
+    }
+
+    /// Get the local path the file was uploaded from or downloaded to, if known.
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    /// Set the local path the file was uploaded from or downloaded to.
+    pub fn set_path(&mut self, path: Option<PathBuf>) {
+        self.path = path;
+    }
+
+    /// Get whether the file was uploaded or downloaded, if known.
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+    }
+
+    /// Set whether the file was uploaded or downloaded.
+    pub fn set_direction(&mut self, direction: Option<Direction>) {
+        self.direction = direction;
+    }
+
+    /// Get the SHA-256 checksum of the file contents, if known.
+    pub fn sha256(&self) -> Option<&str> {
+        self.sha256.as_ref().map(String::as_str)
+    }
+
+    /// Set the SHA-256 checksum of the file contents.
+    pub fn set_sha256(&mut self, sha256: Option<String>) {
+        self.sha256 = sha256;
+    }
+
+    /// Merge properties of the given file into this file, see `RemoteFile::merge()`.
+    ///
+    /// Unknown metadata is always taken from the other file.
+    /// If `overwrite` is set to true, known metadata is overwritten as well.
+    ///
+    /// True is returned if any property changed.
+    pub fn merge(&mut self, other: &HistoryFile, overwrite: bool) -> bool {
+        // Merge metadata, evaluate each so all are merged
+        let changed = [
+            merge_option(&mut self.name, &other.name, overwrite),
+            merge_option(&mut self.size, &other.size, overwrite),
+            merge_option(&mut self.mime, &other.mime, overwrite),
+            merge_option(&mut self.upload_at, &other.upload_at, overwrite),
+            merge_option(&mut self.path, &other.path, overwrite),
+            merge_option(&mut self.direction, &other.direction, overwrite),
+            merge_option(&mut self.sha256, &other.sha256, overwrite),
+        ];
+
+        self.remote.merge(&other.remote, overwrite) || changed.iter().any(|c| *c)
+    }
+}
+
+impl From<RemoteFile> for HistoryFile {
+    fn from(remote: RemoteFile) -> Self {
+        Self::new(remote)
+    }
+}
+
+/// Merge the `other` optional value into `value`.
+///
+/// The value is set if `value` is unknown, or if `overwrite` is true and `other` is known.
+/// True is returned if the value changed.
+fn merge_option<T: Clone + PartialEq>(
+    value: &mut Option<T>,
+    other: &Option<T>,
+    overwrite: bool,
+) -> bool {
+    if other.is_none() || value == other || (value.is_some() && !overwrite) {
+        return false;
+    }
+    *value = other.clone();
+    true
+}
+
+/// Whether a file was uploaded or downloaded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// The file was uploaded.
+    Upload,
+
+    /// The file was downloaded.
+    Download,
+}
+
+impl Direction {
+    /// Get the name of this direction.
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Upload => "upload",
+            Direction::Download => "download",
+        }
+    }
+}
+
+/// A reference to a file in the history.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reference {
+    /// The file at the given index as listed, starting at `1`.
+    Index(usize),
+
+    /// The file that was added to the history last.
+    Last,
+
+    /// The file with the given ID.
+    Id(String),
+
+    /// The file with the given share URL.
+    Url(Url),
+}
+
+impl Reference {
+    /// Parse an explicit history reference, such as `#3` or `@last`.
+    ///
+    /// These are distinguishable from share URLs, `None` is returned if the given string is not
+    /// an explicit reference.
+    pub fn parse_explicit(reference: &str) -> Option<Self> {
+        let reference = reference.trim();
+        if reference.starts_with('#') {
+            reference[1..].parse().ok().map(Reference::Index)
+        } else if reference.eq_ignore_ascii_case("@last") {
+            Some(Reference::Last)
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for Reference {
+    type Err = ();
+
+    /// Parse a history reference.
+    ///
+    /// Besides explicit references, a plain number is parsed as index, a string having an URL
+    /// scheme is parsed as share URL, and anything else is parsed as file ID.
+    fn from_str(reference: &str) -> Result<Self, Self::Err> {
+        let reference = reference.trim();
+        if reference.is_empty() {
+            return Err(());
+        }
+        if let Some(reference) = Self::parse_explicit(reference) {
+            return Ok(reference);
+        }
+        if let Ok(index) = reference.parse() {
+            return Ok(Reference::Index(index));
+        }
+        if reference.contains("://") {
+            return parse_host(reference).map(Reference::Url).map_err(|_| ());
+        }
+        Ok(Reference::Id(reference.into()))
+    }
+}
+
 impl Drop for History {
     fn drop(&mut self) {
         // Automatically save if enabled and something was changed
@@ -256,6 +792,8 @@ impl Default for History {
             files: Vec::new(),
             changed: false,
             autosave: None,
+            lock: None,
+            key: None,
         }
     }
 }
@@ -292,6 +830,22 @@ pub enum LoadError {
     /// Failed to parse the loaded file.
     #[fail(display = "failed to parse the file contents")]
     Parse(#[cause] DeError),
+
+    /// Failed to lock the history file.
+    #[fail(display = "failed to lock the history file")]
+    Lock(#[cause] IoError),
+
+    /// Failed to decrypt the history file.
+    #[fail(display = "failed to decrypt the history file")]
+    Decrypt(#[cause] CryptError),
+
+    /// Timed out while waiting for the lock on the history file held by another process.
+    #[fail(
+        display = "timed out waiting for history file lock '{}', another ffsend process might be \
+                   using it, or is stuck",
+        _0
+    )]
+    LockTimeout(String),
 }
 
 impl From<IoError> for LoadError {
@@ -306,6 +860,12 @@ impl From<DeError> for LoadError {
     }
 }
 
+impl From<CryptError> for LoadError {
+    fn from(err: CryptError) -> Self {
+        LoadError::Decrypt(err)
+    }
+}
+
 #[derive(Debug, Fail)]
 pub enum SaveError {
     /// No autosave file path was present, failed to save.
@@ -316,6 +876,10 @@ pub enum SaveError {
     #[fail(display = "failed to serialize the history for saving")]
     Serialize(#[cause] SerError),
 
+    /// Failed to encrypt the history.
+    #[fail(display = "failed to encrypt the history")]
+    Encrypt(#[cause] CryptError),
+
     /// Failed to write to the history file.
     #[fail(display = "failed to write to the history file")]
     Write(#[cause] IoError),
@@ -336,8 +900,30 @@ impl From<SerError> for SaveError {
     }
 }
 
+impl From<CryptError> for SaveError {
+    fn from(err: CryptError) -> Self {
+        SaveError::Encrypt(err)
+    }
+}
+
 impl From<IoError> for SaveError {
     fn from(err: IoError) -> Self {
         SaveError::Write(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_detected_by_layout() {
+        let legacy = "version = \"0.2.38\"\n\n[[files]]\nid = \"a\"\n";
+        let current = "version = \"0.2.38\"\n\n[[files]]\nname = \"a.txt\"\n\n\
+                       [files.remote]\nid = \"a\"\n";
+        assert!(LegacyHistory::is_legacy(legacy).unwrap());
+        assert!(!LegacyHistory::is_legacy(current).unwrap());
+        assert!(!LegacyHistory::is_legacy("version = \"0.2.38\"\nfiles = []\n").unwrap());
+        assert!(!LegacyHistory::is_legacy("").unwrap());
+    }
+}
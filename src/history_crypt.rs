@@ -0,0 +1,232 @@
+extern crate openssl;
+
+use std::sync::Mutex;
+
+use failure::Fail;
+use ffsend_api::crypto::b64;
+
+use self::openssl::error::ErrorStack;
+use self::openssl::pkcs5::scrypt;
+use self::openssl::rand::rand_bytes;
+use self::openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+/// The name of the key derivation function used.
+const KDF_NAME: &str = "scrypt";
+
+/// The scrypt CPU/memory cost parameter, as power of two.
+const SCRYPT_LOG_N: u8 = 15;
+
+/// The scrypt block size parameter.
+const SCRYPT_R: u64 = 8;
+
+/// The scrypt parallelization parameter.
+const SCRYPT_P: u64 = 1;
+
+/// The maximum amount of memory scrypt may use, in bytes.
+const SCRYPT_MAX_MEM: u64 = 128 * 1024 * 1024;
+
+/// The length of the salt in bytes.
+const SALT_LEN: usize = 16;
+
+/// The length of the derived key in bytes, for AES-256.
+const KEY_LEN: usize = 32;
+
+/// The length of the GCM nonce in bytes.
+const NONCE_LEN: usize = 12;
+
+/// The length of the GCM authentication tag in bytes.
+const TAG_LEN: usize = 16;
+
+/// Encrypted history data, as stored in the history file.
+///
+/// The history is encrypted with AES-256-GCM, using a key derived from a passphrase with scrypt.
+/// The key derivation parameters are stored along with the data, so they may be changed later.
+#[derive(Serialize, Deserialize)]
+pub struct EncryptedData {
+    /// The key derivation function used, only `scrypt` is supported.
+    kdf: String,
+
+    /// The scrypt CPU/memory cost parameter, as power of two.
+    log_n: u8,
+
+    /// The scrypt block size parameter.
+    r: u64,
+
+    /// The scrypt parallelization parameter.
+    p: u64,
+
+    /// The base64 encoded key derivation salt.
+    salt: String,
+
+    /// The base64 encoded encryption nonce.
+    nonce: String,
+
+    /// The base64 encoded encrypted data, with the authentication tag appended.
+    data: String,
+}
+
+impl EncryptedData {
+    /// Decrypt this data with the given passphrase.
+    ///
+    /// The decrypted data is returned, along with the key that was used so the data can be
+    /// encrypted again without deriving the key again.
+    pub fn decrypt(&self, passphrase: &str) -> Result<(Vec<u8>, Key), Error> {
+        // Make sure the key derivation function is supported
+        if self.kdf != KDF_NAME {
+            return Err(Error::UnsupportedKdf(self.kdf.clone()));
+        }
+
+        // Decode the parameters, derive the key
+        let salt = b64::decode(&self.salt).map_err(|_| Error::Malformed)?;
+        let nonce = b64::decode(&self.nonce).map_err(|_| Error::Malformed)?;
+        let mut data = b64::decode(&self.data).map_err(|_| Error::Malformed)?;
+        if data.len() < TAG_LEN {
+            return Err(Error::Malformed);
+        }
+        let key = Key::derive(passphrase, salt, self.log_n, self.r, self.p)?;
+
+        // Split off the tag, and decrypt
+        let tag = data.split_off(data.len() - TAG_LEN);
+        let plaintext = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key.key,
+            Some(&nonce),
+            &[],
+            &data,
+            &tag,
+        )
+        .map_err(|_| Error::Decrypt)?;
+
+        Ok((plaintext, key))
+    }
+}
+
+lazy_static! {
+    /// The last derived key along with its passphrase, so loading the history again within the
+    /// same process doesn't derive the key again.
+    static ref DERIVED: Mutex<Option<(String, Key)>> = Mutex::new(None);
+}
+
+/// A key derived from a passphrase, used to encrypt the history.
+#[derive(Clone)]
+pub struct Key {
+    /// The derived key.
+    key: Vec<u8>,
+
+    /// The salt the key was derived with.
+    salt: Vec<u8>,
+
+    /// The scrypt CPU/memory cost parameter, as power of two.
+    log_n: u8,
+
+    /// The scrypt block size parameter.
+    r: u64,
+
+    /// The scrypt parallelization parameter.
+    p: u64,
+}
+
+impl Key {
+    /// Derive a new key from the given passphrase, with a random salt.
+    pub fn new(passphrase: &str) -> Result<Self, Error> {
+        let mut salt = vec![0u8; SALT_LEN];
+        rand_bytes(&mut salt).map_err(Error::Random)?;
+        Self::derive(passphrase, salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)
+    }
+
+    /// Derive the key from the given passphrase and parameters.
+    fn derive(passphrase: &str, salt: Vec<u8>, log_n: u8, r: u64, p: u64) -> Result<Self, Error> {
+        if log_n >= 64 {
+            return Err(Error::Malformed);
+        }
+
+        // Reuse the last derived key if derived with the same passphrase and parameters
+        let mut derived = DERIVED.lock().unwrap();
+        if let Some((cached, key)) = derived.as_ref() {
+            if cached == passphrase
+                && key.salt == salt
+                && (key.log_n, key.r, key.p) == (log_n, r, p)
+            {
+                return Ok(key.clone());
+            }
+        }
+
+        let mut key = vec![0u8; KEY_LEN];
+        scrypt(
+            passphrase.as_bytes(),
+            &salt,
+            1 << log_n,
+            r,
+            p,
+            SCRYPT_MAX_MEM,
+            &mut key,
+        )
+        .map_err(Error::Derive)?;
+
+        let key = Self {
+            key,
+            salt,
+            log_n,
+            r,
+            p,
+        };
+        *derived = Some((passphrase.into(), key.clone()));
+        Ok(key)
+    }
+
+    /// Encrypt the given data with this key, using a random nonce.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<EncryptedData, Error> {
+        let mut nonce = vec![0u8; NONCE_LEN];
+        rand_bytes(&mut nonce).map_err(Error::Random)?;
+
+        // Encrypt, append the tag to the data
+        let mut tag = vec![0u8; TAG_LEN];
+        let mut data = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(&nonce),
+            &[],
+            plaintext,
+            &mut tag,
+        )
+        .map_err(Error::Encrypt)?;
+        data.extend_from_slice(&tag);
+
+        Ok(EncryptedData {
+            kdf: KDF_NAME.into(),
+            log_n: self.log_n,
+            r: self.r,
+            p: self.p,
+            salt: b64::encode(&self.salt),
+            nonce: b64::encode(&nonce),
+            data: b64::encode(&data),
+        })
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// The history was encrypted with an unsupported key derivation function.
+    #[fail(display = "unsupported key derivation function '{}'", _0)]
+    UnsupportedKdf(String),
+
+    /// The encrypted data or its parameters are malformed.
+    #[fail(display = "malformed encrypted history data")]
+    Malformed,
+
+    /// Failed to generate random data for a salt or nonce.
+    #[fail(display = "failed to generate random data")]
+    Random(#[cause] ErrorStack),
+
+    /// Failed to derive the key from the passphrase.
+    #[fail(display = "failed to derive key from passphrase")]
+    Derive(#[cause] ErrorStack),
+
+    /// Failed to encrypt the history.
+    #[fail(display = "failed to encrypt history")]
+    Encrypt(#[cause] ErrorStack),
+
+    /// Failed to decrypt the history, the passphrase is probably wrong.
+    #[fail(display = "failed to decrypt history, wrong passphrase?")]
+    Decrypt,
+}
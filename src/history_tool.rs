@@ -2,7 +2,7 @@ use failure::Fail;
 use ffsend_api::file::remote_file::RemoteFile;
 
 use crate::cmd::matcher::MainMatcher;
-use crate::history::{Error as HistoryError, History};
+use crate::history::{Error as HistoryError, History, HistoryFile};
 use crate::util::print_error;
 
 /// Load the history from the given path, add the given file, and save it
@@ -15,7 +15,7 @@ use crate::util::print_error;
 /// If there is no file at the given path, new history will be created.
 fn add_error(
     matcher_main: &MainMatcher,
-    file: RemoteFile,
+    file: HistoryFile,
     overwrite: bool,
 ) -> Result<(), HistoryError> {
     // Ignore if incognito
@@ -24,7 +24,9 @@ fn add_error(
     }
 
     // Load the history, add the file, and save
-    let mut history = History::load_or_new(matcher_main.history())?;
+    let mut history = History::load_or_new(matcher_main.history(), &|| {
+        matcher_main.history_passphrase(false)
+    })?;
     history.add(file, overwrite);
     history.save().map_err(|err| err.into())
 }
@@ -37,9 +39,11 @@ fn add_error(
 /// merged with this one. If `overwrite` is set to true, this file will
 /// overwrite properties in the already existing file when merging.
 ///
+/// The file may be a remote file, or a history file holding file metadata.
+///
 /// If an error occurred, the error is printed and ignored.
-pub fn add(matcher_main: &MainMatcher, file: RemoteFile, overwrite: bool) {
-    if let Err(err) = add_error(matcher_main, file, overwrite) {
+pub fn add<F: Into<HistoryFile>>(matcher_main: &MainMatcher, file: F, overwrite: bool) {
+    if let Err(err) = add_error(matcher_main, file.into(), overwrite) {
         print_error(err.context("failed to add file to local history, ignoring"));
     }
 }
@@ -54,7 +58,9 @@ fn remove_error(matcher_main: &MainMatcher, file: &RemoteFile) -> Result<bool, H
     }
 
     // Load the history, remove the file, and save
-    let mut history = History::load_or_new(matcher_main.history())?;
+    let mut history = History::load_or_new(matcher_main.history(), &|| {
+        matcher_main.history_passphrase(false)
+    })?;
     let removed = history.remove(file);
     history.save()?;
     Ok(removed)
@@ -94,7 +100,9 @@ pub fn derive_file_properties(matcher_main: &MainMatcher, file: &mut RemoteFile)
     }
 
     // Load the history
-    let history = match History::load_or_new(matcher_main.history()) {
+    let history = match History::load_or_new(matcher_main.history(), &|| {
+        matcher_main.history_passphrase(false)
+    }) {
         Ok(history) => history,
         Err(err) => {
             print_error(err.context("failed to derive file properties from history, ignoring"));
@@ -103,7 +111,7 @@ pub fn derive_file_properties(matcher_main: &MainMatcher, file: &mut RemoteFile)
     };
 
     // Find a matching file, grab and set the owner token if available
-    match history.get_file(file) {
+    match history.get_file(file).map(|f| f.remote_file()) {
         Some(f) => {
             // Set the secret
             if f.has_secret() {
@@ -121,3 +129,19 @@ pub fn derive_file_properties(matcher_main: &MainMatcher, file: &mut RemoteFile)
         None => false,
     }
 }
+
+/// Get the file from the history matching the given remote file, along with its metadata.
+///
+/// If an error occurred while loading the history, the error is printed and `None` is returned.
+pub fn get(matcher_main: &MainMatcher, file: &RemoteFile) -> Option<HistoryFile> {
+    let history = match History::load_or_new(matcher_main.history(), &|| {
+        matcher_main.history_passphrase(false)
+    }) {
+        Ok(history) => history,
+        Err(err) => {
+            print_error(err.context("failed to load file from history, ignoring"));
+            return None;
+        }
+    };
+    history.get_file(file).cloned()
+}
@@ -22,6 +22,18 @@ pub fn parse_host(host: &str) -> Result<Url, HostError> {
     })
 }
 
+/// Parse the given host to filter files by, into an URL.
+///
+/// Unlike `parse_host`, a host without URL scheme is accepted as well.
+pub fn parse_host_filter(host: &str) -> Result<Url, HostError> {
+    let host = host.trim();
+    if host.contains("://") {
+        parse_host(host)
+    } else {
+        parse_host(&format!("https://{}", host))
+    }
+}
+
 /// An error that has occurred while parsing a host.
 #[derive(Debug, Fail)]
 pub enum HostError {
@@ -0,0 +1,164 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Error as IoError, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use chrono::Local;
+use colored::*;
+use ffsend_api::url::Url;
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::client::Response;
+
+/// The response headers that can't hold secrets, of which the values are logged.
+const SHOW_HEADERS: [&str; 5] = [
+    "accept-ranges",
+    "content-length",
+    "content-range",
+    "content-type",
+    "retry-after",
+];
+
+/// A logger printing records of this application to stderr, and writing them to a log file.
+///
+/// Records of dependencies are ignored, as these can't be redacted.
+struct Logger {
+    /// The maximum level of records to print to stderr.
+    level: LevelFilter,
+
+    /// The log file to write all records to, with a timestamp.
+    file: Option<Mutex<File>>,
+}
+
+impl Logger {
+    /// Check whether records with the given target belong to this application.
+    fn is_own(target: &str) -> bool {
+        let root = module_path!().split("::").next().unwrap();
+        target == root || target.starts_with(&format!("{}::", root))
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        Self::is_own(metadata.target()) && (metadata.level() <= self.level || self.file.is_some())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if record.level() <= self.level {
+            let level = format!("{}:", record.level().to_string().to_lowercase());
+            eprintln!("{} {}", level.dimmed(), record.args());
+        }
+
+        if let Some(file) = &self.file {
+            let _ = writeln!(
+                file.lock().unwrap(),
+                "{} {:<5} {}: {}",
+                Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+                record.level(),
+                record.target(),
+                record.args(),
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
+
+/// Set up logging for the given verbosity, the number of times `-v` is given.
+///
+/// Informational records are printed with `-v`, requests with `-vv` and request details with
+/// `-vvv`. If a log file is given, all records are appended to it regardless of the verbosity.
+pub fn init(verbosity: u64, log_file: Option<&Path>) -> Result<(), Error> {
+    let level = match verbosity {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let file = match log_file {
+        Some(path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|err| Error::File(path.display().to_string(), err))?,
+        ),
+        None => None,
+    };
+
+    log::set_max_level(if file.is_some() {
+        LevelFilter::Trace
+    } else {
+        level
+    });
+    log::set_boxed_logger(Box::new(Logger {
+        level,
+        file: file.map(Mutex::new),
+    }))
+    .map_err(|_| Error::Init)?;
+
+    debug!(
+        "{} {} on {}",
+        crate_name!(),
+        crate_version!(),
+        std::env::consts::OS
+    );
+    Ok(())
+}
+
+/// Format the given URL for logging, with secrets redacted.
+///
+/// The fragment holds the secret of a share URL, it is replaced along with any password.
+pub fn redact_url(url: &Url) -> String {
+    let mut url = url.clone();
+    if url.password().is_some() {
+        let _ = url.set_password(Some("***"));
+    }
+    if url.fragment().is_some() {
+        url.set_fragment(Some("***"));
+    }
+    url.into_string()
+}
+
+/// Log the status of the given response to a request started at `start`.
+///
+/// The response headers are logged as well at the trace level. Only the values of a few headers
+/// that can't hold secrets are included.
+pub fn log_response(response: &Response, start: Instant) {
+    debug!(
+        "got {} from {} in {:.0?}",
+        response.status(),
+        redact_url(response.url()),
+        start.elapsed(),
+    );
+    if !log_enabled!(log::Level::Trace) {
+        return;
+    }
+    for (name, value) in response.headers() {
+        if SHOW_HEADERS.contains(&name.as_str()) {
+            trace!("response header {}: {}", name, value);
+        } else {
+            trace!("response header {}: ***", name);
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// Failed to open the log file.
+    #[fail(display = "failed to open log file '{}'", _0)]
+    File(String, #[cause] IoError),
+
+    /// A logger was set up already.
+    #[fail(display = "logger was already initialized")]
+    Init,
+}
@@ -6,27 +6,44 @@ extern crate derive_builder;
 extern crate failure;
 #[macro_use]
 extern crate lazy_static;
-#[cfg(feature = "history")]
+#[macro_use]
+extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
 mod action;
+mod api;
 #[cfg(feature = "archive")]
 mod archive;
+mod checksum;
 mod client;
 mod cmd;
 mod config;
+mod config_file;
 mod error;
+mod headers;
 #[cfg(feature = "history")]
 mod history;
 #[cfg(feature = "history")]
+mod history_crypt;
+#[cfg(feature = "history")]
 mod history_tool;
 mod host;
+mod logger;
+mod output;
 mod progress;
+mod proxy;
+mod split;
+mod stream_upload;
+mod throttle;
+mod tls;
+mod transfer;
 #[cfg(feature = "urlshorten")]
 mod urlshorten;
 mod util;
 
+use failure::Fail;
+
 use crate::action::debug::Debug;
 use crate::action::delete::Delete;
 use crate::action::download::Download;
@@ -39,12 +56,14 @@ use crate::action::params::Params;
 use crate::action::password::Password;
 use crate::action::upload::Upload;
 use crate::action::version::Version;
+use crate::action::watch::Watch;
 use crate::cmd::{
     matcher::{MainMatcher, Matcher},
     Handler,
 };
 use crate::error::Error;
-use crate::util::{bin_name, highlight, quit_error, ErrorHints};
+use crate::output::OutputFormat;
+use crate::util::{bin_name, highlight, quit_error, quit_error_code, set_error_json, ErrorHints};
 
 /// Application entrypoint.
 fn main() {
@@ -54,9 +73,24 @@ fn main() {
     // Parse CLI arguments
     let cmd_handler = Handler::parse();
 
+    // Report errors as JSON when JSON output is selected
+    let matcher_main = MainMatcher::with(cmd_handler.matches()).unwrap();
+    set_error_json(matcher_main.output_format() == OutputFormat::Json);
+
+    // Set up logging
+    let log_file = matcher_main.log_file();
+    let log_file = log_file.as_ref().map(|path| path.as_path());
+    if let Err(err) = logger::init(matcher_main.verbosity(), log_file) {
+        quit_error(
+            err.context("failed to set up logging"),
+            ErrorHints::default(),
+        );
+    }
+
     // Invoke the proper action
     if let Err(err) = invoke_action(&cmd_handler) {
-        quit_error(err, ErrorHints::default());
+        let code = err.code();
+        quit_error_code(err, code, ErrorHints::default());
     };
 }
 
@@ -145,6 +179,13 @@ fn invoke_action(handler: &Handler) -> Result<(), Error> {
             .map_err(|err| err.into());
     }
 
+    // Match the watch command
+    if handler.watch().is_some() {
+        return Watch::new(handler.matches())
+            .invoke()
+            .map_err(|err| err.into());
+    }
+
     // Get the main matcher
     let matcher_main = MainMatcher::with(handler.matches()).unwrap();
 
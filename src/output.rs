@@ -0,0 +1,167 @@
+use std::str::FromStr;
+
+use prettytable::{format::FormatBuilder, Cell, Row, Table};
+use serde_json::{Map, Value};
+
+/// The names of the output formats that may be selected.
+pub const OUTPUT_FORMATS: [&str; 3] = ["human", "json", "tsv"];
+
+/// The format command results are printed in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable output, such as tables and messages.
+    Human,
+
+    /// A single line JSON object or array.
+    Json,
+
+    /// Tab separated values, with a header row of value names.
+    Tsv,
+}
+
+impl OutputFormat {
+    /// Check whether this format is machine readable.
+    pub fn is_machine(self) -> bool {
+        self != OutputFormat::Human
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = ();
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.trim().to_lowercase().as_str() {
+            "human" => Ok(OutputFormat::Human),
+            "json" => Ok(OutputFormat::Json),
+            "tsv" => Ok(OutputFormat::Tsv),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A record of named values, that forms the output of an action.
+///
+/// Every record produced by an action must always contain the same names in
+/// the same order to keep the output schema stable, `Value::Null` should be
+/// used for values that are unknown.
+pub type Record = Vec<(&'static str, Value)>;
+
+/// Print a single record in the given format.
+///
+/// For JSON a single object is printed, for TSV a header row followed by a
+/// single value row is printed.
+pub fn print_record(format: OutputFormat, record: Record) {
+    match format {
+        OutputFormat::Human => {
+            let mut table = Table::new();
+            table.set_format(FormatBuilder::new().padding(0, 2).build());
+            for (name, value) in &record {
+                table.add_row(Row::new(vec![
+                    Cell::new(&format!("{}:", name)),
+                    Cell::new(&format_tsv_value(value)),
+                ]));
+            }
+            table.printstd();
+        }
+        OutputFormat::Json => println!("{}", record_to_json(record)),
+        OutputFormat::Tsv => {
+            let columns: Vec<&str> = record.iter().map(|(name, _)| *name).collect();
+            print_tsv(&columns, vec![record]);
+        }
+    }
+}
+
+/// Print a list of records in the given format.
+///
+/// The `columns` define the value names each record holds, and are used as
+/// header when printing. For JSON an array of objects is printed, for TSV a
+/// header row followed by a row for each record is printed.
+pub fn print_records(format: OutputFormat, columns: &[&str], records: Vec<Record>) {
+    match format {
+        OutputFormat::Human => {
+            let mut table = Table::new();
+            table.set_format(FormatBuilder::new().padding(0, 2).build());
+            table.add_row(Row::new(
+                columns
+                    .iter()
+                    .map(|c| Cell::new(&c.to_uppercase()))
+                    .collect(),
+            ));
+            for record in &records {
+                table.add_row(Row::new(
+                    record
+                        .iter()
+                        .map(|(_, value)| Cell::new(&format_tsv_value(value)))
+                        .collect(),
+                ));
+            }
+            table.printstd();
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            Value::Array(records.into_iter().map(record_to_json).collect())
+        ),
+        OutputFormat::Tsv => print_tsv(columns, records),
+    }
+}
+
+/// Print a single record that is part of a stream of records, in the given format.
+///
+/// This is used by actions that report records while running. For JSON a single object is
+/// printed on its own line, for TSV the header row is only printed if `header` is set, which
+/// should be done for the first record only.
+pub fn print_record_stream(format: OutputFormat, record: Record, header: bool) {
+    match format {
+        OutputFormat::Tsv if !header => println!(
+            "{}",
+            record
+                .iter()
+                .map(|(_, value)| format_tsv_value(value))
+                .collect::<Vec<_>>()
+                .join("\t"),
+        ),
+        _ => print_record(format, record),
+    }
+}
+
+/// Convert the given record into a JSON object.
+fn record_to_json(record: Record) -> Value {
+    Value::Object(
+        record
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect::<Map<String, Value>>(),
+    )
+}
+
+/// Print the given records as tab separated values, with a header row.
+fn print_tsv(columns: &[&str], records: Vec<Record>) {
+    println!("{}", columns.join("\t"));
+    for record in records {
+        println!(
+            "{}",
+            record
+                .iter()
+                .map(|(_, value)| format_tsv_value(value))
+                .collect::<Vec<_>>()
+                .join("\t"),
+        );
+    }
+}
+
+/// Format a single value for use in a tab separated values row.
+///
+/// Strings are printed as is with tabs and newlines replaced by spaces,
+/// `null` is printed as empty value, and arrays are joined by commas.
+fn format_tsv_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.replace(&['\t', '\n', '\r'][..], " "),
+        Value::Array(values) => values
+            .iter()
+            .map(format_tsv_value)
+            .collect::<Vec<_>>()
+            .join(","),
+        value => value.to_string(),
+    }
+}
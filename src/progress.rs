@@ -3,7 +3,7 @@ extern crate pbr;
 use std::io::{stderr, Stderr};
 use std::time::Duration;
 
-use self::pbr::{ProgressBar as Pbr, Units};
+use self::pbr::{MultiBar, Pipe, ProgressBar as Pbr, Units};
 use ffsend_api::pipe::ProgressReporter;
 
 /// The refresh rate of the progress bar, in milliseconds.
@@ -65,3 +65,83 @@ impl<'a> ProgressReporter for ProgressBar<'a> {
             .finish_print(self.msg_finish);
     }
 }
+
+/// A progress bar reporter, drawn as a line of a `MultiBar` along with other progress bars.
+///
+/// If the progress bar is dropped before it finished, it is finished with a failure message so the
+/// `MultiBar` doesn't wait for it.
+pub struct MultiProgressBar {
+    progress_bar: Pbr<Pipe>,
+    msg_progress: String,
+    msg_finish: String,
+    msg_failed: String,
+    finished: bool,
+}
+
+impl MultiProgressBar {
+    /// Construct a new progress bar on the given multi bar, with the given messages.
+    ///
+    /// The progress bar shows `msg_waiting` until it is started.
+    pub fn new(
+        multi_bar: &mut MultiBar<Stderr>,
+        msg_waiting: &str,
+        msg_progress: String,
+        msg_finish: String,
+        msg_failed: String,
+    ) -> Self {
+        let mut progress_bar = multi_bar.create_bar(0);
+        progress_bar.set_max_refresh_rate(Some(Duration::from_millis(PROGRESS_BAR_FPS_MILLIS)));
+        progress_bar.set_units(Units::Bytes);
+        progress_bar.message(msg_waiting);
+
+        Self {
+            progress_bar,
+            msg_progress,
+            msg_finish,
+            msg_failed,
+            finished: false,
+        }
+    }
+
+    /// Construct a new progress bar on the given multi bar, for downloading the named file.
+    pub fn new_download(multi_bar: &mut MultiBar<Stderr>, name: &str) -> Self {
+        Self::new(
+            multi_bar,
+            &format!("{}: waiting ", name),
+            format!("{}: Download & Decrypt ", name),
+            format!("{}: download complete", name),
+            format!("{}: download failed", name),
+        )
+    }
+}
+
+impl ProgressReporter for MultiProgressBar {
+    /// Start the progress with the given total.
+    fn start(&mut self, total: u64) {
+        self.progress_bar.total = total;
+        self.progress_bar.message(&self.msg_progress);
+        self.progress_bar.set(0);
+    }
+
+    /// A progress update.
+    fn progress(&mut self, progress: u64) {
+        self.progress_bar.set(progress);
+    }
+
+    /// Finish the progress.
+    fn finish(&mut self) {
+        if !self.finished {
+            self.finished = true;
+            self.progress_bar.finish_print(&self.msg_finish);
+        }
+    }
+}
+
+impl Drop for MultiProgressBar {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.finished = true;
+            self.progress_bar.finish_print(&self.msg_failed);
+        }
+    }
+}
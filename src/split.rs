@@ -0,0 +1,368 @@
+use std::ffi::OsString;
+use std::fs::{self, remove_file, File};
+use std::io::{self, Error as IoError, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use failure::Fail;
+use ffsend_api::api::Version;
+use ffsend_api::file::remote_file::RemoteFile;
+use ffsend_api::pipe::ProgressReporter;
+use ffsend_api::url::Url;
+use openssl::sha::Sha256;
+use serde_json::Error as JsonError;
+use tempfile::Builder as TempBuilder;
+
+use crate::api::Metadata as ApiMetadata;
+#[cfg(feature = "archive")]
+use crate::archive::archiver::ArchiveStream;
+use crate::checksum::{hash_reader, hex, sha256_file};
+use crate::client::Client;
+use crate::throttle::Throttle;
+use crate::transfer::{Error as DownloadError, ResumableDownload};
+
+/// The suffix appended to the file name of a split file, to name its manifest.
+pub const MANIFEST_SUFFIX: &str = ".ffsend-split.json";
+
+/// The maximum size of a manifest in bytes, bigger files are never treated as manifest.
+pub const MANIFEST_SIZE_MAX: u64 = 1024 * 1024;
+
+/// The version of the manifest format that is written.
+const MANIFEST_VERSION: u32 = 1;
+
+/// A manifest describing a file that was split into parts, each uploaded as separate file.
+///
+/// The manifest itself is uploaded as small encrypted file, it holds the share URLs including
+/// secrets of all parts.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    /// The version of the manifest format.
+    version: u32,
+
+    /// The original file name.
+    pub name: String,
+
+    /// The original file size in bytes.
+    pub size: u64,
+
+    /// The SHA-256 checksum of the original file, as hexadecimal string.
+    pub sha256: String,
+
+    /// The parts in order.
+    pub parts: Vec<Part>,
+}
+
+impl Manifest {
+    /// Construct a new manifest for a file with the given `name`, `size` and checksum.
+    pub fn new(name: String, size: u64, sha256: String, parts: Vec<Part>) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            name,
+            size,
+            sha256,
+            parts,
+        }
+    }
+
+    /// Check whether the remote file with the given `name` and `size` is a manifest.
+    pub fn is_manifest(name: &str, size: u64) -> bool {
+        name.ends_with(MANIFEST_SUFFIX) && size <= MANIFEST_SIZE_MAX
+    }
+
+    /// Get the file name to upload the manifest for a file with the given `name` as.
+    pub fn file_name(name: &str) -> String {
+        format!("{}{}", name, MANIFEST_SUFFIX)
+    }
+
+    /// Parse a manifest from the given data, and ensure it is consistent.
+    pub fn from_slice(data: &[u8]) -> Result<Self, Error> {
+        let manifest: Manifest = serde_json::from_slice(data).map_err(Error::Parse)?;
+        if manifest.version > MANIFEST_VERSION {
+            return Err(Error::Version(manifest.version));
+        }
+        if manifest.parts.is_empty()
+            || manifest.parts.iter().map(|part| part.size).sum::<u64>() != manifest.size
+        {
+            return Err(Error::Inconsistent);
+        }
+        Ok(manifest)
+    }
+
+    /// Serialize the manifest.
+    pub fn to_vec(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).expect("failed to serialize split file manifest")
+    }
+}
+
+/// A part of a split file.
+#[derive(Serialize, Deserialize)]
+pub struct Part {
+    /// The share URL of the part, including its secret.
+    pub url: String,
+
+    /// The part size in bytes.
+    pub size: u64,
+
+    /// The SHA-256 checksum of the part, as hexadecimal string.
+    pub sha256: String,
+}
+
+/// The data of a file to split into parts.
+pub enum Source<'a> {
+    /// A file on disk.
+    File(&'a Path),
+
+    /// An archive that is built on the fly.
+    #[cfg(feature = "archive")]
+    Archive(&'a ArchiveStream),
+}
+
+impl<'a> Source<'a> {
+    /// Get the size of the data in bytes.
+    pub fn size(&self) -> io::Result<u64> {
+        match self {
+            Source::File(path) => Ok(path.metadata()?.len()),
+            #[cfg(feature = "archive")]
+            Source::Archive(stream) => Ok(stream.size()),
+        }
+    }
+
+    /// Open a reader for `len` bytes of the data, starting at `offset`.
+    ///
+    /// An archive can't seek, so it is rebuilt and all data before `offset` is skipped.
+    pub fn open(&self, offset: u64, len: u64) -> io::Result<Box<dyn Read + Send>> {
+        match self {
+            Source::File(path) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                Ok(Box::new(file.take(len)))
+            }
+            #[cfg(feature = "archive")]
+            Source::Archive(stream) => {
+                let mut reader = stream.reader();
+                io::copy(&mut reader.by_ref().take(offset), &mut io::sink())?;
+                Ok(Box::new(reader.take(len)))
+            }
+        }
+    }
+}
+
+/// A download of a file that was split into parts, as described by its manifest.
+///
+/// Each part is downloaded next to the target and verified, the parts are then joined into the
+/// target and the whole file is verified. Parts that were downloaded and verified before are not
+/// downloaded again, so an interrupted download continues where it left off.
+pub struct SplitDownload<'a> {
+    /// The server API version to use when downloading the parts.
+    version: Version,
+
+    /// The manifest of the file.
+    manifest: &'a Manifest,
+
+    /// The target file to download to.
+    target: PathBuf,
+
+    /// An optional password to decrypt the protected parts.
+    password: Option<String>,
+
+    /// An optional throttle to limit the download rate with.
+    throttle: Option<Throttle>,
+}
+
+impl<'a> SplitDownload<'a> {
+    /// Construct a new download of the file described by the given manifest, to the given target.
+    pub fn new(
+        version: Version,
+        manifest: &'a Manifest,
+        target: PathBuf,
+        password: Option<String>,
+        throttle: Option<Throttle>,
+    ) -> Self {
+        Self {
+            version,
+            manifest,
+            target,
+            password,
+            throttle,
+        }
+    }
+
+    /// Get the paths of the files the parts are downloaded to.
+    pub fn part_paths(&self) -> Vec<PathBuf> {
+        (1..=self.manifest.parts.len())
+            .map(|i| {
+                let mut path = OsString::from(self.target.as_os_str());
+                path.push(format!(".{:03}", i));
+                PathBuf::from(path)
+            })
+            .collect()
+    }
+
+    /// Invoke the download.
+    ///
+    /// Each part is retried up to `retries` times on transient errors. A message is printed
+    /// before each part if `report` is set.
+    pub fn invoke(
+        &self,
+        client: &Client,
+        retries: u32,
+        reporter: Option<Arc<Mutex<dyn ProgressReporter>>>,
+        report: bool,
+    ) -> Result<(), Error> {
+        let paths = self.part_paths();
+        let count = paths.len();
+        for (i, (part, path)) in self.manifest.parts.iter().zip(&paths).enumerate() {
+            // Skip parts that were downloaded before
+            if verify(path, part.size, &part.sha256) {
+                continue;
+            }
+            if report {
+                eprintln!("Downloading part {} of {}...", i + 1, count);
+            }
+
+            // Download the part, and verify it
+            let file = Url::parse(&part.url)
+                .ok()
+                .and_then(|url| RemoteFile::parse_url(url, None).ok())
+                .ok_or(Error::PartUrl(i + 1))?;
+            let metadata = ApiMetadata::new(&file, self.password.clone(), false)
+                .invoke(client)
+                .map_err(|err| Error::Part(i + 1, DownloadError::Metadata(err)))?;
+            ResumableDownload::new(
+                self.version,
+                &file,
+                path.clone(),
+                self.password.clone(),
+                self.throttle.clone(),
+            )
+            .invoke(client, metadata, retries, reporter.clone())
+            .map_err(|err| Error::Part(i + 1, err))?;
+            if !verify(path, part.size, &part.sha256) {
+                let _ = remove_file(path);
+                return Err(Error::PartChecksum(i + 1));
+            }
+        }
+
+        // Join the parts into the target and verify it, remove the parts afterwards
+        let sha256 = join(&paths, &self.target)
+            .map_err(|err| Error::File(self.target.to_string_lossy().into(), err))?;
+        if sha256 != self.manifest.sha256 {
+            let _ = remove_file(&self.target);
+            return Err(Error::Checksum);
+        }
+        for path in &paths {
+            let _ = remove_file(path);
+        }
+
+        Ok(())
+    }
+}
+
+/// Download and parse the manifest of a split file, from the given remote manifest file.
+///
+/// The download is retried up to `retries` times on transient errors.
+pub fn fetch_manifest(
+    client: &Client,
+    version: Version,
+    file: &RemoteFile,
+    password: Option<String>,
+    retries: u32,
+) -> Result<Manifest, Error> {
+    // Download the manifest to a temporary file
+    let tmp = TempBuilder::new()
+        .prefix(&format!(".{}-manifest-", crate_name!()))
+        .tempfile()
+        .map_err(|err| Error::File("temporary manifest file".into(), err))?;
+    let download = ResumableDownload::new(
+        version,
+        file,
+        tmp.path().to_path_buf(),
+        password.clone(),
+        None,
+    );
+    let result = ApiMetadata::new(file, password, false)
+        .invoke(client)
+        .map_err(DownloadError::Metadata)
+        .and_then(|metadata| download.invoke(client, metadata, retries, None));
+    if let Err(err) = result {
+        let _ = remove_file(download.partial_path());
+        return Err(Error::Manifest(err));
+    }
+
+    let data = fs::read(tmp.path())
+        .map_err(|err| Error::File(tmp.path().to_string_lossy().into(), err))?;
+    Manifest::from_slice(&data)
+}
+
+/// Check whether the file at the given path has the given `size` and SHA-256 checksum.
+fn verify(path: &Path, size: u64, sha256: &str) -> bool {
+    path.metadata().map(|m| m.len() == size).unwrap_or(false)
+        && sha256_file(path).map(|s| s == sha256).unwrap_or(false)
+}
+
+/// Get the size of each part, when splitting data of `size` bytes into parts of `part_size`.
+pub fn part_sizes(size: u64, part_size: u64) -> Vec<u64> {
+    let count = size / part_size + if size % part_size == 0 { 0 } else { 1 };
+    (0..count)
+        .map(|i| part_size.min(size - i * part_size))
+        .collect()
+}
+
+/// Join the part files at the given paths into the given target file, in order.
+///
+/// The SHA-256 checksum of the joined file is returned, as hexadecimal string.
+pub fn join(parts: &[PathBuf], target: &Path) -> io::Result<String> {
+    let mut output = File::create(target)?;
+    let mut hasher = Sha256::new();
+    for part in parts {
+        let mut result = Ok(());
+        hash_reader(File::open(part)?, |chunk| {
+            hasher.update(chunk);
+            if result.is_ok() {
+                result = output.write_all(chunk);
+            }
+        })?;
+        result?;
+    }
+    output.flush()?;
+    Ok(hex(&hasher.finish()))
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// The manifest could not be parsed.
+    #[fail(display = "failed to parse split file manifest")]
+    Parse(#[cause] JsonError),
+
+    /// The manifest uses a newer format that is not supported.
+    #[fail(display = "unsupported split file manifest version {}", _0)]
+    Version(u32),
+
+    /// The parts listed in the manifest don't add up to the file size.
+    #[fail(display = "split file manifest is inconsistent")]
+    Inconsistent,
+
+    /// Downloading the manifest failed.
+    #[fail(display = "failed to download split file manifest")]
+    Manifest(#[cause] DownloadError),
+
+    /// The share URL of a part in the manifest is invalid.
+    #[fail(display = "invalid share link for part {}", _0)]
+    PartUrl(usize),
+
+    /// Downloading a part failed.
+    #[fail(display = "failed to download part {}", _0)]
+    Part(usize, #[cause] DownloadError),
+
+    /// A downloaded part doesn't match its checksum, it might be corrupt.
+    #[fail(display = "part {} doesn't match its checksum", _0)]
+    PartChecksum(usize),
+
+    /// The joined file doesn't match its checksum, it might be corrupt.
+    #[fail(display = "the joined file doesn't match its checksum")]
+    Checksum,
+
+    /// An error occurred while using a part or the target file.
+    #[fail(display = "couldn't use the file at '{}'", _0)]
+    File(String, #[cause] IoError),
+}
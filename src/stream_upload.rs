@@ -0,0 +1,378 @@
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+#[cfg(feature = "send3")]
+use chrono::Duration as ChronoDuration;
+use chrono::{DateTime, Utc};
+use ffsend_api::action::params::ParamsData;
+use ffsend_api::action::upload::{
+    Error as UploadError, MetaError, UploadError as UploadRequestError,
+};
+#[cfg(feature = "send3")]
+use ffsend_api::api::request::ResponseError;
+use ffsend_api::api::Version;
+use ffsend_api::crypto::b64;
+use ffsend_api::crypto::key_set::KeySet;
+#[cfg(feature = "send3")]
+use ffsend_api::file::info::FileInfo;
+use ffsend_api::file::metadata::Metadata;
+use ffsend_api::file::remote_file::RemoteFile;
+#[cfg(feature = "send2")]
+use ffsend_api::pipe::crypto::GcmCrypt;
+#[cfg(feature = "send3")]
+use ffsend_api::pipe::crypto::{ece, EceCrypt};
+use ffsend_api::pipe::progress::{ProgressPipe, ProgressReader};
+use ffsend_api::pipe::{prelude::*, ProgressReporter};
+use ffsend_api::url::Url;
+use mime_guess::Mime;
+#[cfg(feature = "send2")]
+use openssl::rand::rand_bytes;
+use openssl::symm::encrypt_aead;
+#[cfg(feature = "send3")]
+use websocket::OwnedMessage;
+
+use crate::api::Password;
+#[cfg(feature = "send2")]
+use crate::api::{ensure_success, header_nonce, Params};
+#[cfg(feature = "send2")]
+use crate::checksum::hex;
+use crate::client::Client;
+#[cfg(feature = "send2")]
+use crate::logger::log_response;
+use crate::logger::redact_url;
+use crate::throttle::Throttle;
+
+/// The length of the AES-GCM authentication tag, appended to encrypted data.
+const GCM_TAG_LEN: u64 = 16;
+
+/// An upload of a stream of data with a known size to a Send server, such as an archive that is
+/// built on the fly.
+///
+/// This mirrors the upload action of the API, which only supports uploading files from disk.
+pub struct StreamUpload {
+    /// The server API version to use.
+    version: Version,
+
+    /// The Send host to upload to.
+    host: Url,
+
+    /// The name of the uploaded file.
+    name: String,
+
+    /// An optional password to protect the file with.
+    password: Option<String>,
+
+    /// Optional file parameters to set.
+    params: Option<ParamsData>,
+
+    /// An optional throttle to limit the upload rate with.
+    throttle: Option<Throttle>,
+}
+
+impl StreamUpload {
+    /// Construct a new stream upload.
+    pub fn new(
+        version: Version,
+        host: Url,
+        name: String,
+        password: Option<String>,
+        params: Option<ParamsData>,
+        throttle: Option<Throttle>,
+    ) -> Self {
+        Self {
+            version,
+            host,
+            name,
+            password,
+            params,
+            throttle,
+        }
+    }
+
+    /// Invoke the upload, reading exactly `len` bytes of data from the given `reader`.
+    pub fn invoke(
+        self,
+        client: &Client,
+        reader: Box<dyn Read + Send>,
+        len: u64,
+        reporter: Option<&Arc<Mutex<dyn ProgressReporter>>>,
+    ) -> Result<RemoteFile, UploadError> {
+        let key = KeySet::generate(true);
+        let mime = mime_guess::from_path(&self.name).first_or_octet_stream();
+
+        // Limit the rate the data is read at
+        let reader: Box<dyn Read + Send> = match &self.throttle {
+            Some(throttle) => Box::new(throttle.reader(reader)),
+            None => reader,
+        };
+
+        // Start the reporter, and report progress while the data is read
+        if let Some(reporter) = reporter {
+            reporter
+                .lock()
+                .map_err(|_| UploadRequestError::Progress)?
+                .start(len);
+        }
+        let reader = ProgressPipe::zero(len, reporter.cloned()).reader(reader);
+
+        // Upload the data
+        let (file, nonce) = match self.version {
+            #[cfg(feature = "send2")]
+            Version::V2 => self.upload_send2(client, &key, &mime, reader, len)?,
+            #[cfg(feature = "send3")]
+            Version::V3 => self.upload_send3(client, &key, &mime, reader, len)?,
+        };
+
+        // Mark the reporter as finished
+        if let Some(reporter) = reporter {
+            reporter
+                .lock()
+                .map_err(|_| UploadRequestError::Progress)?
+                .finish();
+        }
+
+        // Change the password if set
+        if let Some(password) = &self.password {
+            Password::new(&file, password, nonce.clone()).invoke(client)?;
+        }
+
+        // Change parameters if any non-default, these are already set when uploading for Send v3
+        #[cfg(feature = "send2")]
+        {
+            if let (Version::V2, Some(mut params)) = (self.version, self.params) {
+                params.normalize(self.version);
+                if !params.is_empty() {
+                    Params::new(&file, params, nonce).invoke(client)?;
+                }
+            }
+        }
+
+        Ok(file)
+    }
+
+    /// Upload the data to the server, used in Firefox Send v2.
+    #[cfg(feature = "send2")]
+    fn upload_send2(
+        &self,
+        client: &Client,
+        key: &KeySet,
+        mime: &Mime,
+        reader: ProgressReader,
+        len: u64,
+    ) -> Result<(RemoteFile, Option<Vec<u8>>), UploadError> {
+        // Encrypt the metadata
+        let metadata = Metadata::from_send2(key.iv(), self.name.clone(), mime).to_json();
+        let metadata = encrypt_metadata(key, metadata.as_bytes())?;
+
+        // Build the encrypting reader, and wrap it in the multipart form to send
+        let reader = GcmCrypt::encrypt(len as usize, key.file_key().unwrap(), key.iv())
+            .reader(Box::new(reader));
+        let mut boundary = [0u8; 16];
+        rand_bytes(&mut boundary).expect("failed to generate multipart boundary");
+        let boundary = hex(&boundary);
+        let head = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"data\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            boundary,
+        );
+        let tail = format!("\r\n--{}--\r\n", boundary);
+        let body_len = head.len() as u64 + len + GCM_TAG_LEN + tail.len() as u64;
+        let body = io::Cursor::new(head.into_bytes())
+            .chain(reader)
+            .chain(io::Cursor::new(tail.into_bytes()));
+
+        // Send the request
+        let url = self
+            .host
+            .join("api/upload")
+            .map_err(UploadRequestError::from)?;
+        debug!("POST {} with {} bytes", redact_url(&url), len + GCM_TAG_LEN);
+        let start = Instant::now();
+        let mut response = client
+            .post(url.as_str())
+            .header(
+                "Authorization",
+                format!("send-v1 {}", key.auth_key_encoded().unwrap()),
+            )
+            .header("X-File-Metadata", b64::encode(&metadata))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .reader(Box::new(body), body_len)
+            .send()
+            .map_err(|err| {
+                debug!("upload request failed: {}", err);
+                UploadRequestError::Request
+            })?;
+        log_response(&response, start);
+        ensure_success(&response).map_err(UploadRequestError::Response)?;
+
+        // Get the nonce, and decode the response
+        let nonce = header_nonce(&response).ok();
+        let response: UploadResponse = response
+            .json()
+            .map_err(|_| UploadRequestError::InvalidResponse)?;
+        Ok((response.into_file(self.host.clone(), key, None)?, nonce))
+    }
+
+    /// Upload the data to the server over a websocket, used in Firefox Send v3.
+    #[cfg(feature = "send3")]
+    fn upload_send3(
+        &self,
+        client: &Client,
+        key: &KeySet,
+        mime: &Mime,
+        reader: ProgressReader,
+        len: u64,
+    ) -> Result<(RemoteFile, Option<Vec<u8>>), UploadError> {
+        // Connect to the websocket used for uploading
+        let url = self.host.join("api/ws").map_err(UploadRequestError::from)?;
+        debug!("connecting to websocket {}", redact_url(&url));
+        let start = Instant::now();
+        let mut ws = client.websocket(&url).map_err(|err| {
+            debug!("websocket connection failed: {}", err);
+            UploadRequestError::Request
+        })?;
+        debug!("websocket connected in {:.0?}", start.elapsed());
+
+        // Send the encrypted file info, read the upload response
+        let metadata = Metadata::from_send3(self.name.clone(), mime.to_string(), len).to_json();
+        let metadata = encrypt_metadata(key, metadata.as_bytes())?;
+        let expiry = self.params.as_ref().and_then(|p| p.expiry_time);
+        let downloads = self.params.as_ref().and_then(|p| p.download_limit);
+        let info = FileInfo::from(expiry, downloads, b64::encode(&metadata), key).to_json();
+        ws.send_message(&OwnedMessage::Text(info))
+            .map_err(UploadRequestError::from)?;
+        let response: UploadResponse = match ws.recv_message() {
+            Ok(OwnedMessage::Text(data)) => {
+                serde_json::from_str(&data).map_err(|_| UploadRequestError::InvalidResponse)?
+            }
+            Ok(_) => {
+                debug!("unexpected websocket message from server, expected upload response");
+                return Err(UploadRequestError::InvalidResponse.into());
+            }
+            Err(err) => {
+                debug!("failed to receive upload response: {}", err);
+                return Err(UploadRequestError::InvalidResponse.into());
+            }
+        };
+        trace!("sent file info, got upload response");
+
+        // Send the encrypted data in records, starting with the header, end with a footer
+        let mut reader =
+            EceCrypt::encrypt(len as usize, key.secret().to_vec(), None).reader(Box::new(reader));
+        let mut chunk = vec![0u8; ece::HEADER_LEN as usize];
+        loop {
+            let read =
+                read_full(&mut reader, &mut chunk).map_err(|_| UploadRequestError::Request)?;
+            if read == 0 {
+                break;
+            }
+            chunk.truncate(read);
+            ws.send_message(&OwnedMessage::Binary(chunk))
+                .map_err(UploadRequestError::from)?;
+            chunk = vec![0u8; ece::RS as usize];
+        }
+        ws.send_message(&OwnedMessage::Binary(vec![0]))
+            .map_err(UploadRequestError::from)?;
+        debug!("sent {} bytes in {:.0?}", len, start.elapsed());
+
+        // Make sure the server reports success
+        let ok = match ws.recv_message() {
+            Ok(OwnedMessage::Text(status)) => serde_json::from_str::<UploadStatus>(&status)
+                .map(|status| status.ok)
+                .unwrap_or(false),
+            _ => false,
+        };
+        if !ok {
+            debug!("server did not report upload success");
+            return Err(UploadRequestError::Response(ResponseError::Undefined).into());
+        }
+        let _ = ws.shutdown();
+
+        // Build the remote file, set the expiry time if known
+        let expire_at = self
+            .params
+            .as_ref()
+            .and_then(|p| p.expiry_time)
+            .map(|s| Utc::now() + ChronoDuration::seconds(s as i64));
+        Ok((response.into_file(self.host.clone(), key, expire_at)?, None))
+    }
+}
+
+/// Encrypt the given file metadata with the metadata key, and append the tag.
+fn encrypt_metadata(key: &KeySet, metadata: &[u8]) -> Result<Vec<u8>, UploadError> {
+    let mut tag = vec![0u8; GCM_TAG_LEN as usize];
+    let mut encrypted = encrypt_aead(
+        KeySet::cipher(),
+        key.meta_key().unwrap(),
+        Some(&[0u8; 12]),
+        &[],
+        metadata,
+        &mut tag,
+    )
+    .map_err(|_| UploadError::from(MetaError::Encrypt))?;
+    encrypted.append(&mut tag);
+    Ok(encrypted)
+}
+
+/// Read from the given reader until the buffer is full, or the end is reached.
+///
+/// The number of bytes read is returned.
+#[cfg(feature = "send3")]
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut pos = 0;
+    while pos < buf.len() {
+        match reader.read(&mut buf[pos..]) {
+            Ok(0) => break,
+            Ok(read) => pos += read,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(pos)
+}
+
+/// The response from the server after a file has been uploaded.
+#[derive(Debug, Deserialize)]
+struct UploadResponse {
+    /// The file ID.
+    id: String,
+
+    /// The URL the file is reachable at, without the secret.
+    url: String,
+
+    /// The owner token, used to manage the file.
+    #[serde(alias = "ownerToken", alias = "owner")]
+    owner_token: String,
+}
+
+impl UploadResponse {
+    /// Convert this response into a remote file, with the given host and key.
+    fn into_file(
+        self,
+        host: Url,
+        key: &KeySet,
+        expire_at: Option<DateTime<Utc>>,
+    ) -> Result<RemoteFile, UploadRequestError> {
+        Ok(RemoteFile::new(
+            self.id,
+            Some(Utc::now()),
+            expire_at,
+            host,
+            Url::parse(&self.url)?,
+            key.secret().to_vec(),
+            Some(self.owner_token),
+        ))
+    }
+}
+
+/// The status response from the server over the websocket after uploading.
+#[cfg(feature = "send3")]
+#[derive(Debug, Deserialize)]
+struct UploadStatus {
+    /// Whether the upload succeeded.
+    ok: bool,
+}
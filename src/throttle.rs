@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, NaiveTime};
+
+/// The minimum number of bytes to read at once when throttling.
+const CHUNK_MIN: usize = 1024;
+
+/// How far a transfer may fall behind its rate limit before the rate is measured anew.
+///
+/// This prevents bursts after a transfer stalled for a while.
+const LAG_MAX: Duration = Duration::from_secs(1);
+
+/// A bandwidth limit, which may change over the day following a schedule.
+#[derive(Clone, Debug)]
+pub enum RateLimit {
+    /// A fixed rate in bytes per second.
+    Fixed(u64),
+
+    /// Rates in bytes per second from each time of the day, sorted by time. `None` is unlimited.
+    Schedule(Vec<(NaiveTime, Option<u64>)>),
+}
+
+impl RateLimit {
+    /// Parse a rate schedule from the given map of times of the day, such as `19:00`, to rates.
+    ///
+    /// Each rate applies from its time until the next time, the last rate applies until the first
+    /// time of the next day.
+    pub fn schedule(schedule: &HashMap<String, String>) -> Result<Self, RateError> {
+        let mut schedule = schedule
+            .iter()
+            .map(|(time, rate)| {
+                let time = NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                    .map_err(|_| RateError::Time(time.into()))?;
+                Ok((time, parse_rate(rate)?))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        schedule.sort_by_key(|(time, _)| *time);
+        Ok(RateLimit::Schedule(schedule))
+    }
+
+    /// Get the rate in bytes per second at the given time of the day, `None` if unlimited.
+    pub fn rate_at(&self, time: NaiveTime) -> Option<u64> {
+        match self {
+            RateLimit::Fixed(rate) => Some(*rate),
+            RateLimit::Schedule(schedule) => schedule
+                .iter()
+                .rev()
+                .find(|(start, _)| *start <= time)
+                .or_else(|| schedule.last())
+                .and_then(|(_, rate)| *rate),
+        }
+    }
+}
+
+/// Parse the given rate, such as `500K` or `2M`, into bytes per second.
+///
+/// Units are binary, `K` is 1024 bytes. `None` is returned for `0` or `unlimited`, which disables
+/// the limit.
+pub fn parse_rate(rate: &str) -> Result<Option<u64>, RateError> {
+    let raw = rate.trim().to_lowercase();
+    if raw == "unlimited" || raw == "none" {
+        return Ok(None);
+    }
+
+    // Split the number from the unit, allow suffixes such as in 2MB/s
+    let trimmed = raw.trim_end_matches("/s").trim_end_matches('b');
+    let split = trimmed
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "k" | "ki" => 1 << 10,
+        "m" | "mi" => 1 << 20,
+        "g" | "gi" => 1 << 30,
+        _ => return Err(RateError::Invalid(rate.trim().into())),
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .ok()
+        .filter(|number: &f64| number.is_finite() && *number >= 0.0)
+        .ok_or_else(|| RateError::Invalid(rate.trim().into()))?;
+
+    // Reject rates that don't fit, rather than saturating
+    let bytes = number * multiplier as f64;
+    if bytes >= std::u64::MAX as f64 {
+        return Err(RateError::Invalid(rate.trim().into()));
+    }
+    match bytes as u64 {
+        0 => Ok(None),
+        bytes => Ok(Some(bytes)),
+    }
+}
+
+/// A bandwidth throttle.
+///
+/// Clones share their state, so a throttle may be used by multiple concurrent transfers to limit
+/// their total rate.
+#[derive(Clone)]
+pub struct Throttle {
+    /// The rate limit.
+    limit: Arc<RateLimit>,
+
+    /// The state of the current measurement.
+    state: Arc<Mutex<State>>,
+}
+
+/// The state of a throttle, measuring the transferred bytes since the rate was last changed.
+struct State {
+    /// The rate the measurement is for.
+    rate: Option<u64>,
+
+    /// The start of the measurement.
+    start: Instant,
+
+    /// The number of bytes transferred since the start.
+    transferred: u64,
+}
+
+impl Throttle {
+    /// Construct a new throttle with the given rate limit.
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit: Arc::new(limit),
+            state: Arc::new(Mutex::new(State {
+                rate: None,
+                start: Instant::now(),
+                transferred: 0,
+            })),
+        }
+    }
+
+    /// Wrap the given reader, to limit the rate data is read at.
+    pub fn reader<R: Read>(&self, inner: R) -> ThrottledReader<R> {
+        ThrottledReader {
+            inner,
+            throttle: self.clone(),
+        }
+    }
+
+    /// Get the current rate in bytes per second, `None` if unlimited.
+    fn rate(&self) -> Option<u64> {
+        self.limit.rate_at(Local::now().time())
+    }
+
+    /// Get the maximum number of bytes to transfer at once, so the rate is limited smoothly.
+    fn chunk_size(&self) -> usize {
+        match self.rate() {
+            Some(rate) => ((rate / 10) as usize).max(CHUNK_MIN),
+            None => std::usize::MAX,
+        }
+    }
+
+    /// Account for the given number of transferred bytes, and wait to stay within the rate.
+    fn consume(&self, bytes: u64) {
+        let rate = self.rate();
+        let delay = {
+            let mut state = self.state.lock().unwrap();
+
+            // Start measuring anew when the rate changed, or when lagging behind too far
+            let elapsed = state.start.elapsed();
+            let lagging = state
+                .rate
+                .map(|rate| transfer_time(state.transferred, rate))
+                .map(|expected| elapsed > expected + LAG_MAX)
+                .unwrap_or(false);
+            if state.rate != rate || lagging {
+                state.rate = rate;
+                state.start = Instant::now();
+                state.transferred = 0;
+            }
+
+            let rate = match rate {
+                Some(rate) => rate,
+                None => return,
+            };
+            state.transferred += bytes;
+            transfer_time(state.transferred, rate).checked_sub(state.start.elapsed())
+        };
+
+        if let Some(delay) = delay {
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// Get the time it takes to transfer the given number of bytes at the given rate.
+fn transfer_time(bytes: u64, rate: u64) -> Duration {
+    let nanos = u128::from(bytes % rate) * 1_000_000_000 / u128::from(rate);
+    Duration::new(bytes / rate, nanos as u32)
+}
+
+/// A reader limiting the rate data is read at from the inner reader.
+pub struct ThrottledReader<R: Read> {
+    /// The inner reader.
+    inner: R,
+
+    /// The throttle.
+    throttle: Throttle,
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.throttle.chunk_size());
+        let read = self.inner.read(&mut buf[..len])?;
+        self.throttle.consume(read as u64);
+        Ok(read)
+    }
+}
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum RateError {
+    /// The rate is invalid.
+    #[fail(
+        display = "invalid rate '{}', expected bytes per second such as '500K' or '2M'",
+        _0
+    )]
+    Invalid(String),
+
+    /// A time in the rate schedule is invalid.
+    #[fail(display = "invalid time '{}' in rate schedule, expected 'HH:MM'", _0)]
+    Time(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a rate schedule from the given times and rates.
+    fn schedule(entries: &[(&str, &str)]) -> Result<RateLimit, RateError> {
+        let entries = entries
+            .iter()
+            .map(|(time, rate)| (time.to_string(), rate.to_string()))
+            .collect();
+        RateLimit::schedule(&entries)
+    }
+
+    /// Get the time of the day for the given hour and minute.
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn parse_rate_valid() {
+        assert_eq!(parse_rate("1000").unwrap(), Some(1000));
+        assert_eq!(parse_rate("500K").unwrap(), Some(500 * 1024));
+        assert_eq!(parse_rate(" 2M ").unwrap(), Some(2 * 1024 * 1024));
+        assert_eq!(parse_rate("1G").unwrap(), Some(1 << 30));
+        assert_eq!(parse_rate("1.5k").unwrap(), Some(1536));
+        assert_eq!(parse_rate("2MiB/s").unwrap(), Some(2 * 1024 * 1024));
+        assert_eq!(parse_rate("100kb").unwrap(), Some(100 * 1024));
+        assert_eq!(parse_rate("3 m").unwrap(), Some(3 * 1024 * 1024));
+    }
+
+    #[test]
+    fn parse_rate_unlimited() {
+        assert_eq!(parse_rate("0").unwrap(), None);
+        assert_eq!(parse_rate("0M").unwrap(), None);
+        assert_eq!(parse_rate("unlimited").unwrap(), None);
+        assert_eq!(parse_rate("None").unwrap(), None);
+    }
+
+    #[test]
+    fn parse_rate_overflow() {
+        assert!(parse_rate("18446744073709551615").is_err());
+        assert!(parse_rate("99999999999G").is_err());
+        assert!(parse_rate("1e300").is_err());
+        assert_eq!(
+            parse_rate("8589934591G").unwrap(),
+            Some(8_589_934_591 << 30)
+        );
+    }
+
+    #[test]
+    fn parse_rate_invalid() {
+        for rate in &[
+            "",
+            "K",
+            "fast",
+            "5T",
+            "-1M",
+            "1..5K",
+            "NaN",
+            "inf",
+            "5 per second",
+        ] {
+            assert_eq!(
+                parse_rate(rate).unwrap_err(),
+                RateError::Invalid(rate.trim().into()),
+                "rate '{}' should be invalid",
+                rate,
+            );
+        }
+    }
+
+    #[test]
+    fn schedule_rate_at() {
+        let limit =
+            schedule(&[("19:00", "unlimited"), ("08:00", "1M"), ("12:30", "500K")]).unwrap();
+        assert_eq!(limit.rate_at(time(8, 0)), Some(1 << 20));
+        assert_eq!(limit.rate_at(time(12, 29)), Some(1 << 20));
+        assert_eq!(limit.rate_at(time(12, 30)), Some(500 << 10));
+        assert_eq!(limit.rate_at(time(19, 0)), None);
+        assert_eq!(limit.rate_at(time(23, 59)), None);
+    }
+
+    #[test]
+    fn schedule_wraps_around_midnight() {
+        let limit = schedule(&[("08:00", "1M"), ("22:00", "4M")]).unwrap();
+        assert_eq!(limit.rate_at(time(0, 0)), Some(4 << 20));
+        assert_eq!(limit.rate_at(time(7, 59)), Some(4 << 20));
+    }
+
+    #[test]
+    fn schedule_empty_is_unlimited() {
+        let limit = schedule(&[]).unwrap();
+        assert_eq!(limit.rate_at(time(12, 0)), None);
+    }
+
+    #[test]
+    fn schedule_invalid() {
+        let error = |entries: &[(&str, &str)]| schedule(entries).unwrap_err();
+        assert_eq!(error(&[("25:00", "1M")]), RateError::Time("25:00".into()));
+        assert_eq!(
+            error(&[("8 o'clock", "1M")]),
+            RateError::Time("8 o'clock".into())
+        );
+        assert_eq!(
+            error(&[("08:00", "1M"), ("19:00", "fast")]),
+            RateError::Invalid("fast".into())
+        );
+        assert_eq!(
+            error(&[("08:00", "99999999999G")]),
+            RateError::Invalid("99999999999G".into())
+        );
+    }
+
+    #[test]
+    fn fixed_rate_at() {
+        assert_eq!(RateLimit::Fixed(1024).rate_at(time(3, 0)), Some(1024));
+    }
+
+    #[test]
+    fn transfer_time_at_rate() {
+        assert_eq!(transfer_time(0, 1024), Duration::new(0, 0));
+        assert_eq!(transfer_time(2048, 1024), Duration::new(2, 0));
+        assert_eq!(transfer_time(1536, 1024), Duration::new(1, 500_000_000));
+        assert_eq!(transfer_time(1, 3), Duration::new(0, 333_333_333));
+        assert_eq!(
+            transfer_time(std::u64::MAX, std::u64::MAX),
+            Duration::new(1, 0)
+        );
+    }
+}
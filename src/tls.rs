@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Error as IoError;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use ffsend_api::crypto::b64;
+use ffsend_api::url::Url;
+use openssl::error::ErrorStack;
+use openssl::sha::sha256;
+use openssl::ssl::{
+    HandshakeError, SslConnector, SslFiletype, SslMethod, SslStream, SslVerifyMode,
+};
+use openssl::x509::X509VerifyResult;
+
+use crate::checksum::is_sha256;
+use crate::cmd::matcher::MainMatcher;
+use crate::config_file::CONFIG;
+
+/// The prefix a certificate pin may have, as used by curl.
+const PIN_PREFIX: &str = "sha256//";
+
+/// The TLS settings to connect to a host with.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    /// A PEM file with CA certificates to trust.
+    pub ca_cert: Option<PathBuf>,
+
+    /// A PEM file with a TLS client certificate.
+    pub client_cert: Option<PathBuf>,
+
+    /// A PEM file with the private key of the TLS client certificate.
+    pub client_key: Option<PathBuf>,
+
+    /// The SHA-256 hash of the public key the server certificate must have.
+    pub pin_sha256: Option<String>,
+}
+
+impl TlsConfig {
+    /// Get the TLS settings given as arguments.
+    pub fn from_args(matcher_main: &MainMatcher) -> Self {
+        TlsConfig {
+            ca_cert: matcher_main.ca_cert(),
+            client_cert: matcher_main.client_cert(),
+            client_key: matcher_main.client_key(),
+            pin_sha256: matcher_main.pin_sha256().map(|pin| pin.to_owned()),
+        }
+    }
+
+    /// Determine the TLS settings to connect to the given host with.
+    ///
+    /// Arguments take precedence over the settings for the host in the configuration file, which
+    /// take precedence over the default settings.
+    pub fn for_host(matcher_main: &MainMatcher, host: &Url) -> Self {
+        Self::from_args(matcher_main).resolve(host)
+    }
+
+    /// Complete these settings given as arguments with the configured settings for the given
+    /// host.
+    fn resolve(&self, host: &Url) -> Self {
+        let host = CONFIG.host_settings(host);
+        let settings = CONFIG.settings();
+        TlsConfig {
+            ca_cert: self
+                .ca_cert
+                .clone()
+                .or_else(|| host.and_then(|h| h.ca_cert.clone()))
+                .or_else(|| settings.ca_cert.clone()),
+            client_cert: self
+                .client_cert
+                .clone()
+                .or_else(|| host.and_then(|h| h.client_cert.clone()))
+                .or_else(|| settings.client_cert.clone()),
+            client_key: self
+                .client_key
+                .clone()
+                .or_else(|| host.and_then(|h| h.client_key.clone()))
+                .or_else(|| settings.client_key.clone()),
+            pin_sha256: self
+                .pin_sha256
+                .clone()
+                .or_else(|| host.and_then(|h| h.pin_sha256.clone()))
+                .or_else(|| settings.pin_sha256.clone()),
+        }
+    }
+
+    /// Build a TLS connector with these settings.
+    ///
+    /// The CA certificates are trusted in addition to the system certificates, the client
+    /// certificate is presented to the server when it asks for one.
+    fn connector(&self) -> Result<Connector, Error> {
+        let mut builder = SslConnector::builder(SslMethod::tls()).map_err(Error::Setup)?;
+
+        // Trust the CA certificates
+        if let Some(path) = &self.ca_cert {
+            let name = path.display().to_string();
+            fs::File::open(path).map_err(|err| Error::CaCertRead(name.clone(), err))?;
+            builder
+                .set_ca_file(path)
+                .map_err(|_| Error::CaCertParse(name))?;
+        }
+
+        // Use the client certificate, the key may be in the same file
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), key) => {
+                let key = key.as_ref().unwrap_or(cert);
+                builder
+                    .set_certificate_chain_file(cert)
+                    .map_err(|err| Error::ClientCert(cert.display().to_string(), err))?;
+                builder
+                    .set_private_key_file(key, SslFiletype::PEM)
+                    .and_then(|_| builder.check_private_key())
+                    .map_err(|err| Error::ClientKey(key.display().to_string(), err))?;
+            }
+            (None, Some(_)) => return Err(Error::NoClientCert),
+            (None, None) => {}
+        }
+
+        let pin = match &self.pin_sha256 {
+            Some(pin) => Some(parse_pin(pin).ok_or_else(|| Error::PinFormat(pin.into()))?),
+            None => None,
+        };
+
+        Ok(Connector {
+            ssl: builder.build(),
+            pin,
+        })
+    }
+}
+
+/// The TLS connectors used by the network client, built for each host when first connecting to
+/// it as the TLS settings may differ per host.
+#[derive(Default)]
+pub struct Connectors {
+    /// The TLS settings given as arguments, which take precedence over the configuration file.
+    args: TlsConfig,
+
+    /// The connectors built so far, by host name and port.
+    cache: Mutex<HashMap<(String, u16), Arc<Connector>>>,
+}
+
+impl Connectors {
+    /// Construct the TLS connectors, for the TLS settings given as arguments.
+    pub fn new(matcher_main: &MainMatcher) -> Self {
+        Self {
+            args: TlsConfig::from_args(matcher_main),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Build the connectors for the given hosts up front, to report invalid TLS settings before
+    /// any request is made.
+    pub fn prepare<'u, I>(&self, hosts: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = &'u Url>,
+    {
+        for host in hosts {
+            let name = host.host_str().unwrap_or("");
+            if host.scheme() != "https" {
+                if self.args.resolve(host).pin_sha256.is_some() {
+                    return Err(Error::PinScheme(name.into()));
+                }
+                continue;
+            }
+            self.connector(name, host.port_or_known_default().unwrap_or(443))?;
+        }
+        Ok(())
+    }
+
+    /// Get the connector for the given host and port, it is built if not used before.
+    fn connector(&self, host: &str, port: u16) -> Result<Arc<Connector>, Error> {
+        let mut cache = self.cache.lock().unwrap();
+        let key = (host.to_lowercase(), port);
+        if let Some(connector) = cache.get(&key) {
+            return Ok(connector.clone());
+        }
+
+        let url = Url::parse(&format!("https://{}:{}/", host, port)).expect("invalid host");
+        let connector = Arc::new(self.args.resolve(&url).connector()?);
+        cache.insert(key, connector.clone());
+        Ok(connector)
+    }
+
+    /// Set up TLS on the given stream connected to the given host and port.
+    ///
+    /// The certificate chain of the host is verified during the handshake, along with its pin if
+    /// set.
+    pub fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        stream: TcpStream,
+    ) -> Result<SslStream<TcpStream>, Error> {
+        self.connector(host, port)?.connect(host, stream)
+    }
+}
+
+/// A TLS connector for a host.
+struct Connector {
+    /// The OpenSSL connector with the TLS settings for the host.
+    ssl: SslConnector,
+
+    /// The SHA-256 hash of the public key the server certificate must have.
+    pin: Option<Vec<u8>>,
+}
+
+impl Connector {
+    /// Complete the TLS handshake with the given host on the given stream.
+    fn connect(&self, host: &str, stream: TcpStream) -> Result<SslStream<TcpStream>, Error> {
+        let name = host.trim_start_matches('[').trim_end_matches(']');
+        let mut config = self.ssl.configure().map_err(Error::Setup)?;
+
+        // Check the pin against the server certificate once its chain is verified, the public
+        // key hash is kept to report a mismatch
+        let mismatch = Arc::new(Mutex::new(None));
+        if let Some(pin) = self.pin.clone() {
+            let mismatch = mismatch.clone();
+            config.set_verify_callback(SslVerifyMode::PEER, move |verified, ctx| {
+                if !verified || ctx.error_depth() != 0 {
+                    return verified;
+                }
+                let hash = ctx
+                    .current_cert()
+                    .and_then(|cert| cert.public_key().ok())
+                    .and_then(|key| key.public_key_to_der().ok())
+                    .map(|key| sha256(&key));
+                if hash.as_ref().map(|hash| &hash[..]) == Some(&pin[..]) {
+                    return true;
+                }
+                *mismatch.lock().unwrap() =
+                    Some(hash.map(|hash| format_pin(&hash)).unwrap_or_default());
+                ctx.set_error(X509VerifyResult::APPLICATION_VERIFICATION);
+                false
+            });
+        }
+
+        match config.connect(name, stream) {
+            Ok(stream) => Ok(stream),
+            Err(HandshakeError::Failure(stream)) => {
+                if let Some(hash) = mismatch.lock().unwrap().take() {
+                    return Err(Error::Pin(name.into(), hash));
+                }
+                let result = stream.ssl().verify_result();
+                if result != X509VerifyResult::OK {
+                    return Err(Error::Chain(name.into(), result.error_string().into()));
+                }
+                Err(Error::Handshake(name.into(), stream.error().to_string()))
+            }
+            Err(HandshakeError::SetupFailure(err)) => Err(Error::Setup(err)),
+            Err(err) => Err(Error::Handshake(name.into(), err.to_string())),
+        }
+    }
+}
+
+/// Parse the given certificate pin into the raw SHA-256 hash.
+///
+/// The hash may be given as hexadecimal or base64 string, optionally prefixed with `sha256//`.
+pub fn parse_pin(pin: &str) -> Option<Vec<u8>> {
+    let pin = pin.trim();
+    let pin = if pin.starts_with(PIN_PREFIX) {
+        &pin[PIN_PREFIX.len()..]
+    } else {
+        pin
+    };
+    let hash = if is_sha256(pin) {
+        (0..pin.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&pin[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?
+    } else {
+        b64::decode(pin).ok()?
+    };
+    if hash.len() == 32 {
+        Some(hash)
+    } else {
+        None
+    }
+}
+
+/// Format the given SHA-256 hash as certificate pin, in standard padded base64 as used by curl.
+fn format_pin(hash: &[u8]) -> String {
+    let mut pin = b64::encode(hash).replace('-', "+").replace('_', "/");
+    while pin.len() % 4 != 0 {
+        pin.push('=');
+    }
+    format!("{}{}", PIN_PREFIX, pin)
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// Failed to set up TLS.
+    #[fail(display = "failed to set up TLS")]
+    Setup(#[cause] ErrorStack),
+
+    /// Failed to read a CA certificate file.
+    #[fail(display = "failed to read CA certificate file '{}'", _0)]
+    CaCertRead(String, #[cause] IoError),
+
+    /// A CA certificate file has no valid PEM certificates.
+    #[fail(display = "no valid PEM certificate in CA certificate file '{}'", _0)]
+    CaCertParse(String),
+
+    /// Failed to load a client certificate file.
+    #[fail(display = "failed to load TLS client certificate file '{}'", _0)]
+    ClientCert(String, #[cause] ErrorStack),
+
+    /// Failed to load a client key file, or it doesn't match the client certificate.
+    #[fail(display = "failed to load TLS client key file '{}'", _0)]
+    ClientKey(String, #[cause] ErrorStack),
+
+    /// A client key is set without a client certificate.
+    #[fail(display = "a TLS client key is set without a client certificate")]
+    NoClientCert,
+
+    /// A certificate pin is invalid.
+    #[fail(
+        display = "invalid certificate pin '{}', expected a SHA-256 hash in hex or base64",
+        _0
+    )]
+    PinFormat(String),
+
+    /// A certificate pin is set for a host that doesn't use TLS.
+    #[fail(
+        display = "a certificate pin is set for '{}', but it is not an https host",
+        _0
+    )]
+    PinScheme(String),
+
+    /// The certificate chain of a host could not be verified.
+    #[fail(
+        display = "certificate chain of '{}' could not be verified: {}",
+        _0, _1
+    )]
+    Chain(String, String),
+
+    /// The TLS handshake with a host failed for another reason.
+    #[fail(display = "TLS handshake with '{}' failed: {}", _0, _1)]
+    Handshake(String, String),
+
+    /// The public key of the certificate of a host doesn't match its pin.
+    #[fail(
+        display = "certificate pin mismatch for '{}', its public key hash is '{}'",
+        _0, _1
+    )]
+    Pin(String, String),
+}
+
+impl Error {
+    /// Check whether this error is transient, and whether connecting again might succeed.
+    ///
+    /// Only a failed handshake may be caused by a network issue, other errors are caused by the
+    /// TLS settings or the host certificate.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Handshake(..) => true,
+            _ => false,
+        }
+    }
+}
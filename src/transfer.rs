@@ -0,0 +1,763 @@
+use std::cmp::min;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Error as IoError, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use failure::Fail;
+use ffsend_api::action::metadata::{Error as MetadataError, MetadataResponse};
+use ffsend_api::api::request::ResponseError;
+use ffsend_api::api::url::UrlBuilder;
+use ffsend_api::api::Version;
+use ffsend_api::crypto::key_set::KeySet;
+use ffsend_api::crypto::sig::signature_encoded;
+use ffsend_api::file::remote_file::RemoteFile;
+#[cfg(feature = "send3")]
+use ffsend_api::pipe::crypto::EceCrypt;
+#[cfg(feature = "send2")]
+use ffsend_api::pipe::crypto::GcmCrypt;
+use ffsend_api::pipe::{prelude::*, ProgressReporter};
+use ffsend_api::reqwest::StatusCode;
+
+use crate::api::{ensure_success, Metadata as ApiMetadata};
+use crate::client::{Client, Error as ClientError, Response};
+use crate::config::{TRANSFER_RETRY_DELAY, TRANSFER_RETRY_DELAY_MAX};
+use crate::logger::{log_response, redact_url};
+use crate::throttle::Throttle;
+use crate::util::print_warning;
+
+/// The extension appended to the download target, for the partially downloaded encrypted file.
+const PARTIAL_EXTENSION: &str = ".part";
+
+/// The size of the buffer used when downloading, in bytes.
+const DOWNLOAD_BUF_SIZE: usize = 64 * 1024;
+
+/// Invoke the given transfer `attempt`, and retry it up to `retries` times if it fails with an
+/// error that `transient` considers to be transient.
+///
+/// An exponential backoff is used between attempts. A warning is printed for each retry.
+pub fn retry<T, E, F, P>(retries: u32, mut attempt: F, transient: P) -> Result<T, E>
+where
+    E: Fail,
+    F: FnMut() -> Result<T, E>,
+    P: Fn(&E) -> bool,
+{
+    let mut retried = 0;
+    loop {
+        match attempt() {
+            Err(ref err) if retried < retries && transient(err) => {
+                retried += 1;
+                let delay = retry_delay(retried);
+                let err: &dyn Fail = err;
+                info!(
+                    "transient transfer error, retry {} of {} in {}s: {}",
+                    retried,
+                    retries,
+                    delay.as_secs(),
+                    err,
+                );
+                print_warning(format!(
+                    "transfer failed ({}), retrying in {}s ({} of {})",
+                    err.iter_chain().last().unwrap_or(err),
+                    delay.as_secs(),
+                    retried,
+                    retries,
+                ));
+                sleep(delay);
+            }
+            Err(err) => {
+                if retried < retries {
+                    info!("transfer error is not transient, not retrying: {}", err);
+                } else if retries > 0 {
+                    info!("giving up after {} retries: {}", retried, err);
+                }
+                return Err(err);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Get the delay to wait before the given retry, starting at `1`.
+fn retry_delay(retry: u32) -> Duration {
+    let factor = 1u64.checked_shl(retry - 1).unwrap_or(std::u64::MAX);
+    Duration::from_secs(min(
+        TRANSFER_RETRY_DELAY.saturating_mul(factor),
+        TRANSFER_RETRY_DELAY_MAX,
+    ))
+}
+
+/// Check whether the given server response error is transient, and whether the request might
+/// succeed when it is retried.
+pub fn is_transient_response(err: &ResponseError) -> bool {
+    match err {
+        ResponseError::OtherHttp(status, _) => {
+            status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+        }
+        ResponseError::Undefined => true,
+        ResponseError::Expired | ResponseError::Unauthorized => false,
+    }
+}
+
+/// A resumable file download from a Send server.
+///
+/// The encrypted file is first downloaded to a partial file next to the target. If the download is
+/// interrupted, it is resumed from the partial file using a HTTP range request when retried, if the
+/// server supports it. Once complete, the partial file is decrypted into the target from the very
+/// start, so the whole file is verified as usual. Alternatively the file may be decrypted into a
+/// writer while it is received, see `invoke_writer`.
+pub struct ResumableDownload<'a> {
+    /// The server API version to use when downloading the file.
+    version: Version,
+
+    /// The remote file to download.
+    file: &'a RemoteFile,
+
+    /// The target file to download to.
+    target: PathBuf,
+
+    /// An optional password to decrypt a protected file.
+    password: Option<String>,
+
+    /// An optional throttle to limit the download rate with.
+    throttle: Option<Throttle>,
+}
+
+impl<'a> ResumableDownload<'a> {
+    /// Construct a new resumable download for the given remote file, to the given target file.
+    pub fn new(
+        version: Version,
+        file: &'a RemoteFile,
+        target: PathBuf,
+        password: Option<String>,
+        throttle: Option<Throttle>,
+    ) -> Self {
+        Self {
+            version,
+            file,
+            target,
+            password,
+            throttle,
+        }
+    }
+
+    /// Get the path of the partial file the encrypted file is downloaded to.
+    pub fn partial_path(&self) -> PathBuf {
+        let mut path = OsString::from(self.target.as_os_str());
+        path.push(PARTIAL_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    /// Invoke the download.
+    ///
+    /// The fetched `metadata` is used for the first attempt, the download is retried up to
+    /// `retries` times on transient errors.
+    pub fn invoke(
+        &self,
+        client: &Client,
+        metadata: MetadataResponse,
+        retries: u32,
+        reporter: Option<Arc<Mutex<dyn ProgressReporter>>>,
+    ) -> Result<(), Error> {
+        // Fetch the encrypted file, then decrypt it into the target and remove the partial file
+        let (key, len) = self.fetch_retry(client, metadata, retries, reporter)?;
+        self.decrypt(&key, len)?;
+        if let Err(err) = fs::remove_file(self.partial_path()) {
+            return Err(Error::File(self.partial_path_string(), err));
+        }
+
+        Ok(())
+    }
+
+    /// Invoke the download, and write the decrypted file to the given writer while it is received.
+    ///
+    /// No partial file is used. When retried, the download continues at the position that was
+    /// received before. The file is verified while it is written, so an error may be returned after
+    /// data was written.
+    pub fn invoke_writer<W: Write + 'static>(
+        &self,
+        client: &Client,
+        metadata: MetadataResponse,
+        retries: u32,
+        reporter: Option<Arc<Mutex<dyn ProgressReporter>>>,
+        out: W,
+    ) -> Result<(), Error> {
+        let key = self.key_set(&metadata);
+        let len = metadata.size();
+        let mut writer = DecryptWriter {
+            inner: self.decrypt_writer(&key, len, Box::new(out)),
+            failed: false,
+        };
+
+        // Fetch the encrypted file, continue where the last attempt left off when retrying
+        let mut written = 0;
+        self.retry(client, metadata, retries, |metadata| {
+            let (response, start) = match self.request(client, &key, metadata, written)? {
+                Some(response) => response,
+                None => return Ok(()),
+            };
+            self.receive(
+                response,
+                start,
+                len,
+                &mut written,
+                &mut writer,
+                &Error::from_write,
+                reporter.clone(),
+            )
+        })?;
+        writer.flush().map_err(Error::from_write)
+    }
+
+    /// Invoke the download, and get a reader decrypting the downloaded file on the fly.
+    ///
+    /// This is used to process the file without writing it to the target, such as when extracting
+    /// an archive. The caller must remove the partial file once done. The file is verified while
+    /// it is read, so read everything before trusting the data.
+    #[cfg(feature = "archive")]
+    pub fn invoke_reader(
+        &self,
+        client: &Client,
+        metadata: MetadataResponse,
+        retries: u32,
+        reporter: Option<Arc<Mutex<dyn ProgressReporter>>>,
+    ) -> Result<DecryptReader, Error> {
+        let (key, len) = self.fetch_retry(client, metadata, retries, reporter)?;
+        let file = File::open(self.partial_path())
+            .map_err(|err| Error::File(self.partial_path_string(), err))?;
+
+        // Build the decrypting file reader for the selected server API version
+        let reader: Box<dyn Read> = match self.version {
+            #[cfg(feature = "send2")]
+            Version::V2 => {
+                let decrypt = GcmCrypt::decrypt(len as usize, key.file_key().unwrap(), key.iv());
+                Box::new(decrypt.reader(Box::new(file)))
+            }
+            #[cfg(feature = "send3")]
+            Version::V3 => {
+                let decrypt = EceCrypt::decrypt(len as usize, key.secret().to_vec());
+                Box::new(decrypt.reader(Box::new(file)))
+            }
+        };
+        Ok(DecryptReader {
+            inner: reader,
+            failed: false,
+        })
+    }
+
+    /// Fetch the encrypted file into the partial file, retrying up to `retries` times on
+    /// transient errors.
+    ///
+    /// The fetched `metadata` is used for the first attempt. The key set for the file is
+    /// returned, along with the encrypted file size.
+    fn fetch_retry(
+        &self,
+        client: &Client,
+        metadata: MetadataResponse,
+        retries: u32,
+        reporter: Option<Arc<Mutex<dyn ProgressReporter>>>,
+    ) -> Result<(KeySet, u64), Error> {
+        let key = self.key_set(&metadata);
+        let len = metadata.size();
+        self.retry(client, metadata, retries, |metadata| {
+            self.fetch(client, &key, metadata, len, reporter.clone())
+        })?;
+        Ok((key, len))
+    }
+
+    /// Create the key set for the file, set the input vector if known.
+    fn key_set(&self, metadata: &MetadataResponse) -> KeySet {
+        let mut key = KeySet::from(self.file, self.password.as_ref());
+        if let Some(iv) = metadata.metadata().iv() {
+            key.set_iv(iv);
+        }
+        key
+    }
+
+    /// Invoke the given `fetch` attempt, retrying up to `retries` times on transient errors.
+    ///
+    /// The fetched `metadata` is used for the first attempt, each retry needs fresh metadata for
+    /// a new nonce.
+    fn retry<F>(
+        &self,
+        client: &Client,
+        metadata: MetadataResponse,
+        retries: u32,
+        mut fetch: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&MetadataResponse) -> Result<(), Error>,
+    {
+        let mut metadata = Some(metadata);
+        retry(
+            retries,
+            || {
+                let metadata = match metadata.take() {
+                    Some(metadata) => metadata,
+                    None => {
+                        debug!("fetching fresh metadata of file {}", self.file.id());
+                        ApiMetadata::new(self.file, self.password.clone(), false)
+                            .invoke(client)
+                            .map_err(Error::Metadata)?
+                    }
+                };
+                fetch(&metadata)
+            },
+            Error::is_transient,
+        )
+    }
+
+    /// Fetch the encrypted file from the server, into the partial file.
+    ///
+    /// If the partial file already holds data, the download is resumed when the server supports
+    /// range requests. Otherwise the partial file is truncated and the download starts over.
+    fn fetch(
+        &self,
+        client: &Client,
+        key: &KeySet,
+        metadata: &MetadataResponse,
+        len: u64,
+        reporter: Option<Arc<Mutex<dyn ProgressReporter>>>,
+    ) -> Result<(), Error> {
+        // Determine how much was downloaded before, request the rest
+        let path = self.partial_path();
+        let offset = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let (response, mut offset) = match self.request(client, key, metadata, offset)? {
+            Some(response) => response,
+            None => return Ok(()),
+        };
+
+        // Append to the partial file if the server resumed at our offset, or start over
+        let mut out = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(offset > 0)
+            .truncate(offset == 0)
+            .open(&path)
+            .map_err(|err| Error::File(self.partial_path_string(), err))?;
+        let start = offset;
+        self.receive(
+            response,
+            start,
+            len,
+            &mut offset,
+            &mut out,
+            &|err| Error::File(self.partial_path_string(), err),
+            reporter,
+        )?;
+        out.flush()
+            .map_err(|err| Error::File(self.partial_path_string(), err))
+    }
+
+    /// Request the encrypted file from the server, from the given `offset`.
+    ///
+    /// The response is returned along with the position its body starts at, which is `0` if the
+    /// server doesn't support range requests. `None` is returned if there is nothing left to
+    /// download past the offset.
+    fn request(
+        &self,
+        client: &Client,
+        key: &KeySet,
+        metadata: &MetadataResponse,
+        offset: u64,
+    ) -> Result<Option<(Response, u64)>, Error> {
+        // Compute the cryptographic signature
+        let sig = signature_encoded(key.auth_key().unwrap(), metadata.nonce())
+            .map_err(|_| Error::ComputeSignature)?;
+
+        // Build and send the download request, request just the missing part if resuming
+        let url = UrlBuilder::api_download(self.file);
+        let mut request = client
+            .get(url.clone())
+            .header("Authorization", format!("send-v1 {}", sig));
+        if offset > 0 {
+            debug!("GET {}, resuming at byte {}", redact_url(&url), offset);
+            request = request.header("Range", format!("bytes={}-", offset));
+        } else {
+            debug!("GET {}", redact_url(&url));
+        }
+        let start = Instant::now();
+        let response = request.send().map_err(Error::Request)?;
+        log_response(&response, start);
+
+        // The file is already complete if the requested range is past the end
+        if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            return Ok(None);
+        }
+        ensure_success(&response).map_err(Error::Response)?;
+
+        // Determine whether the server resumes at our offset
+        let resume = offset > 0
+            && response.status() == StatusCode::PARTIAL_CONTENT
+            && range_start(&response) == Some(offset);
+        if offset > 0 && !resume {
+            debug!("server did not resume at byte {}, starting over", offset);
+        }
+        Ok(Some((response, if resume { offset } else { 0 })))
+    }
+
+    /// Receive the body of the given download response, which starts at position `start` of the
+    /// encrypted file of `len` bytes.
+    ///
+    /// Data up to `written` was received before, and is skipped. The rest is written to `out`
+    /// at a limited rate, `written` is updated along the way. Write errors are mapped using
+    /// `map_write`.
+    #[allow(clippy::too_many_arguments)]
+    fn receive(
+        &self,
+        response: Response,
+        start: u64,
+        len: u64,
+        written: &mut u64,
+        out: &mut dyn Write,
+        map_write: &dyn Fn(IoError) -> Error,
+        reporter: Option<Arc<Mutex<dyn ProgressReporter>>>,
+    ) -> Result<(), Error> {
+        // Determine the total size of the encrypted file
+        let total = response
+            .content_length()
+            .map(|len| len + start)
+            .unwrap_or(len);
+
+        // Start the progress, at the resumed position
+        if let Some(reporter) = reporter.as_ref() {
+            let mut reporter = reporter.lock().map_err(|_| Error::Progress)?;
+            reporter.start(total);
+            reporter.progress(start);
+        }
+
+        // Download the file at a limited rate, write what wasn't received before
+        let begin = Instant::now();
+        let mut body: Box<dyn Read> = match &self.throttle {
+            Some(throttle) => Box::new(throttle.reader(response)),
+            None => Box::new(response),
+        };
+        let mut buf = vec![0u8; DOWNLOAD_BUF_SIZE];
+        let mut progress = start;
+        loop {
+            let read = match body.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(Error::Download(err)),
+            };
+            let skip = min(written.saturating_sub(progress), read as u64) as usize;
+            out.write_all(&buf[skip..read]).map_err(map_write)?;
+
+            progress += read as u64;
+            *written = (*written).max(progress);
+            if let Some(reporter) = reporter.as_ref() {
+                reporter
+                    .lock()
+                    .map_err(|_| Error::Progress)?
+                    .progress(progress);
+            }
+        }
+
+        debug!(
+            "received {} of {} bytes in {:.0?}",
+            progress - start,
+            total,
+            begin.elapsed(),
+        );
+
+        // The connection may have been closed before everything was received
+        if progress < total {
+            return Err(Error::Download(IoError::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed before the file was fully received",
+            )));
+        }
+
+        // Finish the progress
+        if let Some(reporter) = reporter.as_ref() {
+            reporter.lock().map_err(|_| Error::Progress)?.finish();
+        }
+
+        Ok(())
+    }
+
+    /// Decrypt the downloaded partial file into the target file.
+    ///
+    /// The partial file is removed if decryption fails, as it is corrupt and would fail again when
+    /// resumed.
+    fn decrypt(&self, key: &KeySet, len: u64) -> Result<(), Error> {
+        let target_str = self.target.to_str().unwrap_or("?").to_owned();
+
+        // Open the partial file, and the target file
+        let mut reader = File::open(self.partial_path())
+            .map_err(|err| Error::File(self.partial_path_string(), err))?;
+        let out = File::create(&self.target).map_err(|err| Error::File(target_str.clone(), err))?;
+
+        let mut writer = self.decrypt_writer(key, len, Box::new(out));
+
+        // Decrypt, the crypto pipes panic on invalid data so catch it to report a proper error
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            io::copy(&mut reader, &mut writer)?;
+            writer.flush()
+        }));
+        match result {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(err)) => Err(Error::File(target_str, err)),
+            Err(_) => {
+                let _ = fs::remove_file(self.partial_path());
+                Err(Error::Decrypt)
+            }
+        }
+    }
+
+    /// Build a writer decrypting the encrypted file of `len` bytes into `out`, for the selected
+    /// server API version.
+    fn decrypt_writer(&self, key: &KeySet, len: u64, out: Box<dyn Write>) -> Box<dyn Write> {
+        match self.version {
+            #[cfg(feature = "send2")]
+            Version::V2 => {
+                let decrypt = GcmCrypt::decrypt(len as usize, key.file_key().unwrap(), key.iv());
+                Box::new(decrypt.writer(out))
+            }
+            #[cfg(feature = "send3")]
+            Version::V3 => {
+                let decrypt = EceCrypt::decrypt(len as usize, key.secret().to_vec());
+                Box::new(decrypt.writer(out))
+            }
+        }
+    }
+
+    /// Get the path of the partial file as string, for use in errors.
+    fn partial_path_string(&self) -> String {
+        self.partial_path().to_str().unwrap_or("?").to_owned()
+    }
+}
+
+/// A reader decrypting a downloaded file on the fly.
+///
+/// The crypto pipes panic on invalid data, this is caught and reported as error.
+#[cfg(feature = "archive")]
+pub struct DecryptReader {
+    /// The decrypting reader.
+    inner: Box<dyn Read>,
+
+    /// Whether decryption failed before.
+    failed: bool,
+}
+
+#[cfg(feature = "archive")]
+impl Read for DecryptReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.failed {
+            let inner = &mut self.inner;
+            match panic::catch_unwind(AssertUnwindSafe(|| inner.read(buf))) {
+                Ok(result) => return result,
+                Err(_) => self.failed = true,
+            }
+        }
+        Err(IoError::new(
+            io::ErrorKind::InvalidData,
+            "failed to decrypt the downloaded file",
+        ))
+    }
+}
+
+/// A writer decrypting a downloaded file on the fly.
+///
+/// The crypto pipes panic on invalid data, this is caught and reported as error.
+struct DecryptWriter {
+    /// The decrypting writer.
+    inner: Box<dyn Write>,
+
+    /// Whether decryption failed before.
+    failed: bool,
+}
+
+impl DecryptWriter {
+    /// Run the given operation on the inner writer, report a panic as decryption error.
+    fn guard<T, F>(&mut self, op: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut dyn Write) -> io::Result<T>,
+    {
+        if !self.failed {
+            let inner = &mut self.inner;
+            match panic::catch_unwind(AssertUnwindSafe(|| op(inner.as_mut()))) {
+                Ok(result) => return result,
+                Err(_) => self.failed = true,
+            }
+        }
+        Err(IoError::new(
+            io::ErrorKind::InvalidData,
+            "failed to decrypt the downloaded file",
+        ))
+    }
+}
+
+impl Write for DecryptWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.guard(|inner| inner.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.guard(|inner| inner.flush())
+    }
+}
+
+/// Get the start position of the content range in the given response, if available.
+fn range_start(response: &Response) -> Option<u64> {
+    response
+        .header("Content-Range")?
+        .trim()
+        .trim_start_matches("bytes")
+        .trim()
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// Failed to fetch the file metadata, which is required for a new nonce on retry.
+    #[fail(display = "failed to fetch file metadata")]
+    Metadata(#[cause] MetadataError),
+
+    /// Failed to compute the cryptographic signature used for downloading the file.
+    #[fail(display = "failed to compute cryptographic signature")]
+    ComputeSignature,
+
+    /// Sending the request to download the file failed.
+    #[fail(display = "failed to request file download")]
+    Request(#[cause] ClientError),
+
+    /// The server responded with an error while requesting the file download.
+    #[fail(display = "bad response from server while requesting download")]
+    Response(#[cause] ResponseError),
+
+    /// Failed to start or update the downloading progress.
+    #[fail(display = "failed to update download progress")]
+    Progress,
+
+    /// Receiving the file from the server failed.
+    #[fail(display = "failed to download the file")]
+    Download(#[cause] IoError),
+
+    /// An error occurred while opening or writing to the partial or target file.
+    #[fail(display = "couldn't use the file at '{}'", _0)]
+    File(String, #[cause] IoError),
+
+    /// An error occurred while decrypting the downloaded file, it might be corrupt.
+    #[fail(display = "failed to decrypt the downloaded file")]
+    Decrypt,
+
+    /// An error occurred while writing the decrypted file.
+    #[fail(display = "failed to write the downloaded file")]
+    Write(#[cause] IoError),
+}
+
+impl Error {
+    /// Check whether this error is transient, and whether the download might succeed when it is
+    /// retried.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Metadata(MetadataError::Expired)
+            | Error::Metadata(MetadataError::PasswordRequired) => false,
+            Error::Metadata(_) | Error::Request(_) | Error::Download(_) => true,
+            Error::Response(err) => is_transient_response(err),
+            Error::ComputeSignature
+            | Error::Progress
+            | Error::File(..)
+            | Error::Decrypt
+            | Error::Write(_) => false,
+        }
+    }
+
+    /// Map an error writing the decrypted file, a decryption failure is reported as such.
+    fn from_write(err: IoError) -> Self {
+        match err.kind() {
+            io::ErrorKind::InvalidData => Error::Decrypt,
+            _ => Error::Write(err),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "send3"))]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use ffsend_api::url::Url;
+
+    /// A writer collecting all data in a shared buffer.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Encrypt the given data, and get a writer to decrypt it into the returned buffer.
+    fn setup(data: &[u8]) -> (Vec<u8>, DecryptWriter, SharedBuf) {
+        let key = KeySet::generate(true);
+        let url = Url::parse("https://send.example.com/download/abc/").unwrap();
+        let host = Url::parse("https://send.example.com/").unwrap();
+        let file = RemoteFile::new(
+            "abc".into(),
+            None,
+            None,
+            host,
+            url,
+            key.secret().into(),
+            None,
+        );
+
+        let mut encrypted = Vec::new();
+        EceCrypt::encrypt(data.len(), key.secret().to_vec(), None)
+            .reader(Box::new(Cursor::new(data.to_vec())))
+            .read_to_end(&mut encrypted)
+            .unwrap();
+
+        let download = ResumableDownload::new(Version::V3, &file, "-".into(), None, None);
+        let out = SharedBuf::default();
+        let writer = DecryptWriter {
+            inner: download.decrypt_writer(&key, data.len() as u64, Box::new(out.clone())),
+            failed: false,
+        };
+        (encrypted, writer, out)
+    }
+
+    #[test]
+    fn decrypt_writer_streams() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let (encrypted, mut writer, out) = setup(&data);
+        for chunk in encrypted.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.flush().unwrap();
+        assert!(*out.0.lock().unwrap() == data);
+    }
+
+    #[test]
+    fn decrypt_writer_corrupt() {
+        let data = vec![7u8; 100_000];
+        let (mut encrypted, mut writer, _) = setup(&data);
+        encrypted[100] ^= 0xff;
+        let err = writer
+            .write_all(&encrypted)
+            .and_then(|_| writer.flush())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(writer.write(&[0]).is_err());
+        match Error::from_write(err) {
+            Error::Decrypt => {}
+            err => panic!("expected a decrypt error, got {:?}", err),
+        }
+    }
+}
@@ -1,9 +1,7 @@
 //! URL shortening mechanics.
 
 use ffsend_api::{
-    api::request::{ensure_success, ResponseError},
-    client::Client,
-    reqwest,
+    api::request::ResponseError,
     url::{self, Url},
 };
 use urlshortener::{
@@ -11,6 +9,9 @@ use urlshortener::{
     request::{Method, Request},
 };
 
+use crate::api::ensure_success;
+use crate::client::{Client, Error as ClientError};
+
 /// An URL shortening result.
 type Result<T> = ::std::result::Result<T, Error>;
 
@@ -70,7 +71,7 @@ fn request(client: &Client, req: Request) -> Result<String> {
 pub enum Error {
     /// Failed to send the shortening request.
     #[fail(display = "failed to send URL shorten request")]
-    Request(#[cause] reqwest::Error),
+    Request(#[cause] ClientError),
 
     /// The server responded with a bad response.
     #[fail(display = "failed to shorten URL, got bad response")]
@@ -78,7 +79,7 @@ pub enum Error {
 
     /// The server resonded with a malformed repsonse.
     #[fail(display = "failed to shorten URL, got malformed response")]
-    Malformed(#[cause] reqwest::Error),
+    Malformed(#[cause] ClientError),
 
     /// An error occurred while parsing the shortened URL.
     #[fail(display = "failed to shorten URL, could not parse URL")]
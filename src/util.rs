@@ -15,28 +15,39 @@ use std::io::{stderr, stdin, Error as IoError, Write};
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::{exit, ExitStatus};
-#[cfg(all(feature = "clipboard", target_os = "linux"))]
+#[cfg(any(feature = "history", all(feature = "clipboard", target_os = "linux")))]
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 #[cfg(all(feature = "clipboard", not(target_os = "linux")))]
 use self::clipboard::{ClipboardContext, ClipboardProvider};
 use self::colored::*;
-#[cfg(feature = "history")]
 use self::directories::ProjectDirs;
 use self::fs2::available_space;
 use chrono::Duration;
 use failure::{err_msg, Fail};
 #[cfg(all(feature = "clipboard", not(target_os = "linux")))]
 use failure::{Compat, Error};
-use ffsend_api::{
-    api::request::{ensure_success, ResponseError},
-    client::Client,
-    reqwest,
-    url::Url,
-};
+use ffsend_api::{api::request::ResponseError, api::Version, config::expiry_max, url::Url};
 use rpassword::prompt_password_stderr;
+use serde_json::json;
 
+use crate::api::ensure_success;
+use crate::client::{Client, Error as ClientError};
 use crate::cmd::matcher::MainMatcher;
+use crate::logger::{log_response, redact_url};
+
+/// The error code used for errors that are not related to a specific action.
+pub const ERROR_CODE_GENERIC: &str = "error";
+
+/// Whether to print errors as JSON objects.
+static ERROR_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Set whether to print errors as JSON objects, for machine readable output.
+pub fn set_error_json(json: bool) {
+    ERROR_JSON.store(json, Ordering::Relaxed);
+}
 
 /// Print a success message.
 pub fn print_success(msg: &str) {
@@ -46,24 +57,56 @@ pub fn print_success(msg: &str) {
 /// Print the given error in a proper format for the user,
 /// with it's causes.
 pub fn print_error<E: Fail>(err: impl Borrow<E>) {
-    // Report each printable error, count them
-    let count = err
+    print_error_code(err, ERROR_CODE_GENERIC, &[]);
+}
+
+/// Print the given error in a proper format for the user,
+/// with it's causes.
+///
+/// When errors are printed as JSON, a single JSON object is printed holding
+/// the given stable error `code`, the error message, it's causes and the
+/// given `info` messages. Otherwise the `code` and `info` are not printed.
+pub fn print_error_code<E: Fail>(err: impl Borrow<E>, code: &str, info: &[String]) {
+    // Collect each printable error
+    let mut causes: Vec<String> = err
         .borrow()
         .causes()
         .map(|err| format!("{}", err))
         .filter(|err| !err.is_empty())
-        .enumerate()
-        .map(|(i, err)| {
-            if i == 0 {
-                eprintln!("{} {}", highlight_error("error:"), err);
-            } else {
-                eprintln!("{} {}", highlight_error("caused by:"), err);
-            }
-        })
-        .count();
+        .collect();
+
+    // Print a JSON object
+    if ERROR_JSON.load(Ordering::Relaxed) {
+        let message = if causes.is_empty() {
+            "an undefined error occurred".into()
+        } else {
+            causes.remove(0)
+        };
+        eprintln!(
+            "{}",
+            json!({
+                "error": {
+                    "code": code,
+                    "message": message,
+                    "causes": causes,
+                    "info": info,
+                }
+            }),
+        );
+        return;
+    }
+
+    // Report each printable error
+    for (i, err) in causes.iter().enumerate() {
+        if i == 0 {
+            eprintln!("{} {}", highlight_error("error:"), err);
+        } else {
+            eprintln!("{} {}", highlight_error("caused by:"), err);
+        }
+    }
 
     // Fall back to a basic message
-    if count == 0 {
+    if causes.is_empty() {
         eprintln!(
             "{} {}",
             highlight_error("error:"),
@@ -94,10 +137,27 @@ pub fn quit() -> ! {
     exit(0);
 }
 
+/// Quit the application with the given exit code, without printing anything.
+pub fn quit_code(code: i32) -> ! {
+    exit(code);
+}
+
 /// Quit the application with an error code,
 /// and print the given error.
 pub fn quit_error<E: Fail>(err: E, hints: impl Borrow<ErrorHints>) -> ! {
-    // Print the error
+    quit_error_code(err, ERROR_CODE_GENERIC, hints);
+}
+
+/// Quit the application with an error code,
+/// and print the given error with the given stable error `code`.
+///
+/// See `print_error_code`.
+pub fn quit_error_code<E: Fail>(err: E, code: &str, hints: impl Borrow<ErrorHints>) -> ! {
+    // Print the error, include the info hints in a JSON error
+    if ERROR_JSON.load(Ordering::Relaxed) {
+        print_error_code(err, code, &hints.borrow().info);
+        exit(1);
+    }
     print_error(err);
 
     // Print error hints
@@ -360,6 +420,58 @@ pub enum ClipboardError {
     XclipStatus(i32),
 }
 
+/// Run the given passphrase command, and get the passphrase it outputs.
+///
+/// The command is run through the system shell, so a keyring helper such as
+/// `secret-tool lookup service ffsend` may be used. The first line it outputs is the passphrase.
+#[cfg(feature = "history")]
+pub fn passphrase_cmd(cmd: &str) -> Result<String, PassphraseCmdError> {
+    #[cfg(not(windows))]
+    let mut command = Command::new("sh");
+    #[cfg(not(windows))]
+    command.arg("-c");
+    #[cfg(windows)]
+    let mut command = Command::new("cmd");
+    #[cfg(windows)]
+    command.arg("/C");
+
+    // Run the command, let it interact with the user through stdin and stderr
+    let output = command
+        .arg(cmd)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(PassphraseCmdError::Run)?;
+    if !output.status.success() {
+        return Err(PassphraseCmdError::Status(
+            output.status.code().unwrap_or(0),
+        ));
+    }
+
+    // Take the first line as passphrase
+    let stdout = String::from_utf8(output.stdout).map_err(|_| PassphraseCmdError::Empty)?;
+    match stdout.lines().next() {
+        Some(passphrase) if !passphrase.is_empty() => Ok(passphrase.into()),
+        _ => Err(PassphraseCmdError::Empty),
+    }
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, Fail)]
+pub enum PassphraseCmdError {
+    /// Failed to run the passphrase command.
+    #[fail(display = "failed to run passphrase command")]
+    Run(#[cause] IoError),
+
+    /// The passphrase command exited with a non-successful status code.
+    #[fail(display = "passphrase command exited with status code {}", _0)]
+    Status(i32),
+
+    /// The passphrase command did not output a passphrase.
+    #[fail(display = "passphrase command did not output a passphrase")]
+    Empty,
+}
+
 /// Check for an emtpy password in the given `password`.
 /// If the password is emtpy the program will quit with an error unless
 /// forced.
@@ -423,6 +535,47 @@ pub fn prompt_password(main_matcher: &MainMatcher, optional: bool) -> Option<Str
     }
 }
 
+/// Prompt the user to enter the passphrase for the history.
+/// If `confirm` is set, the passphrase must be entered twice.
+///
+/// The program will quit with an error if no passphrase is entered, or if in no-interact mode.
+#[cfg(feature = "history")]
+pub fn prompt_history_passphrase(main_matcher: &MainMatcher, confirm: bool) -> String {
+    // Quit with an error if we may not interact
+    if main_matcher.no_interact() {
+        quit_error_msg(
+            "missing history passphrase, must be specified in no-interact mode",
+            ErrorHintsBuilder::default()
+                .add_info(format!(
+                    "Use the {} environment variable, or the '{}' option",
+                    highlight("FFSEND_HISTORY_PASSPHRASE"),
+                    highlight("--history-passphrase-cmd")
+                ))
+                .verbose(false)
+                .build()
+                .unwrap(),
+        );
+    }
+
+    // Prompt for the passphrase
+    let prompt = |prompt| match prompt_password_stderr(prompt) {
+        Ok(passphrase) => passphrase,
+        Err(err) => quit_error(
+            err.context("failed to read history passphrase from prompt"),
+            ErrorHints::default(),
+        ),
+    };
+    let passphrase = prompt("History passphrase: ");
+    if passphrase.is_empty() {
+        quit_error_msg("no history passphrase entered", ErrorHints::default());
+    }
+    if confirm && prompt("Confirm history passphrase: ") != passphrase {
+        quit_error_msg("history passphrases don't match", ErrorHints::default());
+    }
+
+    passphrase
+}
+
 /// Get a password if required.
 /// This method will ensure a password is set (or not) in the given `password`
 /// parameter, as defined by `needs`.
@@ -446,7 +599,7 @@ pub fn ensure_password(
     if !needs {
         // Notify the user a set password is ignored
         if password.is_some() {
-            println!("Ignoring password, it is not required");
+            eprintln!("Ignoring password, it is not required");
             *password = None;
         }
         return false;
@@ -627,9 +780,9 @@ pub fn ensure_owner_token(
     // Notify that an owner token is required
     if interact && token.is_none() {
         if optional {
-            println!("The file owner token is recommended for authentication.");
+            eprintln!("The file owner token is recommended for authentication.");
         } else {
-            println!("The file owner token is required for authentication.");
+            eprintln!("The file owner token is required for authentication.");
         }
     }
 
@@ -735,6 +888,106 @@ pub fn format_duration(duration: impl Borrow<Duration>) -> String {
     components.join("")
 }
 
+/// Parse the given human readable duration into a number of seconds.
+/// This method parses a string of time components as produced by
+/// `format_duration`.
+///
+/// The following time units are supported:
+/// - `w`: weeks
+/// - `d`: days
+/// - `h`: hours
+/// - `m`: minutes
+/// - `s`: seconds
+///
+/// A number without a unit is parsed as seconds.
+///
+/// The following time strings may be parsed:
+/// - `7d`
+/// - `1h30m`
+/// - `300`
+pub fn parse_duration(duration: &str) -> Result<usize, ParseDurationError> {
+    // Return immediately if empty
+    let duration = duration.trim();
+    if duration.is_empty() {
+        return Err(ParseDurationError::Empty);
+    }
+
+    // Sum all time components
+    let mut secs = 0;
+    let mut value = String::new();
+    for c in duration.chars() {
+        // Collect the digits of the current component
+        if c.is_ascii_digit() {
+            value.push(c);
+            continue;
+        }
+
+        // Determine the unit size
+        let unit = match c.to_ascii_lowercase() {
+            'w' => 60 * 60 * 24 * 7,
+            'd' => 60 * 60 * 24,
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(ParseDurationError::UnknownUnit(c)),
+        };
+
+        // Parse the component value, and add it to the total
+        if value.is_empty() {
+            return Err(ParseDurationError::MissingValue);
+        }
+        secs = value
+            .parse::<usize>()
+            .ok()
+            .and_then(|value| value.checked_mul(unit))
+            .and_then(|value| value.checked_add(secs))
+            .ok_or(ParseDurationError::Overflow)?;
+        value.clear();
+    }
+
+    // Parse a trailing value without unit as seconds
+    if !value.is_empty() {
+        secs = value
+            .parse::<usize>()
+            .ok()
+            .and_then(|value| value.checked_add(secs))
+            .ok_or(ParseDurationError::Overflow)?;
+    }
+
+    Ok(secs)
+}
+
+/// Check whether the given text matches the given glob pattern.
+///
+/// The pattern may contain `*` to match any number of characters, and `?` to match a single
+/// character. Matching is case insensitive.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    // Walk through both, remember the last star position to backtrack to
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    // Any remaining pattern must be stars
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
 /// Format the given boolean, as `yes` or `no`.
 pub fn format_bool(b: bool) -> &'static str {
     if b {
@@ -805,10 +1058,54 @@ pub fn ensure_enough_space<P: AsRef<Path>>(path: P, size: u64) {
     );
 }
 
+/// Ensure the given expiry time in seconds is supported by the server,
+/// for the given API version.
+///
+/// Send servers only accept a fixed set of expiry times, which differs
+/// between API versions and whether the user is authenticated.
+///
+/// If the expiry time isn't supported and the user isn't forcing,
+/// an error is reported and the program will quit.
+pub fn ensure_expiry_time(
+    expiry_time: usize,
+    version: Version,
+    auth: bool,
+    matcher_main: &MainMatcher,
+) {
+    // Return if the expiry time is supported, or if forced
+    let supported = expiry_max(version, auth);
+    if supported.contains(&expiry_time) || matcher_main.force() {
+        return;
+    }
+
+    // Create an info message listing the supported values
+    let info = format!(
+        "the server supports: {}",
+        supported
+            .iter()
+            .map(|secs| format_duration(Duration::seconds(*secs as i64)))
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+
+    // Print an descriptive error and quit
+    quit_error_msg(
+        format!(
+            "expiry time of {} is not supported by the server",
+            format_duration(Duration::seconds(expiry_time as i64)),
+        ),
+        ErrorHintsBuilder::default()
+            .add_info(info)
+            .force(true)
+            .verbose(false)
+            .build()
+            .unwrap(),
+    );
+}
+
 /// Get the project directories instance for this application.
 /// This may be used to determine the project, cache, configuration, data and
 /// some other directory paths.
-#[cfg(feature = "history")]
 pub fn app_project_dirs() -> ProjectDirs {
     ProjectDirs::from("", "", crate_name!())
         .expect("failed to determine location of project directories")
@@ -884,10 +1181,13 @@ pub fn api_version_list() -> Vec<&'static str> {
 // TODO: extract this into module
 pub fn follow_url(client: &Client, url: &Url) -> Result<Url, FollowError> {
     // Send the request, follow the URL, ensure success
+    debug!("following {}", redact_url(url));
+    let start = Instant::now();
     let response = client
         .get(url.as_str())
         .send()
         .map_err(FollowError::Request)?;
+    log_response(&response, start);
     ensure_success(&response)?;
 
     // Obtain the final URL
@@ -899,7 +1199,7 @@ pub fn follow_url(client: &Client, url: &Url) -> Result<Url, FollowError> {
 pub enum FollowError {
     /// Failed to send the shortening request.
     #[fail(display = "failed to send URL follow request")]
-    Request(#[cause] reqwest::Error),
+    Request(#[cause] ClientError),
 
     /// The server responded with a bad response.
     #[fail(display = "failed to shorten URL, got bad response")]
@@ -911,3 +1211,100 @@ impl From<ResponseError> for FollowError {
         FollowError::Response(err)
     }
 }
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum ParseDurationError {
+    /// No duration was given.
+    #[fail(display = "no time given")]
+    Empty,
+
+    /// A time unit was given without a value.
+    #[fail(display = "time unit without value")]
+    MissingValue,
+
+    /// An unknown time unit was given.
+    #[fail(display = "unknown time unit '{}'", _0)]
+    UnknownUnit(char),
+
+    /// The given time value is too large.
+    #[fail(display = "time value too large")]
+    Overflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_valid() {
+        assert_eq!(parse_duration("300").unwrap(), 300);
+        assert_eq!(parse_duration("5m").unwrap(), 300);
+        assert_eq!(parse_duration("1h30m").unwrap(), 5400);
+        assert_eq!(parse_duration(" 1W1D ").unwrap(), 8 * 24 * 60 * 60);
+        assert_eq!(parse_duration("1m30").unwrap(), 90);
+    }
+
+    #[test]
+    fn parse_duration_overflow() {
+        let max = std::usize::MAX.to_string();
+        assert_eq!(
+            parse_duration(&format!("{}w", max)),
+            Err(ParseDurationError::Overflow)
+        );
+        assert_eq!(
+            parse_duration(&format!("1s{}", max)),
+            Err(ParseDurationError::Overflow)
+        );
+        assert_eq!(
+            parse_duration(&format!("{}s{}s", max, max)),
+            Err(ParseDurationError::Overflow)
+        );
+        assert_eq!(
+            parse_duration("99999999999999999999999999"),
+            Err(ParseDurationError::Overflow)
+        );
+    }
+
+    #[test]
+    fn parse_duration_invalid() {
+        assert_eq!(parse_duration(""), Err(ParseDurationError::Empty));
+        assert_eq!(parse_duration("h"), Err(ParseDurationError::MissingValue));
+        assert_eq!(
+            parse_duration("5x"),
+            Err(ParseDurationError::UnknownUnit('x'))
+        );
+        assert_eq!(
+            parse_duration("-5m"),
+            Err(ParseDurationError::UnknownUnit('-'))
+        );
+    }
+
+    #[test]
+    fn glob_match_valid() {
+        assert!(glob_match("*.log", "debug.log"));
+        assert!(glob_match("*.log", ".log"));
+        assert!(glob_match("file?.txt", "file1.txt"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(glob_match("*.LOG", "Debug.log"));
+        assert!(glob_match("**", ""));
+        assert!(glob_match("", ""));
+    }
+
+    #[test]
+    fn glob_match_invalid() {
+        assert!(!glob_match("*.log", "debug.txt"));
+        assert!(!glob_match("file?.txt", "file.txt"));
+        assert!(!glob_match("file?.txt", "file12.txt"));
+        assert!(!glob_match("a*b*c", "aXbY"));
+        assert!(!glob_match("", "a"));
+        assert!(!glob_match("abc", "ab"));
+    }
+
+    #[test]
+    fn glob_match_long() {
+        // Backtracking must stay fast for many stars and a long text
+        let text = "a".repeat(10_000);
+        assert!(!glob_match(&format!("{}b", "*a".repeat(20)), &text));
+        assert!(glob_match(&"*a".repeat(20), &text));
+    }
+}